@@ -0,0 +1,545 @@
+// src/api/auth.rs
+// Authentication middleware, login sessions and the user and token admin endpoints.
+//
+// Requests are authenticated with an API token, sent as `Authorization: Bearer <token>`,
+// or with the session cookie the web UI gets from logging in. Browsers cannot set headers
+// on WebSocket connections, so `/ws` also accepts the token as an `access_token` query
+// parameter or as a `pegasus.token.<token>` entry in `Sec-WebSocket-Protocol`. The
+// resolved `Principal` is stored in the request extensions for handlers to check
+// ownership against. With authentication disabled every request is an administrator.
+
+use std::collections::HashMap;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::api::AppState;
+use crate::api::error::{ApiError, ApiJson};
+use crate::auth::{Principal, TokenInfo, UserInfo};
+use crate::error::PegasusError;
+
+/// Name of the web UI's session cookie.
+const SESSION_COOKIE: &str = "pegasus_session";
+
+/// Prefix of the WebSocket subprotocol entry that carries an API token.
+const TOKEN_PROTOCOL_PREFIX: &str = "pegasus.token.";
+
+// The JSON body accepted by POST /api/auth/login
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LoginPayload {
+    username: String,
+    password: String,
+}
+
+// Define a struct for the current user response
+#[derive(Serialize, Debug)]
+pub struct MeResponse {
+    /// `None` when authentication is disabled.
+    username: Option<String>,
+    admin: bool,
+    auth_enabled: bool,
+}
+
+// The JSON body accepted when creating a user
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UserPayload {
+    username: String,
+    /// Users without a password can only use API tokens.
+    password: Option<String>,
+    #[serde(default)]
+    admin: bool,
+}
+
+// The JSON body accepted when creating an API token
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TokenPayload {
+    /// What the token is used for, e.g. `home-assistant`.
+    name: String,
+    /// The user the token acts as. Defaults to the caller.
+    username: Option<String>,
+}
+
+// Define a struct for a newly created API token
+#[derive(Serialize, Debug)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    info: TokenInfo,
+    /// The token itself. It is not stored and cannot be shown again.
+    token: String,
+}
+
+/// Middleware that authenticates every request to the routes it wraps.
+///
+/// Rejects unauthenticated requests with `401` and stores the caller's `Principal` in
+/// the request extensions otherwise.
+pub async fn require_auth(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if !state.config.auth.enabled {
+        request.extensions_mut().insert(Principal::anonymous());
+        return next.run(request).await;
+    }
+
+    let principal = match bearer_token(&request) {
+        // A token that was sent but is wrong is rejected even if a session cookie is valid
+        Some(token) => state.auth.authenticate_token(&token).await,
+        None => match cookie(request.headers(), SESSION_COOKIE) {
+            Some(session) => state.auth.authenticate_session(&session).await,
+            None => None,
+        },
+    };
+    let Some(principal) = principal else {
+        debug!(path = %request.uri().path(), "Rejecting unauthenticated request");
+        return PegasusError::Unauthorized(
+            "Send an API token or log in to use this endpoint".to_string(),
+        )
+        .into_response();
+    };
+
+    request.extensions_mut().insert(principal);
+    next.run(request).await
+}
+
+/// Handler for POST /api/auth/login.
+///
+/// Checks a username and password and sets the session cookie used by the web UI.
+pub async fn login(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<LoginPayload>,
+) -> Result<Response, ApiError> {
+    if !state.config.auth.enabled {
+        return Err(PegasusError::ValidationError("Authentication is disabled".to_string()).into());
+    }
+
+    let (session, principal) = state
+        .auth
+        .login(&payload.username, &payload.password)
+        .await?;
+    let max_age = state.config.auth.session_ttl_hours * 3600;
+    let cookie = session_cookie(&session, max_age, state.config.auth.secure_cookies);
+    let body = MeResponse {
+        username: principal.username,
+        admin: principal.admin,
+        auth_enabled: true,
+    };
+    Ok(([(header::SET_COOKIE, cookie)], Json(body)).into_response())
+}
+
+/// Handler for POST /api/auth/logout.
+///
+/// Ends the caller's login session, if any, and clears the session cookie.
+pub async fn logout(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(session) = cookie(&headers, SESSION_COOKIE) {
+        state.auth.logout(&session);
+    }
+    let cookie = session_cookie("", 0, state.config.auth.secure_cookies);
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response()
+}
+
+/// Handler for GET /api/auth/me.
+///
+/// Returns who the caller is authenticated as.
+pub async fn me(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Json<MeResponse> {
+    Json(MeResponse {
+        username: principal.username,
+        admin: principal.admin,
+        auth_enabled: state.config.auth.enabled,
+    })
+}
+
+/// Handler for GET /api/admin/users.
+pub async fn list_users(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<UserInfo>>, ApiError> {
+    principal.require_admin()?;
+    Ok(Json(state.auth.list_users().await))
+}
+
+/// Handler for POST /api/admin/users.
+pub async fn create_user(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    ApiJson(payload): ApiJson<UserPayload>,
+) -> Result<Response, ApiError> {
+    principal.require_admin()?;
+    let user = state
+        .auth
+        .create_user(
+            &payload.username,
+            payload.password.as_deref(),
+            payload.admin,
+        )
+        .await?;
+    Ok((StatusCode::CREATED, Json(user)).into_response())
+}
+
+/// Handler for DELETE /api/admin/users/:username.
+///
+/// Deletes a user together with their API tokens and sessions. Their jobs are kept and
+/// stay visible to administrators.
+pub async fn delete_user(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(username): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    principal.require_admin()?;
+    state.auth.delete_user(&username).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handler for GET /api/admin/tokens.
+pub async fn list_tokens(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<TokenInfo>>, ApiError> {
+    principal.require_admin()?;
+    Ok(Json(state.auth.list_tokens().await))
+}
+
+/// Handler for POST /api/admin/tokens.
+///
+/// Issues an API token. The response is the only time the token itself is shown.
+pub async fn create_token(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    ApiJson(payload): ApiJson<TokenPayload>,
+) -> Result<Response, ApiError> {
+    principal.require_admin()?;
+    let username = payload
+        .username
+        .or(principal.username)
+        .ok_or_else(|| PegasusError::ValidationError("username is required".to_string()))?;
+    let (info, token) = state.auth.create_token(&payload.name, &username).await?;
+    Ok((StatusCode::CREATED, Json(CreatedToken { info, token })).into_response())
+}
+
+/// Handler for DELETE /api/admin/tokens/:id.
+pub async fn delete_token(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(token_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    principal.require_admin()?;
+    state.auth.delete_token(&token_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Decides which progress updates a client may see, remembering the owner of every job
+/// and batch it has looked up.
+pub struct VisibilityCache {
+    state: AppState,
+    principal: Principal,
+    known: HashMap<String, bool>,
+}
+
+impl VisibilityCache {
+    pub fn new(state: AppState, principal: Principal) -> Self {
+        VisibilityCache {
+            state,
+            principal,
+            known: HashMap::new(),
+        }
+    }
+
+    /// The client this cache decides for.
+    pub fn principal(&self) -> &Principal {
+        &self.principal
+    }
+
+    /// Whether the client may see updates of the job or batch with this ID.
+    pub async fn can_see(&mut self, id: &str) -> bool {
+        if self.principal.admin {
+            return true;
+        }
+        if let Some(&visible) = self.known.get(id) {
+            return visible;
+        }
+        let visible = if let Some(job) = self.state.jobs.get(id).await {
+            self.principal.can_access(job.owner.as_deref())
+        } else if let Some(batch) = self.state.batches.get(id).await {
+            self.principal.can_access(batch.owner.as_deref())
+        } else {
+            false
+        };
+        self.known.insert(id.to_string(), visible);
+        visible
+    }
+}
+
+// Finds the API token of a request: the Authorization header, or for WebSocket upgrades
+// the access_token query parameter or the token subprotocol
+fn bearer_token(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    if let Some(value) = headers.get(header::AUTHORIZATION)
+        && let Ok(value) = value.to_str()
+        && let Some(token) = value.strip_prefix("Bearer ")
+    {
+        return Some(token.trim().to_string());
+    }
+
+    if request.uri().path() != "/ws" {
+        return None;
+    }
+    if let Some(query) = request.uri().query()
+        && let Some((_, token)) =
+            url::form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "access_token")
+    {
+        return Some(token.into_owned());
+    }
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|protocol| protocol.trim().strip_prefix(TOKEN_PROTOCOL_PREFIX))
+        .map(str::to_string)
+}
+
+// Reads a cookie from the request headers
+fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then(|| value.to_string())
+        })
+}
+
+// Builds the Set-Cookie value for the session cookie; a max age of 0 clears it
+fn session_cookie(session: &str, max_age: u64, secure: bool) -> HeaderValue {
+    let mut cookie = format!(
+        "{}={}; HttpOnly; SameSite=Strict; Path=/; Max-Age={}",
+        SESSION_COOKIE, session, max_age
+    );
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    // Session values are hex, so the cookie is always a valid header value
+    HeaderValue::from_str(&cookie).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use reqwest::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::api::{AppState, create_router};
+    use crate::auth::AuthStore;
+    use crate::config::Config;
+
+    // Builds the application with authentication enabled and every store under `dir`
+    async fn test_state(dir: &tempfile::TempDir) -> AppState {
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        let mut config = Config::default();
+        config.auth.enabled = true;
+        config.paths.download_dir = path("downloads");
+        config.paths.output_dir = path("output");
+        config.paths.job_store = path("jobs.jsonl");
+        config.paths.subscriptions = path("subscriptions.json");
+        config.paths.batches = path("batches.jsonl");
+        config.paths.auth_store = path("auth.json");
+        config.paths.vault = path("vault.json");
+        config.paths.download_archive = path("archive.txt");
+
+        AppState {
+            jobs: Arc::new(
+                crate::jobs::JobStore::open(dir.path().join("jobs.jsonl").as_path(), None)
+                    .await
+                    .unwrap(),
+            ),
+            queue: crate::queue::JobQueue::new(crate::queue::QueueLimits {
+                max_concurrent: 1,
+                per_domain: 1,
+                domain_overrides: Default::default(),
+            }),
+            cancellations: Default::default(),
+            transfers: Arc::new(
+                crate::transfer::TransferRegistry::from_config(&Default::default(), None).unwrap(),
+            ),
+            subscriptions: Arc::new(
+                crate::subscriptions::SubscriptionStore::open(
+                    &dir.path().join("subscriptions.json"),
+                )
+                .await
+                .unwrap(),
+            ),
+            archive: Arc::new(
+                crate::subscriptions::archive::DownloadArchive::open(
+                    &dir.path().join("archive.txt"),
+                )
+                .await
+                .unwrap(),
+            ),
+            info_cache: Arc::new(crate::download::info_cache::InfoCache::new(
+                Duration::from_secs(60),
+            )),
+            batches: Arc::new(
+                crate::batches::BatchStore::open(&dir.path().join("batches.jsonl"))
+                    .await
+                    .unwrap(),
+            ),
+            auth: Arc::new(
+                AuthStore::open(&dir.path().join("auth.json"), 24)
+                    .await
+                    .unwrap(),
+            ),
+            vault: Arc::new(
+                crate::credentials::CredentialVault::open(
+                    &dir.path().join("vault.json"),
+                    &config.vault,
+                )
+                .await
+                .unwrap(),
+            ),
+            webhooks: Arc::new(crate::webhooks::WebhookDispatcher::new(
+                config.webhooks.clone(),
+            )),
+            roots: Arc::new(crate::roots::OutputRoots::from_config(&config).unwrap()),
+            config: Arc::new(config),
+        }
+    }
+
+    // Serves the application on a free local port and returns its base URL
+    async fn serve(state: AppState) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, create_router(state).into_make_service()).into_future());
+        format!("http://{}", address)
+    }
+
+    async fn status(
+        base: &str,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> StatusCode {
+        let mut request = reqwest::Client::new().request(method, format!("{}{}", base, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        request.send().await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn admin_routes_are_forbidden_to_other_users() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir).await;
+        let admin_token = state.auth.bootstrap(None).await.unwrap().unwrap();
+        state.auth.create_user("alice", None, false).await.unwrap();
+        let (_, alice_token) = state.auth.create_token("alice", "alice").await.unwrap();
+        let base = serve(state).await;
+
+        let cookies = json!({ "type": "cookies", "cookies": "# Netscape HTTP Cookie File\n" });
+        let routes = [
+            (Method::GET, "/api/admin/users", None),
+            (
+                Method::POST,
+                "/api/admin/users",
+                Some(json!({ "username": "mallory", "admin": true })),
+            ),
+            (Method::DELETE, "/api/admin/users/admin", None),
+            (Method::GET, "/api/admin/tokens", None),
+            (
+                Method::POST,
+                "/api/admin/tokens",
+                Some(json!({ "name": "stolen", "username": "admin" })),
+            ),
+            (Method::DELETE, "/api/admin/tokens/anything", None),
+            (Method::GET, "/api/admin/credentials", None),
+            (Method::GET, "/api/admin/credentials/warnings", None),
+            (
+                Method::PUT,
+                "/api/admin/credentials/example.com",
+                Some(cookies),
+            ),
+            (Method::DELETE, "/api/admin/credentials/example.com", None),
+            (Method::GET, "/api/admin/webhooks", None),
+            (Method::GET, "/api/admin/webhooks/deliveries", None),
+        ];
+        for (method, path, body) in routes {
+            assert_eq!(
+                status(&base, method.clone(), path, Some(&alice_token), body).await,
+                StatusCode::FORBIDDEN,
+                "{} {}",
+                method,
+                path
+            );
+        }
+
+        // The same caller can use the routes that are not admin-only, and an
+        // administrator can use the admin ones
+        for (token, path) in [
+            (&alice_token, "/api/auth/me"),
+            (&alice_token, "/api/jobs"),
+            (&admin_token, "/api/admin/users"),
+            (&admin_token, "/api/admin/tokens"),
+            (&admin_token, "/api/admin/webhooks"),
+        ] {
+            assert_eq!(
+                status(&base, Method::GET, path, Some(token), None).await,
+                StatusCode::OK,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_wrong_and_revoked_tokens_are_unauthorized() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir).await;
+        state.auth.create_user("alice", None, false).await.unwrap();
+        let (token_info, token) = state.auth.create_token("alice", "alice").await.unwrap();
+        let auth = state.auth.clone();
+        let base = serve(state).await;
+
+        assert_eq!(
+            status(&base, Method::GET, "/api/jobs", Some(&token), None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&base, Method::GET, "/api/jobs", None, None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&base, Method::GET, "/api/jobs", Some("pgs_wrong"), None).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        auth.delete_token(&token_info.id).await.unwrap();
+        for path in ["/api/jobs", "/api/auth/me", "/api/admin/users"] {
+            assert_eq!(
+                status(&base, Method::GET, path, Some(&token), None).await,
+                StatusCode::UNAUTHORIZED,
+                "{}",
+                path
+            );
+        }
+    }
+}
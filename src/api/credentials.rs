@@ -0,0 +1,134 @@
+// src/api/credentials.rs
+// Admin endpoints for the credential vault.
+//
+// Secrets go in but never come out: responses only describe what is stored for a
+// domain, how many cookies it holds and when they expire.
+
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::api::AppState;
+use crate::api::error::{ApiError, ApiJson};
+use crate::auth::Principal;
+use crate::credentials::{CredentialStatus, ExpiryStatus, Secret};
+
+// The JSON body accepted by PUT /api/admin/credentials/:domain. Deliberately not `Debug`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CredentialPayload {
+    /// The contents of a Netscape cookie file.
+    Cookies {
+        cookies: String,
+        label: Option<String>,
+    },
+    /// A site login for yt-dlp's `--username` and `--password`.
+    Login {
+        username: String,
+        password: String,
+        label: Option<String>,
+    },
+}
+
+/// A cookie file that needs to be re-exported soon.
+#[derive(Serialize, Debug)]
+pub struct ExpiryWarning {
+    pub domain: String,
+    pub expiry_status: ExpiryStatus,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// A human-readable description of the problem.
+    pub message: String,
+}
+
+/// Handler for GET /api/admin/credentials.
+///
+/// Lists the stored credentials without their secrets.
+pub async fn list_credentials(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<CredentialStatus>>, ApiError> {
+    principal.require_admin()?;
+    Ok(Json(state.vault.list().await))
+}
+
+/// Handler for GET /api/admin/credentials/warnings.
+///
+/// Lists the cookie files that have expired or expire within
+/// `vault.expiry_warning_days`.
+pub async fn credential_warnings(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<ExpiryWarning>>, ApiError> {
+    principal.require_admin()?;
+    let warnings = state
+        .vault
+        .warnings()
+        .await
+        .into_iter()
+        .map(|status| {
+            let domain = status.info.domain;
+            let expires_at = status.info.expires_at;
+            let when = expires_at.map_or_else(String::new, |at| format!(" on {}", at.to_rfc3339()));
+            let message = match status.expiry_status {
+                ExpiryStatus::Expired => format!(
+                    "The cookies for {} expired{}; downloads that need a login will fail until they are replaced",
+                    domain, when
+                ),
+                _ => format!("The cookies for {} expire{}", domain, when),
+            };
+            ExpiryWarning {
+                domain,
+                expiry_status: status.expiry_status,
+                expires_at,
+                message,
+            }
+        })
+        .collect();
+    Ok(Json(warnings))
+}
+
+/// Handler for PUT /api/admin/credentials/:domain.
+///
+/// Stores a cookie file or login for a domain and its subdomains, replacing any existing
+/// one. Responds with 201 Created for a new domain and 200 OK for a replacement.
+pub async fn put_credential(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(domain): Path<String>,
+    ApiJson(payload): ApiJson<CredentialPayload>,
+) -> Result<Response, ApiError> {
+    principal.require_admin()?;
+    let (secret, label) = match payload {
+        CredentialPayload::Cookies { cookies, label } => {
+            (Secret::Cookies { contents: cookies }, label)
+        }
+        CredentialPayload::Login {
+            username,
+            password,
+            label,
+        } => (Secret::Login { username, password }, label),
+    };
+    let (status, replaced) = state.vault.store(&domain, secret, label).await?;
+    let code = if replaced {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok((code, Json(status)).into_response())
+}
+
+/// Handler for DELETE /api/admin/credentials/:domain.
+pub async fn delete_credential(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(domain): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    principal.require_admin()?;
+    state.vault.delete(&domain).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,414 @@
+// src/api/error.rs
+// Turns errors into consistent JSON responses.
+//
+// Every API error has the shape
+//
+// ```json
+// { "error": { "code": "job_not_found", "message": "Job not found: ...", "details": null } }
+// ```
+//
+// `code` is stable and meant for automation to branch on; `message` is for humans and
+// may change. The `ApiJson` and `ApiQuery` extractors replace Axum's plain-text
+// rejections with the same shape.
+
+use axum::{
+    Json, async_trait,
+    extract::{
+        FromRequest, FromRequestParts, Query, Request,
+        rejection::{JsonRejection, QueryRejection},
+    },
+    http::{StatusCode, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::PegasusError;
+
+/// An error as returned by the HTTP API.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorPayload<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorPayload<'a> {
+    code: &'a str,
+    message: &'a str,
+    details: &'a Option<Value>,
+}
+
+impl ApiError {
+    /// Creates an error with an explicit status and code.
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Attaches structured details, e.g. the field a validation error is about.
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+/// Maps an application error to the HTTP status it is reported with.
+///
+/// Permanent problems with the requested media are `422`, failures of the sites or
+/// services we talk to are `502`/`503`/`504`, and missing binaries are `503` because
+/// the server cannot do any work until it is fixed.
+pub fn status_for(error: &PegasusError) -> StatusCode {
+    match error {
+        PegasusError::ValidationError(_) => StatusCode::BAD_REQUEST,
+        PegasusError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+        PegasusError::Forbidden(_) => StatusCode::FORBIDDEN,
+        PegasusError::JobNotFound(_)
+        | PegasusError::SubscriptionNotFound(_)
+        | PegasusError::BatchNotFound(_)
+        | PegasusError::UserNotFound(_)
+        | PegasusError::TokenNotFound(_)
+        | PegasusError::CredentialNotFound(_) => StatusCode::NOT_FOUND,
+        PegasusError::JobConflict(_) | PegasusError::Cancelled => StatusCode::CONFLICT,
+        PegasusError::VideoUnavailable(_)
+        | PegasusError::VideoPrivate(_)
+        | PegasusError::GeoBlocked(_)
+        | PegasusError::LoginRequired(_)
+        | PegasusError::UnsupportedUrl(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        PegasusError::RateLimited(_) | PegasusError::BinaryMissing(_) => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        PegasusError::NetworkTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        PegasusError::YtDlpError(_)
+        | PegasusError::TransferError(_)
+        | PegasusError::ExternalServiceError(_) => StatusCode::BAD_GATEWAY,
+        PegasusError::ConfigError(_)
+        | PegasusError::WebServerError(_)
+        | PegasusError::MergeFailed(_)
+        | PegasusError::DownloadError(_)
+        | PegasusError::ProcessingError(_)
+        | PegasusError::IoError(_)
+        | PegasusError::ExternalCommandError(_)
+        | PegasusError::Unknown(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl From<PegasusError> for ApiError {
+    fn from(error: PegasusError) -> Self {
+        ApiError::new(status_for(&error), error.code(), error.to_string())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let (status, code, message) = match &rejection {
+            JsonRejection::JsonSyntaxError(_) => (
+                StatusCode::BAD_REQUEST,
+                "malformed_json",
+                "Request body is not valid JSON",
+            ),
+            JsonRejection::JsonDataError(_) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_body",
+                "Request body does not match the expected shape",
+            ),
+            JsonRejection::MissingJsonContentType(_) => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Expected a request with `Content-Type: application/json`",
+            ),
+            _ if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload_too_large",
+                "Request body is too large",
+            ),
+            _ => (
+                StatusCode::BAD_REQUEST,
+                "invalid_body",
+                "Failed to read the request body",
+            ),
+        };
+        // Serde's explanation names the offending field and position
+        ApiError::new(status, code, message)
+            .with_details(serde_json::json!({ "reason": rejection.body_text() }))
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_query",
+            "Query string does not match the expected parameters",
+        )
+        .with_details(serde_json::json!({ "reason": rejection.body_text() }))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: ErrorPayload {
+                code: self.code,
+                message: &self.message,
+                details: &self.details,
+            },
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl IntoResponse for PegasusError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Like `axum::Json`, but rejects bad bodies with a JSON `ApiError`.
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(ApiJson(value))
+    }
+}
+
+/// Like `axum::extract::Query`, but rejects bad query strings with a JSON `ApiError`.
+pub struct ApiQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(ApiQuery(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, to_bytes};
+    use axum::http::header::CONTENT_TYPE;
+    use serde::Deserialize;
+
+    #[test]
+    fn every_error_has_a_pinned_code_and_status() {
+        let message = || "x".to_string();
+        let cases = [
+            (PegasusError::ConfigError(message()), "config_error", 500),
+            (
+                PegasusError::ValidationError(message()),
+                "validation_error",
+                400,
+            ),
+            (PegasusError::WebServerError(message()), "server_error", 500),
+            (PegasusError::YtDlpError(message()), "yt_dlp_error", 502),
+            (
+                PegasusError::VideoUnavailable(message()),
+                "video_unavailable",
+                422,
+            ),
+            (PegasusError::VideoPrivate(message()), "video_private", 422),
+            (PegasusError::GeoBlocked(message()), "geo_blocked", 422),
+            (
+                PegasusError::LoginRequired(message()),
+                "login_required",
+                422,
+            ),
+            (PegasusError::RateLimited(message()), "rate_limited", 503),
+            (
+                PegasusError::NetworkTimeout(message()),
+                "network_error",
+                504,
+            ),
+            (PegasusError::MergeFailed(message()), "merge_failed", 500),
+            (
+                PegasusError::UnsupportedUrl(message()),
+                "unsupported_url",
+                422,
+            ),
+            (
+                PegasusError::DownloadError(message()),
+                "download_error",
+                500,
+            ),
+            (
+                PegasusError::ProcessingError(message()),
+                "processing_error",
+                500,
+            ),
+            (
+                PegasusError::TransferError(message()),
+                "transfer_error",
+                502,
+            ),
+            (
+                PegasusError::IoError(std::io::Error::other("x")),
+                "io_error",
+                500,
+            ),
+            (
+                PegasusError::ExternalServiceError(message()),
+                "external_service_error",
+                502,
+            ),
+            (
+                PegasusError::ExternalCommandError(message()),
+                "external_command_error",
+                500,
+            ),
+            (
+                PegasusError::BinaryMissing(message()),
+                "binary_missing",
+                503,
+            ),
+            (PegasusError::JobNotFound(message()), "job_not_found", 404),
+            (PegasusError::JobConflict(message()), "job_conflict", 409),
+            (
+                PegasusError::SubscriptionNotFound(message()),
+                "subscription_not_found",
+                404,
+            ),
+            (
+                PegasusError::BatchNotFound(message()),
+                "batch_not_found",
+                404,
+            ),
+            (PegasusError::UserNotFound(message()), "user_not_found", 404),
+            (
+                PegasusError::TokenNotFound(message()),
+                "token_not_found",
+                404,
+            ),
+            (
+                PegasusError::CredentialNotFound(message()),
+                "credential_not_found",
+                404,
+            ),
+            (PegasusError::Unauthorized(message()), "unauthorized", 401),
+            (PegasusError::Forbidden(message()), "forbidden", 403),
+            (PegasusError::Cancelled, "cancelled", 409),
+            (PegasusError::Unknown(message()), "internal_error", 500),
+        ];
+        for (error, code, status) in cases {
+            let description = error.to_string();
+            let api_error = ApiError::from(error);
+            assert_eq!(api_error.code, code, "{}", description);
+            assert_eq!(api_error.status.as_u16(), status, "{}", description);
+            assert_eq!(api_error.message, description);
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct UrlBody {
+        url: String,
+    }
+
+    async fn reject_json(content_type: Option<&str>, body: impl Into<Body>) -> ApiError {
+        let mut request = Request::builder().method("POST").uri("/");
+        if let Some(content_type) = content_type {
+            request = request.header(CONTENT_TYPE, content_type);
+        }
+        let request = request.body(body.into()).unwrap();
+        match ApiJson::<UrlBody>::from_request(request, &()).await {
+            Ok(_) => panic!("the body was accepted"),
+            Err(error) => error,
+        }
+    }
+
+    #[tokio::test]
+    async fn json_rejections_have_pinned_codes_and_statuses() {
+        let json = Some("application/json");
+        let cases = [
+            (reject_json(json, "{\"url\":").await, "malformed_json", 400),
+            (reject_json(json, "{\"url\": 5}").await, "invalid_body", 400),
+            (
+                reject_json(Some("text/plain"), "{\"url\":\"a\"}").await,
+                "unsupported_media_type",
+                415,
+            ),
+            (
+                reject_json(None, "{\"url\":\"a\"}").await,
+                "unsupported_media_type",
+                415,
+            ),
+            // Without a `DefaultBodyLimit` layer Axum caps bodies at 2 MB
+            (
+                reject_json(json, vec![b' '; 3 * 1024 * 1024]).await,
+                "payload_too_large",
+                413,
+            ),
+        ];
+        for (error, code, status) in cases {
+            assert_eq!(error.code, code, "{}", error.message);
+            assert_eq!(error.status.as_u16(), status, "{}", error.message);
+            assert!(error.details.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn query_rejections_have_a_pinned_code_and_status() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Params {
+            limit: usize,
+        }
+
+        let request = Request::builder()
+            .uri("/?limit=lots")
+            .body(Body::empty())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+        let error = match ApiQuery::<Params>::from_request_parts(&mut parts, &()).await {
+            Ok(_) => panic!("the query was accepted"),
+            Err(error) => error,
+        };
+        assert_eq!(error.code, "invalid_query");
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn errors_are_sent_in_the_documented_shape() {
+        let response = PegasusError::JobNotFound("abc".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": {
+                    "code": "job_not_found",
+                    "message": "Job not found: abc",
+                    "details": null,
+                }
+            })
+        );
+    }
+}
@@ -1,47 +1,207 @@
 // src/api/handlers.rs
 // Contains the handler functions for API endpoints.
 
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
 use axum::{
-    Json,
-    extract::ws::{Message, WebSocket},
-    http::StatusCode,
+    Extension, Json,
+    extract::{Path, State},
+    http::{StatusCode, Uri},
     response::{IntoResponse, Response},
 };
-use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
 use tokio::sync::broadcast;
 use tracing::{debug, error, info};
-use uuid::Uuid;
 
 // Import download module
+use crate::api::AppState;
+use crate::api::error::{ApiError, ApiJson, ApiQuery};
+use crate::auth::Principal;
+use crate::batches::{self, BatchProgress, BatchSubmission, MAX_BATCH_URLS, RejectedUrl, extract};
 use crate::download;
+use crate::download::info::{self, MediaInfo};
+use crate::download::naming::NamingOptions;
+use crate::download::options::ProcessingOptions;
+use crate::download::playlist::PlaylistOptions;
+use crate::error::PegasusError;
+use crate::jobs::{Job, JobFilter, JobStatus, NewJob};
+use crate::pipeline;
+use crate::subscriptions::{
+    MAX_INTERVAL_MINUTES, MIN_INTERVAL_MINUTES, NewSubscription, Subscription, scheduler,
+};
 
-// Create a static channel for broadcasting download progress updates
+// Create a static channel for broadcasting download progress updates. It holds as many
+// updates as the history, so a client only has to resync once its backlog could no
+// longer be replayed either
 static PROGRESS_CHANNEL: once_cell::sync::Lazy<(
-    broadcast::Sender<ProgressUpdate>,
-    broadcast::Receiver<ProgressUpdate>,
+    broadcast::Sender<ProgressEvent>,
+    broadcast::Receiver<ProgressEvent>,
 )> = once_cell::sync::Lazy::new(|| {
-    let (tx, rx) = broadcast::channel(100);
+    let (tx, rx) = broadcast::channel(EVENT_HISTORY_LEN);
     (tx, rx)
 });
 
+// Numbers every update and remembers recent ones, so clients that connect late or
+// reconnect can catch up
+static EVENT_LOG: once_cell::sync::Lazy<Mutex<EventLog>> = once_cell::sync::Lazy::new(|| {
+    Mutex::new(EventLog {
+        next_id: 1,
+        history: VecDeque::with_capacity(EVENT_HISTORY_LEN),
+        latest: HashMap::new(),
+    })
+});
+
+/// Number of recent updates kept for clients resuming an event stream.
+pub const EVENT_HISTORY_LEN: usize = 1000;
+
 // Define the structure expected in the JSON request body from the frontend
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SubmitPayload {
     media_url: String,
+    /// Name of the output root. Defaults to the download directory.
+    root: Option<String>,
+    /// Subdirectory of the output root, e.g. `music/2024`.
     output_dir: Option<String>,
-    processing_options: Vec<String>,
+    /// Falls back to the configured default processing options when omitted.
+    processing_options: Option<ProcessingOptions>,
+    /// Higher priorities leave the download queue first. Defaults to 0.
+    #[serde(default)]
+    priority: i32,
+    /// Name of the configured transfer destination. Defaults to the configured default.
+    destination: Option<String>,
+    /// Entry selection used if the URL is a playlist or channel.
+    #[serde(default)]
+    playlist: PlaylistOptions,
+    /// Output template and collision policy, overriding the configured ones.
+    #[serde(default)]
+    naming: NamingOptions,
 }
 
 // Define a struct for the JSON response
 #[derive(Serialize, Debug)]
-struct SubmitResponse {
+pub struct SubmitResponse {
     message: String,
     job_id: String,
 }
 
+// The JSON body accepted when submitting a batch. At least one of `urls` and `text`
+// must be given; the options are shared by every job in the batch.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BatchPayload {
+    /// Defaults to a name derived from the submission time.
+    name: Option<String>,
+    /// URLs to submit as they are, apart from normalization.
+    #[serde(default)]
+    urls: Vec<String>,
+    /// Free-form text or HTML, such as a pasted chat log or an exported bookmarks file,
+    /// whose media URLs are extracted and submitted.
+    text: Option<String>,
+    /// Name of the output root. Defaults to the download directory.
+    root: Option<String>,
+    /// Subdirectory of the output root, e.g. `music/2024`.
+    output_dir: Option<String>,
+    /// Falls back to the configured default processing options when omitted.
+    processing_options: Option<ProcessingOptions>,
+    #[serde(default)]
+    priority: i32,
+    /// Name of the configured transfer destination. Defaults to the configured default.
+    destination: Option<String>,
+    /// Entry selection used for any URL that is a playlist or channel.
+    #[serde(default)]
+    playlist: PlaylistOptions,
+    /// Output template and collision policy, overriding the configured ones.
+    #[serde(default)]
+    naming: NamingOptions,
+}
+
+// Define a struct for the batch submission response
+#[derive(Serialize, Debug)]
+pub struct BatchResponse {
+    batch_id: String,
+    name: String,
+    /// One entry per submitted URL, in submission order.
+    jobs: Vec<BatchJob>,
+    /// Number of URLs dropped because they normalized to one already in the batch.
+    duplicates: usize,
+    /// URLs that could not be submitted, with the reason. The rest of the batch went
+    /// ahead without them.
+    rejected: Vec<RejectedUrl>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BatchJob {
+    job_id: String,
+    url: String,
+}
+
+// The JSON body accepted when creating or replacing a subscription
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SubscriptionPayload {
+    url: String,
+    name: Option<String>,
+    /// How often the URL is polled. Defaults to hourly.
+    #[serde(default = "default_interval_minutes")]
+    interval_minutes: u32,
+    /// Falls back to the configured default processing options when omitted.
+    processing_options: Option<ProcessingOptions>,
+    /// Name of the configured transfer destination. Defaults to the configured default.
+    destination: Option<String>,
+    /// Name of the output root. Defaults to the download directory.
+    root: Option<String>,
+    /// Subdirectory of the output root, e.g. `music/2024`.
+    output_dir: Option<String>,
+    /// Which entries of the listing are considered on every check.
+    #[serde(default)]
+    playlist: PlaylistOptions,
+    /// Output template and collision policy for new items.
+    #[serde(default)]
+    naming: NamingOptions,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+fn default_interval_minutes() -> u32 {
+    60
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// Query parameters accepted by GET /api/jobs
+#[derive(Deserialize, Debug)]
+pub struct ListJobsQuery {
+    /// Comma-separated list of statuses to include (e.g. `downloading,queued`)
+    status: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+// Define a struct for the job listing response
+#[derive(Serialize, Debug)]
+pub struct ListJobsResponse {
+    jobs: Vec<Job>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+}
+
+// Query parameters accepted by GET /api/info
+#[derive(Deserialize, Debug)]
+pub struct InfoQuery {
+    url: String,
+}
+
+// Default and maximum page sizes for job listings
+const DEFAULT_JOBS_PAGE_SIZE: usize = 50;
+const MAX_JOBS_PAGE_SIZE: usize = 500;
+
 // Define a struct for progress updates
 #[derive(Clone, Serialize, Debug)]
 pub struct ProgressUpdate {
@@ -50,91 +210,550 @@ pub struct ProgressUpdate {
     pub status: String,
     pub progress: f32,
     pub message: String,
+    /// Number of jobs ahead of this one in the download queue, while it is queued.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<usize>,
+    /// The child jobs a playlist job was expanded into, sent once on expansion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<ChildJob>>,
+}
+
+impl ProgressUpdate {
+    /// Whether this is the last update its job or batch will send.
+    pub fn is_final(&self) -> bool {
+        match self.status.as_str() {
+            "completed" | "error" | "cancelled" => true,
+            "batch" => self.progress >= 1.0,
+            _ => false,
+        }
+    }
+}
+
+/// A progress update numbered by its position in the stream of all updates.
+///
+/// Event IDs increase by one with every update and restart at 1 when the server restarts.
+#[derive(Clone, Debug)]
+pub struct ProgressEvent {
+    pub id: u64,
+    pub update: ProgressUpdate,
+}
+
+/// Recent updates, in the order they were sent.
+struct EventLog {
+    /// The ID the next update will get.
+    next_id: u64,
+    /// The last `EVENT_HISTORY_LEN` updates.
+    history: VecDeque<ProgressEvent>,
+    /// The last update of every job and batch that has not finished yet.
+    latest: HashMap<String, ProgressUpdate>,
+}
+
+/// The events a resuming client missed, and a receiver for the ones that follow.
+pub struct Resume {
+    /// The events after the requested one that are still in the history, oldest first.
+    pub backlog: Vec<ProgressEvent>,
+    /// Receives every event after the backlog.
+    pub receiver: broadcast::Receiver<ProgressEvent>,
+    /// Whether some missed events had already been dropped from the history.
+    pub incomplete: bool,
+}
+
+/// A child job of a playlist job, as announced to clients.
+#[derive(Clone, Debug, Serialize)]
+pub struct ChildJob {
+    pub job_id: String,
+    pub url: String,
+    pub title: Option<String>,
 }
 
 // Updated handler function for the POST /api/submit route.
 // It now accepts a JSON payload matching the SubmitPayload struct.
 // Marked as async because it now calls the async download_video function.
-pub async fn submit_url(Json(payload): Json<SubmitPayload>) -> Response {
+pub async fn submit_url(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    ApiJson(payload): ApiJson<SubmitPayload>,
+) -> Result<Json<SubmitResponse>, ApiError> {
     // Log the received payload for debugging using tracing::info!
     // Include payload details in structured logging.
-    tracing::info!(media_url = %payload.media_url, output_dir = ?payload.output_dir, processing_options = ?payload.processing_options, "Received submission payload");
+    tracing::info!(media_url = %payload.media_url, root = ?payload.root, output_dir = ?payload.output_dir, processing_options = ?payload.processing_options, "Received submission payload");
+
+    // Submissions without options get the configured defaults
+    let processing_options = payload
+        .processing_options
+        .clone()
+        .unwrap_or_else(|| state.config.default_processing_options.clone());
 
-    // TODO: Validate the input (e.g., URL format, output_dir validity)
+    // The output directory is checked against the output roots when the job is submitted
+    processing_options.validate()?;
+    payload.playlist.validate()?;
+    payload.naming.validate()?;
+    let destination = state.transfers.resolve(payload.destination.as_deref())?;
 
-    // Generate a unique job ID
-    let job_id = Uuid::new_v4().to_string();
-    info!(job_id = %job_id, "Generated new job ID");
+    // Record the job and hand it to the download queue
+    let job = pipeline::submit_job(
+        &state,
+        NewJob {
+            url: payload.media_url.clone(),
+            root: payload.root.clone(),
+            output_dir: payload.output_dir.clone(),
+            processing_options,
+            priority: payload.priority,
+            destination,
+            playlist: payload.playlist.clone(),
+            naming: payload.naming.clone(),
+            playlist_position: None,
+            parent_id: None,
+            subscription_id: None,
+            archive_key: None,
+            batch_id: None,
+            owner: principal.owner(),
+        },
+    )
+    .await
+    .inspect_err(|e| error!(error = %e, "Failed to record job"))?;
+
+    // Return an immediate response with the job ID
+    Ok(Json(SubmitResponse {
+        message: "Submission received and queued for download.".to_string(),
+        job_id: job.id,
+    }))
+}
+
+/// Handler for POST /api/batches.
+///
+/// Submits a named batch: every URL in `urls`, plus every media URL found in `text`, is
+/// normalized, de-duplicated and submitted as its own job with the shared options. The
+/// batch's aggregate progress is available from `GET /api/batches/:id` and is broadcast
+/// as `batch` updates whenever one of its jobs finishes.
+pub async fn create_batch(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    ApiJson(payload): ApiJson<BatchPayload>,
+) -> Result<Response, ApiError> {
+    // Explicit URLs must be valid; text is searched for whatever URLs it contains
+    let mut candidates = Vec::with_capacity(payload.urls.len());
+    for raw in &payload.urls {
+        let url = extract::normalize_url(raw)
+            .ok_or_else(|| PegasusError::ValidationError(format!("Not an http(s) URL: {}", raw)))?;
+        candidates.push(url);
+    }
+    if let Some(text) = &payload.text {
+        candidates.extend(extract::extract_urls(text));
+    }
+
+    let found = candidates.len();
+    let mut seen = HashSet::new();
+    let urls: Vec<String> = candidates
+        .into_iter()
+        .filter(|url| seen.insert(url.clone()))
+        .collect();
+    if urls.is_empty() {
+        return Err(PegasusError::ValidationError("No media URLs found".to_string()).into());
+    }
+    if urls.len() > MAX_BATCH_URLS {
+        return Err(PegasusError::ValidationError(format!(
+            "A batch may contain at most {} URLs, found {}",
+            MAX_BATCH_URLS,
+            urls.len()
+        ))
+        .into());
+    }
 
-    // --- Call Download Logic ---
-    // Define a temporary download directory
-    let download_base_dir = PathBuf::from("/tmp/pegasus_downloads");
-    // Use the output_dir from payload if provided, otherwise use a default within the base dir
-    let target_download_dir = match &payload.output_dir {
-        Some(dir) => download_base_dir.join(dir),
-        None => download_base_dir.join("default"),
+    let processing_options = payload
+        .processing_options
+        .unwrap_or_else(|| state.config.default_processing_options.clone());
+    processing_options.validate()?;
+    payload.playlist.validate()?;
+    payload.naming.validate()?;
+    let destination = state.transfers.resolve(payload.destination.as_deref())?;
+    // Checked up front, so a bad directory does not leave a partial batch behind
+    state.roots.resolve(
+        payload.root.as_deref(),
+        payload.output_dir.as_deref(),
+        principal.owner().as_deref(),
+    )?;
+
+    let name = payload
+        .name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| format!("Batch {}", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S")));
+    let duplicates = found - urls.len();
+    info!(name = %name, urls = urls.len(), duplicates, "Received batch submission");
+
+    let BatchSubmission { batch, rejected } = pipeline::submit_batch(
+        &state,
+        name,
+        urls,
+        NewJob {
+            url: String::new(),
+            root: payload.root,
+            output_dir: payload.output_dir,
+            processing_options,
+            priority: payload.priority,
+            destination,
+            playlist: payload.playlist,
+            naming: payload.naming,
+            playlist_position: None,
+            parent_id: None,
+            subscription_id: None,
+            archive_key: None,
+            batch_id: None,
+            owner: principal.owner(),
+        },
+    )
+    .await
+    .inspect_err(|e| error!(error = %e, "Failed to submit batch"))?;
+
+    let mut jobs = Vec::with_capacity(batch.job_ids.len());
+    for job_id in &batch.job_ids {
+        if let Some(job) = state.jobs.get(job_id).await {
+            jobs.push(BatchJob {
+                job_id: job.id,
+                url: job.url,
+            });
+        }
+    }
+    let response = BatchResponse {
+        batch_id: batch.id,
+        name: batch.name,
+        jobs,
+        duplicates,
+        rejected,
     };
+    Ok((StatusCode::CREATED, Json(response)).into_response())
+}
 
-    // Send initial progress update
-    send_progress_update(
-        &job_id,
-        &payload.media_url,
-        "starting",
-        0.0,
-        "Preparing download...",
-    );
-
-    // Clone values for the async task
-    let job_id_clone = job_id.clone();
-    let url_clone = payload.media_url.clone();
-    let target_dir_clone = target_download_dir.clone();
-    let options_clone = payload.processing_options.clone();
-
-    // Spawn a task to handle the download asynchronously
-    tokio::spawn(async move {
-        // Call the download function asynchronously with progress updates
-        let download_result = download::download_video_with_progress(
-            &url_clone,
-            &target_dir_clone,
-            &options_clone,
-            &job_id_clone,
-        )
-        .await;
-
-        // Handle the download result
-        match download_result {
-            Ok(downloaded_file_path) => {
-                info!(job_id = %job_id_clone, file_path = %downloaded_file_path, "Video download successful");
-                // Send completion update
-                send_progress_update(
-                    &job_id_clone,
-                    &url_clone,
-                    "completed",
-                    1.0,
-                    "Download completed successfully",
-                );
-                // TODO: Trigger processing and transfer steps with downloaded_file_path
-            }
-            Err(e) => {
-                error!(job_id = %job_id_clone, error = %e, "Video download failed");
-                // Send error update
-                send_progress_update(
-                    &job_id_clone,
-                    &url_clone,
-                    "error",
-                    0.0,
-                    &format!("Download failed: {}", e),
-                );
-            }
+/// Handler for GET /api/batches.
+///
+/// Lists the caller's batches with their aggregate progress, newest first. Administrators
+/// see every batch.
+pub async fn list_batches(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Json<Vec<BatchProgress>> {
+    let mut listing = Vec::new();
+    for batch in state.batches.list().await {
+        if !principal.can_access(batch.owner.as_deref()) {
+            continue;
         }
-    });
+        listing.push(batches::progress(&batch, &state.jobs).await);
+    }
+    Json(listing)
+}
 
-    // Return an immediate response with the job ID
-    let response_body = SubmitResponse {
-        message: "Submission received and download started.".to_string(),
-        job_id,
+/// Handler for GET /api/batches/:id.
+///
+/// Returns a batch's aggregate progress and the state of each of its jobs.
+pub async fn get_batch(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(batch_id): Path<String>,
+) -> Result<Json<BatchProgress>, ApiError> {
+    // Other users' batches are reported as missing rather than forbidden
+    let batch = state
+        .batches
+        .get(&batch_id)
+        .await
+        .filter(|batch| principal.can_access(batch.owner.as_deref()))
+        .ok_or(PegasusError::BatchNotFound(batch_id))?;
+    Ok(Json(batches::progress(&batch, &state.jobs).await))
+}
+
+/// Handler for GET /api/info.
+///
+/// Previews a URL before it is submitted: title, uploader, duration, thumbnails, chapters,
+/// subtitle languages and every available format. The extracted info is cached, so a job
+/// submitted for the same URL shortly afterwards reuses it. A format's `selector` can be submitted
+/// as `processingOptions.format` to download exactly that format.
+pub async fn get_media_info(
+    State(state): State<AppState>,
+    ApiQuery(query): ApiQuery<InfoQuery>,
+) -> Result<Json<MediaInfo>, ApiError> {
+    if !(query.url.starts_with("http://") || query.url.starts_with("https://")) {
+        return Err(
+            PegasusError::ValidationError(format!("URL must be http(s): {}", query.url)).into(),
+        );
+    }
+
+    info!(url = %query.url, "Fetching media info preview");
+    let credentials = state.vault.for_url(&query.url).await?;
+    // Cached, so submitting the previewed URL right after does not extract it again
+    let fetched = state
+        .info_cache
+        .get_or_fetch(&query.url, &[], || {
+            download::get_video_info(
+                &query.url,
+                &[],
+                &state.config.binaries.yt_dlp,
+                state.config.timeouts.info_fetch(),
+                credentials.as_ref(),
+            )
+        })
+        .await?;
+    Ok(Json(info::summarize(&fetched.info)))
+}
+
+/// Handler for GET /api/jobs.
+///
+/// Lists recorded jobs, newest first, optionally filtered by status and paginated
+/// with `offset` and `limit` query parameters. Users only see their own jobs;
+/// administrators see every job.
+pub async fn list_jobs(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    ApiQuery(query): ApiQuery<ListJobsQuery>,
+) -> Result<Json<ListJobsResponse>, ApiError> {
+    // Parse the comma-separated status filter
+    let mut statuses = Vec::new();
+    if let Some(raw) = &query.status {
+        for part in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            statuses.push(part.parse::<JobStatus>()?);
+        }
+    }
+
+    let filter = JobFilter {
+        statuses,
+        owner: principal.owner_filter(),
+        offset: query.offset.unwrap_or(0),
+        limit: query
+            .limit
+            .unwrap_or(DEFAULT_JOBS_PAGE_SIZE)
+            .min(MAX_JOBS_PAGE_SIZE),
+    };
+    let (jobs, total) = state.jobs.list(&filter).await;
+
+    Ok(Json(ListJobsResponse {
+        jobs,
+        total,
+        offset: filter.offset,
+        limit: filter.limit,
+    }))
+}
+
+/// Handler for GET /api/jobs/:id.
+///
+/// Returns the current record for a single job, or 404 if the ID is unknown.
+pub async fn get_job(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+) -> Result<Json<Job>, ApiError> {
+    Ok(Json(owned_job(&state, &principal, job_id).await?))
+}
+
+/// Handler for DELETE /api/jobs/:id.
+///
+/// Cancels a queued or running job. Running jobs have their yt-dlp/ffmpeg processes
+/// killed and partial files removed; the final `cancelled` progress update is sent once
+/// that cleanup is done.
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+) -> Result<Response, ApiError> {
+    owned_job(&state, &principal, job_id.clone()).await?;
+    let job = pipeline::cancel_job(&state, &job_id).await?;
+    // Running jobs finish cancelling in the background
+    let status = if job.status == JobStatus::Cancelled {
+        StatusCode::OK
+    } else {
+        StatusCode::ACCEPTED
     };
-    (StatusCode::OK, Json(response_body)).into_response()
+    Ok((status, Json(job)).into_response())
+}
+
+/// Handler for GET /api/subscriptions.
+///
+/// Lists the caller's subscriptions, oldest first. Administrators see every subscription.
+pub async fn list_subscriptions(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Json<Vec<Subscription>> {
+    let mut subscriptions = state.subscriptions.list().await;
+    subscriptions.retain(|subscription| principal.can_access(subscription.owner.as_deref()));
+    Json(subscriptions)
+}
+
+/// Handler for POST /api/subscriptions.
+///
+/// Registers a subscription; it is checked on the scheduler's next tick.
+pub async fn create_subscription(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    ApiJson(payload): ApiJson<SubscriptionPayload>,
+) -> Result<Response, ApiError> {
+    let new = validate_subscription(&state, payload, principal.owner())?;
+    let subscription = state
+        .subscriptions
+        .create(new)
+        .await
+        .inspect_err(|e| error!(error = %e, "Failed to record subscription"))?;
+    info!(subscription_id = %subscription.id, url = %subscription.url, "Subscription created");
+    Ok((StatusCode::CREATED, Json(subscription)).into_response())
+}
+
+/// Handler for GET /api/subscriptions/:id.
+pub async fn get_subscription(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(subscription_id): Path<String>,
+) -> Result<Json<Subscription>, ApiError> {
+    Ok(Json(
+        owned_subscription(&state, &principal, subscription_id).await?,
+    ))
+}
+
+/// Handler for PUT /api/subscriptions/:id.
+///
+/// Replaces a subscription's settings, keeping its check history.
+pub async fn update_subscription(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(subscription_id): Path<String>,
+    ApiJson(payload): ApiJson<SubscriptionPayload>,
+) -> Result<Json<Subscription>, ApiError> {
+    owned_subscription(&state, &principal, subscription_id.clone()).await?;
+    // The caller is named in the audit log of rejected directories; the subscription
+    // keeps its original owner
+    let new = validate_subscription(&state, payload, principal.owner())?;
+    let subscription = state
+        .subscriptions
+        .replace(&subscription_id, new)
+        .await
+        .inspect_err(|e| error!(error = %e, subscription_id = %subscription_id, "Failed to update subscription"))?
+        .ok_or(PegasusError::SubscriptionNotFound(subscription_id))?;
+    info!(subscription_id = %subscription.id, "Subscription updated");
+    Ok(Json(subscription))
+}
+
+/// Handler for DELETE /api/subscriptions/:id.
+///
+/// Removes a subscription. Jobs it already submitted keep running.
+pub async fn delete_subscription(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(subscription_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    owned_subscription(&state, &principal, subscription_id.clone()).await?;
+    let removed = state
+        .subscriptions
+        .delete(&subscription_id)
+        .await
+        .inspect_err(|e| error!(error = %e, subscription_id = %subscription_id, "Failed to delete subscription"))?;
+    if !removed {
+        return Err(PegasusError::SubscriptionNotFound(subscription_id).into());
+    }
+    info!(subscription_id = %subscription_id, "Subscription deleted");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handler for POST /api/subscriptions/:id/check.
+///
+/// Checks a subscription for new items right away instead of waiting for its next
+/// scheduled check. The check runs in the background; the outcome is recorded on the
+/// subscription.
+pub async fn check_subscription(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(subscription_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let subscription = owned_subscription(&state, &principal, subscription_id).await?;
+
+    let check_state = state.clone();
+    let check_subscription = subscription.clone();
+    tokio::spawn(async move {
+        scheduler::check_subscription(&check_state, &check_subscription).await;
+    });
+    Ok((StatusCode::ACCEPTED, Json(subscription)).into_response())
+}
+
+/// Fallback for unknown `/api` routes, so API clients get a JSON error rather than the
+/// frontend's `index.html`.
+pub async fn api_not_found(uri: Uri) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "route_not_found",
+        format!("No API route for {}", uri.path()),
+    )
+}
+
+/// Looks up a job the caller may access.
+///
+/// Other users' jobs are reported as missing rather than forbidden, so job IDs cannot be
+/// probed.
+pub async fn owned_job(
+    state: &AppState,
+    principal: &Principal,
+    job_id: String,
+) -> crate::error::Result<Job> {
+    state
+        .jobs
+        .get(&job_id)
+        .await
+        .filter(|job| principal.can_access(job.owner.as_deref()))
+        .ok_or(PegasusError::JobNotFound(job_id))
+}
+
+// Looks up a subscription the caller may access, like owned_job
+async fn owned_subscription(
+    state: &AppState,
+    principal: &Principal,
+    subscription_id: String,
+) -> crate::error::Result<Subscription> {
+    state
+        .subscriptions
+        .get(&subscription_id)
+        .await
+        .filter(|subscription| principal.can_access(subscription.owner.as_deref()))
+        .ok_or(PegasusError::SubscriptionNotFound(subscription_id))
+}
+
+// Checks a subscription payload and turns it into the fields to store
+fn validate_subscription(
+    state: &AppState,
+    payload: SubscriptionPayload,
+    owner: Option<String>,
+) -> crate::error::Result<NewSubscription> {
+    if !(payload.url.starts_with("http://") || payload.url.starts_with("https://")) {
+        return Err(PegasusError::ValidationError(format!(
+            "Subscription URL must be http(s): {}",
+            payload.url
+        )));
+    }
+    if !(MIN_INTERVAL_MINUTES..=MAX_INTERVAL_MINUTES).contains(&payload.interval_minutes) {
+        return Err(PegasusError::ValidationError(format!(
+            "Interval must be between {} and {} minutes",
+            MIN_INTERVAL_MINUTES, MAX_INTERVAL_MINUTES
+        )));
+    }
+    if let Some(options) = &payload.processing_options {
+        options.validate()?;
+    }
+    payload.playlist.validate()?;
+    payload.naming.validate()?;
+    state.transfers.resolve(payload.destination.as_deref())?;
+    state.roots.resolve(
+        payload.root.as_deref(),
+        payload.output_dir.as_deref(),
+        owner.as_deref(),
+    )?;
+
+    Ok(NewSubscription {
+        url: payload.url,
+        name: payload.name,
+        interval_minutes: payload.interval_minutes,
+        processing_options: payload.processing_options,
+        destination: payload.destination,
+        root: payload.root,
+        output_dir: payload.output_dir,
+        playlist: payload.playlist,
+        naming: payload.naming,
+        priority: payload.priority,
+        enabled: payload.enabled,
+        owner,
+    })
 }
 
 /// Helper function to send progress updates to all connected WebSocket clients
@@ -145,65 +764,126 @@ pub fn send_progress_update(job_id: &str, url: &str, status: &str, progress: f32
         status: status.to_string(),
         progress,
         message: message.to_string(),
+        queue_position: None,
+        children: None,
     };
 
     // Send the update to all subscribers
-    if let Err(e) = PROGRESS_CHANNEL.0.send(update) {
-        debug!("Failed to broadcast progress update: {}", e);
+    publish(update);
+}
+
+/// Helper function to announce the child jobs a playlist job was expanded into
+pub fn send_expansion_update(job_id: &str, url: &str, message: &str, children: Vec<ChildJob>) {
+    let update = ProgressUpdate {
+        job_id: job_id.to_string(),
+        url: url.to_string(),
+        status: "expanded".to_string(),
+        progress: 0.0,
+        message: message.to_string(),
+        queue_position: None,
+        children: Some(children),
+    };
+
+    publish(update);
+}
+
+/// Helper function to broadcast a batch's aggregate progress.
+///
+/// Batch updates carry the batch ID in `job_id` and have the status `batch`.
+pub fn send_batch_update(progress: &BatchProgress) {
+    let update = ProgressUpdate {
+        job_id: progress.id.clone(),
+        url: String::new(),
+        status: "batch".to_string(),
+        progress: progress.progress,
+        message: format!("{}: {}", progress.name, progress.summary()),
+        queue_position: None,
+        children: None,
+    };
+
+    publish(update);
+}
+
+/// Helper function to report a job's position in the download queue
+pub fn send_queue_update(job_id: &str, url: &str, position: usize) {
+    let message = if position == 0 {
+        "Queued, next in line".to_string()
+    } else {
+        format!("Queued, {} ahead", position)
+    };
+    let update = ProgressUpdate {
+        job_id: job_id.to_string(),
+        url: url.to_string(),
+        status: "queued".to_string(),
+        progress: 0.0,
+        message,
+        queue_position: Some(position),
+        children: None,
+    };
+
+    publish(update);
+}
+
+/// Returns the last update sent for a job or batch, if it has not finished yet.
+pub fn latest_update(id: &str) -> Option<ProgressUpdate> {
+    EVENT_LOG.lock().unwrap().latest.get(id).cloned()
+}
+
+/// Subscribes to every progress update sent from now on.
+pub fn subscribe_progress() -> broadcast::Receiver<ProgressEvent> {
+    PROGRESS_CHANNEL.0.subscribe()
+}
+
+/// Subscribes to progress updates, starting right after the event with ID `after`.
+///
+/// Events still in the history are returned as a backlog; the receiver picks up exactly
+/// where the backlog ends. An `after` of 0 returns the whole history.
+pub fn resume_progress(after: u64) -> Resume {
+    // Holding the log keeps new events out until the receiver exists, so none are
+    // missed or delivered twice
+    let log = EVENT_LOG.lock().unwrap();
+    let receiver = PROGRESS_CHANNEL.0.subscribe();
+    // An ID from before a restart is ahead of every current one; the client gets the
+    // whole history instead
+    let restarted = after >= log.next_id;
+    let after = if restarted { 0 } else { after };
+    let backlog: Vec<ProgressEvent> = log
+        .history
+        .iter()
+        .filter(|event| event.id > after)
+        .cloned()
+        .collect();
+    let oldest = log.history.front().map_or(log.next_id, |event| event.id);
+    let incomplete = restarted || (after > 0 && after + 1 < oldest);
+    Resume {
+        backlog,
+        receiver,
+        incomplete,
     }
 }
 
-/// Handle WebSocket connections for real-time progress updates
-pub async fn handle_socket_connection(mut socket: WebSocket) {
-    // Subscribe to the progress channel
-    let mut rx = PROGRESS_CHANNEL.0.subscribe();
+// Numbers an update, remembers it for late subscribers and sends it to every connected
+// client
+fn publish(update: ProgressUpdate) {
+    let mut log = EVENT_LOG.lock().unwrap();
+    if update.is_final() {
+        log.latest.remove(&update.job_id);
+    } else {
+        log.latest.insert(update.job_id.clone(), update.clone());
+    }
 
-    info!("New WebSocket client connected");
+    let event = ProgressEvent {
+        id: log.next_id,
+        update,
+    };
+    log.next_id += 1;
+    if log.history.len() == EVENT_HISTORY_LEN {
+        log.history.pop_front();
+    }
+    log.history.push_back(event.clone());
 
-    // Send a welcome message
-    if let Err(e) = socket
-        .send(Message::Text(
-            "Connected to Pegasus download progress feed".to_string(),
-        ))
-        .await
-    {
-        error!("Failed to send welcome message: {}", e);
-        return;
-    }
-
-    // Main WebSocket message loop
-    loop {
-        tokio::select! {
-            // Handle incoming messages from the client
-            Some(msg) = socket.next() => {
-                match msg {
-                    Ok(Message::Close(_)) => {
-                        info!("WebSocket client disconnected");
-                        break;
-                    },
-                    Ok(_) => {},
-                    Err(e) => {
-                        error!("WebSocket error: {}", e);
-                        break;
-                    }
-                }
-            },
-            // Handle progress updates from the channel
-            Ok(update) = rx.recv() => {
-                // Serialize the update to JSON
-                match serde_json::to_string(&update) {
-                    Ok(json) => {
-                        // Send the update to the client
-                        if let Err(e) = socket.send(Message::Text(json)).await {
-                            error!("Failed to send progress update: {}", e);
-                            break;
-                        }
-                    },
-                    Err(e) => {
-                        error!("Failed to serialize progress update: {}", e);
-                    }
-                }
-            },
-        }
+    // Sent under the lock so receivers see events in ID order
+    if let Err(e) = PROGRESS_CHANNEL.0.send(event) {
+        debug!("Failed to broadcast progress update: {}", e);
     }
 }
@@ -0,0 +1,45 @@
+// src/api/metrics.rs
+// The Prometheus scrape endpoint and the middleware that times HTTP requests.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::AppState;
+use crate::metrics;
+
+/// Handler for GET /metrics.
+///
+/// Returns every metric in the Prometheus text exposition format.
+pub async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, metrics::content_type())],
+        metrics::render(&state).await,
+    )
+}
+
+/// Middleware that records the latency of every request.
+///
+/// Requests are labelled with their route pattern (e.g. `/api/jobs/:id`) rather than the
+/// path, so job IDs do not create a new time series each. Static files and unknown paths
+/// share the `static` label.
+pub async fn track_http(matched: Option<MatchedPath>, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = matched.map_or_else(|| "static".to_string(), |path| path.as_str().to_string());
+
+    let response = next.run(request).await;
+
+    metrics::observe_http_request(
+        method.as_str(),
+        &route,
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    response
+}
@@ -1,20 +1,78 @@
 // src/api/mod.rs
 // This module contains the Axum web server setup, routes, and handlers.
 
+pub mod auth;
+pub mod credentials;
+pub mod error;
 pub mod handlers;
+pub mod metrics;
+pub mod roots;
+pub mod sse;
+pub mod system;
+pub mod webhooks;
+pub mod ws;
+
+use std::sync::Arc;
 
 use axum::{
-    Router,
+    Extension, Router,
     extract::ws::WebSocketUpgrade,
+    extract::{DefaultBodyLimit, State},
+    middleware,
     response::IntoResponse,
-    routing::{get, get_service, post},
+    routing::{any, delete, get, get_service, post, put},
 };
 use tower_http::services::ServeDir;
 
+use crate::auth::{AuthStore, Principal};
+use crate::batches::{BatchStore, MAX_BATCH_BODY_BYTES};
+use crate::config::Config;
+use crate::credentials::CredentialVault;
+use crate::download::info_cache::InfoCache;
+use crate::jobs::JobStore;
+use crate::jobs::cancel::CancelRegistry;
+use crate::queue::JobQueue;
+use crate::roots::OutputRoots;
+use crate::subscriptions::SubscriptionStore;
+use crate::subscriptions::archive::DownloadArchive;
+use crate::transfer::TransferRegistry;
+use crate::webhooks::WebhookDispatcher;
+
+/// Shared state handed to every handler through Axum's `State` extractor.
+#[derive(Clone)]
+pub struct AppState {
+    /// The validated application configuration.
+    pub config: Arc<Config>,
+    /// The persistent registry of submitted jobs.
+    pub jobs: Arc<JobStore>,
+    /// The bounded queue that limits concurrent downloads.
+    pub queue: Arc<JobQueue>,
+    /// Cancellation tokens of jobs that have not finished yet.
+    pub cancellations: Arc<CancelRegistry>,
+    /// Backends for the configured transfer destinations.
+    pub transfers: Arc<TransferRegistry>,
+    /// Followed channels and playlists polled by the scheduler.
+    pub subscriptions: Arc<SubscriptionStore>,
+    /// Items subscriptions have already fetched.
+    pub archive: Arc<DownloadArchive>,
+    /// Recently extracted info JSON, shared by previews and jobs.
+    pub info_cache: Arc<InfoCache>,
+    /// Named groups of jobs submitted together.
+    pub batches: Arc<BatchStore>,
+    /// Users, API tokens and login sessions.
+    pub auth: Arc<AuthStore>,
+    /// Encrypted site cookies and logins passed to yt-dlp.
+    pub vault: Arc<CredentialVault>,
+    /// Outbound webhook endpoints and their recent delivery attempts.
+    pub webhooks: Arc<WebhookDispatcher>,
+    /// The directories jobs may download into.
+    pub roots: Arc<OutputRoots>,
+}
+
 /// Creates the main Axum application router.
 ///
 /// Configures routes for serving static frontend files and API endpoints.
-pub fn create_router() -> Router {
+pub fn create_router(state: AppState) -> Router {
     tracing::info!("Creating Axum router");
 
     // Define the service to serve static files from the `static` directory
@@ -23,20 +81,115 @@ pub fn create_router() -> Router {
     let static_service =
         ServeDir::new("static").fallback(get_service(ServeDir::new("static/index.html")));
 
-    // Build the router
-    Router::new()
+    // Everything but logging in and the static files requires authentication when it
+    // is enabled
+    let protected = Router::new()
         // Define the API route `/api/submit` which accepts POST requests
         // It's linked to the `submit_url` handler function.
         .route("/api/submit", post(handlers::submit_url))
+        // Preview a URL's metadata and formats before submitting it
+        .route("/api/info", get(handlers::get_media_info))
+        // Submit many URLs, or every URL found in a block of text, as one batch
+        .route(
+            "/api/batches",
+            get(handlers::list_batches)
+                .post(handlers::create_batch)
+                .layer(DefaultBodyLimit::max(MAX_BATCH_BODY_BYTES)),
+        )
+        .route("/api/batches/:id", get(handlers::get_batch))
+        // Job registry routes for polling job state without a WebSocket
+        .route("/api/jobs", get(handlers::list_jobs))
+        .route(
+            "/api/jobs/:id",
+            get(handlers::get_job).delete(handlers::cancel_job),
+        )
+        // Server-Sent Events progress streams for clients that cannot use the WebSocket
+        .route("/api/events", get(sse::all_events))
+        .route("/api/jobs/:id/events", get(sse::job_events))
+        // Subscription routes for following channels and playlists
+        .route(
+            "/api/subscriptions",
+            get(handlers::list_subscriptions).post(handlers::create_subscription),
+        )
+        .route(
+            "/api/subscriptions/:id",
+            get(handlers::get_subscription)
+                .put(handlers::update_subscription)
+                .delete(handlers::delete_subscription),
+        )
+        .route(
+            "/api/subscriptions/:id/check",
+            post(handlers::check_subscription),
+        )
         // WebSocket route for real-time download progress updates
         .route("/ws", get(ws_handler))
+        // Who the caller is, and user and API token management for administrators
+        .route("/api/auth/me", get(auth::me))
+        .route(
+            "/api/admin/users",
+            get(auth::list_users).post(auth::create_user),
+        )
+        .route("/api/admin/users/:username", delete(auth::delete_user))
+        .route(
+            "/api/admin/tokens",
+            get(auth::list_tokens).post(auth::create_token),
+        )
+        .route("/api/admin/tokens/:id", delete(auth::delete_token))
+        // Cookie files and logins for sites that need an account
+        .route("/api/admin/credentials", get(credentials::list_credentials))
+        .route(
+            "/api/admin/credentials/warnings",
+            get(credentials::credential_warnings),
+        )
+        .route(
+            "/api/admin/credentials/:domain",
+            put(credentials::put_credential).delete(credentials::delete_credential),
+        )
+        // The output roots jobs may download into
+        .route("/api/roots", get(roots::list_roots))
+        // Binary versions, directories and uptime for the UI footer
+        .route("/api/system", get(system::system_info))
+        // Configured webhook endpoints and the log of recent deliveries
+        .route("/api/admin/webhooks", get(webhooks::list_webhooks))
+        .route(
+            "/api/admin/webhooks/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_auth,
+        ));
+
+    // Build the router
+    Router::new()
+        // Login sessions for the web UI
+        .route("/api/auth/login", post(auth::login))
+        .route("/api/auth/logout", post(auth::logout))
+        // Prometheus scrape endpoint
+        .route("/metrics", get(metrics::metrics_handler))
+        // Liveness and readiness probes for orchestrators
+        .route("/healthz", get(system::healthz))
+        .route("/readyz", get(system::readyz))
+        .merge(protected)
+        // Unknown API routes get a JSON error instead of the frontend
+        .route("/api/*rest", any(handlers::api_not_found))
         // Define a fallback service to serve static files for any other request.
         // This allows serving index.html, styles.css, script.js, etc.
         .fallback_service(static_service)
+        // Time every request, labelled by the route it matched
+        .layer(middleware::from_fn(metrics::track_http))
+        .with_state(state)
 }
 
 /// WebSocket handler for real-time updates
-async fn ws_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
-    // Accept the WebSocket connection and pass it to the handler
-    ws.on_upgrade(handlers::handle_socket_connection)
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> impl IntoResponse {
+    // Accept the WebSocket connection and pass it to the handler. Clients that send their
+    // token as a subprotocol must get one of their protocols back, or the browser closes
+    // the connection.
+    ws.protocols(["pegasus"])
+        .on_upgrade(move |socket| ws::handle_socket_connection(socket, state, principal))
 }
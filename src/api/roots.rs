@@ -0,0 +1,15 @@
+// src/api/roots.rs
+// Lists the output roots, so clients can offer them as download destinations.
+
+use axum::{Json, extract::State};
+
+use crate::api::AppState;
+use crate::roots::RootSummary;
+
+/// Handler for GET /api/roots.
+///
+/// Returns every output root with its path, the default root included. Jobs name one of
+/// them in `root` and may add a relative `outputDir` below it.
+pub async fn list_roots(State(state): State<AppState>) -> Json<Vec<RootSummary>> {
+    Json(state.roots.list())
+}
@@ -0,0 +1,429 @@
+// src/api/sse.rs
+// Server-Sent Events progress streams.
+//
+// The same progress updates as the WebSocket feed, for clients that cannot use WebSockets
+// (curl scripts, home automation integrations, proxies that strip upgrades). Every event
+// carries its ID from the progress history, so a client that reconnects with
+// `Last-Event-ID` receives the events it missed, as long as they are still in the history.
+// Like the WebSocket feed, clients only see their own jobs unless they are administrators.
+
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    Extension,
+    extract::{Path, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures::stream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+
+use crate::api::AppState;
+use crate::api::auth::VisibilityCache;
+use crate::api::error::ApiError;
+use crate::api::handlers::{self, ProgressEvent, ProgressUpdate, Resume};
+use crate::auth::Principal;
+use crate::jobs::{Job, JobStatus};
+
+// How often a comment is sent on an idle stream, so proxies do not time it out
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Handler for GET /api/events.
+///
+/// Streams every progress update of the caller's jobs as it is sent. A client reconnecting
+/// with a `Last-Event-ID` header first receives the updates it missed.
+pub async fn all_events(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+) -> Response {
+    let last_event_id = last_event_id(&headers);
+    info!(last_event_id = ?last_event_id, username = ?principal.username, "New event stream client connected");
+    let resume = match last_event_id {
+        Some(after) => handlers::resume_progress(after),
+        None => Resume {
+            backlog: Vec::new(),
+            receiver: handlers::subscribe_progress(),
+            incomplete: false,
+        },
+    };
+    let scope = Scope::Visible(VisibilityCache::new(state, principal));
+    stream_response(EventStream::new(resume, scope).await)
+}
+
+/// Handler for GET /api/jobs/:id/events.
+///
+/// Streams the updates of one job, and of its children if it is a playlist, and ends
+/// after the job's final update. The job's updates that are still in the history are
+/// replayed first, so the stream starts with the job's current state.
+pub async fn job_events(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let job = handlers::owned_job(&state, &principal, job_id).await?;
+
+    let last_event_id = last_event_id(&headers);
+    let resume = handlers::resume_progress(last_event_id.unwrap_or(0));
+    let mut ids: HashSet<String> = job.child_ids.iter().cloned().collect();
+    ids.insert(job.id.clone());
+    let mut events = EventStream::new(
+        resume,
+        Scope::Job {
+            job_id: job.id.clone(),
+            ids,
+        },
+    )
+    .await;
+
+    // The job finished before its final update could be replayed from the history
+    if job.status.is_terminal() && !events.finished {
+        if last_event_id.is_some() && events.pending.is_empty() {
+            // The client has seen everything; 204 tells EventSource not to reconnect
+            return Ok(StatusCode::NO_CONTENT.into_response());
+        }
+        events
+            .pending
+            .push_back(progress_event(None, &final_update(&job)));
+        events.finished = true;
+    }
+
+    Ok(stream_response(events))
+}
+
+/// The updates a stream carries.
+enum Scope {
+    /// Every update the client may see.
+    Visible(VisibilityCache),
+    /// One job and the children of a playlist job.
+    Job {
+        job_id: String,
+        ids: HashSet<String>,
+    },
+}
+
+/// The state of one client's event stream.
+struct EventStream {
+    /// Events to send before reading from the receiver.
+    pending: VecDeque<Event>,
+    receiver: broadcast::Receiver<ProgressEvent>,
+    scope: Scope,
+    /// Set once the scoped job's final update has been queued.
+    finished: bool,
+}
+
+impl EventStream {
+    async fn new(resume: Resume, scope: Scope) -> Self {
+        let mut events = EventStream {
+            pending: VecDeque::new(),
+            receiver: resume.receiver,
+            scope,
+            finished: false,
+        };
+        if resume.incomplete {
+            events.pending.push_back(resync_event(None));
+        }
+        for event in resume.backlog {
+            if let Some(sse) = events.accept(event).await {
+                events.pending.push_back(sse);
+            }
+            if events.finished {
+                break;
+            }
+        }
+        events
+    }
+
+    /// Returns the next event to send, or `None` once the stream is over.
+    async fn next(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    if let Some(sse) = self.accept(event).await {
+                        return Some(sse);
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(missed, "Event stream client fell behind the progress feed");
+                    return Some(resync_event(Some(missed)));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    // Converts an update into an SSE event if this stream wants it
+    async fn accept(&mut self, event: ProgressEvent) -> Option<Event> {
+        match &mut self.scope {
+            Scope::Visible(visibility) => {
+                if !visibility.can_see(&event.update.job_id).await {
+                    return None;
+                }
+            }
+            Scope::Job { job_id, ids } => {
+                if !ids.contains(&event.update.job_id) {
+                    return None;
+                }
+                // Follow the children of a playlist job as soon as it is expanded
+                if let Some(children) = &event.update.children {
+                    ids.extend(children.iter().map(|child| child.job_id.clone()));
+                }
+                if event.update.job_id == *job_id && event.update.is_final() {
+                    self.finished = true;
+                }
+            }
+        }
+        Some(progress_event(Some(event.id), &event.update))
+    }
+}
+
+/// Wraps an event stream in an SSE response with keep-alive comments.
+fn stream_response(events: EventStream) -> Response {
+    let stream = stream::unfold(events, |mut events| async move {
+        events
+            .next()
+            .await
+            .map(|event| (Ok::<_, Infallible>(event), events))
+    });
+    let sse = Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(KEEP_ALIVE_INTERVAL)
+            .text("keep-alive"),
+    );
+    // Stops nginx from buffering the stream
+    (
+        [(
+            HeaderName::from_static("x-accel-buffering"),
+            HeaderValue::from_static("no"),
+        )],
+        sse,
+    )
+        .into_response()
+}
+
+/// Builds the SSE event for a progress update.
+fn progress_event(id: Option<u64>, update: &ProgressUpdate) -> Event {
+    let event = match Event::default().json_data(update) {
+        Ok(event) => event,
+        Err(e) => {
+            error!(error = %e, "Failed to serialize progress update");
+            return Event::default().comment("unserializable update");
+        }
+    };
+    match id {
+        Some(id) => event.id(id.to_string()),
+        None => event,
+    }
+}
+
+/// Tells the client it missed updates that can no longer be replayed, so it should
+/// refetch the state of the jobs it cares about.
+fn resync_event(missed: Option<u64>) -> Event {
+    Event::default()
+        .event("resync")
+        .data(serde_json::json!({ "missed": missed }).to_string())
+}
+
+/// The final update of a job that finished before the stream started.
+fn final_update(job: &Job) -> ProgressUpdate {
+    let (status, progress, message) = match job.status {
+        JobStatus::Completed => ("completed", 1.0, "Job completed successfully".to_string()),
+        JobStatus::Cancelled => ("cancelled", 0.0, "Job cancelled".to_string()),
+        _ => (
+            "error",
+            0.0,
+            format!(
+                "Job failed: {}",
+                job.error.as_deref().unwrap_or("unknown error")
+            ),
+        ),
+    };
+    ProgressUpdate {
+        job_id: job.id.clone(),
+        url: job.url.clone(),
+        status: status.to_string(),
+        progress,
+        message,
+        queue_position: None,
+        children: None,
+    }
+}
+
+/// Reads the ID of the last event the client received when it reconnects.
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    // The progress feed is shared by the whole process; tests that flood it must not
+    // push another test's events out of the history
+    static FEED: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// One event as a client parses it.
+    #[derive(Debug)]
+    struct Received {
+        id: Option<u64>,
+        event: Option<String>,
+        data: serde_json::Value,
+    }
+
+    fn unique_job_id(name: &str) -> String {
+        format!("sse-{}-{}", name, uuid::Uuid::new_v4())
+    }
+
+    fn job_scope(job_id: &str) -> Scope {
+        Scope::Job {
+            job_id: job_id.to_string(),
+            ids: HashSet::from([job_id.to_string()]),
+        }
+    }
+
+    // Sends the stream as a response and reads it until the job's final update ends it
+    async fn receive(events: EventStream) -> Vec<Received> {
+        let body = tokio::time::timeout(
+            Duration::from_secs(5),
+            to_bytes(stream_response(events).into_body(), usize::MAX),
+        )
+        .await
+        .expect("the stream did not end")
+        .unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .split("\n\n")
+            .filter(|block| !block.trim().is_empty())
+            .map(|block| {
+                let mut received = Received {
+                    id: None,
+                    event: None,
+                    data: serde_json::Value::Null,
+                };
+                for line in block.lines() {
+                    match line.split_once(':') {
+                        Some(("id", value)) => received.id = value.trim().parse().ok(),
+                        Some(("event", value)) => received.event = Some(value.trim().to_string()),
+                        Some(("data", value)) => {
+                            received.data = serde_json::from_str(value.trim()).unwrap()
+                        }
+                        _ => {}
+                    }
+                }
+                received
+            })
+            .collect()
+    }
+
+    fn messages(received: &[Received]) -> Vec<&str> {
+        received
+            .iter()
+            .filter_map(|event| event.data["message"].as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn history_is_replayed_before_live_updates() {
+        let _feed = FEED.lock().await;
+        let job_id = unique_job_id("replay");
+        handlers::send_progress_update(&job_id, "https://a.example/", "downloading", 0.1, "one");
+        handlers::send_progress_update(&job_id, "https://a.example/", "downloading", 0.5, "two");
+
+        let events = EventStream::new(handlers::resume_progress(0), job_scope(&job_id)).await;
+        handlers::send_progress_update(&job_id, "https://a.example/", "completed", 1.0, "three");
+        let received = receive(events).await;
+
+        assert_eq!(messages(&received), vec!["one", "two", "three"]);
+        let ids: Vec<u64> = received.iter().map(|event| event.id.unwrap()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+    }
+
+    #[tokio::test]
+    async fn last_event_id_resumes_after_the_last_seen_event() {
+        let _feed = FEED.lock().await;
+        let job_id = unique_job_id("resume");
+        for (status, message) in [
+            ("downloading", "one"),
+            ("downloading", "two"),
+            ("completed", "three"),
+        ] {
+            handlers::send_progress_update(&job_id, "https://a.example/", status, 0.0, message);
+        }
+        let first =
+            receive(EventStream::new(handlers::resume_progress(0), job_scope(&job_id)).await).await;
+        let seen = first[0].id.unwrap();
+
+        let resumed =
+            receive(EventStream::new(handlers::resume_progress(seen), job_scope(&job_id)).await)
+                .await;
+        assert_eq!(messages(&resumed), vec!["two", "three"]);
+        assert!(resumed.iter().all(|event| event.event.is_none()));
+    }
+
+    #[tokio::test]
+    async fn an_id_from_before_a_restart_replays_everything_after_a_resync() {
+        let _feed = FEED.lock().await;
+        let job_id = unique_job_id("restart");
+        handlers::send_progress_update(&job_id, "https://a.example/", "downloading", 0.1, "one");
+        handlers::send_progress_update(&job_id, "https://a.example/", "error", 0.0, "two");
+
+        let received = receive(
+            EventStream::new(handlers::resume_progress(u64::MAX), job_scope(&job_id)).await,
+        )
+        .await;
+        assert_eq!(received[0].event.as_deref(), Some("resync"));
+        assert_eq!(received[0].data["missed"], serde_json::Value::Null);
+        assert_eq!(messages(&received[1..]), vec!["one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn lagging_clients_are_told_to_resync() {
+        let _feed = FEED.lock().await;
+        let job_id = unique_job_id("lag");
+        let flood_id = unique_job_id("flood");
+        handlers::send_progress_update(&job_id, "https://a.example/", "downloading", 0.1, "before");
+        let before = handlers::resume_progress(0)
+            .backlog
+            .last()
+            .map(|event| event.id)
+            .unwrap();
+
+        // A client that stops reading while more updates than the channel holds are sent.
+        // The channel rounds its capacity up to a power of two, so send plenty
+        let events = EventStream::new(handlers::resume_progress(before), job_scope(&job_id)).await;
+        for _ in 0..2 * handlers::EVENT_HISTORY_LEN {
+            handlers::send_progress_update(&flood_id, "https://b.example/", "downloading", 0.5, "");
+        }
+        handlers::send_progress_update(&flood_id, "https://b.example/", "completed", 1.0, "");
+        handlers::send_progress_update(&job_id, "https://a.example/", "completed", 1.0, "after");
+
+        let received = receive(events).await;
+        assert_eq!(received[0].event.as_deref(), Some("resync"));
+        assert!(received[0].data["missed"].as_u64().unwrap() > 0);
+        assert_eq!(messages(&received[1..]), vec!["after"]);
+
+        // Resuming from an event that has since left the history resyncs as well
+        let resumed =
+            receive(EventStream::new(handlers::resume_progress(before), job_scope(&job_id)).await)
+                .await;
+        assert_eq!(resumed[0].event.as_deref(), Some("resync"));
+        assert_eq!(messages(&resumed[1..]), vec!["after"]);
+    }
+}
@@ -0,0 +1,42 @@
+// src/api/system.rs
+// Liveness, readiness and system information endpoints.
+//
+// `/healthz` and `/readyz` are public so orchestrators can probe them without a token;
+// they reveal nothing beyond which checks failed. `/api/system` lists versions and paths
+// and requires authentication like the rest of the API.
+
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+
+use crate::api::AppState;
+use crate::system::{self, Readiness, SystemInfo};
+
+/// Handler for GET /healthz.
+///
+/// Always returns 200 while the server is accepting requests.
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Handler for GET /readyz.
+///
+/// Returns 200 when the binaries are executable, the working directories are writable
+/// with enough free space and the job store responds, or 503 otherwise. Both carry every
+/// check's result.
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = system::readiness(&state).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// Handler for GET /api/system.
+///
+/// Returns the build, the versions of yt-dlp, ffmpeg and ffprobe, ffmpeg's encoders and
+/// muxers, the working directories with their free space, and the uptime.
+pub async fn system_info(State(state): State<AppState>) -> Json<SystemInfo> {
+    Json(system::system_info(&state).await)
+}
@@ -0,0 +1,88 @@
+// src/api/webhooks.rs
+// Admin endpoints for inspecting webhook endpoints and their delivery log.
+//
+// Endpoints are configured in the config file; these routes only report on them. Shared
+// secrets and header values are never returned.
+
+use axum::{Extension, Json, extract::State};
+use serde::{Deserialize, Serialize};
+
+use crate::api::AppState;
+use crate::api::error::{ApiError, ApiQuery};
+use crate::auth::Principal;
+use crate::jobs::JobStatus;
+use crate::webhooks::{DeliveryAttempt, DeliveryFilter};
+
+/// Default and maximum number of delivery attempts returned at once.
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// A configured webhook endpoint, without its secrets.
+#[derive(Serialize, Debug)]
+pub struct WebhookSummary {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<JobStatus>,
+    /// Whether deliveries carry an `X-Pegasus-Signature` header.
+    pub signed: bool,
+    /// The names of the extra headers sent with every delivery.
+    pub headers: Vec<String>,
+}
+
+// Query parameters accepted by GET /api/admin/webhooks/deliveries
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DeliveriesQuery {
+    /// Only return attempts for this endpoint.
+    webhook: Option<String>,
+    /// Only return attempts for this job.
+    job_id: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Handler for GET /api/admin/webhooks.
+///
+/// Lists the configured endpoints, sorted by name.
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<WebhookSummary>>, ApiError> {
+    principal.require_admin()?;
+    let mut webhooks: Vec<WebhookSummary> = state
+        .webhooks
+        .endpoints()
+        .iter()
+        .map(|(name, endpoint)| {
+            let mut headers: Vec<String> = endpoint.headers.keys().cloned().collect();
+            headers.sort();
+            WebhookSummary {
+                name: name.clone(),
+                url: endpoint.url.clone(),
+                events: endpoint.events.clone(),
+                signed: endpoint.secret.is_some(),
+                headers,
+            }
+        })
+        .collect();
+    webhooks.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(webhooks))
+}
+
+/// Handler for GET /api/admin/webhooks/deliveries.
+///
+/// Returns the most recent delivery attempts, newest first, optionally filtered by
+/// endpoint (`webhook`) or `job_id`. Every retry is a separate entry sharing its
+/// delivery's `delivery_id`.
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    ApiQuery(query): ApiQuery<DeliveriesQuery>,
+) -> Result<Json<Vec<DeliveryAttempt>>, ApiError> {
+    principal.require_admin()?;
+    let filter = DeliveryFilter {
+        webhook: query.webhook,
+        job_id: query.job_id,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT),
+    };
+    Ok(Json(state.webhooks.deliveries(&filter)))
+}
@@ -0,0 +1,431 @@
+// src/api/ws.rs
+// The WebSocket progress feed.
+//
+// Clients choose what they hear about: after connecting they send `subscribe` commands
+// naming job IDs, batch IDs or "all", and immediately get a snapshot of the current state
+// of those jobs, so a client that connects mid-download does not have to wait for the
+// next progress line. Clients only hear about their own jobs unless they are
+// administrators. A client that falls too far behind the progress channel is sent a
+// fresh snapshot instead of silently missing updates.
+
+use std::collections::HashSet;
+
+use axum::extract::ws::{Message, WebSocket};
+use futures::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, info, warn};
+
+use crate::api::AppState;
+use crate::api::auth::VisibilityCache;
+use crate::api::handlers::{self, ProgressEvent, ProgressUpdate};
+use crate::auth::Principal;
+use crate::batches::{self, BatchProgress};
+use crate::error::Result;
+use crate::jobs::{Job, JobFilter, JobStatus};
+use crate::metrics;
+use crate::pipeline;
+
+// Commands a WebSocket client can send
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Cancel a queued or running job
+    Cancel {
+        #[serde(alias = "jobId")]
+        job_id: String,
+    },
+    /// Start receiving updates for some jobs or batches, or for every job
+    Subscribe { jobs: JobSelection },
+    /// Stop receiving updates for some jobs or batches, or for everything
+    Unsubscribe { jobs: JobSelection },
+}
+
+/// The jobs a `subscribe` or `unsubscribe` command applies to: a job or batch ID, a list
+/// of them, or the string `"all"`.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum JobSelection {
+    Ids(Vec<String>),
+    One(String),
+}
+
+impl JobSelection {
+    /// The selected IDs, or `None` for every job.
+    fn ids(self) -> Option<Vec<String>> {
+        match self {
+            JobSelection::One(id) if id == "all" => None,
+            JobSelection::One(id) => Some(vec![id]),
+            JobSelection::Ids(ids) => Some(ids),
+        }
+    }
+}
+
+/// The current state of the jobs and batches a client subscribed to.
+///
+/// Sent in reply to `subscribe` (reason `subscribe`) and whenever the client fell behind
+/// the progress channel and missed updates (reason `resync`).
+#[derive(Serialize, Debug)]
+struct Snapshot {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    reason: &'static str,
+    jobs: Vec<JobSnapshot>,
+    batches: Vec<BatchProgress>,
+    /// Requested IDs that match no job or batch.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unknown: Vec<String>,
+}
+
+/// A job record together with the last progress update it sent, if it is still running.
+#[derive(Serialize, Debug)]
+struct JobSnapshot {
+    #[serde(flatten)]
+    job: Job,
+    last_update: Option<ProgressUpdate>,
+}
+
+impl Snapshot {
+    fn new(reason: &'static str) -> Self {
+        Snapshot {
+            kind: "snapshot",
+            reason,
+            jobs: Vec::new(),
+            batches: Vec::new(),
+            unknown: Vec::new(),
+        }
+    }
+
+    // Adds a job, once, without its children
+    fn push_job(&mut self, job: Job) {
+        if self.jobs.iter().any(|snapshot| snapshot.job.id == job.id) {
+            return;
+        }
+        let last_update = handlers::latest_update(&job.id);
+        self.jobs.push(JobSnapshot { job, last_update });
+    }
+}
+
+/// What one connected client has subscribed to.
+struct Feed {
+    /// Every job the client may see, including ones submitted later.
+    all: bool,
+    /// Job and batch IDs, including the children of subscribed playlist jobs and the
+    /// members of subscribed batches.
+    ids: HashSet<String>,
+    /// Decides which jobs the client may see in `all` mode.
+    visibility: VisibilityCache,
+}
+
+impl Feed {
+    fn new(state: AppState, principal: Principal) -> Self {
+        Feed {
+            all: false,
+            ids: HashSet::new(),
+            visibility: VisibilityCache::new(state, principal),
+        }
+    }
+
+    /// Whether the client wants to receive this update.
+    async fn wants(&mut self, update: &ProgressUpdate) -> bool {
+        // Explicit subscriptions were checked for access when they were made
+        if self.ids.contains(&update.job_id) {
+            return true;
+        }
+        self.all && self.visibility.can_see(&update.job_id).await
+    }
+
+    /// The client this feed belongs to.
+    fn principal(&self) -> &Principal {
+        self.visibility.principal()
+    }
+
+    /// Subscribes to the given IDs, or to everything, and snapshots their current state.
+    async fn subscribe(&mut self, state: &AppState, ids: Option<Vec<String>>) -> Snapshot {
+        let mut snapshot = Snapshot::new("subscribe");
+        match ids {
+            None => {
+                self.all = true;
+                self.snapshot_all(state, &mut snapshot).await;
+            }
+            Some(ids) => {
+                for id in ids {
+                    if !self.add(state, &id, &mut snapshot).await {
+                        snapshot.unknown.push(id);
+                    }
+                }
+            }
+        }
+        snapshot
+    }
+
+    /// Unsubscribes from the given IDs, or from everything.
+    async fn unsubscribe(&mut self, state: &AppState, ids: Option<Vec<String>>) {
+        let Some(ids) = ids else {
+            self.all = false;
+            self.ids.clear();
+            return;
+        };
+        for id in ids {
+            self.ids.remove(&id);
+            // A batch or playlist is dropped together with the jobs it was followed for
+            let mut members = Vec::new();
+            if let Some(batch) = state.batches.get(&id).await {
+                members.extend(batch.job_ids);
+            } else if let Some(job) = state.jobs.get(&id).await {
+                members.extend(job.child_ids);
+            }
+            for member in members {
+                if let Some(job) = state.jobs.get(&member).await {
+                    for child_id in &job.child_ids {
+                        self.ids.remove(child_id);
+                    }
+                }
+                self.ids.remove(&member);
+            }
+        }
+    }
+
+    /// Snapshots everything the client is subscribed to, after it missed updates.
+    async fn resync(&self, state: &AppState) -> Snapshot {
+        let mut snapshot = Snapshot::new("resync");
+        if self.all {
+            self.snapshot_all(state, &mut snapshot).await;
+            return snapshot;
+        }
+        for id in &self.ids {
+            if let Some(job) = state.jobs.get(id).await {
+                snapshot.push_job(job);
+            } else if let Some(batch) = state.batches.get(id).await {
+                snapshot
+                    .batches
+                    .push(batches::progress(&batch, &state.jobs).await);
+            }
+        }
+        snapshot
+    }
+
+    /// Follows the children of a subscribed playlist job as soon as it is expanded.
+    fn follow_children(&mut self, update: &ProgressUpdate) {
+        if let Some(children) = &update.children {
+            self.ids
+                .extend(children.iter().map(|child| child.job_id.clone()));
+        }
+    }
+
+    // Subscribes to a job or batch and its members, adding them to the snapshot.
+    // Returns false if the ID is unknown or belongs to another user.
+    async fn add(&mut self, state: &AppState, id: &str, snapshot: &mut Snapshot) -> bool {
+        let principal = self.principal();
+        let members = if let Some(batch) = state.batches.get(id).await
+            && principal.can_access(batch.owner.as_deref())
+        {
+            snapshot
+                .batches
+                .push(batches::progress(&batch, &state.jobs).await);
+            batch.job_ids
+        } else if let Some(job) = state.jobs.get(id).await
+            && principal.can_access(job.owner.as_deref())
+        {
+            vec![job.id]
+        } else {
+            return false;
+        };
+        self.ids.insert(id.to_string());
+
+        for member in members {
+            let Some(job) = state.jobs.get(&member).await else {
+                continue;
+            };
+            self.ids.insert(job.id.clone());
+            let child_ids = job.child_ids.clone();
+            snapshot.push_job(job);
+            for child_id in &child_ids {
+                if let Some(child) = state.jobs.get(child_id).await {
+                    self.ids.insert(child.id.clone());
+                    snapshot.push_job(child);
+                }
+            }
+        }
+        true
+    }
+
+    // Adds every unfinished job and batch the client may see to the snapshot
+    async fn snapshot_all(&self, state: &AppState, snapshot: &mut Snapshot) {
+        let filter = JobFilter {
+            statuses: JobStatus::ACTIVE.to_vec(),
+            owner: self.principal().owner_filter(),
+            offset: 0,
+            limit: usize::MAX,
+        };
+        let (jobs, _) = state.jobs.list(&filter).await;
+        for job in jobs {
+            snapshot.push_job(job);
+        }
+        for batch in state.batches.list().await {
+            if !self.principal().can_access(batch.owner.as_deref()) {
+                continue;
+            }
+            let progress = batches::progress(&batch, &state.jobs).await;
+            if !progress.finished {
+                snapshot.batches.push(progress);
+            }
+        }
+    }
+}
+
+/// Handle WebSocket connections for real-time progress updates
+///
+/// Clients receive nothing until they send `{"type": "subscribe", "jobs": [...]}` with job
+/// or batch IDs, or `{"type": "subscribe", "jobs": "all"}`; each subscription is answered
+/// with a snapshot of the current state of the subscribed jobs. Clients may also send
+/// `{"type": "unsubscribe", "jobs": ...}` and `{"type": "cancel", "jobId": "..."}`.
+pub async fn handle_socket_connection(
+    mut socket: WebSocket,
+    state: AppState,
+    principal: Principal,
+) {
+    // Subscribe to the progress channel
+    let mut rx = handlers::subscribe_progress();
+    info!(username = ?principal.username, "New WebSocket client connected");
+    let _connected = metrics::websocket_connected();
+    let mut feed = Feed::new(state.clone(), principal);
+
+    // Send a welcome message
+    if let Err(e) = socket
+        .send(Message::Text(
+            "Connected to Pegasus download progress feed".to_string(),
+        ))
+        .await
+    {
+        error!("Failed to send welcome message: {}", e);
+        return;
+    }
+
+    // Main WebSocket message loop
+    loop {
+        tokio::select! {
+            // Handle incoming messages from the client
+            msg = socket.next() => {
+                match msg {
+                    None | Some(Ok(Message::Close(_))) => {
+                        info!("WebSocket client disconnected");
+                        break;
+                    },
+                    Some(Ok(Message::Text(text))) => {
+                        if let Some(reply) = handle_client_message(&state, &mut feed, &text).await
+                            && !send_json(&mut socket, &reply).await
+                        {
+                            break;
+                        }
+                    },
+                    Some(Ok(_)) => {},
+                    Some(Err(e)) => {
+                        error!("WebSocket error: {}", e);
+                        break;
+                    }
+                }
+            },
+            // Handle progress updates from the channel
+            received = rx.recv() => {
+                match received {
+                    Ok(ProgressEvent { update, .. }) => {
+                        if !feed.wants(&update).await {
+                            continue;
+                        }
+                        feed.follow_children(&update);
+                        if !send_json(&mut socket, &update).await {
+                            break;
+                        }
+                    },
+                    Err(RecvError::Lagged(missed)) => {
+                        // Skip the backlog and send the current state instead
+                        warn!(missed, "WebSocket client fell behind the progress feed, resyncing");
+                        rx = rx.resubscribe();
+                        if !feed.all && feed.ids.is_empty() {
+                            continue;
+                        }
+                        let snapshot = feed.resync(&state).await;
+                        if !send_json(&mut socket, &snapshot).await {
+                            break;
+                        }
+                    },
+                    Err(RecvError::Closed) => break,
+                }
+            },
+        }
+    }
+}
+
+/// Handles a command sent by a WebSocket client.
+///
+/// # Returns
+///
+/// A JSON reply to send back to the client, if any.
+async fn handle_client_message(
+    state: &AppState,
+    feed: &mut Feed,
+    text: &str,
+) -> Option<serde_json::Value> {
+    let message: ClientMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => {
+            debug!(error = %e, "Ignoring unrecognised WebSocket message");
+            return Some(
+                serde_json::json!({ "type": "error", "code": "invalid_command", "message": format!("Invalid command: {}", e) }),
+            );
+        }
+    };
+
+    match message {
+        ClientMessage::Cancel { job_id } => {
+            match cancel_owned_job(state, feed.principal(), &job_id).await {
+                Ok(job) => {
+                    Some(serde_json::json!({ "type": "cancel_requested", "job_id": job.id }))
+                }
+                Err(e) => Some(
+                    serde_json::json!({ "type": "error", "job_id": job_id, "code": e.code(), "message": e.to_string() }),
+                ),
+            }
+        }
+        ClientMessage::Subscribe { jobs } => {
+            let snapshot = feed.subscribe(state, jobs.ids()).await;
+            serde_json::to_value(&snapshot)
+                .inspect_err(|e| error!("Failed to serialize snapshot: {}", e))
+                .ok()
+        }
+        ClientMessage::Unsubscribe { jobs } => {
+            let ids = jobs.ids();
+            let reply = serde_json::json!({ "type": "unsubscribed", "jobs": ids.as_deref().map_or(serde_json::json!("all"), |ids| serde_json::json!(ids)) });
+            feed.unsubscribe(state, ids).await;
+            Some(reply)
+        }
+    }
+}
+
+// Cancels a job if the client may access it
+async fn cancel_owned_job(state: &AppState, principal: &Principal, job_id: &str) -> Result<Job> {
+    handlers::owned_job(state, principal, job_id.to_string()).await?;
+    pipeline::cancel_job(state, job_id).await
+}
+
+/// Serializes a message and sends it to the client.
+///
+/// # Returns
+///
+/// `false` if the connection is gone and the loop should stop.
+async fn send_json<T: Serialize>(socket: &mut WebSocket, message: &T) -> bool {
+    let json = match serde_json::to_string(message) {
+        Ok(json) => json,
+        Err(e) => {
+            // Nothing to send, but the connection is still fine
+            error!("Failed to serialize WebSocket message: {}", e);
+            return true;
+        }
+    };
+    if let Err(e) = socket.send(Message::Text(json)).await {
+        error!("Failed to send WebSocket message: {}", e);
+        return false;
+    }
+    true
+}
@@ -0,0 +1,646 @@
+// src/auth/mod.rs
+// This module contains the user and API token registry and the login sessions.
+//
+// API tokens are long random strings handed out once and only stored as SHA-256 hashes,
+// which is enough for secrets with that much entropy. Passwords are hashed with Argon2.
+// Users and tokens rarely change, so they are persisted as a single JSON file that is
+// rewritten on every change, like the subscription registry. Login sessions of the web UI
+// only live in memory; a restart logs everyone out.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex as StdMutex;
+
+use argon2::Argon2;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::error::{PegasusError, Result};
+
+/// Prefix of every API token, so leaked tokens are easy to recognise.
+const TOKEN_PREFIX: &str = "pgs_";
+
+/// Name of the administrator created when the registry is empty.
+pub const BOOTSTRAP_ADMIN: &str = "admin";
+
+/// Who is making a request.
+#[derive(Clone, Debug)]
+pub struct Principal {
+    /// `None` when authentication is disabled.
+    pub username: Option<String>,
+    pub admin: bool,
+}
+
+impl Principal {
+    /// The principal of every request while authentication is disabled. It can see and
+    /// manage everything, and the jobs it submits have no owner.
+    pub fn anonymous() -> Self {
+        Principal {
+            username: None,
+            admin: true,
+        }
+    }
+
+    /// The owner recorded on jobs, subscriptions and batches this principal creates.
+    pub fn owner(&self) -> Option<String> {
+        self.username.clone()
+    }
+
+    /// Whether this principal may see something owned by `owner`. Administrators see
+    /// everything; other users only what they own.
+    pub fn can_access(&self, owner: Option<&str>) -> bool {
+        self.admin || (owner.is_some() && owner == self.username.as_deref())
+    }
+
+    /// The owner listings are restricted to, or `None` for administrators.
+    pub fn owner_filter(&self) -> Option<String> {
+        if self.admin {
+            None
+        } else {
+            self.username.clone()
+        }
+    }
+
+    /// Fails with `Forbidden` unless this principal is an administrator.
+    pub fn require_admin(&self) -> Result<()> {
+        if self.admin {
+            Ok(())
+        } else {
+            Err(PegasusError::Forbidden(
+                "This action requires an administrator".to_string(),
+            ))
+        }
+    }
+}
+
+/// A user account.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    /// Argon2 hash in PHC format. Users without a password can only use API tokens.
+    pub password_hash: Option<String>,
+    pub admin: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user account as returned by the API.
+#[derive(Clone, Debug, Serialize)]
+pub struct UserInfo {
+    pub username: String,
+    pub admin: bool,
+    pub has_password: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&User> for UserInfo {
+    fn from(user: &User) -> Self {
+        UserInfo {
+            username: user.username.clone(),
+            admin: user.admin,
+            has_password: user.password_hash.is_some(),
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// An API token. Only the hash of the token itself is kept.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    /// What the token is used for, e.g. `home-assistant`.
+    pub name: String,
+    /// The user the token acts as.
+    pub username: String,
+    /// Hex-encoded SHA-256 of the token.
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An API token as returned by the API, without its hash.
+#[derive(Clone, Debug, Serialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub name: String,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&ApiToken> for TokenInfo {
+    fn from(token: &ApiToken) -> Self {
+        TokenInfo {
+            id: token.id.clone(),
+            name: token.name.clone(),
+            username: token.username.clone(),
+            created_at: token.created_at,
+        }
+    }
+}
+
+/// The contents of the registry file.
+#[derive(Default, Serialize, Deserialize)]
+struct AuthData {
+    users: BTreeMap<String, User>,
+    tokens: BTreeMap<String, ApiToken>,
+}
+
+/// A logged-in web UI session.
+struct Session {
+    username: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Thread-safe registry of users and API tokens backed by a JSON file.
+pub struct AuthStore {
+    data: RwLock<AuthData>,
+    path: PathBuf,
+    /// Serialises writes so concurrent changes cannot interleave their file writes.
+    write_lock: Mutex<()>,
+    /// Login sessions keyed by the hash of their cookie value.
+    sessions: StdMutex<HashMap<String, Session>>,
+    session_ttl: Duration,
+}
+
+impl AuthStore {
+    /// Opens the registry file, creating it on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The location of the JSON file.
+    /// * `session_ttl_hours` - How long login sessions last.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the opened `AuthStore`, or a `PegasusError` on failure.
+    pub async fn open(path: &Path, session_ttl_hours: u64) -> Result<Self> {
+        info!(path = %path.display(), "Opening auth store");
+
+        let data: AuthData = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| {
+                error!(error = %e, path = %path.display(), "Failed to parse auth store");
+                PegasusError::ConfigError(format!(
+                    "Failed to parse auth store {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AuthData::default(),
+            Err(e) => {
+                error!(error = %e, path = %path.display(), "Failed to read auth store");
+                return Err(PegasusError::IoError(e));
+            }
+        };
+        info!(
+            users = data.users.len(),
+            tokens = data.tokens.len(),
+            "Loaded users and API tokens"
+        );
+
+        Ok(AuthStore {
+            data: RwLock::new(data),
+            path: path.to_path_buf(),
+            write_lock: Mutex::new(()),
+            sessions: StdMutex::new(HashMap::new()),
+            session_ttl: Duration::hours(session_ttl_hours as i64),
+        })
+    }
+
+    /// Creates the first administrator if there are no users yet.
+    ///
+    /// The administrator gets `password` if one is given, and always an API token so the
+    /// API can be used right away.
+    ///
+    /// # Returns
+    ///
+    /// The new administrator's API token, to be shown once, or `None` if users already
+    /// existed.
+    pub async fn bootstrap(&self, password: Option<&str>) -> Result<Option<String>> {
+        if !self.data.read().await.users.is_empty() {
+            return Ok(None);
+        }
+        self.create_user(BOOTSTRAP_ADMIN, password, true).await?;
+        let (_, token) = self.create_token("bootstrap", BOOTSTRAP_ADMIN).await?;
+        Ok(Some(token))
+    }
+
+    /// Resolves an API token to the user it acts as.
+    pub async fn authenticate_token(&self, token: &str) -> Option<Principal> {
+        let hash = hash_secret(token);
+        let data = self.data.read().await;
+        let token = data.tokens.values().find(|t| t.token_hash == hash)?;
+        let user = data.users.get(&token.username)?;
+        Some(Principal {
+            username: Some(user.username.clone()),
+            admin: user.admin,
+        })
+    }
+
+    /// Checks a username and password and starts a login session.
+    ///
+    /// # Returns
+    ///
+    /// The session's cookie value and the logged-in principal, or `Unauthorized` if the
+    /// credentials are wrong.
+    pub async fn login(&self, username: &str, password: &str) -> Result<(String, Principal)> {
+        let invalid = || PegasusError::Unauthorized("Invalid username or password".to_string());
+        let user = self
+            .data
+            .read()
+            .await
+            .users
+            .get(username)
+            .cloned()
+            .ok_or_else(invalid)?;
+        let hash = user.password_hash.clone().ok_or_else(invalid)?;
+
+        // Argon2 is deliberately slow, so keep it off the async workers
+        let password = password.to_string();
+        let valid = tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&hash)
+                .map(|parsed| {
+                    Argon2::default()
+                        .verify_password(password.as_bytes(), &parsed)
+                        .is_ok()
+                })
+                .unwrap_or(false)
+        })
+        .await
+        .map_err(|e| PegasusError::Unknown(format!("Password check failed: {}", e)))?;
+        if !valid {
+            warn!(username = %username, "Failed login attempt");
+            return Err(invalid());
+        }
+
+        let session = generate_secret();
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        // Forget expired sessions whenever someone logs in
+        sessions.retain(|_, s| s.expires_at > now);
+        sessions.insert(
+            hash_secret(&session),
+            Session {
+                username: user.username.clone(),
+                expires_at: now + self.session_ttl,
+            },
+        );
+        info!(username = %user.username, "User logged in");
+        Ok((
+            session,
+            Principal {
+                username: Some(user.username),
+                admin: user.admin,
+            },
+        ))
+    }
+
+    /// Resolves a session cookie to the logged-in user.
+    pub async fn authenticate_session(&self, session: &str) -> Option<Principal> {
+        let username = {
+            let sessions = self.sessions.lock().unwrap();
+            let session = sessions.get(&hash_secret(session))?;
+            if session.expires_at <= Utc::now() {
+                return None;
+            }
+            session.username.clone()
+        };
+        let data = self.data.read().await;
+        let user = data.users.get(&username)?;
+        Some(Principal {
+            username: Some(user.username.clone()),
+            admin: user.admin,
+        })
+    }
+
+    /// Ends a login session.
+    pub fn logout(&self, session: &str) {
+        self.sessions.lock().unwrap().remove(&hash_secret(session));
+    }
+
+    /// Lists every user, sorted by name.
+    pub async fn list_users(&self) -> Vec<UserInfo> {
+        self.data
+            .read()
+            .await
+            .users
+            .values()
+            .map(UserInfo::from)
+            .collect()
+    }
+
+    /// Creates a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - Letters, digits, `.`, `_` and `-`, at most 64 characters.
+    /// * `password` - The login password, or `None` for a token-only user.
+    /// * `admin` - Whether the user can see every job and manage users and tokens.
+    pub async fn create_user(
+        &self,
+        username: &str,
+        password: Option<&str>,
+        admin: bool,
+    ) -> Result<UserInfo> {
+        validate_username(username)?;
+        let password_hash = match password {
+            Some(password) => Some(hash_password(password).await?),
+            None => None,
+        };
+
+        let user = User {
+            username: username.to_string(),
+            password_hash,
+            admin,
+            created_at: Utc::now(),
+        };
+        {
+            let mut data = self.data.write().await;
+            if data.users.contains_key(username) {
+                return Err(PegasusError::ValidationError(format!(
+                    "User {} already exists",
+                    username
+                )));
+            }
+            data.users.insert(user.username.clone(), user.clone());
+        }
+        self.persist().await?;
+        info!(username = %username, admin, "User created");
+        Ok(UserInfo::from(&user))
+    }
+
+    /// Deletes a user along with their API tokens and sessions. Their jobs are kept.
+    pub async fn delete_user(&self, username: &str) -> Result<()> {
+        {
+            let mut data = self.data.write().await;
+            let user = data
+                .users
+                .get(username)
+                .ok_or_else(|| PegasusError::UserNotFound(username.to_string()))?;
+            if user.admin && data.users.values().filter(|u| u.admin).count() == 1 {
+                return Err(PegasusError::ValidationError(
+                    "The last administrator cannot be deleted".to_string(),
+                ));
+            }
+            data.users.remove(username);
+            data.tokens.retain(|_, token| token.username != username);
+        }
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, session| session.username != username);
+        self.persist().await?;
+        info!(username = %username, "User deleted");
+        Ok(())
+    }
+
+    /// Lists every API token, sorted by ID.
+    pub async fn list_tokens(&self) -> Vec<TokenInfo> {
+        self.data
+            .read()
+            .await
+            .tokens
+            .values()
+            .map(TokenInfo::from)
+            .collect()
+    }
+
+    /// Issues a new API token for a user.
+    ///
+    /// # Returns
+    ///
+    /// The token's record and the token itself, which is not stored and cannot be shown
+    /// again.
+    pub async fn create_token(&self, name: &str, username: &str) -> Result<(TokenInfo, String)> {
+        let secret = format!("{}{}", TOKEN_PREFIX, generate_secret());
+        let token = ApiToken {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            username: username.to_string(),
+            token_hash: hash_secret(&secret),
+            created_at: Utc::now(),
+        };
+        {
+            let mut data = self.data.write().await;
+            if !data.users.contains_key(username) {
+                return Err(PegasusError::UserNotFound(username.to_string()));
+            }
+            data.tokens.insert(token.id.clone(), token.clone());
+        }
+        self.persist().await?;
+        info!(token_id = %token.id, name = %name, username = %username, "API token created");
+        Ok((TokenInfo::from(&token), secret))
+    }
+
+    /// Revokes an API token.
+    pub async fn delete_token(&self, id: &str) -> Result<()> {
+        let removed = self.data.write().await.tokens.remove(id).is_some();
+        if !removed {
+            return Err(PegasusError::TokenNotFound(id.to_string()));
+        }
+        self.persist().await?;
+        info!(token_id = %id, "API token revoked");
+        Ok(())
+    }
+
+    /// Writes the registry to the JSON file, replacing it atomically.
+    async fn persist(&self) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+        let contents = serde_json::to_string_pretty(&*self.data.read().await)
+            .map_err(|e| PegasusError::Unknown(format!("Failed to serialize auth store: {}", e)))?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, contents).await.map_err(|e| {
+            error!(error = %e, path = %tmp_path.display(), "Failed to write auth store");
+            PegasusError::IoError(e)
+        })?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+// Usernames end up in logs and job records, so keep them simple
+fn validate_username(username: &str) -> Result<()> {
+    let valid = !username.is_empty()
+        && username.len() <= 64
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(PegasusError::ValidationError(format!(
+            "Invalid username {:?}: use letters, digits, '.', '_' and '-' (at most 64)",
+            username
+        )))
+    }
+}
+
+// Hashes a password with Argon2 and a random salt, off the async workers
+async fn hash_password(password: &str) -> Result<String> {
+    if password.len() < 8 {
+        return Err(PegasusError::ValidationError(
+            "Passwords must be at least 8 characters long".to_string(),
+        ));
+    }
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| PegasusError::Unknown(format!("Failed to hash password: {}", e)))
+    })
+    .await
+    .map_err(|e| PegasusError::Unknown(format!("Failed to hash password: {}", e)))?
+}
+
+/// Generates a random 256-bit secret, hex-encoded.
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a token or session secret for storage and lookup.
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn open_store(dir: &tempfile::TempDir) -> AuthStore {
+        AuthStore::open(&dir.path().join("auth.json"), 24)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn secrets_are_random_and_hashed_deterministically() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 64);
+        assert!(secret.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(secret, generate_secret());
+
+        // SHA-256 of "abc"
+        assert_eq!(
+            hash_secret("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hash_secret(&secret), hash_secret(&secret));
+        assert_ne!(hash_secret(&secret), hash_secret(&generate_secret()));
+    }
+
+    #[tokio::test]
+    async fn tokens_are_stored_hashed_and_resolve_to_their_user() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(&dir).await;
+        store.create_user("alice", None, false).await.unwrap();
+        let (info, token) = store.create_token("scripts", "alice").await.unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+
+        let principal = store.authenticate_token(&token).await.unwrap();
+        assert_eq!(principal.username.as_deref(), Some("alice"));
+        assert!(!principal.admin);
+        assert!(store.authenticate_token("pgs_wrong").await.is_none());
+        assert!(store.authenticate_token(&token[1..]).await.is_none());
+
+        // Only the hash reaches the disk
+        let stored = std::fs::read_to_string(dir.path().join("auth.json")).unwrap();
+        assert!(!stored.contains(&token));
+        assert!(stored.contains(&hash_secret(&token)));
+
+        // Tokens survive a restart
+        drop(store);
+        let store = open_store(&dir).await;
+        assert!(store.authenticate_token(&token).await.is_some());
+        assert_eq!(store.list_tokens().await[0].id, info.id);
+    }
+
+    #[tokio::test]
+    async fn revoked_tokens_stop_working() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(&dir).await;
+        store.create_user("alice", None, false).await.unwrap();
+        store.create_user("bob", None, false).await.unwrap();
+        let (revoked, revoked_token) = store.create_token("old", "alice").await.unwrap();
+        let (_, kept_token) = store.create_token("new", "alice").await.unwrap();
+        let (_, bob_token) = store.create_token("bob", "bob").await.unwrap();
+
+        store.delete_token(&revoked.id).await.unwrap();
+        assert!(store.authenticate_token(&revoked_token).await.is_none());
+        assert!(store.authenticate_token(&kept_token).await.is_some());
+        assert!(matches!(
+            store.delete_token(&revoked.id).await,
+            Err(PegasusError::TokenNotFound(_))
+        ));
+
+        // Deleting a user revokes all of their tokens
+        store.delete_user("alice").await.unwrap();
+        assert!(store.authenticate_token(&kept_token).await.is_none());
+        assert!(store.authenticate_token(&bob_token).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn login_checks_the_argon2_password_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(&dir).await;
+        store
+            .create_user("alice", Some("correct horse"), true)
+            .await
+            .unwrap();
+        store.create_user("robot", None, false).await.unwrap();
+
+        let stored = std::fs::read_to_string(dir.path().join("auth.json")).unwrap();
+        assert!(stored.contains("$argon2id$"));
+        assert!(!stored.contains("correct horse"));
+
+        let (session, principal) = store.login("alice", "correct horse").await.unwrap();
+        assert_eq!(principal.username.as_deref(), Some("alice"));
+        assert!(principal.admin);
+        let resolved = store.authenticate_session(&session).await.unwrap();
+        assert_eq!(resolved.username.as_deref(), Some("alice"));
+
+        // Wrong passwords, unknown users and token-only users all get the same answer
+        for (username, password) in [
+            ("alice", "wrong horse"),
+            ("alice", ""),
+            ("mallory", "correct horse"),
+            ("robot", "correct horse"),
+        ] {
+            match store.login(username, password).await {
+                Err(PegasusError::Unauthorized(message)) => {
+                    assert_eq!(message, "Invalid username or password")
+                }
+                other => panic!("{} logged in: {:?}", username, other.map(|(_, p)| p)),
+            }
+        }
+
+        store.logout(&session);
+        assert!(store.authenticate_session(&session).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn short_passwords_and_odd_usernames_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(&dir).await;
+        for (username, password) in [
+            ("alice", Some("short")),
+            ("", None),
+            ("a b", None),
+            ("../etc", None),
+        ] {
+            assert!(matches!(
+                store.create_user(username, password, false).await,
+                Err(PegasusError::ValidationError(_))
+            ));
+        }
+        assert!(store.list_users().await.is_empty());
+    }
+}
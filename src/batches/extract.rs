@@ -0,0 +1,255 @@
+// src/batches/extract.rs
+// Finds media URLs in free-form text such as a pasted chat log or an exported bookmarks
+// file, and normalizes URLs so the same media is only submitted once.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+// Anything that starts like a web link, up to the next character that cannot be part of
+// one in running text or markup
+static URL_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)https?://[^\s<>"'`{}|\\^]+"#).unwrap());
+
+// Query parameters that only track where a link was shared from
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "dclid", "igshid", "mc_cid", "mc_eid"];
+
+// Query parameters YouTube adds to share links
+const YOUTUBE_SHARE_PARAMS: &[&str] = &["si", "feature", "pp"];
+
+// Links to page assets rather than media, common in saved HTML pages
+const ASSET_EXTENSIONS: &[&str] = &[
+    "css", "js", "json", "xml", "ico", "png", "jpg", "jpeg", "gif", "svg", "webp", "woff", "woff2",
+    "ttf",
+];
+
+/// Extracts every media URL from a block of text or HTML.
+///
+/// HTML entities in attribute values are decoded, punctuation trailing a link in prose is
+/// dropped, links to page assets (stylesheets, scripts, images, fonts) are skipped, and
+/// each URL is normalized with [`normalize_url`].
+///
+/// # Arguments
+///
+/// * `text` - Plain text, Markdown or HTML.
+///
+/// # Returns
+///
+/// The normalized URLs in the order they appear. A link that appears more than once is
+/// returned every time, so callers can report how many duplicates they dropped.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let decoded = decode_entities(text);
+    URL_PATTERN
+        .find_iter(&decoded)
+        .filter_map(|found| normalize_url(trim_trailing(found.as_str())))
+        .filter(|url| !is_asset(url))
+        .collect()
+}
+
+/// Normalizes a URL so different spellings of the same link compare equal.
+///
+/// The scheme and host are lowercased, the fragment and tracking parameters are removed,
+/// and YouTube's short, mobile and music hosts are rewritten to the canonical
+/// `www.youtube.com/watch?v=` form.
+///
+/// # Returns
+///
+/// The normalized URL, or `None` if the input is not an absolute http(s) URL.
+pub fn normalize_url(raw: &str) -> Option<String> {
+    let mut url = Url::parse(raw.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none_or(str::is_empty) {
+        return None;
+    }
+    url.set_fragment(None);
+
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let is_youtube = host == "youtu.be" || host == "youtube.com" || host.ends_with(".youtube.com");
+    if host == "youtu.be" {
+        // youtu.be/<id> is the same video as youtube.com/watch?v=<id>
+        let id = url.path().trim_matches('/').to_string();
+        if !id.is_empty() && !id.contains('/') {
+            let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+            url = Url::parse("https://www.youtube.com/watch").ok()?;
+            url.query_pairs_mut()
+                .append_pair("v", &id)
+                .extend_pairs(query);
+        }
+    } else if is_youtube && host != "www.youtube.com" {
+        url.set_host(Some("www.youtube.com")).ok()?;
+    }
+    if is_youtube {
+        url.set_scheme("https").ok()?;
+    }
+
+    // Drop tracking parameters, keeping the rest in their original order
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_ascii_lowercase();
+            !(key.starts_with("utm_")
+                || TRACKING_PARAMS.contains(&key.as_str())
+                || (is_youtube && YOUTUBE_SHARE_PARAMS.contains(&key.as_str())))
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+
+    Some(url.to_string())
+}
+
+// Decodes the HTML entities that show up inside URLs in markup
+fn decode_entities(text: &str) -> String {
+    text.replace("&amp;", "&")
+        .replace("&#38;", "&")
+        .replace("&#x26;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+}
+
+// Drops punctuation that ends the sentence around a link rather than the link itself.
+// A closing bracket is kept when the URL opened one, as in Wikipedia-style links.
+fn trim_trailing(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let Some(last) = url.chars().last() else {
+            return url;
+        };
+        let unbalanced = match last {
+            ')' => url.matches(')').count() > url.matches('(').count(),
+            ']' => url.matches(']').count() > url.matches('[').count(),
+            '.' | ',' | ';' | ':' | '!' | '?' | '*' | '>' => true,
+            _ => false,
+        };
+        if !unbalanced {
+            return url;
+        }
+        url = &url[..url.len() - last.len_utf8()];
+    }
+}
+
+// Whether a URL points at a stylesheet, script, image or font
+fn is_asset(url: &str) -> bool {
+    let Ok(parsed) = Url::parse(url) else {
+        return false;
+    };
+    parsed
+        .path()
+        .rsplit_once('.')
+        .map(|(_, ext)| ASSET_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_rewrites_youtube_links_to_the_watch_form() {
+        let cases = [
+            (
+                "https://youtu.be/dQw4w9WgXcQ",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            ),
+            (
+                "https://youtu.be/dQw4w9WgXcQ?t=42&si=abcdef",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42",
+            ),
+            (
+                "http://m.youtube.com/watch?v=dQw4w9WgXcQ&feature=share",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            ),
+            (
+                "https://music.youtube.com/watch?v=dQw4w9WgXcQ&list=PL1",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL1",
+            ),
+            (
+                "https://YouTube.com/watch?v=dQw4w9WgXcQ#comments",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            ),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(normalize_url(raw).as_deref(), Some(expected), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn normalize_removes_tracking_parameters_only() {
+        assert_eq!(
+            normalize_url("https://Example.com/v/1?utm_source=x&id=7&FBCLID=abc&gclid=1#t")
+                .as_deref(),
+            Some("https://example.com/v/1?id=7")
+        );
+        assert_eq!(
+            normalize_url("https://example.com/v/1?utm_medium=social").as_deref(),
+            Some("https://example.com/v/1")
+        );
+        // YouTube's share parameters mean something elsewhere
+        assert_eq!(
+            normalize_url("https://example.com/watch?feature=hd&si=2").as_deref(),
+            Some("https://example.com/watch?feature=hd&si=2")
+        );
+    }
+
+    #[test]
+    fn normalize_rejects_anything_but_absolute_http_urls() {
+        for raw in [
+            "ftp://example.com/v",
+            "file:///etc/passwd",
+            "example.com/v",
+            "javascript:alert(1)",
+            "",
+        ] {
+            assert_eq!(normalize_url(raw), None, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn extract_drops_trailing_punctuation() {
+        let text = "Watch https://example.com/a. Also (see https://example.com/b), \
+                    and https://en.wikipedia.org/wiki/Rust_(programming_language)! \
+                    Or [https://example.com/c]?";
+        assert_eq!(
+            extract_urls(text),
+            vec![
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+                "https://example.com/c",
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_skips_assets_and_decodes_html() {
+        let html = r#"<link href="https://example.com/site.css">
+            <script src="https://cdn.example.com/app.JS"></script>
+            <img src="https://example.com/thumb.jpg">
+            <a href="https://example.com/watch?v=1&amp;utm_source=feed&amp;t=5">one</a>
+            <a href='https://youtu.be/abc'>two</a>"#;
+        assert_eq!(
+            extract_urls(html),
+            vec![
+                "https://example.com/watch?v=1&t=5",
+                "https://www.youtube.com/watch?v=abc",
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_keeps_repeated_links() {
+        let text = "https://youtu.be/abc and https://www.youtube.com/watch?v=abc";
+        assert_eq!(
+            extract_urls(text),
+            vec![
+                "https://www.youtube.com/watch?v=abc",
+                "https://www.youtube.com/watch?v=abc",
+            ]
+        );
+    }
+}
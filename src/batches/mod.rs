@@ -0,0 +1,249 @@
+// src/batches/mod.rs
+// This module contains the batch registry.
+//
+// A batch is a named group of jobs submitted together, from a list of URLs or from the
+// URLs found in a pasted block of text. Each URL becomes a regular job carrying the batch
+// ID; the batch itself only records its name and members, and its progress is computed
+// from the member jobs whenever it is asked for. Batches never change once created, so the
+// registry is persisted as an append-only JSON-lines file.
+
+pub mod extract;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{error, info, warn};
+
+use crate::error::{PegasusError, Result};
+use crate::jobs::{JobStatus, JobStore};
+
+/// Most URLs accepted in a single batch.
+pub const MAX_BATCH_URLS: usize = 500;
+
+/// Largest batch request body accepted, in bytes. Pasted text such as an exported
+/// bookmarks file easily exceeds Axum's default limit of 2 MiB.
+pub const MAX_BATCH_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// A named group of jobs submitted together.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// The member jobs, one per submitted URL, in submission order.
+    pub job_ids: Vec<String>,
+    /// The user who submitted the batch, or `None` if authentication was disabled.
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+/// A URL of a batch that could not be submitted.
+#[derive(Clone, Debug, Serialize)]
+pub struct RejectedUrl {
+    pub url: String,
+    /// The stable error code, as used in API error responses.
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// The outcome of submitting a batch.
+#[derive(Debug)]
+pub struct BatchSubmission {
+    /// The recorded batch, holding the jobs that were submitted.
+    pub batch: Batch,
+    /// The URLs that could not be submitted, in URL order.
+    pub rejected: Vec<RejectedUrl>,
+}
+
+/// A batch's aggregate progress, computed from its member jobs.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchProgress {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub total: usize,
+    /// Members still waiting in the queue.
+    pub queued: usize,
+    /// Members currently fetching, downloading, processing or transferring.
+    pub active: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// Fraction of members that have finished, from 0.0 to 1.0.
+    pub progress: f32,
+    /// Whether every member has finished.
+    pub finished: bool,
+    pub jobs: Vec<BatchMember>,
+}
+
+/// The state of one member job, as reported in a batch's progress.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchMember {
+    pub job_id: String,
+    pub url: String,
+    pub status: JobStatus,
+    pub error: Option<String>,
+}
+
+impl BatchProgress {
+    /// A one-line summary such as `3 of 5 finished (1 failed, 0 cancelled)`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} finished ({} failed, {} cancelled)",
+            self.completed + self.failed + self.cancelled,
+            self.total,
+            self.failed,
+            self.cancelled
+        )
+    }
+}
+
+/// The persistent batch registry.
+pub struct BatchStore {
+    batches: RwLock<HashMap<String, Batch>>,
+    journal: Mutex<File>,
+}
+
+impl BatchStore {
+    /// Opens the batch journal at `path`, creating it on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The location of the JSON-lines journal file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the opened `BatchStore`, or a `PegasusError` on failure.
+    pub async fn open(path: &Path) -> Result<Self> {
+        info!(path = %path.display(), "Opening batch store");
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| {
+                error!(error = %e, path = %parent.display(), "Failed to create batch store directory");
+                PegasusError::IoError(e)
+            })?;
+        }
+
+        let mut batches = HashMap::new();
+        match tokio::fs::read_to_string(path).await {
+            Ok(contents) => {
+                for (line_no, line) in contents.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<Batch>(line) {
+                        Ok(batch) => {
+                            batches.insert(batch.id.clone(), batch);
+                        }
+                        Err(e) => {
+                            warn!(line = line_no + 1, error = %e, "Skipping malformed batch journal entry");
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                error!(error = %e, path = %path.display(), "Failed to read batch journal");
+                return Err(PegasusError::IoError(e));
+            }
+        }
+
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        info!(batches = batches.len(), "Batch store loaded");
+        Ok(BatchStore {
+            batches: RwLock::new(batches),
+            journal: Mutex::new(journal),
+        })
+    }
+
+    /// Records a batch whose member jobs have already been created.
+    pub async fn create(&self, batch: Batch) -> Result<()> {
+        let mut line = serde_json::to_string(&batch).map_err(|e| {
+            PegasusError::Unknown(format!("Failed to serialize batch {}: {}", batch.id, e))
+        })?;
+        line.push('\n');
+
+        {
+            let mut journal = self.journal.lock().await;
+            journal.write_all(line.as_bytes()).await.map_err(|e| {
+                error!(batch_id = %batch.id, error = %e, "Failed to write batch journal entry");
+                PegasusError::IoError(e)
+            })?;
+            journal.flush().await?;
+        }
+
+        self.batches.write().await.insert(batch.id.clone(), batch);
+        Ok(())
+    }
+
+    /// Returns a single batch.
+    pub async fn get(&self, id: &str) -> Option<Batch> {
+        self.batches.read().await.get(id).cloned()
+    }
+
+    /// Lists every batch, newest first.
+    pub async fn list(&self) -> Vec<Batch> {
+        let mut batches: Vec<Batch> = self.batches.read().await.values().cloned().collect();
+        batches.sort_by_key(|batch| std::cmp::Reverse(batch.created_at));
+        batches
+    }
+}
+
+/// Computes a batch's aggregate progress from the current state of its member jobs.
+///
+/// A member that is missing from the job store counts as failed.
+pub async fn progress(batch: &Batch, jobs: &JobStore) -> BatchProgress {
+    let mut progress = BatchProgress {
+        id: batch.id.clone(),
+        name: batch.name.clone(),
+        created_at: batch.created_at,
+        total: batch.job_ids.len(),
+        queued: 0,
+        active: 0,
+        completed: 0,
+        failed: 0,
+        cancelled: 0,
+        progress: 0.0,
+        finished: false,
+        jobs: Vec::with_capacity(batch.job_ids.len()),
+    };
+
+    for job_id in &batch.job_ids {
+        let Some(job) = jobs.get(job_id).await else {
+            progress.failed += 1;
+            continue;
+        };
+        match job.status {
+            JobStatus::Queued => progress.queued += 1,
+            JobStatus::Completed => progress.completed += 1,
+            JobStatus::Failed => progress.failed += 1,
+            JobStatus::Cancelled => progress.cancelled += 1,
+            _ => progress.active += 1,
+        }
+        progress.jobs.push(BatchMember {
+            job_id: job.id,
+            url: job.url,
+            status: job.status,
+            error: job.error,
+        });
+    }
+
+    let finished = progress.completed + progress.failed + progress.cancelled;
+    progress.finished = finished == progress.total;
+    progress.progress = if progress.total == 0 {
+        1.0
+    } else {
+        finished as f32 / progress.total as f32
+    };
+    progress
+}
@@ -1,22 +1,871 @@
 // src/config.rs
 // Handles application configuration.
+//
+// Configuration is read from a TOML file (`pegasus.toml` in the working directory, or the
+// path in `PEGASUS_CONFIG`) and then overridden by `PEGASUS_*` environment variables.
+// Every setting has a default, so both the file and the variables are optional.
+//
+// Example `pegasus.toml`:
+//
+// ```toml
+// [server]
+// bind_address = "0.0.0.0"
+// port = 8000
+//
+// [paths]
+// download_dir = "/data/downloads"
+// output_dir = "/data/output"
+// job_store = "/data/jobs.jsonl"
+// subscriptions = "/data/subscriptions.json"
+// batches = "/data/batches.jsonl"
+// auth_store = "/data/auth.json"
+// vault = "/data/vault.json"
+// download_archive = "/data/archive.txt"
+// roots = { music = "/mnt/media/music", shows = "/mnt/media/shows" }
+//
+// [binaries]
+// yt_dlp = "/usr/local/bin/yt-dlp"
+// ffmpeg = "ffmpeg"
+// ffprobe = "ffprobe"
+//
+// [limits]
+// max_concurrent_downloads = 3
+// max_downloads_per_domain = 2
+// domains = { "youtube.com" = 1 }
+//
+// [timeouts]
+// info_fetch_secs = 120
+// download_stall_minutes = 10
+//
+// [cache]
+// info_ttl_secs = 300
+//
+// [jobs]
+// retention_days = 30
+//
+// [health]
+// min_free_disk_mb = 1024
+//
+// [naming]
+// template = "{uploader,channel|Unknown}/{upload_date:%Y}/{title} [{id}].{ext}"
+// collision = "counter"
+//
+// [filenames]
+// max_bytes = 200
+// windows_safe = true
+// ascii_only = false
+//
+// [auth]
+// enabled = true
+// session_ttl_hours = 168
+// secure_cookies = true
+//
+// [vault]
+// key = "<64 hex characters, e.g. from `openssl rand -hex 32`>"
+// expiry_warning_days = 7
+//
+// [webhooks]
+// max_attempts = 5
+// endpoints.ci = { url = "https://ci.example.com/hooks/pegasus", events = ["completed", "failed"], secret = "<shared secret>" }
+//
+// [retry]
+// max_attempts = 4
+// base_delay_secs = 10
+// max_delay_secs = 600
+//
+// [default_processing_options]
+// audioOnly = true
+// audioFormat = "opus"
+//
+// [transfer]
+// default_destination = "nas"
+// destinations.nas = { type = "local", path = "/mnt/nas/media", mode = "copy" }
+// destinations.seedbox = { type = "sftp", host = "seedbox.example", username = "media", remote_dir = "/srv/media", host_key_fingerprint = "SHA256:..." }
+// ```
 
-// Example struct (to be replaced)
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::download::naming::{self, CollisionPolicy, OutputTemplate};
+use crate::download::options::ProcessingOptions;
+use crate::download::sanitize::{self, SanitizePolicy};
+use crate::error::{PegasusError, Result};
+use crate::roots;
+use crate::transfer::{DestinationConfig, LocalMode};
+use crate::webhooks::{self, WebhooksConfig};
+
+/// Config file read when `PEGASUS_CONFIG` is not set, if it exists.
+const DEFAULT_CONFIG_FILE: &str = "pegasus.toml";
+
+/// The complete application configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub media_server_path: String,
+    pub server: ServerConfig,
+    pub paths: PathsConfig,
+    pub binaries: BinaryPaths,
+    pub limits: LimitsConfig,
+    pub timeouts: TimeoutsConfig,
+    pub retry: RetryConfig,
+    pub cache: CacheConfig,
+    pub jobs: JobsConfig,
+    pub health: HealthConfig,
+    pub naming: NamingConfig,
+    pub filenames: SanitizePolicy,
+    pub auth: AuthConfig,
+    pub vault: VaultConfig,
+    /// Processing options applied to submissions that do not specify their own.
+    pub default_processing_options: ProcessingOptions,
+    pub transfer: TransferConfig,
+    pub webhooks: WebhooksConfig,
+}
+
+/// Where the HTTP server listens.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "0.0.0.0".to_string(),
+            port: 8000,
+        }
+    }
+}
+
+/// Directories and files the application writes to.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    /// Base directory downloads are written to; each job writes to a subdirectory. This
+    /// is the `default` output root.
     pub download_dir: String,
-    pub processed_dir: String,
+    /// No longer used: processed files are written next to their download. Still
+    /// accepted so existing config files load.
+    pub processed_dir: Option<String>,
+    /// Directory served by the built-in `output` transfer destination.
+    pub output_dir: String,
+    /// The job journal.
+    pub job_store: String,
+    /// The subscription registry.
+    pub subscriptions: String,
+    /// The journal of submitted batches.
+    pub batches: String,
+    /// Users and hashed API tokens.
+    pub auth_store: String,
+    /// Encrypted site cookies and logins.
+    pub vault: String,
+    /// The download archive of items subscriptions have fetched, in yt-dlp's format.
+    pub download_archive: String,
+    /// Further named directories jobs may download into, besides `download_dir`.
+    pub roots: HashMap<String, String>,
 }
 
-impl Config {
-    pub fn load() -> Self {
-        // TODO: Load configuration from file or environment variables
-        // Use tracing::info! instead of println!
-        tracing::info!("Loading configuration (placeholder)");
-        Config {
-            media_server_path: "/path/to/media/server".to_string(),
+impl Default for PathsConfig {
+    fn default() -> Self {
+        PathsConfig {
             download_dir: "/tmp/pegasus/downloads".to_string(),
-            processed_dir: "/tmp/pegasus/processed".to_string(),
+            processed_dir: None,
+            output_dir: "output".to_string(),
+            job_store: "/tmp/pegasus/jobs.jsonl".to_string(),
+            subscriptions: "/tmp/pegasus/subscriptions.json".to_string(),
+            batches: "/tmp/pegasus/batches.jsonl".to_string(),
+            auth_store: "/tmp/pegasus/auth.json".to_string(),
+            vault: "/tmp/pegasus/vault.json".to_string(),
+            download_archive: "/tmp/pegasus/archive.txt".to_string(),
+            roots: HashMap::new(),
+        }
+    }
+}
+
+/// Paths of the external binaries. Bare names are looked up in `PATH`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BinaryPaths {
+    pub yt_dlp: String,
+    pub ffmpeg: String,
+    pub ffprobe: String,
+}
+
+impl Default for BinaryPaths {
+    fn default() -> Self {
+        BinaryPaths {
+            yt_dlp: "yt-dlp".to_string(),
+            ffmpeg: "ffmpeg".to_string(),
+            ffprobe: "ffprobe".to_string(),
+        }
+    }
+}
+
+/// Download concurrency limits.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_concurrent_downloads: usize,
+    pub max_downloads_per_domain: usize,
+    /// Per-domain overrides of `max_downloads_per_domain`.
+    pub domains: HashMap<String, usize>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_concurrent_downloads: 3,
+            max_downloads_per_domain: 2,
+            domains: HashMap::new(),
+        }
+    }
+}
+
+/// How long external commands may run before a job is failed.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    /// Maximum time for yt-dlp to fetch a URL's video or playlist information.
+    pub info_fetch_secs: u64,
+    /// A download that reports no progress for this long is considered stalled and killed.
+    pub download_stall_minutes: u64,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        TimeoutsConfig {
+            info_fetch_secs: 120,
+            download_stall_minutes: 10,
+        }
+    }
+}
+
+impl TimeoutsConfig {
+    /// The info fetch timeout as a `Duration`.
+    pub fn info_fetch(&self) -> Duration {
+        Duration::from_secs(self.info_fetch_secs)
+    }
+
+    /// The download stall timeout as a `Duration`.
+    pub fn download_stall(&self) -> Duration {
+        Duration::from_secs(self.download_stall_minutes * 60)
+    }
+}
+
+/// How long extracted information is reused.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// How long a URL's extracted info JSON is reused by later previews and jobs.
+    /// 0 disables the cache.
+    pub info_ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { info_ttl_secs: 300 }
+    }
+}
+
+/// How long finished jobs are kept in the job registry.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// Finished jobs older than this are moved out of the registry into its archive
+    /// file. 0 keeps them forever.
+    pub retention_days: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig { retention_days: 30 }
+    }
+}
+
+impl JobsConfig {
+    /// The retention period, or `None` if finished jobs are kept forever.
+    pub fn retention(&self) -> Option<Duration> {
+        (self.retention_days > 0).then(|| Duration::from_secs(self.retention_days * 24 * 60 * 60))
+    }
+}
+
+/// Thresholds used by the `/readyz` readiness check.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// The server reports itself as not ready when a working directory has less free
+    /// space than this.
+    pub min_free_disk_mb: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            min_free_disk_mb: 1024,
         }
     }
 }
+
+/// How downloaded files are named. Jobs may override both settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamingConfig {
+    /// Output template for the path below the job's download directory; see
+    /// `download::naming` for the syntax.
+    pub template: String,
+    /// What happens when the templated file already exists.
+    pub collision: CollisionPolicy,
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        NamingConfig {
+            template: naming::DEFAULT_TEMPLATE.to_string(),
+            collision: CollisionPolicy::default(),
+        }
+    }
+}
+
+/// Who may use the API and the progress feeds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Require an API token or a login session for the API and the progress feeds.
+    /// When disabled, every client is treated as an administrator.
+    pub enabled: bool,
+    /// How long a login session of the web UI lasts.
+    pub session_ttl_hours: u64,
+    /// Only send the session cookie over HTTPS.
+    pub secure_cookies: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            enabled: false,
+            session_ttl_hours: 168,
+            secure_cookies: false,
+        }
+    }
+}
+
+/// Encryption of the site credentials passed to yt-dlp.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VaultConfig {
+    /// Hex-encoded 256-bit key the stored cookies and logins are encrypted with. Without
+    /// it no credentials can be stored or used.
+    pub key: Option<String>,
+    /// How many days before a stored cookie expires it is reported as expiring.
+    pub expiry_warning_days: u32,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        VaultConfig {
+            key: None,
+            expiry_warning_days: 7,
+        }
+    }
+}
+
+// Written by hand so the key never ends up in a log line
+impl std::fmt::Debug for VaultConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultConfig")
+            .field("key", &self.key.as_ref().map(|_| "<redacted>"))
+            .field("expiry_warning_days", &self.expiry_warning_days)
+            .finish()
+    }
+}
+
+/// How transient yt-dlp failures such as rate limiting are retried.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Total attempts per stage, including the first one. 1 disables retrying.
+    pub max_attempts: u32,
+    /// Delay before the first retry; it doubles with every further attempt.
+    pub base_delay_secs: u64,
+    /// Upper bound for the delay between attempts.
+    pub max_delay_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 4,
+            base_delay_secs: 10,
+            max_delay_secs: 600,
+        }
+    }
+}
+
+/// Transfer destinations finished files can be delivered to.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransferConfig {
+    pub destinations: HashMap<String, DestinationConfig>,
+    /// Destination used when a submission does not name one.
+    pub default_destination: Option<String>,
+}
+
+impl Config {
+    /// Loads the configuration from the config file and the environment, then validates it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the validated configuration, or a `PegasusError::ConfigError`
+    /// listing every problem that was found.
+    pub fn load() -> Result<Self> {
+        let mut config = Self::read_file()?;
+
+        let mut problems = config.apply_env();
+        config.add_builtin_destinations();
+        problems.extend(config.validate());
+        if !problems.is_empty() {
+            return Err(PegasusError::ConfigError(format!(
+                "{} problem(s) found:\n  - {}",
+                problems.len(),
+                problems.join("\n  - ")
+            )));
+        }
+
+        info!(
+            bind_address = %config.server.bind_address,
+            port = config.server.port,
+            destinations = config.transfer.destinations.len(),
+            "Configuration loaded"
+        );
+        Ok(config)
+    }
+
+    /// Reads the TOML config file, falling back to the defaults if there is none.
+    fn read_file() -> Result<Self> {
+        let (path, required) = match std::env::var("PEGASUS_CONFIG") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+
+        if !required && !path.exists() {
+            info!("No config file found, using defaults and environment variables");
+            return Ok(Config::default());
+        }
+
+        info!(path = %path.display(), "Reading config file");
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            PegasusError::ConfigError(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        toml::from_str(&contents).map_err(|e| {
+            PegasusError::ConfigError(format!("Failed to parse {}: {}", path.display(), e))
+        })
+    }
+
+    /// Applies `PEGASUS_*` environment variable overrides.
+    ///
+    /// # Returns
+    ///
+    /// A description of every variable that could not be parsed.
+    fn apply_env(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        env_string("PEGASUS_BIND_ADDRESS", &mut self.server.bind_address);
+        env_parse("PEGASUS_PORT", &mut self.server.port, &mut problems);
+        // `SERVER_PORT` predates the `PEGASUS_*` variables and is still honoured
+        if std::env::var("PEGASUS_PORT").is_err() && std::env::var("SERVER_PORT").is_ok() {
+            warn!("SERVER_PORT is deprecated, use PEGASUS_PORT instead");
+            env_parse("SERVER_PORT", &mut self.server.port, &mut problems);
+        }
+
+        env_string("PEGASUS_DOWNLOAD_DIR", &mut self.paths.download_dir);
+        if std::env::var("PEGASUS_PROCESSED_DIR").is_ok() {
+            warn!(
+                "PEGASUS_PROCESSED_DIR is no longer used, processed files are written next to their download"
+            );
+        }
+        env_string("PEGASUS_OUTPUT_DIR", &mut self.paths.output_dir);
+        env_string("PEGASUS_JOB_STORE", &mut self.paths.job_store);
+        env_string("PEGASUS_SUBSCRIPTIONS", &mut self.paths.subscriptions);
+        env_string("PEGASUS_BATCHES", &mut self.paths.batches);
+        env_string("PEGASUS_AUTH_STORE", &mut self.paths.auth_store);
+        env_string("PEGASUS_VAULT", &mut self.paths.vault);
+        env_string("PEGASUS_DOWNLOAD_ARCHIVE", &mut self.paths.download_archive);
+        // Output roots are given as `name=path` pairs separated by commas
+        if let Ok(value) = std::env::var("PEGASUS_OUTPUT_ROOTS") {
+            for pair in value.split(',').filter(|pair| !pair.trim().is_empty()) {
+                match pair.split_once('=') {
+                    Some((name, path)) => {
+                        self.paths
+                            .roots
+                            .insert(name.trim().to_string(), path.trim().to_string());
+                    }
+                    None => problems.push(format!(
+                        "PEGASUS_OUTPUT_ROOTS entry {:?} is not of the form name=path",
+                        pair
+                    )),
+                }
+            }
+        }
+
+        env_string("PEGASUS_YT_DLP", &mut self.binaries.yt_dlp);
+        env_string("PEGASUS_FFMPEG", &mut self.binaries.ffmpeg);
+        env_string("PEGASUS_FFPROBE", &mut self.binaries.ffprobe);
+
+        env_parse(
+            "PEGASUS_MAX_CONCURRENT_DOWNLOADS",
+            &mut self.limits.max_concurrent_downloads,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_MAX_DOWNLOADS_PER_DOMAIN",
+            &mut self.limits.max_downloads_per_domain,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_INFO_TIMEOUT_SECS",
+            &mut self.timeouts.info_fetch_secs,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_STALL_TIMEOUT_MINUTES",
+            &mut self.timeouts.download_stall_minutes,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_INFO_CACHE_TTL_SECS",
+            &mut self.cache.info_ttl_secs,
+            &mut problems,
+        );
+        env_string("PEGASUS_OUTPUT_TEMPLATE", &mut self.naming.template);
+        env_parse(
+            "PEGASUS_COLLISION_POLICY",
+            &mut self.naming.collision,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_FILENAME_MAX_BYTES",
+            &mut self.filenames.max_bytes,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_FILENAME_WINDOWS_SAFE",
+            &mut self.filenames.windows_safe,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_FILENAME_ASCII_ONLY",
+            &mut self.filenames.ascii_only,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_JOB_RETENTION_DAYS",
+            &mut self.jobs.retention_days,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_MIN_FREE_DISK_MB",
+            &mut self.health.min_free_disk_mb,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_RETRY_MAX_ATTEMPTS",
+            &mut self.retry.max_attempts,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_RETRY_BASE_DELAY_SECS",
+            &mut self.retry.base_delay_secs,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_RETRY_MAX_DELAY_SECS",
+            &mut self.retry.max_delay_secs,
+            &mut problems,
+        );
+
+        env_parse(
+            "PEGASUS_AUTH_ENABLED",
+            &mut self.auth.enabled,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_SESSION_TTL_HOURS",
+            &mut self.auth.session_ttl_hours,
+            &mut problems,
+        );
+        env_parse(
+            "PEGASUS_SECURE_COOKIES",
+            &mut self.auth.secure_cookies,
+            &mut problems,
+        );
+
+        if let Ok(value) = std::env::var("PEGASUS_VAULT_KEY") {
+            self.vault.key = Some(value).filter(|v| !v.is_empty());
+        }
+        env_parse(
+            "PEGASUS_COOKIE_EXPIRY_WARNING_DAYS",
+            &mut self.vault.expiry_warning_days,
+            &mut problems,
+        );
+
+        // Default processing options use the same JSON shape as the submit API
+        if let Ok(value) = std::env::var("PEGASUS_DEFAULT_PROCESSING_OPTIONS") {
+            match serde_json::from_str(&value) {
+                Ok(options) => self.default_processing_options = options,
+                Err(e) => problems.push(format!(
+                    "PEGASUS_DEFAULT_PROCESSING_OPTIONS is not valid: {}",
+                    e
+                )),
+            }
+        }
+
+        if let Ok(value) = std::env::var("PEGASUS_DEFAULT_DESTINATION") {
+            self.transfer.default_destination = Some(value).filter(|v| !v.is_empty());
+        }
+
+        problems
+    }
+
+    /// Checks that directories are writable, binaries are executable and values are sane.
+    ///
+    /// # Returns
+    ///
+    /// A description of every problem found.
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.paths.processed_dir.is_some() {
+            warn!(
+                "paths.processed_dir is no longer used, processed files are written next to their download"
+            );
+        }
+
+        if self
+            .server
+            .bind_address
+            .parse::<std::net::IpAddr>()
+            .is_err()
+        {
+            problems.push(format!(
+                "server.bind_address {:?} is not an IP address",
+                self.server.bind_address
+            ));
+        }
+
+        for (key, dir) in [
+            ("paths.download_dir", &self.paths.download_dir),
+            ("paths.output_dir", &self.paths.output_dir),
+        ] {
+            if let Err(e) = check_writable_dir(Path::new(dir)) {
+                problems.push(format!("{} {:?} is not writable: {}", key, dir, e));
+            }
+        }
+        for (name, dir) in &self.paths.roots {
+            if name == roots::DEFAULT_ROOT
+                || name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                problems.push(format!(
+                    "paths.roots: {:?} is not a valid root name (letters, digits, - and _; {:?} is reserved for paths.download_dir)",
+                    name,
+                    roots::DEFAULT_ROOT
+                ));
+            }
+            if !Path::new(dir).is_absolute() {
+                problems.push(format!(
+                    "paths.roots.{} {:?} must be an absolute path",
+                    name, dir
+                ));
+            } else if let Err(e) = check_writable_dir(Path::new(dir)) {
+                problems.push(format!(
+                    "paths.roots.{} {:?} is not writable: {}",
+                    name, dir, e
+                ));
+            }
+        }
+        for (key, file) in [
+            ("paths.job_store", &self.paths.job_store),
+            ("paths.subscriptions", &self.paths.subscriptions),
+            ("paths.batches", &self.paths.batches),
+            ("paths.auth_store", &self.paths.auth_store),
+            ("paths.vault", &self.paths.vault),
+            ("paths.download_archive", &self.paths.download_archive),
+        ] {
+            let dir = Path::new(file)
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            if let Err(e) = check_writable_dir(dir) {
+                problems.push(format!("{} {:?} is not writable: {}", key, file, e));
+            }
+        }
+
+        for (key, binary) in [
+            ("binaries.yt_dlp", &self.binaries.yt_dlp),
+            ("binaries.ffmpeg", &self.binaries.ffmpeg),
+            ("binaries.ffprobe", &self.binaries.ffprobe),
+        ] {
+            if find_executable(binary).is_none() {
+                problems.push(format!("{} {:?} is not an executable", key, binary));
+            }
+        }
+
+        if self.limits.max_concurrent_downloads == 0 {
+            problems.push("limits.max_concurrent_downloads must be at least 1".to_string());
+        }
+        if self.limits.max_downloads_per_domain == 0 {
+            problems.push("limits.max_downloads_per_domain must be at least 1".to_string());
+        }
+        for (domain, limit) in &self.limits.domains {
+            if *limit == 0 {
+                problems.push(format!("limits.domains.{:?} must be at least 1", domain));
+            }
+        }
+
+        if self.timeouts.info_fetch_secs == 0 {
+            problems.push("timeouts.info_fetch_secs must be at least 1".to_string());
+        }
+        if self.timeouts.download_stall_minutes == 0 {
+            problems.push("timeouts.download_stall_minutes must be at least 1".to_string());
+        }
+
+        if self.retry.max_attempts == 0 {
+            problems.push("retry.max_attempts must be at least 1".to_string());
+        }
+        if self.retry.max_delay_secs < self.retry.base_delay_secs {
+            problems.push(
+                "retry.max_delay_secs must not be less than retry.base_delay_secs".to_string(),
+            );
+        }
+
+        if self.auth.session_ttl_hours == 0 {
+            problems.push("auth.session_ttl_hours must be at least 1".to_string());
+        }
+
+        // The key itself is never echoed back
+        if let Some(key) = &self.vault.key
+            && (key.len() != 64 || hex::decode(key).is_err())
+        {
+            problems.push("vault.key must be 64 hex characters (32 bytes)".to_string());
+        }
+
+        if let Err(e) = self.default_processing_options.validate() {
+            problems.push(format!("default_processing_options: {}", e));
+        }
+
+        if let Err(e) = OutputTemplate::parse(&self.naming.template) {
+            problems.push(format!("naming.template: {}", e));
+        }
+        if !(sanitize::MIN_MAX_BYTES..=255).contains(&self.filenames.max_bytes) {
+            problems.push(format!(
+                "filenames.max_bytes must be between {} and 255",
+                sanitize::MIN_MAX_BYTES
+            ));
+        }
+
+        if self.webhooks.max_attempts == 0 {
+            problems.push("webhooks.max_attempts must be at least 1".to_string());
+        }
+        if self.webhooks.max_delay_secs < self.webhooks.base_delay_secs {
+            problems.push(
+                "webhooks.max_delay_secs must not be less than webhooks.base_delay_secs"
+                    .to_string(),
+            );
+        }
+        if self.webhooks.timeout_secs == 0 {
+            problems.push("webhooks.timeout_secs must be at least 1".to_string());
+        }
+        for (name, endpoint) in &self.webhooks.endpoints {
+            problems.extend(webhooks::validate_endpoint(name, endpoint));
+        }
+
+        if let Some(default) = &self.transfer.default_destination
+            && !self.transfer.destinations.contains_key(default)
+        {
+            problems.push(format!(
+                "transfer.default_destination {:?} is not a configured destination",
+                default
+            ));
+        }
+
+        problems
+    }
+
+    /// Adds the `output` destination, which moves finished files into `paths.output_dir`,
+    /// unless the config file defines its own destination with that name.
+    fn add_builtin_destinations(&mut self) {
+        self.transfer
+            .destinations
+            .entry("output".to_string())
+            .or_insert_with(|| DestinationConfig::Local {
+                path: self.paths.output_dir.clone(),
+                mode: LocalMode::Move,
+            });
+    }
+}
+
+/// Overwrites `target` with an environment variable, if it is set.
+fn env_string(name: &str, target: &mut String) {
+    if let Ok(value) = std::env::var(name) {
+        *target = value;
+    }
+}
+
+/// Parses an environment variable into `target`, recording a problem if it is malformed.
+fn env_parse<T: FromStr>(name: &str, target: &mut T, problems: &mut Vec<String>)
+where
+    T::Err: std::fmt::Display,
+{
+    if let Ok(value) = std::env::var(name) {
+        match value.parse() {
+            Ok(parsed) => *target = parsed,
+            Err(e) => problems.push(format!("{}={:?} is not valid: {}", name, value, e)),
+        }
+    }
+}
+
+/// Creates a directory if needed and checks that files can be created in it.
+pub fn check_writable_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(".pegasus-write-test");
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
+
+/// Resolves a binary the way a shell would: paths are checked directly, bare names are
+/// looked up in `PATH`.
+///
+/// # Returns
+///
+/// The path of the executable, or `None` if it cannot be found or is not executable.
+pub fn find_executable(binary: &str) -> Option<PathBuf> {
+    if binary.contains(std::path::MAIN_SEPARATOR) {
+        let path = PathBuf::from(binary);
+        return is_executable(&path).then_some(path);
+    }
+
+    let search_path = std::env::var_os("PATH")?;
+    std::env::split_paths(&search_path)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
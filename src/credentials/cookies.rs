@@ -0,0 +1,102 @@
+// src/credentials/cookies.rs
+// Reads Netscape cookie files, the format browser extensions export and yt-dlp's
+// `--cookies` option expects: one cookie per line with seven tab-separated fields
+// (domain, include subdomains, path, secure, expiry, name, value).
+
+use chrono::{DateTime, Utc};
+
+use crate::error::{PegasusError, Result};
+
+/// The header yt-dlp's cookie jar expects on the first line.
+pub const NETSCAPE_HEADER: &str = "# Netscape HTTP Cookie File";
+
+// Browser exports mark HttpOnly cookies by prefixing the line, which otherwise looks like
+// a comment
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// One cookie from a cookie file.
+pub struct Cookie {
+    /// The cookie's domain, without a leading dot.
+    pub domain: String,
+    /// `None` for session cookies.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub name: String,
+    pub value: String,
+}
+
+/// What a cookie file holds for one site, without the cookies themselves.
+pub struct CookieSummary {
+    /// Number of cookies for the site.
+    pub count: usize,
+    /// When the first of the site's persistent cookies expires.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Parses a Netscape cookie file.
+///
+/// # Returns
+///
+/// Every cookie in the file, or a `ValidationError` naming the first malformed line.
+pub fn parse(contents: &str) -> Result<Vec<Cookie>> {
+    let mut cookies = Vec::new();
+    for (line_no, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let line = line.strip_prefix(HTTP_ONLY_PREFIX).unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let expiry = fields.get(4).and_then(|field| field.parse::<i64>().ok());
+        let (Some(expiry), 7) = (expiry, fields.len()) else {
+            // Deliberately not quoting the line, it may hold a cookie value
+            return Err(PegasusError::ValidationError(format!(
+                "Line {} is not a Netscape cookie (expected 7 tab-separated fields)",
+                line_no + 1
+            )));
+        };
+        cookies.push(Cookie {
+            domain: fields[0].trim_start_matches('.').to_ascii_lowercase(),
+            // An expiry of 0 marks a session cookie
+            expires_at: (expiry > 0)
+                .then(|| DateTime::from_timestamp(expiry, 0))
+                .flatten(),
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+        });
+    }
+    Ok(cookies)
+}
+
+/// Summarizes the cookies of a cookie file that belong to `domain`.
+///
+/// # Returns
+///
+/// The summary, or a `ValidationError` if the file is malformed or holds no cookies for
+/// the domain, which usually means the export was made on the wrong site.
+pub fn summarize(contents: &str, domain: &str) -> Result<CookieSummary> {
+    let cookies = parse(contents)?;
+    let matching: Vec<&Cookie> = cookies
+        .iter()
+        .filter(|cookie| super::domain_matches(&cookie.domain, domain))
+        .collect();
+    if matching.is_empty() {
+        return Err(PegasusError::ValidationError(format!(
+            "The cookie file has no cookies for {}",
+            domain
+        )));
+    }
+    Ok(CookieSummary {
+        count: matching.len(),
+        expires_at: matching.iter().filter_map(|cookie| cookie.expires_at).min(),
+    })
+}
+
+/// Adds the header yt-dlp requires if an export left it out.
+pub fn with_header(contents: &str) -> String {
+    if contents.starts_with(NETSCAPE_HEADER) || contents.starts_with("# HTTP Cookie File") {
+        contents.to_string()
+    } else {
+        format!("{}\n{}", NETSCAPE_HEADER, contents)
+    }
+}
@@ -0,0 +1,802 @@
+// src/credentials/mod.rs
+// This module contains the credential vault.
+//
+// Age-restricted and members-only media can only be fetched with a logged-in session, so
+// administrators can store a Netscape cookie file or a username and password per site.
+// Whenever yt-dlp is run for a URL, the credential of the most specific stored domain
+// matching the URL's host is decrypted into a private temporary file and passed with
+// `--cookies` or `--config-locations`, which keeps it off yt-dlp's command line. The file
+// is deleted as soon as yt-dlp is done with it.
+//
+// Secrets are encrypted with AES-256-GCM under the configured vault key, bound to their
+// domain so a ciphertext cannot be moved to another site. What is not secret (the domain,
+// the kind of credential and when cookies expire) is stored in the clear so it can be
+// listed without the key. Like the subscription registry, the vault is a single JSON file
+// that is rewritten on every change.
+
+pub mod cookies;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info, warn};
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::VaultConfig;
+use crate::error::{PegasusError, Result};
+
+/// The kinds of credential that can be stored for a site.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialKind {
+    /// A Netscape cookie file exported from a logged-in browser.
+    Cookies,
+    /// A username and password yt-dlp logs in with.
+    Login,
+}
+
+/// The non-secret description of a stored credential.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CredentialInfo {
+    /// The site the credential is used for, including its subdomains.
+    pub domain: String,
+    pub kind: CredentialKind,
+    /// A note for administrators, e.g. which account the cookies belong to.
+    pub label: Option<String>,
+    /// Number of cookies for the domain, for cookie files.
+    #[serde(default)]
+    pub cookie_count: usize,
+    /// When the first of the domain's persistent cookies expires, for cookie files.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// How close a stored cookie file is to expiring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryStatus {
+    /// A login, or cookies that do not expire soon.
+    Valid,
+    /// Cookies that expire within the configured warning period.
+    Expiring,
+    Expired,
+}
+
+/// A stored credential as returned by the API.
+#[derive(Clone, Debug, Serialize)]
+pub struct CredentialStatus {
+    #[serde(flatten)]
+    pub info: CredentialInfo,
+    pub expiry_status: ExpiryStatus,
+}
+
+/// The secret part of a credential, encrypted at rest. Deliberately not `Debug`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Secret {
+    Cookies { contents: String },
+    Login { username: String, password: String },
+}
+
+impl Secret {
+    /// The kind of credential this secret is.
+    pub fn kind(&self) -> CredentialKind {
+        match self {
+            Secret::Cookies { .. } => CredentialKind::Cookies,
+            Secret::Login { .. } => CredentialKind::Login,
+        }
+    }
+}
+
+/// An encrypted secret as stored in the vault file.
+#[derive(Clone, Serialize, Deserialize)]
+struct Sealed {
+    /// Hex-encoded 96-bit AES-GCM nonce.
+    nonce: String,
+    /// Hex-encoded ciphertext and authentication tag.
+    ciphertext: String,
+}
+
+/// A credential as stored in the vault file.
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredCredential {
+    #[serde(flatten)]
+    info: CredentialInfo,
+    secret: Sealed,
+}
+
+/// Replaces secrets in text that is about to be logged or shown to clients.
+///
+/// Secrets are only replaced where they stand as a whole: a password between characters
+/// that cannot be part of it, and a cookie value after its name (`name=value` in headers,
+/// `name<TAB>value` in a cookie file). Every secret is redacted however short it is, so
+/// matching a value like `1` anywhere would otherwise turn `HTTP Error 500` into
+/// nonsense.
+#[derive(Clone, Default)]
+pub struct Redactor {
+    passwords: Vec<String>,
+    /// Cookie names and values.
+    cookies: Vec<(String, String)>,
+}
+
+impl Redactor {
+    /// Returns `text` with every secret replaced by `[redacted]`.
+    pub fn redact(&self, text: &str) -> String {
+        let mut redacted = text.to_string();
+        for password in &self.passwords {
+            redacted = replace_whole(&redacted, "", password, |c| c.is_alphanumeric());
+        }
+        for (name, value) in &self.cookies {
+            for separator in ["=", "\t"] {
+                let prefix = format!("{}{}", name, separator);
+                redacted = replace_whole(&redacted, &prefix, value, is_cookie_value_char);
+            }
+        }
+        redacted
+    }
+}
+
+#[cfg(test)]
+impl Redactor {
+    /// A redactor for the given secrets, for tests elsewhere in the crate.
+    pub fn with_secrets(passwords: &[&str], cookies: &[(&str, &str)]) -> Redactor {
+        Redactor {
+            passwords: passwords.iter().map(|p| p.to_string()).collect(),
+            cookies: cookies
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+// Replaces `secret` with `[redacted]` wherever it follows `prefix` as a whole: the
+// match may not run into a neighbouring character that could continue it. After a
+// prefix that is a cookie name, that is a character of a longer name
+fn replace_whole(text: &str, prefix: &str, secret: &str, joins: impl Fn(char) -> bool) -> String {
+    let needle = format!("{}{}", prefix, secret);
+    let continues_before: &dyn Fn(char) -> bool = if prefix.is_empty() {
+        &joins
+    } else {
+        &is_cookie_name_char
+    };
+
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut from = 0;
+    while let Some(found) = text[from..].find(&needle) {
+        let start = from + found;
+        let end = start + needle.len();
+        let whole = !text[..start]
+            .chars()
+            .next_back()
+            .is_some_and(continues_before)
+            && !text[end..].chars().next().is_some_and(&joins);
+        if whole {
+            result.push_str(&text[copied..start]);
+            result.push_str(prefix);
+            result.push_str("[redacted]");
+            copied = end;
+            from = end;
+        } else {
+            // Step one character on, as a whole match may overlap this one
+            from = start + text[start..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+// Characters that can be part of a cookie name (an RFC 6265 token). The single quote
+// is left out, as yt-dlp quotes names when it prints them
+fn is_cookie_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&*+-.^_`|~".contains(c)
+}
+
+// Characters that can be part of a cookie value; `;`, `,`, backslashes and whitespace
+// end one, and so do quotes, which yt-dlp wraps values in when it prints them
+fn is_cookie_value_char(c: char) -> bool {
+    c.is_ascii_graphic() && !matches!(c, ';' | ',' | '"' | '\'' | '\\')
+}
+
+/// A decrypted credential written out for one yt-dlp run.
+///
+/// The temporary file is deleted when this is dropped.
+pub struct YtDlpCredentials {
+    /// The stored domain the URL matched.
+    pub domain: String,
+    pub kind: CredentialKind,
+    /// When the cookies expire, if they are a cookie file.
+    pub expires_at: Option<DateTime<Utc>>,
+    file: PrivateFile,
+    redactor: Redactor,
+}
+
+impl YtDlpCredentials {
+    /// The yt-dlp arguments that load the credential. They only hold a file path.
+    pub fn args(&self) -> Vec<String> {
+        let option = match self.kind {
+            CredentialKind::Cookies => "--cookies",
+            CredentialKind::Login => "--config-locations",
+        };
+        vec![
+            option.to_string(),
+            self.file.path().to_string_lossy().to_string(),
+        ]
+    }
+
+    /// Replaces this credential's secrets in yt-dlp output.
+    pub fn redactor(&self) -> Redactor {
+        self.redactor.clone()
+    }
+
+    /// Whether the stored cookies have already expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+}
+
+/// A file only the server's user can read, in the private temporary directory.
+///
+/// Besides credentials this holds anything else that may carry them, such as the info
+/// JSON handed to yt-dlp, which includes the request headers and cookies it extracted
+/// with. The file is deleted when this is dropped.
+pub struct PrivateFile {
+    path: PathBuf,
+}
+
+impl PrivateFile {
+    /// Writes `contents` to a new private file.
+    ///
+    /// # Returns
+    ///
+    /// The file, or a `PegasusError` if the private directory or the file cannot be
+    /// created.
+    pub async fn create(contents: &[u8]) -> Result<PrivateFile> {
+        let dir = PRIVATE_DIR.get_or_try_init(create_private_dir).await?;
+
+        let path = dir.join(Uuid::new_v4().to_string());
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&path).await.map_err(|e| {
+            error!(error = %e, path = %path.display(), "Failed to create private temporary file");
+            PegasusError::IoError(e)
+        })?;
+        // From here on dropping the guard removes the file, also if writing fails
+        let private = PrivateFile { path };
+        // tokio finishes writes in the background; flushing waits for them, so the file
+        // is complete before anything else reads it
+        tokio::io::AsyncWriteExt::write_all(&mut file, contents).await?;
+        tokio::io::AsyncWriteExt::flush(&mut file).await?;
+        Ok(private)
+    }
+
+    /// Where the file is.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PrivateFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(error = %e, path = %self.path.display(), "Failed to remove private temporary file");
+        }
+    }
+}
+
+/// Thread-safe store of encrypted site credentials backed by a JSON file.
+pub struct CredentialVault {
+    credentials: RwLock<BTreeMap<String, StoredCredential>>,
+    path: PathBuf,
+    /// `None` when no vault key is configured; nothing can be stored or used then.
+    cipher: Option<Aes256Gcm>,
+    expiry_warning: Duration,
+    /// Serialises writes so concurrent changes cannot interleave their file writes.
+    write_lock: Mutex<()>,
+}
+
+impl CredentialVault {
+    /// Opens the vault file, creating it on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The location of the JSON file.
+    /// * `config` - The vault key and the cookie expiry warning period.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the opened `CredentialVault`, or a `PegasusError` on failure.
+    pub async fn open(path: &Path, config: &VaultConfig) -> Result<Self> {
+        info!(path = %path.display(), "Opening credential vault");
+
+        let credentials: BTreeMap<String, StoredCredential> =
+            match tokio::fs::read_to_string(path).await {
+                Ok(contents) => serde_json::from_str(&contents).map_err(|e| {
+                    error!(error = %e, path = %path.display(), "Failed to parse credential vault");
+                    PegasusError::ConfigError(format!(
+                        "Failed to parse credential vault {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => {
+                    error!(error = %e, path = %path.display(), "Failed to read credential vault");
+                    return Err(PegasusError::IoError(e));
+                }
+            };
+
+        // The key was validated with the rest of the configuration
+        let cipher = config
+            .key
+            .as_deref()
+            .and_then(|key| hex::decode(key).ok())
+            .map(|key| Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+        if cipher.is_none() && !credentials.is_empty() {
+            warn!(
+                count = credentials.len(),
+                "The credential vault holds credentials but no vault key is configured; they will not be used"
+            );
+        }
+        info!(count = credentials.len(), "Loaded site credentials");
+
+        let vault = CredentialVault {
+            credentials: RwLock::new(credentials),
+            path: path.to_path_buf(),
+            cipher,
+            expiry_warning: Duration::days(config.expiry_warning_days.into()),
+            write_lock: Mutex::new(()),
+        };
+        for status in vault.warnings().await {
+            warn!(domain = %status.info.domain, expires_at = ?status.info.expires_at, status = ?status.expiry_status, "Stored cookies need to be refreshed");
+        }
+        Ok(vault)
+    }
+
+    /// Lists every stored credential with its expiry status, sorted by domain.
+    pub async fn list(&self) -> Vec<CredentialStatus> {
+        let now = Utc::now();
+        self.credentials
+            .read()
+            .await
+            .values()
+            .map(|stored| self.status(&stored.info, now))
+            .collect()
+    }
+
+    /// Lists the cookie files that have expired or expire within the warning period.
+    pub async fn warnings(&self) -> Vec<CredentialStatus> {
+        let mut warnings: Vec<CredentialStatus> = self
+            .list()
+            .await
+            .into_iter()
+            .filter(|status| status.expiry_status != ExpiryStatus::Valid)
+            .collect();
+        warnings.sort_by_key(|status| status.info.expires_at);
+        warnings
+    }
+
+    /// Stores the credential for a domain, replacing any existing one.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The site, e.g. `youtube.com`; it also covers subdomains.
+    /// * `secret` - The cookie file or login to encrypt.
+    /// * `label` - An optional note for administrators.
+    ///
+    /// # Returns
+    ///
+    /// The stored credential's status, and whether it replaced an existing one.
+    pub async fn store(
+        &self,
+        domain: &str,
+        secret: Secret,
+        label: Option<String>,
+    ) -> Result<(CredentialStatus, bool)> {
+        let domain = normalize_domain(domain)?;
+        let (cookie_count, expires_at) = match &secret {
+            Secret::Cookies { contents } => {
+                let summary = cookies::summarize(contents, &domain)?;
+                (summary.count, summary.expires_at)
+            }
+            Secret::Login { username, password } => {
+                if username.is_empty() || password.is_empty() {
+                    return Err(PegasusError::ValidationError(
+                        "A login needs a username and a password".to_string(),
+                    ));
+                }
+                (0, None)
+            }
+        };
+        let sealed = self.seal(&domain, &secret)?;
+
+        let now = Utc::now();
+        let (stored, replaced) = {
+            let mut credentials = self.credentials.write().await;
+            let created_at = credentials
+                .get(&domain)
+                .map_or(now, |existing| existing.info.created_at);
+            let stored = StoredCredential {
+                info: CredentialInfo {
+                    domain: domain.clone(),
+                    kind: secret.kind(),
+                    label,
+                    cookie_count,
+                    expires_at,
+                    created_at,
+                    updated_at: now,
+                },
+                secret: sealed,
+            };
+            let replaced = credentials.insert(domain.clone(), stored.clone()).is_some();
+            (stored, replaced)
+        };
+        self.persist().await?;
+        info!(domain = %domain, kind = ?stored.info.kind, replaced, "Stored site credential");
+        Ok((self.status(&stored.info, now), replaced))
+    }
+
+    /// Removes the credential for a domain.
+    pub async fn delete(&self, domain: &str) -> Result<()> {
+        let domain = normalize_domain(domain)?;
+        let removed = self.credentials.write().await.remove(&domain).is_some();
+        if !removed {
+            return Err(PegasusError::CredentialNotFound(domain));
+        }
+        self.persist().await?;
+        info!(domain = %domain, "Removed site credential");
+        Ok(())
+    }
+
+    /// Writes out the credential matching a URL's host for one yt-dlp run.
+    ///
+    /// The most specific stored domain wins, so `music.example.com` is preferred over
+    /// `example.com` for URLs on `music.example.com`.
+    ///
+    /// # Returns
+    ///
+    /// The credential, or `None` if no stored domain matches or no vault key is
+    /// configured. Fails if the credential cannot be decrypted, e.g. because the key
+    /// changed.
+    pub async fn for_url(&self, url: &str) -> Result<Option<YtDlpCredentials>> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(None);
+        };
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        else {
+            return Ok(None);
+        };
+        let Some(stored) = self
+            .credentials
+            .read()
+            .await
+            .values()
+            .filter(|stored| domain_matches(&host, &stored.info.domain))
+            .max_by_key(|stored| stored.info.domain.len())
+            .cloned()
+        else {
+            return Ok(None);
+        };
+
+        let domain = &stored.info.domain;
+        let secret = open_sealed(cipher, domain, &stored.secret)?;
+        let (contents, redactor) = match &secret {
+            Secret::Cookies { contents } => {
+                let cookies = cookies::parse(contents)
+                    .map(|cookies| {
+                        cookies
+                            .into_iter()
+                            .filter(|cookie| !cookie.name.is_empty() && !cookie.value.is_empty())
+                            .map(|cookie| (cookie.name, cookie.value))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let redactor = Redactor {
+                    passwords: Vec::new(),
+                    cookies,
+                };
+                (cookies::with_header(contents), redactor)
+            }
+            Secret::Login { username, password } => {
+                let redactor = Redactor {
+                    passwords: Some(password.clone())
+                        .filter(|password| !password.is_empty())
+                        .into_iter()
+                        .collect(),
+                    cookies: Vec::new(),
+                };
+                let contents = format!(
+                    "--username {}\n--password {}\n",
+                    shell_quote(username),
+                    shell_quote(password)
+                );
+                (contents, redactor)
+            }
+        };
+        let file = PrivateFile::create(contents.as_bytes()).await?;
+        debug!(domain = %domain, kind = ?stored.info.kind, "Passing stored credential to yt-dlp");
+
+        Ok(Some(YtDlpCredentials {
+            domain: domain.clone(),
+            kind: stored.info.kind,
+            expires_at: stored.info.expires_at,
+            file,
+            redactor,
+        }))
+    }
+
+    // Works out whether a credential's cookies are expiring
+    fn status(&self, info: &CredentialInfo, now: DateTime<Utc>) -> CredentialStatus {
+        let expiry_status = match info.expires_at {
+            Some(expires_at) if expires_at <= now => ExpiryStatus::Expired,
+            Some(expires_at) if expires_at <= now + self.expiry_warning => ExpiryStatus::Expiring,
+            _ => ExpiryStatus::Valid,
+        };
+        CredentialStatus {
+            info: info.clone(),
+            expiry_status,
+        }
+    }
+
+    // Encrypts a secret for a domain under a fresh nonce
+    fn seal(&self, domain: &str, secret: &Secret) -> Result<Sealed> {
+        let cipher = self.cipher.as_ref().ok_or_else(|| {
+            PegasusError::ValidationError(
+                "Credential storage is disabled; configure vault.key to enable it".to_string(),
+            )
+        })?;
+        let plaintext = serde_json::to_vec(secret)
+            .map_err(|e| PegasusError::Unknown(format!("Failed to serialize credential: {}", e)))?;
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: domain.as_bytes(),
+                },
+            )
+            .map_err(|_| PegasusError::Unknown("Failed to encrypt credential".to_string()))?;
+        Ok(Sealed {
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Writes every credential to the JSON file, replacing it atomically.
+    async fn persist(&self) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+        let contents =
+            serde_json::to_string_pretty(&*self.credentials.read().await).map_err(|e| {
+                PegasusError::Unknown(format!("Failed to serialize credential vault: {}", e))
+            })?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, contents).await.map_err(|e| {
+            error!(error = %e, path = %tmp_path.display(), "Failed to write credential vault");
+            PegasusError::IoError(e)
+        })?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+pub fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// Lowercases a domain and checks it looks like a host name
+fn normalize_domain(domain: &str) -> Result<String> {
+    let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+    let valid = domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if valid {
+        Ok(domain)
+    } else {
+        Err(PegasusError::ValidationError(format!(
+            "Not a domain name: {:?}",
+            domain
+        )))
+    }
+}
+
+// Decrypts a sealed secret
+fn open_sealed(cipher: &Aes256Gcm, domain: &str, sealed: &Sealed) -> Result<Secret> {
+    let failed = || {
+        error!(domain = %domain, "Failed to decrypt stored credential; was the vault key changed?");
+        PegasusError::ConfigError(format!(
+            "The stored credential for {} cannot be decrypted with the configured vault key",
+            domain
+        ))
+    };
+    let nonce = hex::decode(&sealed.nonce).map_err(|_| failed())?;
+    let ciphertext = hex::decode(&sealed.ciphertext).map_err(|_| failed())?;
+    if nonce.len() != 12 {
+        return Err(failed());
+    }
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: domain.as_bytes(),
+            },
+        )
+        .map_err(|_| failed())?;
+    serde_json::from_slice(&plaintext).map_err(|_| failed())
+}
+
+// Quotes a value for a yt-dlp config file, which is split like a shell command line
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r#"'"'"'"#))
+}
+
+// The private directory credential files are written to, created on first use
+static PRIVATE_DIR: tokio::sync::OnceCell<PathBuf> = tokio::sync::OnceCell::const_new();
+
+// Creates a directory only the server's user can enter, under a name nobody can predict.
+// A shared, well-known name could be created or linked elsewhere by another local user
+// first, letting them read the credentials written into it
+async fn create_private_dir() -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("pegasus-credentials-{}", Uuid::new_v4()));
+    let mut builder = tokio::fs::DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(0o700);
+    // Not recursive, so anything already at the name is an error rather than reused
+    builder.create(&dir).await.map_err(|e| {
+        error!(error = %e, path = %dir.display(), "Failed to create private credential directory");
+        PegasusError::IoError(e)
+    })?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = tokio::fs::symlink_metadata(&dir).await?;
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let uid = unsafe { libc::geteuid() };
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            error!(path = %dir.display(), owner = metadata.uid(), mode = format!("{:o}", metadata.mode() & 0o777), "Credential directory is not private");
+            return Err(PegasusError::ConfigError(format!(
+                "Credential directory {} is not private to this user",
+                dir.display()
+            )));
+        }
+    }
+    debug!(path = %dir.display(), "Created private credential directory");
+    Ok(dir)
+}
+
+/// Removes the private directory credential files are written to, along with any file
+/// still in it. Called on shutdown; yt-dlp runs still using a file are stopped by then.
+pub async fn remove_private_dir() {
+    let Some(dir) = PRIVATE_DIR.get() else {
+        return;
+    };
+    match tokio::fs::remove_dir_all(dir).await {
+        Ok(()) => debug!(path = %dir.display(), "Removed private credential directory"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            warn!(error = %e, path = %dir.display(), "Failed to remove private credential directory")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie_redactor(cookies: &[(&str, &str)]) -> Redactor {
+        Redactor {
+            passwords: Vec::new(),
+            cookies: cookies
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn short_cookie_values_only_match_after_their_name() {
+        let redactor = cookie_redactor(&[("PREF", "0"), ("hl", "en"), ("f6", "1")]);
+
+        for text in [
+            "ERROR: unable to download video data: HTTP Error 500: Internal Server Error",
+            "ERROR: [youtube] abc: This video has been removed by the uploader",
+            "[download]  10.0% of 1.00MiB",
+        ] {
+            assert_eq!(redactor.redact(text), text);
+        }
+        assert_eq!(
+            redactor.redact("Cookie: PREF=0; hl=en; f6=1"),
+            "Cookie: PREF=[redacted]; hl=[redacted]; f6=[redacted]"
+        );
+        // A longer name or value is a different cookie
+        assert_eq!(
+            redactor.redact("XPREF=0 PREF=01 hl=en-GB"),
+            "XPREF=0 PREF=01 hl=en-GB"
+        );
+    }
+
+    #[test]
+    fn cookie_file_lines_are_redacted() {
+        let redactor = cookie_redactor(&[("SID", "abc.def")]);
+        assert_eq!(
+            redactor.redact(".youtube.com\tTRUE\t/\tTRUE\t0\tSID\tabc.def"),
+            ".youtube.com\tTRUE\t/\tTRUE\t0\tSID\t[redacted]"
+        );
+        assert_eq!(
+            redactor.redact("'Cookie': 'SID=abc.def'"),
+            "'Cookie': 'SID=[redacted]'"
+        );
+    }
+
+    #[test]
+    fn passwords_are_redacted_as_whole_words() {
+        let redactor = Redactor {
+            passwords: vec!["hunter2".to_string(), "7".to_string()],
+            cookies: Vec::new(),
+        };
+        assert_eq!(
+            redactor.redact("login failed for 'hunter2' (hunter2)"),
+            "login failed for '[redacted]' ([redacted])"
+        );
+        assert_eq!(redactor.redact("password: 7"), "password: [redacted]");
+        assert_eq!(
+            redactor.redact("HTTP Error 470 at hunter22"),
+            "HTTP Error 470 at hunter22"
+        );
+    }
+
+    #[test]
+    fn default_redactor_changes_nothing() {
+        let text = "ERROR: PREF=0 password hunter2";
+        assert_eq!(Redactor::default().redact(text), text);
+    }
+
+    #[tokio::test]
+    async fn private_files_are_owner_only_and_removed_on_drop() {
+        let file = PrivateFile::create(b"{\"http_headers\": {}}")
+            .await
+            .unwrap();
+        let path = file.path().to_path_buf();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"http_headers\": {}}"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode(&path) & 0o777, 0o600);
+            assert_eq!(mode(path.parent().unwrap()) & 0o777, 0o700);
+        }
+
+        drop(file);
+        assert!(!path.exists());
+        remove_private_dir().await;
+        assert!(!path.parent().unwrap().exists());
+    }
+}
@@ -0,0 +1,321 @@
+// src/download/failure.rs
+// Classifies yt-dlp failures from its stderr output.
+//
+// yt-dlp reports every failure with the same exit status, so the only way to tell a
+// private video from a rate limit is the wording of its `ERROR:` lines. The patterns
+// below cover the messages of the common extractors; anything unrecognised is left to
+// the caller's generic error.
+//
+// Classification runs on yt-dlp's raw output: redacting stored secrets first could alter
+// the very words matched here. Only the message the error carries is redacted.
+
+use crate::credentials::Redactor;
+use crate::error::PegasusError;
+
+/// Builds the typed error for a failure kind from yt-dlp's message.
+type MakeError = fn(String) -> PegasusError;
+
+/// Message fragments (lowercase) that identify each kind of failure, most specific first.
+///
+/// Order matters: e.g. "private video" must win over the generic "video unavailable"
+/// that YouTube prints alongside it.
+const PATTERNS: &[(&[&str], MakeError)] = &[
+    (&["unsupported url"], PegasusError::UnsupportedUrl),
+    (
+        &[
+            "http error 429",
+            "too many requests",
+            "rate-limited",
+            "rate limit",
+        ],
+        PegasusError::RateLimited,
+    ),
+    (
+        &["private video", "video is private", "this video is private"],
+        PegasusError::VideoPrivate,
+    ),
+    (
+        &[
+            "not available in your country",
+            "not made this video available in your country",
+            "geo restriction",
+            "geo-restrict",
+            "blocked it in your country",
+        ],
+        PegasusError::GeoBlocked,
+    ),
+    (
+        &[
+            "sign in to confirm your age",
+            "age-restricted",
+            "age restricted",
+            "login required",
+            "requires authentication",
+            "members-only",
+            "join this channel",
+            "sign in to confirm you",
+            "use --cookies",
+        ],
+        PegasusError::LoginRequired,
+    ),
+    (
+        &[
+            "video unavailable",
+            "video is not available",
+            "has been removed",
+            "no longer available",
+            "does not exist",
+            "http error 404",
+            "http error 410",
+        ],
+        PegasusError::VideoUnavailable,
+    ),
+    (
+        &[
+            "error merging",
+            "postprocessing: conversion failed",
+            "ffmpeg exited with code",
+        ],
+        PegasusError::MergeFailed,
+    ),
+    (
+        &[
+            "timed out",
+            "timeout",
+            "connection reset",
+            "connection refused",
+            "connection aborted",
+            "network is unreachable",
+            "temporary failure in name resolution",
+            "name or service not known",
+            "incompleteread",
+            "remote end closed connection",
+            "http error 500",
+            "http error 502",
+            "http error 503",
+            "http error 504",
+        ],
+        PegasusError::NetworkTimeout,
+    ),
+];
+
+/// Classifies a yt-dlp failure from the last lines it wrote to stderr.
+///
+/// `ERROR:` lines are checked first, newest first, since they carry the actual reason;
+/// the remaining lines are only consulted if none of them matches.
+///
+/// # Arguments
+///
+/// * `stderr` - The tail of yt-dlp's stderr output, unredacted.
+/// * `redactor` - Removes the credential's secrets from the message the error carries.
+///
+/// # Returns
+///
+/// The typed error carrying yt-dlp's message, or `None` if the failure is not recognised.
+pub fn classify(stderr: &[String], redactor: &Redactor) -> Option<PegasusError> {
+    let errors = stderr.iter().rev().filter(|line| line.contains("ERROR"));
+    let others = stderr.iter().rev().filter(|line| !line.contains("ERROR"));
+
+    errors.chain(others).find_map(|line| {
+        let lower = line.to_lowercase();
+        PATTERNS
+            .iter()
+            .find(|(fragments, _)| fragments.iter().any(|f| lower.contains(f)))
+            .map(|(_, make)| make(redactor.redact(&clean_message(line))))
+    })
+}
+
+/// Strips yt-dlp's `ERROR: [extractor] id: ` prefix, leaving the human-readable reason.
+fn clean_message(line: &str) -> String {
+    let message = line.trim();
+    let message = message.strip_prefix("ERROR:").unwrap_or(message).trim();
+    let message = match message.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((_, rest)) => rest.trim(),
+            None => message,
+        },
+        None => message,
+    };
+
+    // Extractors prefix the video ID, e.g. `dQw4w9WgXcQ: Private video`
+    match message.split_once(": ") {
+        Some((id, reason)) if !id.contains(' ') && !reason.is_empty() => reason.to_string(),
+        _ => message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify_lines(lines: &[&str], redactor: &Redactor) -> Option<PegasusError> {
+        let stderr: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        classify(&stderr, redactor)
+    }
+
+    fn code_of(lines: &[&str]) -> Option<&'static str> {
+        classify_lines(lines, &Redactor::default()).map(|e| e.code())
+    }
+
+    #[test]
+    fn real_yt_dlp_errors_are_classified() {
+        let cases = [
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video",
+                "video_private",
+            ),
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video is private",
+                "video_private",
+            ),
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video has been removed by the uploader",
+                "video_unavailable",
+            ),
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. The uploader has not made this video available in your country",
+                "geo_blocked",
+            ),
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm your age. This video may be inappropriate for some users. Use --cookies-from-browser or --cookies for the authentication.",
+                "login_required",
+            ),
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm you’re not a bot. Use --cookies-from-browser or --cookies for the authentication.",
+                "login_required",
+            ),
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Join this channel to get access to members-only content like this video, and other exclusive perks.",
+                "login_required",
+            ),
+            (
+                "ERROR: Unsupported URL: https://example.com/about",
+                "unsupported_url",
+            ),
+            (
+                "ERROR: [generic] Unable to download webpage: HTTP Error 429: Too Many Requests (caused by <HTTPError 429: Too Many Requests>)",
+                "rate_limited",
+            ),
+            (
+                "ERROR: [vimeo] 76979871: The web page does not exist",
+                "video_unavailable",
+            ),
+            (
+                "ERROR: unable to download video data: HTTP Error 404: Not Found",
+                "video_unavailable",
+            ),
+            (
+                "ERROR: unable to download video data: <urlopen error [Errno 110] Connection timed out>",
+                "network_error",
+            ),
+            (
+                "ERROR: [generic] Unable to download webpage: <urlopen error [Errno -3] Temporary failure in name resolution> (caused by URLError(gaierror(-3, 'Temporary failure in name resolution')))",
+                "network_error",
+            ),
+            (
+                "ERROR: [download] Got error: ('Connection aborted.', RemoteDisconnected('Remote end closed connection without response'))",
+                "network_error",
+            ),
+            (
+                "ERROR: [youtube] dQw4w9WgXcQ: Unable to download API page: HTTP Error 503: Service Unavailable",
+                "network_error",
+            ),
+            ("ERROR: Postprocessing: Conversion failed!", "merge_failed"),
+            (
+                "ERROR: Postprocessing: ffmpeg exited with code 1",
+                "merge_failed",
+            ),
+        ];
+        for (line, code) in cases {
+            assert_eq!(code_of(&[line]), Some(code), "{}", line);
+        }
+    }
+
+    #[test]
+    fn near_misses_are_not_classified() {
+        for line in [
+            "ERROR: unable to open for writing: [Errno 28] No space left on device",
+            "ERROR: [youtube] dQw4w9WgXcQ: Requested format is not available. Use --list-formats for a list of available formats",
+            "ERROR: ffmpeg not found. Please install or provide the path using --ffmpeg-location",
+            "ERROR: [youtube] dQw4w9WgXcQ: HTTP Error 403: Forbidden",
+            "WARNING: [youtube] Falling back to generic n function search",
+            "",
+        ] {
+            assert_eq!(code_of(&[line]), None, "{}", line);
+        }
+        assert_eq!(code_of(&[]), None);
+    }
+
+    #[test]
+    fn error_lines_win_over_other_output() {
+        // The newest ERROR line is the reason, even if a warning follows it
+        let stderr = [
+            "ERROR: [youtube] abc: HTTP Error 429: Too Many Requests",
+            "ERROR: [youtube] abc: Private video. Sign in if you've been granted access to this video",
+            "WARNING: [youtube] abc: HTTP Error 503: Service Unavailable, retrying",
+        ];
+        assert_eq!(code_of(&stderr), Some("video_private"));
+
+        // Other lines only count when no ERROR line is recognised
+        let stderr = [
+            "WARNING: [youtube] abc: HTTP Error 503: Service Unavailable, giving up",
+            "ERROR: [youtube] abc: Requested format is not available",
+        ];
+        assert_eq!(code_of(&stderr), Some("network_error"));
+    }
+
+    #[test]
+    fn messages_lose_the_extractor_prefix() {
+        let error = classify_lines(
+            &[
+                "ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video",
+            ],
+            &Redactor::default(),
+        );
+        assert!(matches!(
+            error,
+            Some(PegasusError::VideoPrivate(message))
+                if message == "Private video. Sign in if you've been granted access to this video"
+        ));
+        assert_eq!(
+            clean_message("ERROR: Unsupported URL: https://example.com/about"),
+            "Unsupported URL: https://example.com/about"
+        );
+    }
+
+    #[test]
+    fn secrets_are_redacted_after_classification() {
+        // A password that is part of the very words matched used to defeat classification
+        let redactor = Redactor::with_secrets(&["video", "0"], &[]);
+        let error = classify_lines(
+            &["ERROR: [youtube] abc: Private video. HTTP Error 500"],
+            &redactor,
+        );
+        assert!(matches!(
+            error,
+            Some(PegasusError::VideoPrivate(message))
+                if message == "Private [redacted]. HTTP Error 500"
+        ));
+
+        let error = classify_lines(
+            &["ERROR: [generic] abc: HTTP Error 500: Internal Server Error"],
+            &redactor,
+        );
+        assert!(matches!(
+            error,
+            Some(PegasusError::NetworkTimeout(message))
+                if message == "HTTP Error 500: Internal Server Error"
+        ));
+
+        let redactor = Redactor::with_secrets(&[], &[("SID", "s3cr3t")]);
+        let error = classify_lines(
+            &["ERROR: [youtube] abc: Cookie SID=s3cr3t rejected. Sign in to confirm your age"],
+            &redactor,
+        );
+        assert!(matches!(
+            error,
+            Some(PegasusError::LoginRequired(message))
+                if message == "Cookie SID=[redacted] rejected. Sign in to confirm your age"
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+// src/download/info.rs
+// Builds a typed preview of a URL from yt-dlp's JSON output.
+//
+// yt-dlp's JSON is large and loosely typed, and its shape varies between extractors. The
+// preview keeps the fields a user needs to decide what to download — and which exact
+// format to pick — with every field optional where extractors disagree.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::playlist::{self, PlaylistEntry};
+
+/// A curated summary of a video or playlist.
+#[derive(Debug, Serialize)]
+pub struct MediaInfo {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    /// Duration in seconds.
+    pub duration: Option<f64>,
+    pub upload_date: Option<NaiveDate>,
+    pub webpage_url: Option<String>,
+    /// The yt-dlp extractor that handles the URL, e.g. `Youtube`.
+    pub extractor: Option<String>,
+    pub thumbnails: Vec<Thumbnail>,
+    pub chapters: Vec<Chapter>,
+    /// Languages with uploaded subtitles.
+    pub subtitle_languages: Vec<String>,
+    /// Languages with automatically generated captions.
+    pub automatic_caption_languages: Vec<String>,
+    pub formats: Vec<Format>,
+    /// The entries, if the URL is a playlist or channel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playlist: Option<PlaylistSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Thumbnail {
+    pub url: String,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Chapter {
+    pub title: Option<String>,
+    /// Start of the chapter in seconds.
+    pub start_time: f64,
+    /// End of the chapter in seconds.
+    pub end_time: Option<f64>,
+}
+
+/// What kind of streams a format contains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatKind {
+    /// Video and audio in one file.
+    Muxed,
+    VideoOnly,
+    AudioOnly,
+}
+
+/// A single downloadable format.
+#[derive(Debug, Serialize)]
+pub struct Format {
+    pub format_id: String,
+    /// The value to submit as `processingOptions.format` to download this format.
+    /// Video-only formats are paired with the best audio.
+    pub selector: String,
+    pub kind: FormatKind,
+    pub ext: Option<String>,
+    /// e.g. `1920x1080` or `audio only`.
+    pub resolution: Option<String>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub fps: Option<f64>,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    /// Total bitrate in kbit/s.
+    pub bitrate_kbps: Option<f64>,
+    /// File size in bytes, exact or estimated.
+    pub filesize: Option<u64>,
+    /// `true` if `filesize` is an estimate.
+    pub filesize_is_estimate: bool,
+    /// yt-dlp's description, e.g. `1080p` or `medium`.
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlaylistSummary {
+    /// Number of entries with a downloadable URL.
+    pub entry_count: usize,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+/// Summarises yt-dlp's `--dump-single-json` output for a preview.
+///
+/// # Arguments
+///
+/// * `info` - The JSON returned by `get_video_info`.
+///
+/// # Returns
+///
+/// The typed summary. Fields the extractor did not provide are `None` or empty.
+pub fn summarize(info: &Value) -> MediaInfo {
+    let duration = info["duration"].as_f64();
+
+    let playlist = playlist::is_playlist(info).then(|| {
+        let listing = playlist::parse_playlist(info);
+        PlaylistSummary {
+            entry_count: listing.entries.len(),
+            entries: listing.entries,
+        }
+    });
+
+    MediaInfo {
+        id: string(&info["id"]),
+        title: string(&info["title"]),
+        uploader: string(&info["uploader"]).or_else(|| string(&info["channel"])),
+        duration,
+        upload_date: info["upload_date"]
+            .as_str()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok()),
+        webpage_url: string(&info["webpage_url"]),
+        extractor: extractor(info),
+        thumbnails: parse_thumbnails(info),
+        chapters: array(&info["chapters"])
+            .filter_map(|chapter| {
+                Some(Chapter {
+                    title: string(&chapter["title"]),
+                    start_time: chapter["start_time"].as_f64()?,
+                    end_time: chapter["end_time"].as_f64(),
+                })
+            })
+            .collect(),
+        subtitle_languages: languages(&info["subtitles"]),
+        automatic_caption_languages: languages(&info["automatic_captions"]),
+        formats: array(&info["formats"])
+            .filter_map(|format| parse_format(format, duration))
+            .collect(),
+        playlist,
+    }
+}
+
+fn parse_thumbnails(info: &Value) -> Vec<Thumbnail> {
+    let mut thumbnails: Vec<Thumbnail> = array(&info["thumbnails"])
+        .filter_map(|thumbnail| {
+            Some(Thumbnail {
+                url: string(&thumbnail["url"])?,
+                width: thumbnail["width"].as_u64(),
+                height: thumbnail["height"].as_u64(),
+            })
+        })
+        .collect();
+
+    // Single-thumbnail extractors only set `thumbnail`
+    if thumbnails.is_empty()
+        && let Some(url) = string(&info["thumbnail"])
+    {
+        thumbnails.push(Thumbnail {
+            url,
+            width: None,
+            height: None,
+        });
+    }
+    thumbnails
+}
+
+fn parse_format(format: &Value, duration: Option<f64>) -> Option<Format> {
+    let format_id = string(&format["format_id"])?;
+    let vcodec = string(&format["vcodec"]);
+    let acodec = string(&format["acodec"]);
+
+    // yt-dlp marks missing streams with "none"; unknown codecs are left out entirely
+    let has_video = vcodec.as_deref() != Some("none");
+    let has_audio = acodec.as_deref() != Some("none");
+    let kind = match (has_video, has_audio) {
+        (true, true) => FormatKind::Muxed,
+        (true, false) => FormatKind::VideoOnly,
+        (false, true) => FormatKind::AudioOnly,
+        // Storyboards and other image-only formats cannot be downloaded as media
+        (false, false) => return None,
+    };
+    let selector = match kind {
+        FormatKind::VideoOnly => format!("{}+bestaudio", format_id),
+        _ => format_id.clone(),
+    };
+
+    let bitrate_kbps = format["tbr"].as_f64();
+    let (filesize, filesize_is_estimate) = match (
+        format["filesize"].as_u64(),
+        format["filesize_approx"].as_u64(),
+    ) {
+        (Some(size), _) => (Some(size), false),
+        (None, Some(size)) => (Some(size), true),
+        // Estimate from the bitrate: kbit/s * seconds * 1000 / 8 bytes
+        (None, None) => match (bitrate_kbps, duration) {
+            (Some(kbps), Some(seconds)) => (Some((kbps * seconds * 125.0) as u64), true),
+            _ => (None, false),
+        },
+    };
+
+    Some(Format {
+        format_id,
+        selector,
+        kind,
+        ext: string(&format["ext"]),
+        resolution: string(&format["resolution"]),
+        width: format["width"].as_u64(),
+        height: format["height"].as_u64(),
+        fps: format["fps"].as_f64(),
+        vcodec: vcodec.filter(|_| has_video),
+        acodec: acodec.filter(|_| has_audio),
+        bitrate_kbps,
+        filesize,
+        filesize_is_estimate,
+        note: string(&format["format_note"]),
+    })
+}
+
+/// Returns the sorted language codes of a `subtitles` or `automatic_captions` map.
+fn languages(map: &Value) -> Vec<String> {
+    let mut languages: Vec<String> = map
+        .as_object()
+        .map(|map| {
+            map.keys()
+                .filter(|lang| lang.as_str() != "live_chat")
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    languages.sort();
+    languages
+}
+
+/// The name of the yt-dlp extractor that produced `info`, e.g. `Youtube`.
+pub fn extractor(info: &Value) -> Option<String> {
+    string(&info["extractor_key"]).or_else(|| string(&info["extractor"]))
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+fn array(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
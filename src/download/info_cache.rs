@@ -0,0 +1,169 @@
+// src/download/info_cache.rs
+// Short-lived cache of yt-dlp's info JSON, so one extraction serves the preview, the job's
+// info lookup and the download itself.
+//
+// Extraction is the slow part of a yt-dlp run and the part that hits the site, so
+// repeated extractions of the same URL both waste time and get us rate limited. Entries
+// expire after a short TTL because the format URLs inside them eventually do too.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::error::Result;
+
+/// Extracted info for a URL.
+#[derive(Clone, Debug)]
+pub struct FetchedInfo {
+    pub info: Arc<Value>,
+    /// How long the extraction took, i.e. how much every reuse saves.
+    pub extraction_time: Duration,
+    /// `true` if the info came from the cache.
+    pub cached: bool,
+}
+
+struct CachedInfo {
+    info: Arc<Value>,
+    extraction_time: Duration,
+    fetched_at: Instant,
+}
+
+/// Counters describing how much extraction work the cache has avoided.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct InfoCacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that ran yt-dlp.
+    pub misses: u64,
+    /// Extractions avoided, counting cache hits and downloads that loaded cached info.
+    pub extractions_saved: u64,
+    /// Total extraction time avoided, in seconds.
+    pub extraction_seconds_saved: f64,
+}
+
+/// Thread-safe cache of info JSON keyed by URL and extraction arguments.
+pub struct InfoCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, CachedInfo>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    extractions_saved: AtomicU64,
+    saved_micros: AtomicU64,
+}
+
+impl InfoCache {
+    /// Creates an empty cache. A zero `ttl` disables caching by URL; reuse within a job
+    /// is still counted.
+    pub fn new(ttl: Duration) -> Self {
+        InfoCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            extractions_saved: AtomicU64::new(0),
+            saved_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the info for a URL, extracting it with `fetch` unless a fresh entry exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to look up.
+    /// * `extra_args` - The extra yt-dlp arguments; they are part of the cache key.
+    /// * `fetch` - Runs the extraction on a cache miss.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the info, or the extraction's error (errors are not cached).
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        url: &str,
+        extra_args: &[String],
+        fetch: F,
+    ) -> Result<FetchedInfo>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Value>>,
+    {
+        let key = cache_key(url, extra_args);
+        if let Some(hit) = self.lookup(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            self.record_saved(hit.extraction_time);
+            debug!(url = %url, saved_ms = hit.extraction_time.as_millis() as u64, "Info cache hit");
+            return Ok(hit);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let started = Instant::now();
+        let info = Arc::new(fetch().await?);
+        let extraction_time = started.elapsed();
+
+        if !self.ttl.is_zero() {
+            let mut entries = self.entries.lock().unwrap();
+            // Drop expired entries so the map does not grow without bound
+            entries.retain(|_, entry| entry.fetched_at.elapsed() < self.ttl);
+            entries.insert(
+                key,
+                CachedInfo {
+                    info: info.clone(),
+                    extraction_time,
+                    fetched_at: Instant::now(),
+                },
+            );
+        }
+
+        Ok(FetchedInfo {
+            info,
+            extraction_time,
+            cached: false,
+        })
+    }
+
+    /// Records an extraction that was avoided, e.g. a download that loaded cached info
+    /// instead of extracting the URL again.
+    pub fn record_saved(&self, extraction_time: Duration) {
+        self.extractions_saved.fetch_add(1, Ordering::Relaxed);
+        self.saved_micros
+            .fetch_add(extraction_time.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the cache's counters.
+    pub fn stats(&self) -> InfoCacheStats {
+        InfoCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            extractions_saved: self.extractions_saved.load(Ordering::Relaxed),
+            extraction_seconds_saved: self.saved_micros.load(Ordering::Relaxed) as f64
+                / 1_000_000.0,
+        }
+    }
+
+    fn lookup(&self, key: &str) -> Option<FetchedInfo> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+        if entry.fetched_at.elapsed() >= self.ttl {
+            return None;
+        }
+        Some(FetchedInfo {
+            info: entry.info.clone(),
+            extraction_time: entry.extraction_time,
+            cached: true,
+        })
+    }
+}
+
+fn cache_key(url: &str, extra_args: &[String]) -> String {
+    let mut key = url.to_string();
+    for arg in extra_args {
+        key.push('\0');
+        key.push_str(arg);
+    }
+    key
+}
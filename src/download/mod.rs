@@ -1,43 +1,103 @@
 // src/download/mod.rs
 
+pub mod failure;
+pub mod info;
+pub mod info_cache;
+pub mod naming;
+pub mod options;
+pub mod playlist;
+pub mod sanitize;
+
+// yt-dlp runs as a `tokio::process` child whose output is read with async line readers,
+// so a long download never blocks a runtime worker thread. Every run is bounded: fetching
+// information has a hard timeout and downloads are killed once they stop making progress.
+
 use crate::api::handlers::send_progress_update;
+use crate::config::Config;
+use crate::credentials::{PrivateFile, YtDlpCredentials};
 use crate::error::{PegasusError, Result};
+use crate::jobs::cancel::{self, CancelToken};
+use crate::metrics;
+use naming::OutputTarget;
+use options::ProcessingOptions;
 use regex::Regex;
 use serde_json::Value;
-use std::io::{BufRead, BufReader};
 use std::path::Path;
-use std::process::{Command, Stdio}; // Added for piping stdout/stderr
-use tracing::{debug, error, info};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
 
 /// Gets video information from a URL using yt-dlp.
 ///
+/// Playlist and channel URLs produce a single JSON object with `_type` set to
+/// `"playlist"` and a flat list of `entries` instead of the details of every video.
+///
 /// # Arguments
 ///
 /// * `url` - The URL of the video to get information for.
+/// * `extra_args` - Additional yt-dlp arguments, e.g. a playlist item selection.
+/// * `yt_dlp` - Path of the yt-dlp binary.
+/// * `timeout` - How long yt-dlp may take before it is killed and the fetch fails.
+/// * `credentials` - The stored cookies or login for the URL's site, if any.
 ///
 /// # Returns
 ///
 /// A `Result` containing the video information as a JSON Value.
-async fn get_video_info(url: &str) -> Result<Value> {
-    info!(url = %url, "Getting video information");
-
-    // Use yt-dlp to get video information in JSON format
-    let output = Command::new("yt-dlp")
-        .arg("--dump-json")
+pub async fn get_video_info(
+    url: &str,
+    extra_args: &[String],
+    yt_dlp: &str,
+    timeout: Duration,
+    credentials: Option<&YtDlpCredentials>,
+) -> Result<Value> {
+    info!(url = %url, credentials = ?credentials.map(|c| &c.domain), "Getting video information");
+
+    // `--dump-single-json` keeps playlists to one JSON document instead of one per entry
+    let mut cmd = Command::new(yt_dlp);
+    cmd.arg("--dump-single-json")
+        .arg("--flat-playlist")
+        .args(extra_args)
+        .args(credentials.map(YtDlpCredentials::args).unwrap_or_default())
         .arg(url)
-        .output()
-        .map_err(|e| {
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+
+    // Dropping the timed-out future kills yt-dlp
+    let started = std::time::Instant::now();
+    let result = tokio::time::timeout(timeout, cmd.output()).await;
+    metrics::observe_process("yt-dlp", "info", started.elapsed());
+    let output = match result {
+        Ok(output) => output.map_err(|e| {
             error!(error = %e, "Failed to execute yt-dlp command");
-            PegasusError::ExternalCommandError(format!("Failed to execute yt-dlp command: {}", e))
-        })?;
+            PegasusError::from_spawn_error(yt_dlp, e)
+        })?,
+        Err(_) => {
+            error!(url = %url, timeout_secs = timeout.as_secs(), "yt-dlp timed out fetching video information");
+            return Err(PegasusError::YtDlpError(format!(
+                "Timed out after {}s fetching video information",
+                timeout.as_secs()
+            )));
+        }
+    };
 
     if !output.status.success() {
+        // yt-dlp may echo cookie values or a password back in its errors. The raw output
+        // is classified; only what is logged or returned is redacted
         let stderr = String::from_utf8_lossy(&output.stderr);
-        error!(stderr = %stderr, "yt-dlp command failed");
-        return Err(PegasusError::ExternalCommandError(format!(
-            "yt-dlp command failed: {}",
-            stderr
-        )));
+        let redactor = credentials
+            .map(YtDlpCredentials::redactor)
+            .unwrap_or_default();
+        let redacted = redactor.redact(&stderr);
+        error!(stderr = %redacted, "yt-dlp command failed");
+        let lines: Vec<String> = stderr.lines().map(str::to_string).collect();
+        return Err(failure::classify(&lines, &redactor).unwrap_or_else(|| {
+            PegasusError::ExternalCommandError(format!("yt-dlp command failed: {}", redacted))
+        }));
     }
 
     // Parse the JSON output
@@ -50,57 +110,41 @@ async fn get_video_info(url: &str) -> Result<Value> {
     Ok(video_info)
 }
 
-/// Sanitizes a filename to ensure it's valid for the filesystem.
-///
-/// # Arguments
-///
-/// * `filename` - The filename to sanitize.
-///
-/// # Returns
-///
-/// A sanitized filename string.
-fn sanitize_filename(filename: &str) -> String {
-    // Replace characters that are problematic in filenames
-    let mut sanitized = filename.replace(
-        &['/', '\\', ':', '*', '?', '"', '<', '>', '|', '\0'][..],
-        "_",
-    );
-
-    // Trim whitespace and limit length
-    sanitized = sanitized.trim().to_string();
-    if sanitized.len() > 200 {
-        sanitized = sanitized[..200].to_string();
-    }
-
-    // Ensure we have a valid filename
-    if sanitized.is_empty() {
-        sanitized = "unknown_title".to_string();
-    }
-
-    sanitized
-}
-
 /// Downloads media using the yt-dlp binary directly with progress updates.
 ///
 /// # Arguments
 ///
 /// * `url` - The URL of the media to download.
-/// * `output_dir` - The directory where the downloaded file should be saved.
-/// * `processing_options` - A slice of strings representing the desired processing options (e.g., "audio-only", "add-thumbnail").
+/// * `target` - Where the downloaded file should be saved, as claimed by
+///   `naming::claim`.
+/// * `processing_options` - The typed processing options selected for this job.
+/// * `video_info` - The video information returned by `get_video_info`. yt-dlp loads it
+///   with `--load-info-json` rather than extracting the URL again.
 /// * `job_id` - A unique identifier for this download job.
+/// * `cancel` - The job's cancellation token; cancelling kills the yt-dlp process group.
+/// * `config` - The application configuration, for the yt-dlp path and stall timeout.
+/// * `credentials` - The stored cookies or login for the URL's site, if any. Their
+///   secrets are removed from yt-dlp's output before it is logged or sent to clients.
 ///
 /// # Returns
 ///
 /// A `Result` containing the full path (`String`) to the primary downloaded file on success,
-/// or a `PegasusError` on failure.
+/// or a `PegasusError` on failure. A download that makes no progress for the configured
+/// stall timeout is killed and fails with `PegasusError::YtDlpError`.
+#[allow(clippy::too_many_arguments)]
 pub async fn download_video_with_progress(
     url: &str,
-    output_dir: &Path,
-    processing_options: &[String],
+    target: &OutputTarget,
+    processing_options: &ProcessingOptions,
+    video_info: &Value,
     job_id: &str,
+    cancel: &CancelToken,
+    config: &Config,
+    credentials: Option<&YtDlpCredentials>,
 ) -> Result<String> {
     // Log the start of the download process with job ID
-    info!(job_id = %job_id, url = %url, output_path = ?output_dir, options = ?processing_options, "Attempting to download using yt-dlp binary with progress tracking");
+    let output_dir = target.dir();
+    info!(job_id = %job_id, url = %url, output_path = %target.path.display(), options = ?processing_options, "Attempting to download using yt-dlp binary with progress tracking");
 
     // Ensure the output directory exists, creating it if necessary.
     if !output_dir.exists() {
@@ -111,14 +155,8 @@ pub async fn download_video_with_progress(
         })?;
     }
 
-    // First, get video information to use for naming and thumbnails
-    info!(job_id = %job_id, "Fetching video information");
-    send_progress_update(job_id, url, "info", 0.1, "Fetching video information...");
-
-    let video_info = get_video_info(url).await?;
-
     let video_title = video_info["title"].as_str().unwrap_or("unknown_title");
-    let safe_title = sanitize_filename(video_title);
+    let safe_title = target.stem();
 
     send_progress_update(
         job_id,
@@ -128,248 +166,387 @@ pub async fn download_video_with_progress(
         &format!("Found video: {}", video_title),
     );
 
-    let is_audio_only = processing_options.contains(&"audio-only".to_string());
-    let add_thumbnail = processing_options.contains(&"add-thumbnail".to_string());
-
-    let output_path = if is_audio_only {
-        // Download audio only
-        info!(job_id = %job_id, "Downloading audio only");
-        let output_filename = format!("{}.mp3", safe_title);
-        let output_path = output_dir.join(&output_filename);
+    // yt-dlp picks the final extension itself (e.g. after audio extraction), so the
+    // template leaves it open; the target's path was predicted from the options.
+    let output_template = target.yt_dlp_template();
+    let output_path = &target.path;
 
-        send_progress_update(
-            job_id,
-            url,
-            "downloading",
-            0.3,
-            "Starting audio download...",
-        );
+    if processing_options.audio_only {
+        info!(job_id = %job_id, format = processing_options.audio_format.extension(), "Downloading audio only");
+    } else {
+        info!(job_id = %job_id, container = processing_options.container.extension(), "Downloading full video");
+    }
 
-        // Build yt-dlp command for audio extraction with progress
-        let mut cmd = Command::new("yt-dlp");
-        cmd.arg("-o \"%(title)s.%(ext)s\"")
-            .arg("--extract-audio")
-            .arg("--audio-format")
-            .arg("mp3")
-            .arg("--audio-quality")
-            .arg("0") // Best quality
-            .arg("--embed-metadata")
-            .arg("--newline") // Important for progress parsing
-            .arg("--progress");
-
-        // Add thumbnail embedding if requested
-        if add_thumbnail {
-            info!(job_id = %job_id, "Adding thumbnail embedding to download");
-            cmd.arg("--embed-thumbnail");
-        }
+    send_progress_update(
+        job_id,
+        url,
+        "downloading",
+        0.3,
+        if processing_options.audio_only {
+            "Starting audio download..."
+        } else {
+            "Starting video download..."
+        },
+    );
 
-        // Execute the command with stdout/stderr capture for progress tracking
-        cmd.arg("--output")
-            .arg(output_path.to_string_lossy().to_string())
-            .arg(url)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+    // Hand yt-dlp the info we already extracted, so it downloads straight away instead of
+    // extracting the URL a second time. The info carries the cookies and headers it was
+    // extracted with, so it goes in the private directory rather than the output
+    // directory, and is removed whichever way this function returns
+    let info_json = serde_json::to_vec(video_info).map_err(|e| {
+        PegasusError::Unknown(format!("Failed to serialize video information: {}", e))
+    })?;
+    let info_file = PrivateFile::create(&info_json).await?;
+
+    // Build yt-dlp command from the typed options, with progress output enabled
+    let mut cmd = Command::new(&config.binaries.yt_dlp);
+    cmd.args(processing_options.yt_dlp_args())
+        .arg("--no-playlist")
+        .arg("--newline") // Important for progress parsing
+        .arg("--progress")
+        .args(credentials.map(YtDlpCredentials::args).unwrap_or_default());
+    // The collision policy has already decided an existing file may be replaced
+    if target.overwrite {
+        cmd.arg("--force-overwrites");
+    }
 
-        // Start the command
-        let mut child = cmd.spawn().map_err(|e| {
+    // Execute the command with stdout/stderr capture for progress tracking
+    cmd.arg("--output")
+        .arg(output_template)
+        .arg("--load-info-json")
+        .arg(info_file.path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    // Run yt-dlp in its own process group so cancelling also stops the ffmpeg it spawns
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    // Start the command
+    let started = Instant::now();
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
             error!(error = %e, "Failed to execute yt-dlp command");
-            PegasusError::ExternalCommandError(format!("Failed to execute yt-dlp command: {}", e))
-        })?;
-
-        // Track progress from stdout
-        if let Some(stdout) = child.stdout.take() {
-            let job_id = job_id.to_string();
-            let url = url.to_string();
-            tokio::spawn(async move {
-                let reader = BufReader::new(stdout);
-                parse_yt_dlp_progress(reader, &job_id, &url);
-            });
+            return Err(PegasusError::from_spawn_error(&config.binaries.yt_dlp, e));
         }
+    };
+    let pid = child.id();
+    if let Some(pid) = pid {
+        cancel.attach_process(pid);
+    }
 
-        // Track errors from stderr
-        if let Some(stderr) = child.stderr.take() {
-            let job_id = job_id.to_string();
-            let url = url.to_string();
-            tokio::spawn(async move {
-                let reader = BufReader::new(stderr);
-                for line in reader
-                    .lines()
-                    .map_while(|r: std::result::Result<String, std::io::Error>| r.ok())
-                {
-                    debug!("yt-dlp stderr: {}", line);
-                    // Only send error messages to the client if they seem important
-                    if line.contains("ERROR") {
-                        send_progress_update(&job_id, &url, "warning", 0.0, &line);
+    // Track errors from stderr, keeping the last few raw lines to explain a failure.
+    // What is logged or sent to clients is redacted
+    let redactor = credentials
+        .map(YtDlpCredentials::redactor)
+        .unwrap_or_default();
+    // Every stderr line also counts as a sign of life for the stall detector below, as
+    // yt-dlp reports retries and slow fragments there
+    let stderr_activity = Arc::new(Notify::new());
+    let stderr_task = child.stderr.take().map(|stderr| {
+        let job_id = job_id.to_string();
+        let url = url.to_string();
+        let redactor = redactor.clone();
+        let activity = stderr_activity.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            let mut tail: Vec<String> = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                activity.notify_one();
+                let redacted = redactor.redact(&line);
+                debug!("yt-dlp stderr: {}", redacted);
+                // Only send error messages to the client if they seem important
+                if line.contains("ERROR") {
+                    send_progress_update(&job_id, &url, "warning", 0.0, &redacted);
+                }
+                tail.push(line);
+                if tail.len() > 20 {
+                    tail.remove(0);
+                }
+            }
+            tail
+        })
+    });
+
+    // Track progress from stdout. The stall deadline moves forward whenever the download
+    // advances or yt-dlp writes to stderr, so a connection that hangs (or keeps repeating
+    // the same percentage) is caught even though yt-dlp itself never gives up. Once
+    // yt-dlp starts post-processing, ffmpeg is working on local files and may stay quiet
+    // for a long time, so the deadline is lifted until another download starts
+    let stall_timeout = config.timeouts.download_stall();
+    let mut stalled = false;
+    if let Some(stdout) = child.stdout.take() {
+        let mut tracker = ProgressTracker::new(job_id, url);
+        let mut lines = BufReader::new(stdout).lines();
+        let mut deadline = Some(Instant::now() + stall_timeout);
+        loop {
+            let stall = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        let advanced = tracker.handle_line(&line);
+                        if is_post_processing(&line) {
+                            if deadline.take().is_some() {
+                                debug!(job_id = %job_id, step = %line, "Post-processing, stall detection paused");
+                            }
+                        } else if advanced {
+                            deadline = Some(Instant::now() + stall_timeout);
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!(job_id = %job_id, error = %e, "Failed to read yt-dlp output");
+                        break;
+                    }
+                },
+                _ = stderr_activity.notified() => {
+                    if deadline.is_some() {
+                        deadline = Some(Instant::now() + stall_timeout);
                     }
                 }
-            });
-        }
-
-        // Wait for the command to complete
-        let status = child.wait().map_err(|e| {
-            error!(error = %e, "Failed to wait for yt-dlp command");
-            PegasusError::ExternalCommandError(format!("Failed to wait for yt-dlp command: {}", e))
-        })?;
-
-        if !status.success() {
-            error!(job_id = %job_id, "yt-dlp command failed with status: {}", status);
-            return Err(PegasusError::ExternalCommandError(format!(
-                "yt-dlp command failed with status: {}",
-                status
-            )));
+                _ = stall => {
+                    stalled = true;
+                    break;
+                }
+            }
         }
+    }
 
-        output_path
-    } else {
-        // Download full video
-        info!(job_id = %job_id, "Downloading full video");
-        let output_filename = format!("{}.mp4", safe_title);
-        let output_path = output_dir.join(&output_filename);
-
-        send_progress_update(
-            job_id,
-            url,
-            "downloading",
-            0.3,
-            "Starting video download...",
-        );
-
-        // Build yt-dlp command for video download with progress
-        let mut cmd = Command::new("yt-dlp");
-        cmd.arg("-f")
-            .arg("bestvideo[ext=mp4]+bestaudio[ext=m4a]/best[ext=mp4]/best")
-            .arg("--merge-output-format")
-            .arg("mp4")
-            .arg("--newline") // Important for progress parsing
-            .arg("--progress");
-
-        // Add thumbnail embedding if requested
-        if add_thumbnail {
-            info!(job_id = %job_id, "Adding thumbnail embedding to download");
-            cmd.arg("--embed-thumbnail");
+    if stalled {
+        warn!(job_id = %job_id, stall_minutes = config.timeouts.download_stall_minutes, "Download stalled, killing yt-dlp");
+        match pid {
+            Some(pid) => cancel::kill_process_group(pid),
+            None => {
+                let _ = child.start_kill();
+            }
         }
+    }
 
-        // Execute the command with stdout/stderr capture for progress tracking
-        cmd.arg("--output")
-            .arg(output_path.to_string_lossy().to_string())
-            .arg(url)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-
-        // Start the command
-        let mut child = cmd.spawn().map_err(|e| {
-            error!(error = %e, "Failed to execute yt-dlp command");
-            PegasusError::ExternalCommandError(format!("Failed to execute yt-dlp command: {}", e))
-        })?;
-
-        // Track progress from stdout
-        if let Some(stdout) = child.stdout.take() {
-            let job_id = job_id.to_string();
-            let url = url.to_string();
-            tokio::spawn(async move {
-                let reader = BufReader::new(stdout);
-                parse_yt_dlp_progress(reader, &job_id, &url);
-            });
-        }
+    // Wait for the command to complete
+    let status = child.wait().await;
+    let elapsed = started.elapsed();
+    metrics::observe_process("yt-dlp", "download", elapsed);
+    cancel.detach_process();
+    drop(info_file);
+    let status = status.map_err(|e| {
+        error!(error = %e, "Failed to wait for yt-dlp command");
+        PegasusError::ExternalCommandError(format!("Failed to wait for yt-dlp command: {}", e))
+    })?;
 
-        // Track errors from stderr
-        if let Some(stderr) = child.stderr.take() {
-            let job_id = job_id.to_string();
-            let url = url.to_string();
-            tokio::spawn(async move {
-                let reader = BufReader::new(stderr);
-                for line in reader
-                    .lines()
-                    .map_while(|r: std::result::Result<String, std::io::Error>| r.ok())
-                {
-                    debug!("yt-dlp stderr: {}", line);
-                    // Only send error messages to the client if they seem important
-                    if line.contains("ERROR") {
-                        send_progress_update(&job_id, &url, "warning", 0.0, &line);
-                    }
-                }
-            });
-        }
+    let stderr_tail = match stderr_task {
+        Some(task) => task.await.unwrap_or_default(),
+        None => Vec::new(),
+    };
 
-        // Wait for the command to complete
-        let status = child.wait().map_err(|e| {
-            error!(error = %e, "Failed to wait for yt-dlp command");
-            PegasusError::ExternalCommandError(format!("Failed to wait for yt-dlp command: {}", e))
-        })?;
+    if cancel.is_cancelled() {
+        info!(job_id = %job_id, "Download cancelled, cleaning up partial files");
+        remove_partial_files(output_dir, &safe_title).await;
+        return Err(PegasusError::Cancelled);
+    }
 
-        if !status.success() {
-            error!(job_id = %job_id, "yt-dlp command failed with status: {}", status);
-            return Err(PegasusError::ExternalCommandError(format!(
-                "yt-dlp command failed with status: {}",
-                status
-            )));
-        }
+    if stalled {
+        remove_partial_files(output_dir, &safe_title).await;
+        return Err(PegasusError::YtDlpError(format!(
+            "Download stalled: no progress for {} minutes",
+            config.timeouts.download_stall_minutes
+        )));
+    }
 
-        output_path
-    };
+    if !status.success() {
+        let reason = redactor.redact(stderr_tail.last().map_or("", String::as_str));
+        error!(job_id = %job_id, status = %status, stderr = %redactor.redact(&stderr_tail.join("\n")), "yt-dlp command failed");
+        return Err(
+            failure::classify(&stderr_tail, &redactor).unwrap_or_else(|| {
+                PegasusError::ExternalCommandError(format!(
+                    "yt-dlp command failed with status {}: {}",
+                    status, reason
+                ))
+            }),
+        );
+    }
 
     info!(job_id = %job_id, file_path = %output_path.display(), "Download successful");
+    match tokio::fs::metadata(&output_path).await {
+        Ok(metadata) => metrics::record_download(
+            info::extractor(video_info).as_deref(),
+            metadata.len(),
+            elapsed,
+        ),
+        Err(e) => {
+            debug!(error = %e, path = %output_path.display(), "Failed to read downloaded file size")
+        }
+    }
     // Send final progress update to indicate completion
     send_progress_update(
         job_id,
         url,
-        "completed",
+        "downloaded",
         1.0,
-        &format!(
-            "Download and conversion complete: {}",
-            output_path.display()
-        ),
+        &format!("Download complete: {}", output_path.display()),
     );
     Ok(output_path.display().to_string())
 }
 
-/// Parse yt-dlp progress output and send progress updates
+/// Removes the intermediate files yt-dlp leaves behind for an interrupted download.
 ///
-/// This function parses the output from yt-dlp and extracts progress information,
-/// then sends updates to connected clients via WebSockets.
-fn parse_yt_dlp_progress<R: BufRead>(reader: R, job_id: &str, url: &str) {
-    // Regex patterns for progress extraction
-    let download_regex = Regex::new(r"\[download\]\s+([\d.]+)%").unwrap();
-    let eta_regex = Regex::new(r"ETA\s+([\d:]+)").unwrap();
-    let speed_regex = Regex::new(r"at\s+([\d.]+[KMGT]?iB/s)").unwrap();
-
-    for line in reader
-        .lines()
-        .map_while(|r: std::result::Result<String, std::io::Error>| r.ok())
-    {
-        // Extract download percentage
-        let mut progress = 0.0;
-        let mut status = "processing".to_string(); // Default status
-        let mut message_str = line.clone(); // Default message is the line itself
-
-        if let Some(caps) = download_regex.captures(&line) {
-            if let Some(progress_str) = caps.get(1) {
-                if let Ok(progress_val) = progress_str.as_str().parse::<f32>() {
-                    progress = progress_val / 100.0;
-                    status = "downloading".to_string();
-                    // Try to extract ETA and speed if progress is found
-                    let eta = eta_regex
-                        .captures(&line)
-                        .and_then(|c| c.get(1))
-                        .map_or("N/A", |m| m.as_str());
-                    let speed = speed_regex
-                        .captures(&line)
-                        .and_then(|c| c.get(1))
-                        .map_or("N/A", |m| m.as_str());
-                    message_str = format!(
-                        "Progress: {:.1}%, ETA: {}, Speed: {}",
-                        progress * 100.0,
-                        eta,
-                        speed
-                    );
-                }
-            }
+/// Only files named after `stem` that look like yt-dlp partial or temporary files are
+/// removed (`.part`, `.ytdl`, `.temp.*` and per-format `.fNNN.*` downloads), so an
+/// existing completed file with the same name is left alone.
+async fn remove_partial_files(output_dir: &Path, stem: &str) {
+    let partial_regex =
+        Regex::new(r"(\.part(-Frag\d+)?|\.ytdl|\.temp\.[^.]+|\.f[\d-]+\.[^.]+(\.part)?)$").unwrap();
+
+    let mut entries = match tokio::fs::read_dir(output_dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!(error = %e, path = ?output_dir, "Failed to read output directory for cleanup");
+            return;
+        }
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(rest) = name.strip_prefix(stem) else {
+            continue;
+        };
+        if !rest.starts_with('.') || !partial_regex.is_match(rest) {
+            continue;
+        }
+        match tokio::fs::remove_file(entry.path()).await {
+            Ok(()) => debug!(file = %name, "Removed partial download file"),
+            Err(e) => error!(error = %e, file = %name, "Failed to remove partial download file"),
+        }
+    }
+}
+
+/// yt-dlp post-processors that run ffmpeg on the downloaded files.
+const POST_PROCESSORS: &[&str] = &[
+    "[Merger]",
+    "[ExtractAudio]",
+    "[EmbedSubtitle]",
+    "[EmbedThumbnail]",
+    "[Metadata]",
+    "[VideoConvertor]",
+    "[VideoRemuxer]",
+    "[FixupM3u8]",
+    "[FixupM4a]",
+    "[FixupStretched]",
+    "[FixupDuplicateMoov]",
+    "[FixupTimestamp]",
+    "[ModifyChapters]",
+    "[SplitChapters]",
+];
+
+/// Returns `true` if a line of yt-dlp output announces a post-processing step.
+fn is_post_processing(line: &str) -> bool {
+    POST_PROCESSORS
+        .iter()
+        .any(|prefix| line.trim_start().starts_with(prefix))
+}
+
+/// Parses yt-dlp `--newline` progress output and sends progress updates
+///
+/// Progress lines are turned into `downloading` updates for connected clients. The
+/// tracker also remembers the last percentage so the caller can tell a download that is
+/// moving from one that is stuck.
+struct ProgressTracker<'a> {
+    job_id: &'a str,
+    url: &'a str,
+    download_regex: Regex,
+    eta_regex: Regex,
+    speed_regex: Regex,
+    last_progress: Option<f32>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    fn new(job_id: &'a str, url: &'a str) -> Self {
+        // Regex patterns for progress extraction
+        ProgressTracker {
+            job_id,
+            url,
+            download_regex: Regex::new(r"\[download\]\s+([\d.]+)%").unwrap(),
+            eta_regex: Regex::new(r"ETA\s+([\d:]+)").unwrap(),
+            speed_regex: Regex::new(r"at\s+([\d.]+[KMGT]?iB/s)").unwrap(),
+            last_progress: None,
         }
+    }
 
-        // Send update if status changed to 'downloading' or if it's a different important message
-        // Avoid sending every single line from yt-dlp if it's not a progress line.
-        if status == "downloading" {
-            send_progress_update(job_id, url, &status, progress, &message_str);
+    /// Handles one line of yt-dlp output.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the line shows the download moving forward: a higher percentage, or any
+    /// other output such as a new file starting or a post-processing step.
+    fn handle_line(&mut self, line: &str) -> bool {
+        // Extract download percentage
+        let Some(progress) = self
+            .download_regex
+            .captures(line)
+            .and_then(|caps| caps.get(1))
+            .and_then(|m| m.as_str().parse::<f32>().ok())
+            .map(|value| value / 100.0)
+        else {
+            // Anything else starts a new phase, which begins again from 0%
+            self.last_progress = None;
+            return true;
+        };
+
+        // Try to extract ETA and speed if progress is found
+        let eta = self
+            .eta_regex
+            .captures(line)
+            .and_then(|c| c.get(1))
+            .map_or("N/A", |m| m.as_str());
+        let speed = self
+            .speed_regex
+            .captures(line)
+            .and_then(|c| c.get(1))
+            .map_or("N/A", |m| m.as_str());
+        let message = format!(
+            "Progress: {:.1}%, ETA: {}, Speed: {}",
+            progress * 100.0,
+            eta,
+            speed
+        );
+        send_progress_update(self.job_id, self.url, "downloading", progress, &message);
+
+        let advanced = self.last_progress.is_none_or(|last| progress > last);
+        self.last_progress = Some(progress);
+        advanced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_processing_steps_are_recognised() {
+        for line in [
+            "[Merger] Merging formats into \"Title.mp4\"",
+            "[ExtractAudio] Destination: Title.mp3",
+            "[EmbedSubtitle] Embedding subtitles in \"Title.mp4\"",
+            "[EmbedThumbnail] ffmpeg: Adding thumbnail to \"Title.mp4\"",
+            "[FixupM3u8] Fixing MPEG-TS in MP4 container of \"Title.mp4\"",
+        ] {
+            assert!(is_post_processing(line), "{}", line);
+        }
+        for line in [
+            "[download]  42.0% of 10.00MiB at  1.00MiB/s ETA 00:06",
+            "[download] Destination: Title.f137.mp4",
+            "[youtube] abc: Downloading webpage",
+            "[info] abc: Downloading 1 format(s): 137+140",
+            "Merger finished",
+        ] {
+            assert!(!is_post_processing(line), "{}", line);
         }
     }
 }
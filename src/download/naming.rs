@@ -0,0 +1,857 @@
+// src/download/naming.rs
+// Builds the paths downloads are saved under from output templates.
+//
+// A template is a relative path with tokens filled in from yt-dlp's info JSON, e.g.
+// `{uploader}/{upload_date:%Y}/{title} [{id}].{ext}`:
+//
+// * `{field}` is any top-level field of the info JSON, or one of `ext` (the extension of
+//   the finished file), `job_id`, `playlist`, `playlist_id`, `playlist_index` and
+//   `playlist_count` for entries of a playlist.
+// * `{field:spec}` formats the value: `%Y-%m` style specs format dates (`upload_date` and
+//   Unix timestamps), digits pad numbers with zeros (`{playlist_index:03}`) and `.N` cuts
+//   text to N characters (`{title:.80}`).
+// * `{uploader,channel}` uses the first field that has a value, `{uploader|Unknown}`
+//   falls back to fixed text, and a token without a value becomes `NA`.
+// * `<...>` is left out entirely unless every token inside it has a value, e.g.
+//   `<{playlist_index:03} - >{title}.{ext}`. `<` and `>` cannot appear in filenames on
+//   every filesystem, so they are never needed as literal text.
+// * `{{` and `}}` are literal braces.
+//
+// Token values never create directories: separators in them are replaced, so only the
+// template's own `/` separate path components.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::playlist::PlaylistPosition;
+use super::sanitize::SanitizePolicy;
+use crate::error::{PegasusError, Result};
+
+/// The template used when neither the config nor the job sets one. Playlist entries go
+/// into a directory named after their playlist.
+pub const DEFAULT_TEMPLATE: &str = "<{playlist}/>{title|unknown_title}.{ext}";
+
+/// The text used for a token that has no value and no default.
+const MISSING: &str = "NA";
+
+/// What happens when the file a template names already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    /// Keep the existing file and skip the download.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Add ` (1)`, ` (2)`, ... to the name until it is free.
+    #[default]
+    Counter,
+    /// Add the video's ID, ` [id]`, to the name, then a counter if that is taken too.
+    Id,
+}
+
+impl std::str::FromStr for CollisionPolicy {
+    type Err = PegasusError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(CollisionPolicy::Skip),
+            "overwrite" => Ok(CollisionPolicy::Overwrite),
+            "counter" => Ok(CollisionPolicy::Counter),
+            "id" => Ok(CollisionPolicy::Id),
+            other => Err(PegasusError::ValidationError(format!(
+                "Unknown collision policy: {:?} (expected skip, overwrite, counter or id)",
+                other
+            ))),
+        }
+    }
+}
+
+/// A job's overrides of the configured output template and collision policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct NamingOptions {
+    pub template: Option<String>,
+    pub collision: Option<CollisionPolicy>,
+}
+
+impl NamingOptions {
+    /// Checks that the template, if any, parses.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(template) = &self.template {
+            OutputTemplate::parse(template)?;
+        }
+        Ok(())
+    }
+}
+
+/// A parsed output template.
+#[derive(Clone, Debug)]
+pub struct OutputTemplate {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Token(Token),
+    /// Rendered only if every token inside has a value.
+    Optional(Vec<Segment>),
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    /// Tried in order; the first with a value is used.
+    fields: Vec<Field>,
+    default: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Field {
+    name: String,
+    format: Format,
+}
+
+#[derive(Clone, Debug)]
+enum Format {
+    Plain,
+    /// A strftime pattern applied to a `YYYYMMDD` date or a Unix timestamp.
+    Date(String),
+    /// Zero-pad numbers to this width.
+    Pad(usize),
+    /// Keep at most this many characters.
+    Truncate(usize),
+}
+
+/// The values a template is rendered with, besides the info JSON.
+pub struct TemplateContext<'a> {
+    /// The extension of the finished file, without the dot.
+    pub ext: &'a str,
+    pub job_id: &'a str,
+    /// The job's position in its playlist, if it is a playlist entry.
+    pub playlist: Option<&'a PlaylistPosition>,
+    /// How each path component is made a valid name.
+    pub sanitize: &'a SanitizePolicy,
+}
+
+impl OutputTemplate {
+    /// Parses a template.
+    ///
+    /// A template that does not end in `.{ext}` gets it appended, so the finished file
+    /// always has the extension the download produces.
+    ///
+    /// # Returns
+    ///
+    /// The parsed template, or a `ValidationError` describing the first problem found.
+    pub fn parse(template: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            PegasusError::ValidationError(format!(
+                "Invalid output template {:?}: {}",
+                template, reason
+            ))
+        };
+
+        let trimmed = template.trim();
+        if trimmed.is_empty() {
+            return Err(invalid("it is empty"));
+        }
+        if trimmed.starts_with('/') || trimmed.starts_with('\\') {
+            return Err(invalid("it must be a relative path"));
+        }
+        if trimmed
+            .split(['/', '\\'])
+            .any(|component| matches!(component.trim(), "." | ".."))
+        {
+            return Err(invalid("it must not contain . or .. components"));
+        }
+        let source = if trimmed.ends_with(".{ext}") {
+            trimmed.to_string()
+        } else {
+            format!("{}.{{ext}}", trimmed)
+        };
+
+        let mut segments = Vec::new();
+        let mut optional: Option<Vec<Segment>> = None;
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            let segment = match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    Segment::Literal("{".to_string())
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    Segment::Literal("}".to_string())
+                }
+                '{' => {
+                    let mut body = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => return Err(invalid("unclosed {")),
+                            Some(c) => body.push(c),
+                        }
+                    }
+                    Segment::Token(parse_token(&body).map_err(|reason| invalid(&reason))?)
+                }
+                '}' => return Err(invalid("unmatched }")),
+                '<' => {
+                    if optional.is_some() {
+                        return Err(invalid("optional sections cannot be nested"));
+                    }
+                    optional = Some(Vec::new());
+                    continue;
+                }
+                '>' => {
+                    let inner = optional.take().ok_or_else(|| invalid("unmatched >"))?;
+                    segments.push(Segment::Optional(inner));
+                    continue;
+                }
+                c => Segment::Literal(c.to_string()),
+            };
+            match optional.as_mut() {
+                Some(inner) => inner.push(segment),
+                None => segments.push(segment),
+            }
+        }
+        if optional.is_some() {
+            return Err(invalid("unclosed <"));
+        }
+
+        Ok(OutputTemplate { segments })
+    }
+
+    /// Fills in the template for a video.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - yt-dlp's info JSON for the video.
+    /// * `context` - The extension, job ID and playlist position.
+    ///
+    /// # Returns
+    ///
+    /// A relative path whose components are all valid filenames, ending in `.<ext>`.
+    pub fn render(&self, info: &Value, context: &TemplateContext) -> PathBuf {
+        let lookup = |name: &str| lookup(info, context, name);
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => rendered.push_str(text),
+                Segment::Token(token) => {
+                    rendered.push_str(&token.render(&lookup).unwrap_or_else(|| MISSING.into()))
+                }
+                Segment::Optional(inner) => {
+                    let parts: Option<Vec<String>> = inner
+                        .iter()
+                        .map(|segment| match segment {
+                            Segment::Literal(text) => Some(text.clone()),
+                            Segment::Token(token) => token.render(&lookup),
+                            Segment::Optional(_) => None,
+                        })
+                        .collect();
+                    if let Some(parts) = parts {
+                        rendered.push_str(&parts.concat());
+                    }
+                }
+            }
+        }
+
+        // Every component is made a valid name; the extension is never shortened away
+        let mut components: Vec<&str> = rendered
+            .split('/')
+            .map(str::trim)
+            .filter(|component| !component.is_empty())
+            .collect();
+        let file_name = components.pop().unwrap_or_default();
+        let suffix = format!(".{}", context.ext);
+        let stem = file_name.strip_suffix(&suffix).unwrap_or(file_name);
+
+        let sanitize = context.sanitize;
+        let mut path: PathBuf = components
+            .iter()
+            .map(|component| sanitize.sanitize(component))
+            .collect();
+        path.push(sanitize.file_name(stem, &suffix));
+        path
+    }
+}
+
+impl Token {
+    fn render(&self, lookup: &impl Fn(&str) -> Option<String>) -> Option<String> {
+        self.fields
+            .iter()
+            .find_map(|field| lookup(&field.name).and_then(|value| field.format.apply(&value)))
+            .or_else(|| self.default.clone())
+            // Only the template's own separators create directories
+            .map(|value| value.replace(['/', '\\'], "_"))
+    }
+}
+
+impl Format {
+    fn apply(&self, value: &str) -> Option<String> {
+        match self {
+            Format::Plain => Some(value.to_string()),
+            Format::Date(pattern) => {
+                let date = match NaiveDate::parse_from_str(value, "%Y%m%d") {
+                    Ok(date) => date.and_hms_opt(0, 0, 0)?,
+                    Err(_) => {
+                        DateTime::from_timestamp(value.parse::<f64>().ok()? as i64, 0)?.naive_utc()
+                    }
+                };
+                let mut formatted = String::new();
+                write!(formatted, "{}", date.format(pattern)).ok()?;
+                Some(formatted)
+            }
+            Format::Pad(width) => Some(match value.parse::<i64>() {
+                Ok(number) => format!("{:0width$}", number, width = *width),
+                Err(_) => value.to_string(),
+            }),
+            Format::Truncate(max) => Some(value.graphemes(true).take(*max).collect()),
+        }
+    }
+}
+
+// Parses the inside of a `{...}` token
+fn parse_token(body: &str) -> std::result::Result<Token, String> {
+    let (fields, default) = match body.split_once('|') {
+        Some((fields, default)) => (fields, Some(default.to_string())),
+        None => (body, None),
+    };
+
+    let fields = fields
+        .split(',')
+        .map(|field| {
+            let (name, spec) = match field.split_once(':') {
+                Some((name, spec)) => (name.trim(), Some(spec)),
+                None => (field.trim(), None),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("{:?} is not a field name", name));
+            }
+            Ok(Field {
+                name: name.to_string(),
+                format: spec.map(parse_format).transpose()?.unwrap_or(Format::Plain),
+            })
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok(Token { fields, default })
+}
+
+// Parses the format spec after a field's `:`
+fn parse_format(spec: &str) -> std::result::Result<Format, String> {
+    if spec.contains('%') {
+        if StrftimeItems::new(spec).any(|item| matches!(item, Item::Error)) {
+            return Err(format!("{:?} is not a valid date format", spec));
+        }
+        return Ok(Format::Date(spec.to_string()));
+    }
+    if let Some(max) = spec.strip_prefix('.') {
+        return max
+            .parse()
+            .map(Format::Truncate)
+            .map_err(|_| format!("{:?} is not a valid length", spec));
+    }
+    spec.parse()
+        .map(Format::Pad)
+        .map_err(|_| format!("{:?} is not a known format", spec))
+}
+
+// Finds a token's value, preferring the job's own values over the info JSON
+fn lookup(info: &Value, context: &TemplateContext, name: &str) -> Option<String> {
+    let playlist = context.playlist;
+    let value = match name {
+        "ext" => Some(context.ext.to_string()),
+        "job_id" => Some(context.job_id.to_string()),
+        "playlist" | "playlist_title" if playlist.is_some() => {
+            playlist.and_then(|p| p.title.clone())
+        }
+        "playlist_id" if playlist.is_some() => playlist.and_then(|p| p.id.clone()),
+        "playlist_index" if playlist.is_some() => playlist.map(|p| p.index.to_string()),
+        "playlist_count" if playlist.is_some() => playlist.map(|p| p.count.to_string()),
+        name => match &info[name] {
+            Value::String(text) => Some(text.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            Value::Bool(flag) => Some(flag.to_string()),
+            // Lists such as `tags` or `artists`
+            Value::Array(items) => Some(
+                items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Value::Null | Value::Object(_) => None,
+        },
+    };
+    value.filter(|value| !value.trim().is_empty())
+}
+
+/// How many names `counter` and `id` try before giving up on a path.
+const MAX_CANDIDATES: usize = 10_000;
+
+/// Paths claimed by downloads in progress and the job holding each, so two jobs rendering
+/// the same name do not both pick it before either file exists.
+static CLAIMED: Lazy<Mutex<HashMap<PathBuf, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Where a download should be written.
+#[derive(Debug)]
+pub struct OutputTarget {
+    /// The path of the finished file.
+    pub path: PathBuf,
+    /// Whether yt-dlp may replace an existing file at `path`.
+    pub overwrite: bool,
+    /// The job holding the claim on `path`.
+    job_id: String,
+}
+
+impl OutputTarget {
+    /// The directory the file goes in.
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// The file name without its extension, which yt-dlp's partial files start with.
+    pub fn stem(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// The `--output` template for yt-dlp. yt-dlp picks the extension itself (e.g. after
+    /// audio extraction), so it is left open.
+    pub fn yt_dlp_template(&self) -> String {
+        let stem = self.dir().join(self.stem());
+        // `%` starts a field in yt-dlp's own template syntax
+        format!("{}.%(ext)s", stem.to_string_lossy().replace('%', "%%"))
+    }
+}
+
+impl Drop for OutputTarget {
+    fn drop(&mut self) {
+        // Only this job's own claim is released
+        let mut claimed = CLAIMED.lock().unwrap();
+        if claimed.get(&self.path) == Some(&self.job_id) {
+            claimed.remove(&self.path);
+        }
+    }
+}
+
+/// The outcome of applying a collision policy.
+#[derive(Debug)]
+pub enum Claim {
+    /// Download to this target. The path stays claimed until the target is dropped.
+    Download(OutputTarget),
+    /// The file already exists and the policy says to keep it.
+    Existing(PathBuf),
+    /// Another job is producing the file and the policy says to keep it.
+    InProgress {
+        path: PathBuf,
+        /// The job holding the claim.
+        job_id: String,
+    },
+}
+
+/// Picks the path a download is saved under, applying a collision policy.
+///
+/// # Arguments
+///
+/// * `path` - The path the template produced.
+/// * `policy` - What to do if that path is taken.
+/// * `id` - The video's ID, for `CollisionPolicy::Id`.
+/// * `sanitize` - Keeps suffixed names valid and within the length limit.
+/// * `job_id` - The job the path is claimed for.
+///
+/// # Returns
+///
+/// The target to download to, or the existing file to keep. A `JobConflict` if the
+/// policy is `overwrite` and another job is still writing the path, or if `counter` or
+/// `id` find no free name.
+pub fn claim(
+    path: PathBuf,
+    policy: CollisionPolicy,
+    id: &str,
+    sanitize: &SanitizePolicy,
+    job_id: &str,
+) -> Result<Claim> {
+    let mut claimed = CLAIMED.lock().unwrap();
+    let taken = |path: &Path| claimed.contains_key(path) || path.symlink_metadata().is_ok();
+
+    // Two jobs must never write the same file at once, whatever the policy
+    if policy == CollisionPolicy::Overwrite
+        && let Some(owner) = claimed.get(&path)
+    {
+        return Err(PegasusError::JobConflict(format!(
+            "{} is being written by job {}",
+            path.display(),
+            owner
+        )));
+    }
+
+    let (path, overwrite) = match policy {
+        // A path another job has claimed will exist once that job is done
+        CollisionPolicy::Skip if claimed.contains_key(&path) => {
+            let job_id = claimed[&path].clone();
+            info!(path = %path.display(), owner = %job_id, "Output file is being produced by another job, skipping download");
+            return Ok(Claim::InProgress { path, job_id });
+        }
+        CollisionPolicy::Skip if taken(&path) => {
+            info!(path = %path.display(), "Output file exists, skipping download");
+            return Ok(Claim::Existing(path));
+        }
+        CollisionPolicy::Skip => (path, false),
+        CollisionPolicy::Overwrite => (path, true),
+        CollisionPolicy::Counter | CollisionPolicy::Id => {
+            let free = candidates(&path, policy, id, sanitize)
+                .take(MAX_CANDIDATES)
+                .find(|candidate| !taken(candidate))
+                .ok_or_else(|| {
+                    PegasusError::JobConflict(format!(
+                        "No free name for {} after {} tries",
+                        path.display(),
+                        MAX_CANDIDATES
+                    ))
+                })?;
+            (free, false)
+        }
+    };
+
+    debug!(path = %path.display(), policy = ?policy, job_id = %job_id, "Claimed output path");
+    claimed.insert(path.clone(), job_id.to_string());
+    Ok(Claim::Download(OutputTarget {
+        path,
+        overwrite,
+        job_id: job_id.to_string(),
+    }))
+}
+
+/// Lists the paths a file may be saved under, in the order a collision policy tries them.
+///
+/// `Skip` and `Overwrite` only ever use `path` itself. `Counter` continues with ` (1)`,
+/// ` (2)`, ... and `Id` with the video's ID, then the ID and a counter. The list of the
+/// last two never ends, so callers stop at the first free path.
+pub fn candidates<'a>(
+    path: &'a Path,
+    policy: CollisionPolicy,
+    id: &'a str,
+    sanitize: &'a SanitizePolicy,
+) -> impl Iterator<Item = PathBuf> + Send + 'a {
+    let alternatives: Box<dyn Iterator<Item = PathBuf> + Send + 'a> = match policy {
+        CollisionPolicy::Skip | CollisionPolicy::Overwrite => Box::new(std::iter::empty()),
+        CollisionPolicy::Counter => Box::new(with_counters(path.to_path_buf(), sanitize)),
+        CollisionPolicy::Id => {
+            let with_id = with_suffix(path, &format!(" [{}]", id), sanitize);
+            Box::new(std::iter::once(with_id.clone()).chain(with_counters(with_id, sanitize)))
+        }
+    };
+    std::iter::once(path.to_path_buf()).chain(alternatives)
+}
+
+// Adds ` (1)`, ` (2)`, ... to a file name
+fn with_counters(
+    path: PathBuf,
+    sanitize: &SanitizePolicy,
+) -> impl Iterator<Item = PathBuf> + Send + '_ {
+    (1..).map(move |n| with_suffix(&path, &format!(" ({})", n), sanitize))
+}
+
+// Inserts `suffix` between a file's stem and its extension, shortening the stem if the
+// name would get too long
+fn with_suffix(path: &Path, suffix: &str, sanitize: &SanitizePolicy) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let suffix = match path.extension() {
+        Some(ext) => format!("{}.{}", suffix, ext.to_string_lossy()),
+        None => suffix.to_string(),
+    };
+    path.with_file_name(sanitize.file_name(&stem, &suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: &str, info: &Value) -> String {
+        render_with(template, info, None)
+    }
+
+    fn render_with(template: &str, info: &Value, playlist: Option<&PlaylistPosition>) -> String {
+        let context = TemplateContext {
+            ext: "mp4",
+            job_id: "job-1",
+            playlist,
+            sanitize: &SanitizePolicy::default(),
+        };
+        let path = OutputTemplate::parse(template)
+            .unwrap()
+            .render(info, &context);
+        path.to_string_lossy().to_string()
+    }
+
+    fn info() -> Value {
+        json!({
+            "id": "abc123",
+            "title": "A/B: Test?",
+            "uploader": "Someone",
+            "channel": "Channel",
+            "upload_date": "20240102",
+            "timestamp": 1704153600,
+            "view_count": 42,
+            "tags": ["one", "two"],
+            "description": "   ",
+        })
+    }
+
+    #[test]
+    fn parse_rejects_invalid_templates() {
+        for template in [
+            "",
+            "   ",
+            "/abs/{title}",
+            "\\abs\\{title}",
+            "../{title}",
+            "a/./{title}",
+            "{title",
+            "title}",
+            "{ti{tle}",
+            "{}",
+            "{bad-name}",
+            "<{title}",
+            "{title}>",
+            "<<{title}>>",
+            "{upload_date:%Q}",
+            "{title:.x}",
+            "{view_count:wide}",
+        ] {
+            assert!(
+                OutputTemplate::parse(template).is_err(),
+                "{:?} should not parse",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn parse_accepts_valid_templates() {
+        for template in [
+            DEFAULT_TEMPLATE,
+            "{title}",
+            "{uploader,channel|Unknown}/{upload_date:%Y}/{title} [{id}].{ext}",
+            "<{playlist_index:03} - >{title:.80}",
+            "{{literal}} {title}",
+        ] {
+            assert!(
+                OutputTemplate::parse(template).is_ok(),
+                "{:?} should parse",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn render_fills_in_fields() {
+        assert_eq!(render("{title}", &info()), "A_B_ Test_.mp4");
+        assert_eq!(render("{id}.{ext}", &info()), "abc123.mp4");
+        assert_eq!(
+            render("{uploader}/{upload_date:%Y}/{title} [{id}]", &info()),
+            "Someone/2024/A_B_ Test_ [abc123].mp4"
+        );
+        assert_eq!(render("{timestamp:%Y-%m-%d}", &info()), "2024-01-02.mp4");
+        assert_eq!(render("{view_count:05}", &info()), "00042.mp4");
+        assert_eq!(render("{uploader:.4}", &info()), "Some.mp4");
+        assert_eq!(render("{tags}", &info()), "one, two.mp4");
+        assert_eq!(render("{{{id}}}", &info()), "{abc123}.mp4");
+        assert_eq!(render("{job_id}", &info()), "job-1.mp4");
+    }
+
+    #[test]
+    fn render_falls_back_for_missing_fields() {
+        assert_eq!(render("{artist,uploader}", &info()), "Someone.mp4");
+        assert_eq!(
+            render("{artist|Unknown} - {id}", &info()),
+            "Unknown - abc123.mp4"
+        );
+        assert_eq!(render("{artist} - {id}", &info()), "NA - abc123.mp4");
+        // Blank values count as missing
+        assert_eq!(render("{description|none}", &info()), "none.mp4");
+    }
+
+    #[test]
+    fn render_drops_optional_sections_without_values() {
+        assert_eq!(render("<{artist} - >{id}", &info()), "abc123.mp4");
+        assert_eq!(
+            render("<{uploader} - >{id}", &info()),
+            "Someone - abc123.mp4"
+        );
+        assert_eq!(render(DEFAULT_TEMPLATE, &info()), "A_B_ Test_.mp4");
+
+        let position = PlaylistPosition {
+            title: Some("My List".to_string()),
+            id: Some("pl".to_string()),
+            index: 7,
+            count: 12,
+        };
+        assert_eq!(
+            render_with(DEFAULT_TEMPLATE, &info(), Some(&position)),
+            "My List/A_B_ Test_.mp4"
+        );
+        assert_eq!(
+            render_with("<{playlist_index:03} - >{id}", &info(), Some(&position)),
+            "007 - abc123.mp4"
+        );
+    }
+
+    #[test]
+    fn render_never_leaves_the_directory() {
+        let hostile = json!({"title": "../../etc/passwd", "uploader": ".."});
+        assert_eq!(render("{title}", &hostile), ".._.._etc_passwd.mp4");
+        assert_eq!(
+            render("{uploader}/{title}", &hostile),
+            "unknown_title/.._.._etc_passwd.mp4"
+        );
+        assert_eq!(render("{uploader}//{id}", &json!({})), "NA/NA.mp4");
+        for component in Path::new(&render("{uploader}/{title}", &hostile)).components() {
+            assert!(matches!(component, std::path::Component::Normal(_)));
+        }
+    }
+
+    #[test]
+    fn render_keeps_the_extension_when_shortening() {
+        let long = json!({"title": "x".repeat(500)});
+        let rendered = render("{title}", &long);
+        assert!(rendered.ends_with(".mp4"));
+        assert_eq!(rendered.len(), SanitizePolicy::default().max_bytes);
+    }
+
+    #[test]
+    fn candidates_follow_the_policy() {
+        let sanitize = SanitizePolicy::default();
+        let path = Path::new("dir/Title.mp4");
+        let take = |policy| -> Vec<PathBuf> {
+            candidates(path, policy, "abc", &sanitize).take(3).collect()
+        };
+
+        assert_eq!(
+            take(CollisionPolicy::Skip),
+            vec![PathBuf::from("dir/Title.mp4")]
+        );
+        assert_eq!(
+            take(CollisionPolicy::Overwrite),
+            vec![PathBuf::from("dir/Title.mp4")]
+        );
+        assert_eq!(
+            take(CollisionPolicy::Counter),
+            vec![
+                PathBuf::from("dir/Title.mp4"),
+                PathBuf::from("dir/Title (1).mp4"),
+                PathBuf::from("dir/Title (2).mp4"),
+            ]
+        );
+        assert_eq!(
+            take(CollisionPolicy::Id),
+            vec![
+                PathBuf::from("dir/Title.mp4"),
+                PathBuf::from("dir/Title [abc].mp4"),
+                PathBuf::from("dir/Title [abc] (1).mp4"),
+            ]
+        );
+    }
+
+    fn claim_for(path: &Path, policy: CollisionPolicy, job_id: &str) -> Result<Claim> {
+        claim(
+            path.to_path_buf(),
+            policy,
+            "abc",
+            &SanitizePolicy::default(),
+            job_id,
+        )
+    }
+
+    fn download(claim: Result<Claim>) -> OutputTarget {
+        match claim {
+            Ok(Claim::Download(target)) => target,
+            other => panic!("expected a download, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn claim_applies_the_collision_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Title.mp4");
+        std::fs::write(&path, b"existing").unwrap();
+
+        let counter = download(claim_for(&path, CollisionPolicy::Counter, "job-1"));
+        assert_eq!(counter.path, dir.path().join("Title (1).mp4"));
+
+        // The first claim is still in progress, so the counter moves on
+        let next = download(claim_for(&path, CollisionPolicy::Counter, "job-2"));
+        assert_eq!(next.path, dir.path().join("Title (2).mp4"));
+
+        let overwrite = download(claim_for(&path, CollisionPolicy::Overwrite, "job-3"));
+        assert_eq!(overwrite.path, path);
+        assert!(overwrite.overwrite);
+        drop(overwrite);
+
+        assert!(matches!(
+            claim_for(&path, CollisionPolicy::Skip, "job-4"),
+            Ok(Claim::Existing(existing)) if existing == path
+        ));
+        // A name another job is still producing is kept too, naming that job
+        let pending = dir.path().join("Title (1).mp4");
+        assert!(matches!(
+            claim_for(&pending, CollisionPolicy::Skip, "job-4"),
+            Ok(Claim::InProgress { path, job_id }) if path == pending && job_id == "job-1"
+        ));
+    }
+
+    #[test]
+    fn overwrite_rejects_a_path_another_job_is_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Title.mp4");
+
+        let first = download(claim_for(&path, CollisionPolicy::Overwrite, "job-1"));
+        assert!(matches!(
+            claim_for(&path, CollisionPolicy::Overwrite, "job-2"),
+            Err(PegasusError::JobConflict(_))
+        ));
+
+        // Once the first job is done the path is free again
+        drop(first);
+        let second = download(claim_for(&path, CollisionPolicy::Overwrite, "job-2"));
+        assert_eq!(second.path, path);
+    }
+
+    #[test]
+    fn claims_are_released_by_their_owner_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Title.mp4");
+
+        let owner = download(claim_for(&path, CollisionPolicy::Skip, "job-1"));
+        // A target for the same path held by another job does not release the claim
+        drop(OutputTarget {
+            path: path.clone(),
+            overwrite: false,
+            job_id: "job-2".to_string(),
+        });
+        assert!(matches!(
+            claim_for(&path, CollisionPolicy::Skip, "job-3"),
+            Ok(Claim::InProgress { job_id, .. }) if job_id == "job-1"
+        ));
+
+        drop(owner);
+        download(claim_for(&path, CollisionPolicy::Skip, "job-3"));
+    }
+}
@@ -0,0 +1,252 @@
+// src/download/options.rs
+// Typed processing options submitted with a job and their mapping onto yt-dlp arguments.
+
+use crate::error::{PegasusError, Result};
+use crate::process::PostProcessOptions;
+use serde::{Deserialize, Serialize};
+
+/// Quality presets for video downloads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoQuality {
+    /// Best available video and audio streams.
+    #[default]
+    High,
+    /// Capped at 720p.
+    Medium,
+    /// Capped at 480p.
+    Low,
+}
+
+impl VideoQuality {
+    /// Maximum video height for this preset, or `None` for no limit.
+    fn max_height(self) -> Option<u32> {
+        match self {
+            VideoQuality::High => None,
+            VideoQuality::Medium => Some(720),
+            VideoQuality::Low => Some(480),
+        }
+    }
+}
+
+/// Containers a video download can be merged into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoContainer {
+    #[default]
+    Mp4,
+    Mkv,
+    Webm,
+}
+
+impl VideoContainer {
+    /// File extension (and yt-dlp merge format) for this container.
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoContainer::Mp4 => "mp4",
+            VideoContainer::Mkv => "mkv",
+            VideoContainer::Webm => "webm",
+        }
+    }
+}
+
+/// Codecs audio-only downloads can be extracted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    M4a,
+    Opus,
+}
+
+impl AudioFormat {
+    /// File extension (and yt-dlp `--audio-format` value) for this codec.
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Opus => "opus",
+        }
+    }
+}
+
+/// Quality presets for audio-only downloads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioQuality {
+    #[default]
+    High,
+    Medium,
+    Low,
+}
+
+impl AudioQuality {
+    /// Target bitrate in kbit/s for this preset, tuned per codec.
+    fn bitrate_kbps(self, format: AudioFormat) -> u32 {
+        match (format, self) {
+            (AudioFormat::Mp3, AudioQuality::High) => 320,
+            (AudioFormat::Mp3, AudioQuality::Medium) => 192,
+            (AudioFormat::Mp3, AudioQuality::Low) => 128,
+            (AudioFormat::M4a, AudioQuality::High) => 256,
+            (AudioFormat::M4a, AudioQuality::Medium) => 192,
+            (AudioFormat::M4a, AudioQuality::Low) => 128,
+            (AudioFormat::Opus, AudioQuality::High) => 160,
+            (AudioFormat::Opus, AudioQuality::Medium) => 128,
+            (AudioFormat::Opus, AudioQuality::Low) => 96,
+        }
+    }
+}
+
+/// Processing options for a single job.
+///
+/// Every field is optional in the submitted JSON and falls back to its default;
+/// unknown fields and unknown enum values are rejected during deserialization.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct ProcessingOptions {
+    /// Extract the audio track only instead of downloading video.
+    pub audio_only: bool,
+    /// An explicit yt-dlp format selector (e.g. a format ID). Overrides `video_quality`.
+    pub format: Option<String>,
+    pub video_quality: VideoQuality,
+    pub container: VideoContainer,
+    pub audio_format: AudioFormat,
+    pub audio_quality: AudioQuality,
+    /// Explicit audio bitrate in kbit/s. Overrides `audio_quality`.
+    pub audio_bitrate: Option<u32>,
+    /// Embed subtitles into the video file.
+    pub embed_subtitles: bool,
+    /// Subtitle languages to embed (yt-dlp `--sub-langs` patterns). Empty means all.
+    pub subtitle_languages: Vec<String>,
+    /// Embed title, uploader, chapters etc. into the output file.
+    pub embed_metadata: bool,
+    /// Embed the thumbnail as cover art.
+    pub embed_thumbnail: bool,
+    /// ffmpeg processing to run once the download has finished.
+    pub post_process: PostProcessOptions,
+}
+
+impl ProcessingOptions {
+    /// Checks the values that serde cannot validate on its own.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the options are usable, or a `PegasusError::ValidationError` describing
+    /// the first invalid value.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(format) = &self.format
+            && (format.is_empty() || format.chars().any(char::is_whitespace))
+        {
+            return Err(PegasusError::ValidationError(format!(
+                "Invalid format selector: {:?}",
+                format
+            )));
+        }
+
+        if let Some(bitrate) = self.audio_bitrate
+            && !(32..=512).contains(&bitrate)
+        {
+            return Err(PegasusError::ValidationError(format!(
+                "Audio bitrate must be between 32 and 512 kbit/s, got {}",
+                bitrate
+            )));
+        }
+
+        for lang in &self.subtitle_languages {
+            let valid = !lang.is_empty()
+                && lang
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '*'));
+            if !valid {
+                return Err(PegasusError::ValidationError(format!(
+                    "Invalid subtitle language: {:?}",
+                    lang
+                )));
+            }
+        }
+
+        if self.audio_only
+            && self
+                .post_process
+                .profile
+                .is_some_and(|profile| profile.has_video())
+        {
+            return Err(PegasusError::ValidationError(
+                "A video transcode profile cannot be used with an audio-only download".to_string(),
+            ));
+        }
+
+        self.post_process.validate()
+    }
+
+    /// Extension of the file yt-dlp will produce for these options.
+    pub fn output_extension(&self) -> &'static str {
+        if self.audio_only {
+            self.audio_format.extension()
+        } else {
+            self.container.extension()
+        }
+    }
+
+    /// Builds the yt-dlp arguments that select formats and post-processing for these options.
+    ///
+    /// The returned arguments do not include the URL, output path or progress flags.
+    pub fn yt_dlp_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+
+        if self.audio_only {
+            let bitrate = self
+                .audio_bitrate
+                .unwrap_or_else(|| self.audio_quality.bitrate_kbps(self.audio_format));
+            args.extend([
+                "-f".to_string(),
+                self.format
+                    .clone()
+                    .unwrap_or_else(|| "bestaudio/best".to_string()),
+                "--extract-audio".to_string(),
+                "--audio-format".to_string(),
+                self.audio_format.extension().to_string(),
+                "--audio-quality".to_string(),
+                format!("{}K", bitrate),
+            ]);
+        } else {
+            let selector =
+                self.format
+                    .clone()
+                    .unwrap_or_else(|| match self.video_quality.max_height() {
+                        Some(height) => format!(
+                            "bestvideo*[height<={h}]+bestaudio/best[height<={h}]/best",
+                            h = height
+                        ),
+                        None => "bestvideo*+bestaudio/best".to_string(),
+                    });
+            args.extend([
+                "-f".to_string(),
+                selector,
+                "--merge-output-format".to_string(),
+                self.container.extension().to_string(),
+            ]);
+
+            if self.embed_subtitles {
+                let langs = if self.subtitle_languages.is_empty() {
+                    "all,-live_chat".to_string()
+                } else {
+                    self.subtitle_languages.join(",")
+                };
+                args.extend(["--embed-subs".to_string(), "--sub-langs".to_string(), langs]);
+            }
+        }
+
+        if self.embed_metadata {
+            args.push("--embed-metadata".to_string());
+            args.push("--embed-chapters".to_string());
+        }
+
+        if self.embed_thumbnail {
+            args.push("--embed-thumbnail".to_string());
+        }
+
+        args
+    }
+}
@@ -0,0 +1,191 @@
+// src/download/playlist.rs
+// Detects playlist and channel URLs and selects the entries to download.
+//
+// Playlists are probed with `yt-dlp --flat-playlist --dump-single-json`, which lists the
+// entries without extracting each one. A job for a playlist URL becomes a parent job with
+// one child job per selected entry.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::error::{PegasusError, Result};
+
+/// How the entries of a playlist or channel are selected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct PlaylistOptions {
+    /// yt-dlp `--playlist-items` selection, 1-based, e.g. `"1-5,8,10:20"`.
+    pub items: Option<String>,
+    /// Only keep the newest N entries.
+    pub newest: Option<usize>,
+    /// Download the entries in reverse order.
+    pub reverse: bool,
+}
+
+impl PlaylistOptions {
+    /// Checks the options for values yt-dlp would reject.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(items) = &self.items
+            && (items.is_empty()
+                || !items
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || matches!(c, '-' | ':' | ',')))
+        {
+            return Err(PegasusError::ValidationError(format!(
+                "Invalid playlist item selection: {:?}",
+                items
+            )));
+        }
+
+        if self.newest == Some(0) {
+            return Err(PegasusError::ValidationError(
+                "Newest item count must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the yt-dlp arguments for probing a URL with these options.
+    pub fn yt_dlp_args(&self) -> Vec<String> {
+        match &self.items {
+            Some(items) => vec!["--playlist-items".to_string(), items.clone()],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A single entry of a flat playlist listing.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlaylistEntry {
+    pub url: String,
+    pub title: Option<String>,
+    /// Upload time as a Unix timestamp, when the extractor provides one.
+    #[serde(skip)]
+    pub timestamp: Option<i64>,
+    /// The entry's key in a yt-dlp style download archive (`extractor id`).
+    #[serde(skip)]
+    pub archive_key: Option<String>,
+    /// The entry's 1-based position in the playlist.
+    #[serde(skip)]
+    pub index: usize,
+}
+
+/// A playlist or channel listing.
+#[derive(Clone, Debug)]
+pub struct Playlist {
+    pub title: Option<String>,
+    pub id: Option<String>,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+/// Where an entry sits in the playlist it was expanded from, for output templates.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistPosition {
+    pub title: Option<String>,
+    pub id: Option<String>,
+    /// The entry's 1-based position in the playlist.
+    pub index: usize,
+    /// The number of entries in the playlist.
+    pub count: usize,
+}
+
+/// Returns `true` if yt-dlp's JSON output describes a playlist rather than a single video.
+pub fn is_playlist(info: &Value) -> bool {
+    matches!(
+        info["_type"].as_str(),
+        Some("playlist") | Some("multi_video")
+    )
+}
+
+/// Builds a playlist from yt-dlp's `--flat-playlist` JSON output.
+///
+/// Entries without a usable URL (e.g. private or deleted videos) are skipped.
+pub fn parse_playlist(info: &Value) -> Playlist {
+    let entries = info["entries"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .enumerate()
+                .filter_map(|(position, entry)| parse_entry(entry, position + 1))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Playlist {
+        title: info["title"].as_str().map(str::to_string),
+        id: info["id"].as_str().map(str::to_string),
+        entries,
+    }
+}
+
+fn parse_entry(entry: &Value, position: usize) -> Option<PlaylistEntry> {
+    let url = ["url", "webpage_url"]
+        .iter()
+        .filter_map(|key| entry[*key].as_str())
+        .find(|url| url.starts_with("http://") || url.starts_with("https://"));
+
+    let Some(url) = url else {
+        warn!(entry_id = ?entry["id"].as_str(), "Skipping playlist entry without a URL");
+        return None;
+    };
+
+    // Flat listings carry either a timestamp or an upload date, if anything
+    let timestamp = entry["timestamp"].as_i64().or_else(|| {
+        entry["upload_date"]
+            .as_str()
+            .and_then(|date| chrono::NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|date| date.and_utc().timestamp())
+    });
+
+    Some(PlaylistEntry {
+        url: url.to_string(),
+        title: entry["title"].as_str().map(str::to_string),
+        timestamp,
+        archive_key: archive_key(entry),
+        // Item selections number entries by their place in the full playlist
+        index: entry["playlist_index"]
+            .as_u64()
+            .map_or(position, |index| index as usize),
+    })
+}
+
+/// Builds the download archive key for a video or flat playlist entry.
+///
+/// The format matches yt-dlp's `--download-archive` files: the lowercased extractor key
+/// and the video ID, separated by a space.
+pub fn archive_key(info: &Value) -> Option<String> {
+    let extractor = info["ie_key"]
+        .as_str()
+        .or_else(|| info["extractor_key"].as_str())?;
+    let id = info["id"].as_str()?;
+    Some(format!("{} {}", extractor.to_lowercase(), id))
+}
+
+/// Applies the "newest N" and reverse options to a playlist's entries.
+///
+/// Entries are ordered newest first by upload time when every entry has one; otherwise
+/// the listing order is kept, which for channels is already newest first.
+///
+/// # Returns
+///
+/// The entries to create child jobs for, in the order they should be queued.
+pub fn select_entries(playlist: &Playlist, options: &PlaylistOptions) -> Vec<PlaylistEntry> {
+    let mut entries = playlist.entries.clone();
+
+    if let Some(newest) = options.newest {
+        if entries.iter().all(|e| e.timestamp.is_some()) {
+            entries.sort_by_key(|e| std::cmp::Reverse(e.timestamp));
+        }
+        entries.truncate(newest);
+    }
+
+    if options.reverse {
+        entries.reverse();
+    }
+
+    entries
+}
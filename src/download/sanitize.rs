@@ -0,0 +1,274 @@
+// src/download/sanitize.rs
+// Turns titles and other metadata into names that are valid on the target filesystem.
+//
+// Titles come from arbitrary websites, so they can hold anything: path separators,
+// control characters, emoji and scripts where one character takes several bytes.
+// Filesystems limit names in bytes (255 on most Linux filesystems), so names are
+// shortened by a byte budget, but only ever between grapheme clusters so a character,
+// or a character and its combining accents, is never cut in half. Everything is
+// normalized to NFC first, which is how macOS, Windows and most media libraries expect
+// accented names to be stored.
+
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The name used when nothing is left of the original.
+const FALLBACK: &str = "unknown_title";
+
+/// What characters that are not allowed in names are replaced with.
+const REPLACEMENT: char = '_';
+
+/// Characters that are not allowed in names on Windows, and so on SMB shares, or that
+/// shells and other tools handle badly.
+const FORBIDDEN: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Smallest allowed `max_bytes`, leaving room for a readable name next to an extension
+/// and a collision suffix.
+pub const MIN_MAX_BYTES: usize = 32;
+
+/// How file and directory names are made safe.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanitizePolicy {
+    /// Longest name in bytes, including the extension. The default leaves room for the
+    /// suffixes of yt-dlp's partial files (e.g. `.f137.mp4.part`) below the common
+    /// 255-byte limit.
+    pub max_bytes: usize,
+    /// Also follow NTFS and SMB rules: no reserved device names such as `CON` or `NUL`
+    /// and no trailing dots or spaces.
+    pub windows_safe: bool,
+    /// Transliterate names to ASCII, e.g. `Crème brûlée` to `Creme brulee`.
+    pub ascii_only: bool,
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> Self {
+        SanitizePolicy {
+            max_bytes: 200,
+            windows_safe: false,
+            ascii_only: false,
+        }
+    }
+}
+
+impl SanitizePolicy {
+    /// Sanitizes a name that has no extension of its own, such as a directory name.
+    ///
+    /// # Returns
+    ///
+    /// A non-empty name of at most `max_bytes` bytes without separators or control
+    /// characters.
+    pub fn sanitize(&self, name: &str) -> String {
+        self.file_name(name, "")
+    }
+
+    /// Sanitizes a file name, keeping `suffix` whole and shortening `stem` to fit.
+    ///
+    /// # Arguments
+    ///
+    /// * `stem` - The name without its extension, e.g. a video title.
+    /// * `suffix` - Kept at the end of the name, e.g. `.mp4` or ` (2).mp4`.
+    ///
+    /// # Returns
+    ///
+    /// A non-empty name of at most `max_bytes` bytes without separators or control
+    /// characters.
+    pub fn file_name(&self, stem: &str, suffix: &str) -> String {
+        let suffix = self.clean(suffix);
+        let budget = self.max_bytes.saturating_sub(suffix.len());
+
+        let mut stem = self.trim(&truncate(self.clean(stem).trim(), budget));
+        if stem.is_empty() || stem.chars().all(|c| c == '.') {
+            stem = truncate(FALLBACK, budget);
+        }
+        if self.windows_safe && is_reserved(&stem) {
+            // `CON.txt` becomes `CON_.txt`; the last grapheme makes room if needed
+            stem = self.trim(&truncate(&stem, budget.saturating_sub(1)));
+            let base_len = stem.find('.').unwrap_or(stem.len());
+            stem.insert(base_len, REPLACEMENT);
+        }
+        stem + &suffix
+    }
+
+    // Applies the character rules, without shortening
+    fn clean(&self, name: &str) -> String {
+        let name = if self.ascii_only {
+            deunicode::deunicode_with_tofu(&name.nfc().collect::<String>(), "_")
+        } else {
+            name.to_string()
+        };
+        let cleaned: String = name
+            .chars()
+            .filter_map(|c| match c {
+                // Line breaks and tabs separate words, other control characters are noise
+                '\t' | '\n' | '\r' => Some(' '),
+                c if c.is_control() => None,
+                c if FORBIDDEN.contains(&c) => Some(REPLACEMENT),
+                c => Some(c),
+            })
+            .collect();
+        // Normalized last, as removing a character can leave an accent next to a letter
+        // it combines with
+        cleaned.nfc().collect()
+    }
+
+    // Removes what a name may not end with
+    fn trim(&self, name: &str) -> String {
+        if self.windows_safe {
+            name.trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+                .to_string()
+        } else {
+            name.trim_end().to_string()
+        }
+    }
+}
+
+/// Shortens `name` to at most `max_bytes` bytes without splitting a grapheme cluster.
+pub fn truncate(name: &str, max_bytes: usize) -> String {
+    let mut end = 0;
+    for (offset, grapheme) in name.grapheme_indices(true) {
+        if offset + grapheme.len() > max_bytes {
+            break;
+        }
+        end = offset + grapheme.len();
+    }
+    name[..end].to_string()
+}
+
+// Windows treats `CON`, `con.txt` and `CON .mp4` alike
+fn is_reserved(name: &str) -> bool {
+    let base = name.split('.').next().unwrap_or_default().trim_end();
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use unicode_normalization::is_nfc;
+
+    // Arbitrary strings, plus ones dense in the characters the rules care about: dots,
+    // spaces, separators, control characters, combining accents, multi-byte characters
+    // and reserved names
+    fn names() -> impl Strategy<Value = String> {
+        prop_oneof![
+            any::<String>(),
+            "[ .a-zA-Z0-9/\\\\:*?\"<>|\t\n\u{0}-\u{1f}\u{7f}\u{300}-\u{36f}é한👍🏽]{0,300}",
+            "(CON|con|NUL|Aux|COM1|lpt9)[ .]{0,3}(\\.[a-z]{0,4})?[ .]{0,3}",
+        ]
+    }
+
+    // Suffixes the naming code passes: an extension, optionally after a counter or a
+    // video ID
+    fn suffixes() -> impl Strategy<Value = String> {
+        (
+            prop_oneof![
+                Just(String::new()),
+                "( \\([0-9]{1,4}\\))",
+                "( \\[[A-Za-z0-9_-]{1,11}\\])",
+                "( \\[[A-Za-z0-9_-]{1,11}\\] \\([0-9]{1,3}\\))",
+            ],
+            prop_oneof![Just(String::new()), "\\.[a-z0-9]{1,5}"],
+        )
+            .prop_map(|(tag, ext)| tag + &ext)
+    }
+
+    fn policies() -> impl Strategy<Value = SanitizePolicy> {
+        (MIN_MAX_BYTES..=255usize, any::<bool>(), any::<bool>()).prop_map(
+            |(max_bytes, windows_safe, ascii_only)| SanitizePolicy {
+                max_bytes,
+                windows_safe,
+                ascii_only,
+            },
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2048))]
+
+        #[test]
+        fn file_name_is_always_valid(
+            policy in policies(),
+            stem in names(),
+            suffix in suffixes(),
+        ) {
+            let name = policy.file_name(&stem, &suffix);
+
+            prop_assert!(!name.is_empty());
+            prop_assert!(name.len() <= policy.max_bytes, "{} bytes: {:?}", name.len(), name);
+            prop_assert!(name.ends_with(&suffix), "{:?} lost its suffix {:?}", name, suffix);
+            prop_assert!(
+                !name.chars().any(|c| c.is_control() || FORBIDDEN.contains(&c)),
+                "{:?} holds a separator or control character",
+                name
+            );
+            prop_assert!(is_nfc(&name), "{:?} is not NFC", name);
+            if policy.ascii_only {
+                prop_assert!(name.is_ascii(), "{:?} is not ASCII", name);
+            }
+            if policy.windows_safe {
+                prop_assert!(!name.ends_with(['.', ' ']), "{:?} ends in a dot or space", name);
+                prop_assert!(!is_reserved(&name), "{:?} is a reserved name", name);
+            }
+        }
+
+        #[test]
+        fn sanitize_is_always_valid(policy in policies(), name in names()) {
+            let name = policy.sanitize(&name);
+
+            prop_assert!(!name.is_empty());
+            prop_assert!(name.len() <= policy.max_bytes);
+            prop_assert!(!name.chars().any(|c| c.is_control() || FORBIDDEN.contains(&c)));
+            prop_assert!(is_nfc(&name));
+            prop_assert!(!name.chars().all(|c| c == '.'), "{:?} is only dots", name);
+            if policy.windows_safe {
+                prop_assert!(!name.ends_with(['.', ' ']));
+                prop_assert!(!is_reserved(&name));
+            }
+        }
+
+        #[test]
+        fn truncate_keeps_whole_graphemes(name in names(), max_bytes in 0usize..400) {
+            let truncated = truncate(&name, max_bytes);
+
+            prop_assert!(truncated.len() <= max_bytes);
+            prop_assert!(name.starts_with(&truncated));
+            // It ends between graphemes, and the next one would exceed the budget
+            let next = name
+                .grapheme_indices(true)
+                .find(|(offset, _)| *offset == truncated.len());
+            match next {
+                Some((_, grapheme)) => prop_assert!(truncated.len() + grapheme.len() > max_bytes),
+                None => prop_assert_eq!(truncated.len(), name.len()),
+            }
+        }
+    }
+
+    #[test]
+    fn reserved_names_are_renamed() {
+        let policy = SanitizePolicy {
+            windows_safe: true,
+            ..SanitizePolicy::default()
+        };
+        assert_eq!(policy.file_name("CON", ".mp4"), "CON_.mp4");
+        assert_eq!(policy.file_name("nul. ", ""), "nul_");
+        assert_eq!(policy.file_name("Title...", ".mp4"), "Title.mp4");
+    }
+
+    #[test]
+    fn empty_names_fall_back() {
+        let policy = SanitizePolicy::default();
+        assert_eq!(policy.sanitize(""), FALLBACK);
+        assert_eq!(policy.file_name("\u{0}\u{1} ", ".mp4"), "unknown_title.mp4");
+        assert_eq!(policy.sanitize("..."), FALLBACK);
+    }
+}
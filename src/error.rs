@@ -12,12 +12,39 @@ pub enum PegasusError {
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+
     #[error("Web server error: {0}")]
     WebServerError(String),
 
     #[error("Download error (yt-dlp): {0}")]
     YtDlpError(String),
 
+    #[error("Video unavailable: {0}")]
+    VideoUnavailable(String),
+
+    #[error("Video is private: {0}")]
+    VideoPrivate(String),
+
+    #[error("Video is not available in this region: {0}")]
+    GeoBlocked(String),
+
+    #[error("Login required (age-restricted or members-only video): {0}")]
+    LoginRequired(String),
+
+    #[error("Rate limited by the site: {0}")]
+    RateLimited(String),
+
+    #[error("Network error: {0}")]
+    NetworkTimeout(String),
+
+    #[error("Failed to merge the downloaded streams: {0}")]
+    MergeFailed(String),
+
+    #[error("Unsupported URL: {0}")]
+    UnsupportedUrl(String),
+
     #[error("Download error (general): {0}")]
     DownloadError(String),
 
@@ -36,6 +63,125 @@ pub enum PegasusError {
     #[error("External command error: {0}")]
     ExternalCommandError(String),
 
+    #[error("Required binary is missing: {0}")]
+    BinaryMissing(String),
+
+    #[error("Job not found: {0}")]
+    JobNotFound(String),
+
+    #[error("Job conflict: {0}")]
+    JobConflict(String),
+
+    #[error("Subscription not found: {0}")]
+    SubscriptionNotFound(String),
+
+    #[error("Batch not found: {0}")]
+    BatchNotFound(String),
+
+    #[error("User not found: {0}")]
+    UserNotFound(String),
+
+    #[error("API token not found: {0}")]
+    TokenNotFound(String),
+
+    #[error("No credentials stored for {0}")]
+    CredentialNotFound(String),
+
+    #[error("Authentication required: {0}")]
+    Unauthorized(String),
+
+    #[error("Permission denied: {0}")]
+    Forbidden(String),
+
+    #[error("Job cancelled")]
+    Cancelled,
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
+
+impl PegasusError {
+    /// Wraps a failure to start an external command.
+    ///
+    /// A binary that does not exist or cannot be executed becomes `BinaryMissing`, so the
+    /// API can report it as a deployment problem rather than a failed download.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary` - The name or path of the command.
+    /// * `error` - The error returned by `spawn` or `output`.
+    pub fn from_spawn_error(binary: &str, error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied => {
+                PegasusError::BinaryMissing(format!(
+                    "{} is not installed or not executable: {}",
+                    binary, error
+                ))
+            }
+            _ => PegasusError::ExternalCommandError(format!(
+                "Failed to execute {}: {}",
+                binary, error
+            )),
+        }
+    }
+
+    /// Returns the stable, machine-readable code for this error, as used in API responses.
+    ///
+    /// Codes are part of the API contract: clients branch on them, so existing codes must
+    /// never be renamed.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PegasusError::ConfigError(_) => "config_error",
+            PegasusError::ValidationError(_) => "validation_error",
+            PegasusError::WebServerError(_) => "server_error",
+            PegasusError::YtDlpError(_) => "yt_dlp_error",
+            PegasusError::VideoUnavailable(_) => "video_unavailable",
+            PegasusError::VideoPrivate(_) => "video_private",
+            PegasusError::GeoBlocked(_) => "geo_blocked",
+            PegasusError::LoginRequired(_) => "login_required",
+            PegasusError::RateLimited(_) => "rate_limited",
+            PegasusError::NetworkTimeout(_) => "network_error",
+            PegasusError::MergeFailed(_) => "merge_failed",
+            PegasusError::UnsupportedUrl(_) => "unsupported_url",
+            PegasusError::DownloadError(_) => "download_error",
+            PegasusError::ProcessingError(_) => "processing_error",
+            PegasusError::TransferError(_) => "transfer_error",
+            PegasusError::IoError(_) => "io_error",
+            PegasusError::ExternalServiceError(_) => "external_service_error",
+            PegasusError::ExternalCommandError(_) => "external_command_error",
+            PegasusError::BinaryMissing(_) => "binary_missing",
+            PegasusError::JobNotFound(_) => "job_not_found",
+            PegasusError::JobConflict(_) => "job_conflict",
+            PegasusError::SubscriptionNotFound(_) => "subscription_not_found",
+            PegasusError::BatchNotFound(_) => "batch_not_found",
+            PegasusError::UserNotFound(_) => "user_not_found",
+            PegasusError::TokenNotFound(_) => "token_not_found",
+            PegasusError::CredentialNotFound(_) => "credential_not_found",
+            PegasusError::Unauthorized(_) => "unauthorized",
+            PegasusError::Forbidden(_) => "forbidden",
+            PegasusError::Cancelled => "cancelled",
+            PegasusError::Unknown(_) => "internal_error",
+        }
+    }
+
+    /// Returns `true` for failures that are likely to go away on their own, such as rate
+    /// limiting or a dropped connection, and are therefore worth retrying.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            PegasusError::RateLimited(_) | PegasusError::NetworkTimeout(_)
+        )
+    }
+
+    /// Returns `true` for failures that trying again will not fix, such as a deleted,
+    /// private or geo-blocked video.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            PegasusError::VideoUnavailable(_)
+                | PegasusError::VideoPrivate(_)
+                | PegasusError::GeoBlocked(_)
+                | PegasusError::UnsupportedUrl(_)
+        )
+    }
+}
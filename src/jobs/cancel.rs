@@ -0,0 +1,111 @@
+// src/jobs/cancel.rs
+// Cancellation tokens that let an API request stop a running job and its child processes.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// Cancellation state for a single job.
+///
+/// The job attaches the process group of whatever external command it is currently
+/// running, so cancelling can terminate yt-dlp and any ffmpeg it spawned in one go.
+#[derive(Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+    process_group: Mutex<Option<u32>>,
+    notify: Notify,
+}
+
+impl CancelToken {
+    /// Requests cancellation and kills the attached process group, if any.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+        if let Some(pgid) = *self.process_group.lock().unwrap() {
+            kill_process_group(pgid);
+        }
+    }
+
+    /// Returns `true` once cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Waits until cancellation is requested, for racing against other work such as a
+    /// retry delay.
+    pub async fn cancelled(&self) {
+        loop {
+            // Register interest before checking, so a cancel in between is not missed
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Records the process group of a newly spawned child.
+    ///
+    /// If cancellation was already requested the group is killed immediately.
+    pub fn attach_process(&self, pgid: u32) {
+        *self.process_group.lock().unwrap() = Some(pgid);
+        if self.is_cancelled() {
+            kill_process_group(pgid);
+        }
+    }
+
+    /// Forgets the attached process group once the child has exited.
+    pub fn detach_process(&self) {
+        *self.process_group.lock().unwrap() = None;
+    }
+}
+
+/// Tracks the cancellation token of every job that has not finished yet.
+#[derive(Default)]
+pub struct CancelRegistry {
+    tokens: Mutex<HashMap<String, Arc<CancelToken>>>,
+}
+
+impl CancelRegistry {
+    /// Creates a token for a job, replacing any previous one.
+    pub fn register(&self, job_id: &str) -> Arc<CancelToken> {
+        let token = Arc::new(CancelToken::default());
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(job_id.to_string(), token.clone());
+        token
+    }
+
+    /// Returns the token for a job that is still tracked.
+    pub fn get(&self, job_id: &str) -> Option<Arc<CancelToken>> {
+        self.tokens.lock().unwrap().get(job_id).cloned()
+    }
+
+    /// Stops tracking a job once it has finished.
+    pub fn remove(&self, job_id: &str) {
+        self.tokens.lock().unwrap().remove(job_id);
+    }
+}
+
+/// Sends `SIGKILL` to every process in a process group.
+#[cfg(unix)]
+pub fn kill_process_group(pgid: u32) {
+    info!(pgid, "Killing process group");
+    // SAFETY: `kill` has no memory-safety preconditions; a negative PID targets the group.
+    let result = unsafe { libc::kill(-(pgid as libc::pid_t), libc::SIGKILL) };
+    if result != 0 {
+        warn!(pgid, error = %std::io::Error::last_os_error(), "Failed to kill process group");
+    }
+}
+
+#[cfg(not(unix))]
+pub fn kill_process_group(pgid: u32) {
+    warn!(
+        pgid,
+        "Killing process groups is not supported on this platform"
+    );
+}
@@ -0,0 +1,944 @@
+// src/jobs/mod.rs
+// This module contains the persistent job registry.
+//
+// Every submitted job is recorded here along with its state machine, timestamps,
+// output path and last error. The registry is persisted as an append-only JSON-lines
+// journal: each line is a full snapshot of a job, and the last line for a given job ID
+// wins when the journal is replayed at startup. The journal is rewritten with one line per
+// job at startup and whenever it has doubled in size; finished jobs older than the
+// retention period are moved to an archive file next to it at the same time, so neither
+// the journal nor the in-memory registry grows without bound.
+//
+// Every state change is also published on a broadcast channel, so features that react to
+// jobs finishing (webhooks, metrics) do not need hooks in each place a job can finish.
+
+pub mod cancel;
+
+use crate::download::naming::NamingOptions;
+use crate::download::options::ProcessingOptions;
+use crate::download::playlist::{PlaylistOptions, PlaylistPosition};
+use crate::error::{PegasusError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock, broadcast};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// The lifecycle states a job moves through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    FetchingInfo,
+    Downloading,
+    Processing,
+    Transferring,
+    /// A playlist job whose child jobs are still running.
+    Expanded,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Every state a job can be in before it finishes.
+    pub const ACTIVE: [JobStatus; 6] = [
+        JobStatus::Queued,
+        JobStatus::FetchingInfo,
+        JobStatus::Downloading,
+        JobStatus::Processing,
+        JobStatus::Transferring,
+        JobStatus::Expanded,
+    ];
+
+    /// Returns `true` if the job can no longer change state.
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+
+    /// Returns the string used for this status in the API and the journal.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::FetchingInfo => "fetching_info",
+            JobStatus::Downloading => "downloading",
+            JobStatus::Processing => "processing",
+            JobStatus::Transferring => "transferring",
+            JobStatus::Expanded => "expanded",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::str::FromStr for JobStatus {
+    type Err = PegasusError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "queued" => Ok(JobStatus::Queued),
+            "fetching_info" => Ok(JobStatus::FetchingInfo),
+            "downloading" => Ok(JobStatus::Downloading),
+            "processing" => Ok(JobStatus::Processing),
+            "transferring" => Ok(JobStatus::Transferring),
+            "expanded" => Ok(JobStatus::Expanded),
+            "completed" => Ok(JobStatus::Completed),
+            "failed" => Ok(JobStatus::Failed),
+            "cancelled" => Ok(JobStatus::Cancelled),
+            other => Err(PegasusError::ValidationError(format!(
+                "Unknown job status: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A single download job as recorded in the registry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub url: String,
+    /// The output root the job downloads into; the default root if `None`.
+    #[serde(default)]
+    pub root: Option<String>,
+    /// The subdirectory of the output root.
+    pub output_dir: Option<String>,
+    pub processing_options: ProcessingOptions,
+    /// Higher priorities leave the queue first.
+    #[serde(default)]
+    pub priority: i32,
+    /// The transfer destination the finished file is delivered to, if any.
+    #[serde(default)]
+    pub destination: Option<String>,
+    /// How entries are selected if the URL turns out to be a playlist or channel.
+    #[serde(default)]
+    pub playlist: PlaylistOptions,
+    /// Overrides of the configured output template and collision policy.
+    #[serde(default)]
+    pub naming: NamingOptions,
+    /// Where this job sits in the playlist it was expanded from.
+    #[serde(default)]
+    pub playlist_position: Option<PlaylistPosition>,
+    /// The playlist job this job was expanded from.
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// The jobs created for the entries of a playlist job.
+    #[serde(default)]
+    pub child_ids: Vec<String>,
+    /// The subscription that found this job's URL.
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+    /// Download archive key (`extractor id`) recorded once the job completes.
+    #[serde(default)]
+    pub archive_key: Option<String>,
+    /// The batch this job was submitted as part of.
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    /// The user who submitted the job, or `None` if authentication was disabled.
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// The yt-dlp extractor that handled the URL, e.g. `Youtube`, once it is known.
+    #[serde(default)]
+    pub extractor: Option<String>,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub output_path: Option<String>,
+    pub error: Option<String>,
+    /// The machine-readable kind of `error`, e.g. `rate_limited`.
+    #[serde(default)]
+    pub error_code: Option<String>,
+}
+
+/// The caller-supplied fields of a job about to be created.
+#[derive(Clone, Debug)]
+pub struct NewJob {
+    pub url: String,
+    pub root: Option<String>,
+    pub output_dir: Option<String>,
+    pub processing_options: ProcessingOptions,
+    pub priority: i32,
+    pub destination: Option<String>,
+    pub playlist: PlaylistOptions,
+    pub naming: NamingOptions,
+    pub playlist_position: Option<PlaylistPosition>,
+    pub parent_id: Option<String>,
+    pub subscription_id: Option<String>,
+    pub archive_key: Option<String>,
+    pub batch_id: Option<String>,
+    pub owner: Option<String>,
+}
+
+/// Criteria used when listing jobs.
+#[derive(Debug, Default)]
+pub struct JobFilter {
+    /// Only return jobs in one of these states. An empty list matches every job.
+    pub statuses: Vec<JobStatus>,
+    /// Only return jobs submitted by this user. `None` matches every job.
+    pub owner: Option<String>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// How many state changes a slow subscriber may fall behind before it misses some.
+const TRANSITION_CAPACITY: usize = 1024;
+
+/// A job that has just changed state.
+#[derive(Clone, Debug)]
+pub struct JobTransition {
+    /// The state the job was in before, or `None` for a newly created job.
+    pub previous: Option<JobStatus>,
+    /// The job after the change.
+    pub job: Job,
+}
+
+/// Smallest journal size, in bytes, at which it is compacted while the server runs.
+const COMPACT_MIN_BYTES: u64 = 8 * 1024 * 1024;
+
+/// The persistent job registry.
+pub struct JobStore {
+    jobs: RwLock<HashMap<String, Job>>,
+    journal: Mutex<Journal>,
+    path: PathBuf,
+    /// How long finished jobs stay in the registry, or `None` to keep them forever.
+    retention: Option<chrono::Duration>,
+    transitions: broadcast::Sender<JobTransition>,
+}
+
+/// The open journal and how far it has grown.
+struct Journal {
+    file: File,
+    /// Bytes in the journal file.
+    len: u64,
+    /// Size at which the journal is next compacted.
+    compact_at: u64,
+}
+
+impl Journal {
+    /// Opens the journal at `path` for appending after it has been rewritten.
+    async fn reopen(path: &Path, len: u64) -> Result<Journal> {
+        let file = OpenOptions::new().append(true).open(path).await?;
+        Ok(Journal {
+            file,
+            len,
+            compact_at: COMPACT_MIN_BYTES.max(len.saturating_mul(2)),
+        })
+    }
+
+    /// Appends a job snapshot.
+    async fn append(&mut self, job: &Job) -> Result<()> {
+        let mut line = serialize_job(job)?;
+        line.push('\n');
+
+        self.file.write_all(line.as_bytes()).await.map_err(|e| {
+            error!(job_id = %job.id, error = %e, "Failed to write job journal entry");
+            PegasusError::IoError(e)
+        })?;
+        self.file.flush().await?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl JobStore {
+    /// Opens the job journal at `path`, replaying any existing entries.
+    ///
+    /// Jobs that were still running when the server stopped are marked as failed, and
+    /// the journal is compacted so it only holds one line per job.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The location of the JSON-lines journal file.
+    /// * `retention` - How long finished jobs are kept before they are moved to the
+    ///   archive file next to the journal, or `None` to keep them forever.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the opened `JobStore`, or a `PegasusError` on failure.
+    pub async fn open(path: &Path, retention: Option<Duration>) -> Result<Self> {
+        info!(path = %path.display(), "Opening job store");
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| {
+                error!(error = %e, path = %parent.display(), "Failed to create job store directory");
+                PegasusError::IoError(e)
+            })?;
+        }
+
+        // Replay the journal, keeping the latest snapshot of every job
+        let mut jobs: HashMap<String, Job> = HashMap::new();
+        match tokio::fs::read_to_string(path).await {
+            Ok(contents) => {
+                for (line_no, line) in contents.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<Job>(line) {
+                        Ok(job) => {
+                            jobs.insert(job.id.clone(), job);
+                        }
+                        Err(e) => {
+                            warn!(line = line_no + 1, error = %e, "Skipping malformed job journal entry");
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                error!(error = %e, path = %path.display(), "Failed to read job journal");
+                return Err(PegasusError::IoError(e));
+            }
+        }
+
+        // Anything that was in flight when we stopped will never finish
+        let now = Utc::now();
+        for job in jobs.values_mut() {
+            if !job.status.is_terminal() {
+                warn!(job_id = %job.id, status = job.status.as_str(), "Marking interrupted job as failed");
+                job.status = JobStatus::Failed;
+                job.error = Some("Interrupted by server restart".to_string());
+                job.updated_at = now;
+                job.finished_at = Some(now);
+            }
+        }
+
+        // Move expired jobs to the archive and compact the journal by rewriting it with
+        // a single snapshot per job
+        let retention = retention.and_then(|retention| chrono::Duration::from_std(retention).ok());
+        let expired = expired_jobs(&jobs, retention, now);
+        archive_jobs(path, &expired).await?;
+        for job in &expired {
+            jobs.remove(&job.id);
+        }
+        let len = write_snapshot(path, &jobs).await?;
+        let journal = Journal::reopen(path, len).await?;
+
+        info!(
+            jobs = jobs.len(),
+            archived = expired.len(),
+            "Job store loaded"
+        );
+        Ok(JobStore {
+            jobs: RwLock::new(jobs),
+            journal: Mutex::new(journal),
+            path: path.to_path_buf(),
+            retention,
+            transitions: broadcast::channel(TRANSITION_CAPACITY).0,
+        })
+    }
+
+    /// Registers a new job in the `queued` state under a freshly generated ID.
+    pub async fn create(&self, new_job: NewJob) -> Result<Job> {
+        let now = Utc::now();
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            url: new_job.url,
+            root: new_job.root,
+            output_dir: new_job.output_dir,
+            processing_options: new_job.processing_options,
+            priority: new_job.priority,
+            destination: new_job.destination,
+            playlist: new_job.playlist,
+            naming: new_job.naming,
+            playlist_position: new_job.playlist_position,
+            parent_id: new_job.parent_id,
+            child_ids: Vec::new(),
+            subscription_id: new_job.subscription_id,
+            archive_key: new_job.archive_key,
+            batch_id: new_job.batch_id,
+            owner: new_job.owner,
+            extractor: None,
+            status: JobStatus::Queued,
+            created_at: now,
+            updated_at: now,
+            started_at: None,
+            finished_at: None,
+            output_path: None,
+            error: None,
+            error_code: None,
+        };
+
+        // Journaled first, so a job that could not be recorded never shows up
+        let mut journal = self.journal.lock().await;
+        journal.append(&job).await?;
+        self.jobs.write().await.insert(job.id.clone(), job.clone());
+        self.publish(None, &job);
+        self.compact_if_needed(&mut journal).await;
+        Ok(job)
+    }
+
+    /// Checks that the store is responsive and its journal is still on disk.
+    ///
+    /// Used by the readiness check: a journal that stays locked means writes are stuck,
+    /// and a missing file means the volume holding it went away.
+    pub async fn check(&self) -> Result<()> {
+        let journal = tokio::time::timeout(Duration::from_secs(2), self.journal.lock())
+            .await
+            .map_err(|_| {
+                PegasusError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "job store journal stayed locked for 2s",
+                ))
+            })?;
+        journal.file.metadata().await?;
+        drop(journal);
+        tokio::fs::metadata(&self.path).await?;
+        Ok(())
+    }
+
+    /// Subscribes to the state changes of every job, starting with the next one.
+    pub fn subscribe(&self) -> broadcast::Receiver<JobTransition> {
+        self.transitions.subscribe()
+    }
+
+    /// Returns a snapshot of a single job.
+    ///
+    /// Finished jobs that have been moved to the archive are no longer found.
+    pub async fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.read().await.get(id).cloned()
+    }
+
+    /// Lists jobs matching `filter`, newest first.
+    ///
+    /// # Returns
+    ///
+    /// The requested page of jobs and the total number of jobs matching the filter.
+    pub async fn list(&self, filter: &JobFilter) -> (Vec<Job>, usize) {
+        let jobs = self.jobs.read().await;
+        let mut matching: Vec<&Job> = jobs
+            .values()
+            .filter(|job| filter.statuses.is_empty() || filter.statuses.contains(&job.status))
+            .filter(|job| filter.owner.is_none() || job.owner == filter.owner)
+            .collect();
+        matching.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+
+        let total = matching.len();
+        let page = matching
+            .into_iter()
+            .skip(filter.offset)
+            .take(filter.limit)
+            .cloned()
+            .collect();
+        (page, total)
+    }
+
+    /// Moves a job into a new non-terminal state.
+    pub async fn set_status(&self, id: &str, status: JobStatus) -> Result<Option<Job>> {
+        self.update(id, |job| {
+            if job.started_at.is_none() && status != JobStatus::Queued {
+                job.started_at = Some(Utc::now());
+            }
+            job.status = status;
+        })
+        .await
+    }
+
+    /// Marks a job as completed with the path of its final output file.
+    pub async fn complete(&self, id: &str, output_path: &str) -> Result<Option<Job>> {
+        self.update(id, |job| {
+            job.status = JobStatus::Completed;
+            job.output_path = Some(output_path.to_string());
+            job.error = None;
+            job.error_code = None;
+            job.finished_at = Some(Utc::now());
+        })
+        .await
+    }
+
+    /// Records the child jobs of a playlist job and moves it to the `expanded` state.
+    pub async fn expand(&self, id: &str, child_ids: Vec<String>) -> Result<Option<Job>> {
+        self.update(id, |job| {
+            job.status = JobStatus::Expanded;
+            job.child_ids = child_ids;
+        })
+        .await
+    }
+
+    /// Marks a job as failed, recording the error that caused it.
+    pub async fn fail(&self, id: &str, error: &PegasusError) -> Result<Option<Job>> {
+        self.update(id, |job| {
+            job.status = JobStatus::Failed;
+            job.error = Some(error.to_string());
+            job.error_code = Some(error.code().to_string());
+            job.finished_at = Some(Utc::now());
+        })
+        .await
+    }
+
+    /// Marks a job as cancelled.
+    pub async fn cancel(&self, id: &str) -> Result<Option<Job>> {
+        self.update(id, |job| {
+            job.status = JobStatus::Cancelled;
+            job.error = Some("Cancelled by user".to_string());
+            job.finished_at = Some(Utc::now());
+        })
+        .await
+    }
+
+    /// Applies `change` to a job and persists the result.
+    ///
+    /// Jobs that have already reached a terminal state are left untouched.
+    ///
+    /// # Returns
+    ///
+    /// The updated job, or `None` if no job with that ID exists.
+    pub async fn update<F>(&self, id: &str, change: F) -> Result<Option<Job>>
+    where
+        F: FnOnce(&mut Job),
+    {
+        // Every change holds the journal lock from reading the job to storing the result,
+        // so changes reach the journal in the order they are applied; replaying it would
+        // otherwise bring back an older snapshot. The new snapshot is written before it
+        // replaces the old one in memory, so if writing fails readers keep seeing what
+        // is on disk, and the map itself is never locked while writing
+        let mut journal = self.journal.lock().await;
+        let Some(mut job) = self.get(id).await else {
+            return Ok(None);
+        };
+        if job.status.is_terminal() {
+            return Ok(Some(job));
+        }
+        let previous = job.status;
+        change(&mut job);
+        job.updated_at = Utc::now();
+
+        journal.append(&job).await?;
+        self.jobs.write().await.insert(job.id.clone(), job.clone());
+        if job.status != previous {
+            self.publish(Some(previous), &job);
+        }
+        self.compact_if_needed(&mut journal).await;
+        Ok(Some(job))
+    }
+
+    /// Tells subscribers about a state change. Nobody listening is not an error.
+    fn publish(&self, previous: Option<JobStatus>, job: &Job) {
+        let _ = self.transitions.send(JobTransition {
+            previous,
+            job: job.clone(),
+        });
+    }
+
+    /// Compacts the journal once it has grown to twice its compacted size, moving
+    /// expired finished jobs to the archive on the way.
+    ///
+    /// A failure is logged rather than returned: the change that triggered it has
+    /// already been recorded, and the journal is still complete.
+    async fn compact_if_needed(&self, journal: &mut Journal) {
+        if journal.len < journal.compact_at {
+            return;
+        }
+        if let Err(e) = self.compact(journal).await {
+            error!(error = %e, path = %self.path.display(), "Failed to compact job journal");
+            // Try again once it has grown some more rather than on every change
+            journal.compact_at = journal.len.saturating_add(COMPACT_MIN_BYTES);
+        }
+    }
+
+    /// Rewrites the journal with one snapshot per job, without the expired ones.
+    async fn compact(&self, journal: &mut Journal) -> Result<()> {
+        let before = journal.len;
+        // Holding the journal lock keeps every writer out, so the snapshot stays current
+        let mut retained = self.jobs.read().await.clone();
+        let expired = expired_jobs(&retained, self.retention, Utc::now());
+        for job in &expired {
+            retained.remove(&job.id);
+        }
+        archive_jobs(&self.path, &expired).await?;
+        let len = write_snapshot(&self.path, &retained).await?;
+        *journal = Journal::reopen(&self.path, len).await?;
+
+        let mut jobs = self.jobs.write().await;
+        for job in &expired {
+            jobs.remove(&job.id);
+        }
+        info!(
+            before,
+            after = len,
+            jobs = jobs.len(),
+            archived = expired.len(),
+            "Compacted job journal"
+        );
+        Ok(())
+    }
+}
+
+/// Finds the finished jobs that have been kept for longer than `retention`.
+fn expired_jobs(
+    jobs: &HashMap<String, Job>,
+    retention: Option<chrono::Duration>,
+    now: DateTime<Utc>,
+) -> Vec<Job> {
+    let Some(retention) = retention else {
+        return Vec::new();
+    };
+    jobs.values()
+        .filter(|job| job.status.is_terminal())
+        .filter(|job| job.finished_at.unwrap_or(job.updated_at) + retention < now)
+        .cloned()
+        .collect()
+}
+
+/// Appends the given jobs to the archive file next to the journal.
+///
+/// Finished jobs leave the registry this way rather than being deleted, so their history
+/// is still on disk.
+async fn archive_jobs(path: &Path, jobs: &[Job]) -> Result<()> {
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for job in jobs {
+        lines.push_str(&serialize_job(job)?);
+        lines.push('\n');
+    }
+
+    let archive_path = archive_path(path);
+    let mut archive = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&archive_path)
+        .await?;
+    archive.write_all(lines.as_bytes()).await.map_err(|e| {
+        error!(error = %e, path = %archive_path.display(), "Failed to write job archive");
+        PegasusError::IoError(e)
+    })?;
+    archive.flush().await?;
+    info!(jobs = jobs.len(), path = %archive_path.display(), "Archived finished jobs");
+    Ok(())
+}
+
+/// Where jobs moved out of the journal at `path` are kept, e.g. `jobs.archive.jsonl`.
+fn archive_path(path: &Path) -> PathBuf {
+    path.with_extension("archive.jsonl")
+}
+
+/// Replaces the journal at `path` with one line per job.
+///
+/// # Returns
+///
+/// The size of the new journal in bytes.
+async fn write_snapshot(path: &Path, jobs: &HashMap<String, Job>) -> Result<u64> {
+    let mut compacted = String::new();
+    for job in jobs.values() {
+        compacted.push_str(&serialize_job(job)?);
+        compacted.push('\n');
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp_path, &compacted).await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    Ok(compacted.len() as u64)
+}
+
+/// Serializes a job into a single journal line.
+fn serialize_job(job: &Job) -> Result<String> {
+    serde_json::to_string(job)
+        .map_err(|e| PegasusError::Unknown(format!("Failed to serialize job {}: {}", job.id, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn new_job(url: &str) -> NewJob {
+        NewJob {
+            url: url.to_string(),
+            root: None,
+            output_dir: None,
+            processing_options: ProcessingOptions::default(),
+            priority: 0,
+            destination: None,
+            playlist: PlaylistOptions::default(),
+            naming: NamingOptions::default(),
+            playlist_position: None,
+            parent_id: None,
+            subscription_id: None,
+            archive_key: None,
+            batch_id: None,
+            owner: None,
+        }
+    }
+
+    fn journal_lines(path: &Path) -> Vec<Job> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn replay_restores_the_latest_snapshot_of_every_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.jsonl");
+
+        let store = JobStore::open(&path, None).await.unwrap();
+        let done = store
+            .create(new_job("https://example.com/a"))
+            .await
+            .unwrap();
+        let failed = store
+            .create(new_job("https://example.com/b"))
+            .await
+            .unwrap();
+        store
+            .set_status(&done.id, JobStatus::Downloading)
+            .await
+            .unwrap();
+        store.complete(&done.id, "/media/a.mp4").await.unwrap();
+        store
+            .fail(
+                &failed.id,
+                &PegasusError::VideoUnavailable("gone".to_string()),
+            )
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = JobStore::open(&path, None).await.unwrap();
+        let done = store.get(&done.id).await.unwrap();
+        assert_eq!(done.status, JobStatus::Completed);
+        assert_eq!(done.output_path.as_deref(), Some("/media/a.mp4"));
+        assert!(done.started_at.is_some());
+        let failed = store.get(&failed.id).await.unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.error_code.as_deref(), Some("video_unavailable"));
+
+        // Compacted to one line per job
+        assert_eq!(journal_lines(&path).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn replay_fails_interrupted_jobs_and_skips_malformed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.jsonl");
+
+        let store = JobStore::open(&path, None).await.unwrap();
+        let running = store
+            .create(new_job("https://example.com/a"))
+            .await
+            .unwrap();
+        let queued = store
+            .create(new_job("https://example.com/b"))
+            .await
+            .unwrap();
+        store
+            .set_status(&running.id, JobStatus::Downloading)
+            .await
+            .unwrap();
+        drop(store);
+
+        // A line cut short by a crash, and a blank line
+        let mut contents = std::fs::read_to_string(&path).unwrap();
+        contents.push_str("{\"id\":\"trunc\n\n");
+        std::fs::write(&path, contents).unwrap();
+
+        let store = JobStore::open(&path, None).await.unwrap();
+        for id in [&running.id, &queued.id] {
+            let job = store.get(id).await.unwrap();
+            assert_eq!(job.status, JobStatus::Failed);
+            assert_eq!(job.error.as_deref(), Some("Interrupted by server restart"));
+            assert!(job.finished_at.is_some());
+        }
+        assert!(store.get("trunc").await.is_none());
+        assert_eq!(journal_lines(&path).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn terminal_jobs_are_not_updated() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JobStore::open(&dir.path().join("jobs.jsonl"), None)
+            .await
+            .unwrap();
+        let job = store
+            .create(new_job("https://example.com/a"))
+            .await
+            .unwrap();
+        store.cancel(&job.id).await.unwrap();
+
+        let after = store
+            .set_status(&job.id, JobStatus::Downloading)
+            .await
+            .unwrap();
+        assert_eq!(after.unwrap().status, JobStatus::Cancelled);
+        assert!(store.update("missing", |_| {}).await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_updates_reach_the_journal_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.jsonl");
+        let store = Arc::new(JobStore::open(&path, None).await.unwrap());
+        let job = store
+            .create(new_job("https://example.com/a"))
+            .await
+            .unwrap();
+
+        let tasks: Vec<_> = (0..64)
+            .map(|priority| {
+                let store = store.clone();
+                let id = job.id.clone();
+                tokio::spawn(async move {
+                    store
+                        .update(&id, |job| job.priority = priority)
+                        .await
+                        .unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // The last line written is the state the store ended up in
+        let in_memory = store.get(&job.id).await.unwrap();
+        let last = journal_lines(&path).pop().unwrap();
+        assert_eq!(last.priority, in_memory.priority);
+        assert_eq!(last.updated_at, in_memory.updated_at);
+    }
+
+    #[tokio::test]
+    async fn failed_journal_writes_leave_the_job_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.jsonl");
+        let store = JobStore::open(&path, None).await.unwrap();
+        let job = store
+            .create(new_job("https://example.com/a"))
+            .await
+            .unwrap();
+
+        // A read-only handle makes every append fail
+        store.journal.lock().await.file = File::open(&path).await.unwrap();
+        assert!(
+            store
+                .set_status(&job.id, JobStatus::Downloading)
+                .await
+                .is_err()
+        );
+        assert!(
+            store
+                .create(new_job("https://example.com/b"))
+                .await
+                .is_err()
+        );
+
+        assert_eq!(store.get(&job.id).await.unwrap().status, JobStatus::Queued);
+        let (jobs, total) = store
+            .list(&JobFilter {
+                limit: 10,
+                ..Default::default()
+            })
+            .await;
+        assert_eq!((jobs.len(), total), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn the_journal_is_compacted_once_it_has_doubled() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.jsonl");
+        let store = JobStore::open(&path, None).await.unwrap();
+        let job = store
+            .create(new_job("https://example.com/a"))
+            .await
+            .unwrap();
+        store
+            .create(new_job("https://example.com/b"))
+            .await
+            .unwrap();
+
+        let single = std::fs::metadata(&path).unwrap().len();
+        store.journal.lock().await.compact_at = single * 2;
+        let mut priority = 0;
+        let mut len = single;
+        loop {
+            priority += 1;
+            store
+                .update(&job.id, |job| job.priority = priority)
+                .await
+                .unwrap();
+            let grown = std::fs::metadata(&path).unwrap().len();
+            if grown < len {
+                break;
+            }
+            len = grown;
+            assert!(priority < 100, "journal was never compacted");
+        }
+
+        // Back to one line per job, holding the latest state, and still appendable
+        let lines = journal_lines(&path);
+        assert_eq!(lines.len(), 2);
+        let compacted = lines.iter().find(|line| line.id == job.id).unwrap();
+        assert_eq!(compacted.priority, priority);
+        store.cancel(&job.id).await.unwrap();
+        assert_eq!(journal_lines(&path).len(), 3);
+        assert!(store.journal.lock().await.compact_at >= COMPACT_MIN_BYTES);
+    }
+
+    #[tokio::test]
+    async fn expired_finished_jobs_move_to_the_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.jsonl");
+        let store = JobStore::open(&path, None).await.unwrap();
+        let old = store
+            .create(new_job("https://example.com/old"))
+            .await
+            .unwrap();
+        let running = store
+            .create(new_job("https://example.com/running"))
+            .await
+            .unwrap();
+        let recent = store
+            .create(new_job("https://example.com/recent"))
+            .await
+            .unwrap();
+        store.complete(&old.id, "/media/old.mp4").await.unwrap();
+        store
+            .complete(&recent.id, "/media/recent.mp4")
+            .await
+            .unwrap();
+        store
+            .set_status(&running.id, JobStatus::Downloading)
+            .await
+            .unwrap();
+        drop(store);
+
+        // Pretend the old job finished (and the running one started) long ago
+        let long_ago = Utc::now() - chrono::Duration::days(40);
+        let rewritten: Vec<String> = journal_lines(&path)
+            .into_iter()
+            .map(|mut job| {
+                if job.id != recent.id {
+                    job.updated_at = long_ago;
+                    job.finished_at = job.finished_at.map(|_| long_ago);
+                }
+                serde_json::to_string(&job).unwrap()
+            })
+            .collect();
+        std::fs::write(&path, rewritten.join("\n")).unwrap();
+
+        let retention = Some(Duration::from_secs(30 * 24 * 60 * 60));
+        let store = JobStore::open(&path, retention).await.unwrap();
+        assert!(store.get(&old.id).await.is_none());
+        assert!(store.get(&recent.id).await.is_some());
+        // Interrupted just now by the restart, so kept
+        assert!(store.get(&running.id).await.is_some());
+        assert_eq!(journal_lines(&path).len(), 2);
+
+        let archived = journal_lines(&archive_path(&path));
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].id, old.id);
+        assert_eq!(archived[0].output_path.as_deref(), Some("/media/old.mp4"));
+    }
+}
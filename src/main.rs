@@ -3,13 +3,27 @@
 
 // Declare modules
 pub mod api;
+pub mod auth;
+pub mod batches;
 pub mod config;
+pub mod credentials;
 pub mod download;
 pub mod error;
+pub mod jobs;
+pub mod metrics;
+pub mod pipeline;
 pub mod process;
+pub mod queue;
+pub mod roots;
+pub mod subscriptions;
+pub mod system;
 pub mod transfer;
+pub mod webhooks;
 
+use std::future::IntoFuture;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 // Use common types
 use config::Config;
@@ -20,13 +34,15 @@ use axum::serve; // Updated import for serve
 use tokio::net::TcpListener;
 
 // Import tracing macros and subscriber initialization
-use tracing::{error, info};
+use tracing::{error, info, warn};
 // Import dotenvy for loading .env files
 use dotenvy::dotenv;
 // Import EnvFilter for tracing configuration
-use std::process::Command;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
+/// How long open connections may keep the server alive after a shutdown signal.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Load environment variables from .env file
@@ -46,26 +62,113 @@ async fn main() -> Result<()> {
 
     // Basic placeholder
     info!("Pegasus starting...");
+    system::mark_started();
+
+    // Load and validate the configuration before touching anything else
+    let config = Config::load().inspect_err(|e| error!(error = %e, "Invalid configuration"))?;
+
+    // Open the persistent job registry
+    let job_store = jobs::JobStore::open(
+        &PathBuf::from(&config.paths.job_store),
+        config.jobs.retention(),
+    )
+    .await?;
 
-    // Load configuration (placeholder)
-    let _config = Config::load();
-    // Log configuration loading
-    info!("Configuration loaded (placeholder)");
+    // Open the subscription registry and the download archive
+    let subscriptions =
+        subscriptions::SubscriptionStore::open(&PathBuf::from(&config.paths.subscriptions)).await?;
+    let archive = subscriptions::archive::DownloadArchive::open(&PathBuf::from(
+        &config.paths.download_archive,
+    ))
+    .await?;
 
-    // Install ffmpeg and yt-dlp
-    install_binaries().await?;
+    // Open the batch registry
+    let batches = batches::BatchStore::open(&PathBuf::from(&config.paths.batches)).await?;
+
+    // Open the user and API token registry, creating the first administrator if needed
+    let auth_store = auth::AuthStore::open(
+        &PathBuf::from(&config.paths.auth_store),
+        config.auth.session_ttl_hours,
+    )
+    .await?;
+    if config.auth.enabled {
+        let password = std::env::var("PEGASUS_ADMIN_PASSWORD").ok();
+        if let Some(token) = auth_store.bootstrap(password.as_deref()).await? {
+            // The token is only stored hashed, so this is the only chance to see it. It is
+            // printed directly so it shows up whatever the log filter is.
+            info!(
+                username = auth::BOOTSTRAP_ADMIN,
+                "Created the first administrator"
+            );
+            eprintln!(
+                "Created the administrator '{}' with the API token below. Store it now; it will not be shown again.
+{}",
+                auth::BOOTSTRAP_ADMIN,
+                token
+            );
+        }
+    }
+
+    // Open the encrypted store of site cookies and logins
+    let vault =
+        credentials::CredentialVault::open(&PathBuf::from(&config.paths.vault), &config.vault)
+            .await?;
+
+    // Check that ffmpeg and yt-dlp work
+    install_binaries(&config.binaries).await?;
 
     // Create the Axum router
-    let app = api::create_router();
+    // Create the bounded download queue
+    let job_queue = queue::JobQueue::new(queue::QueueLimits {
+        max_concurrent: config.limits.max_concurrent_downloads,
+        per_domain: config.limits.max_downloads_per_domain,
+        domain_overrides: config.limits.domains.clone(),
+    });
+
+    // Build a transfer backend for every configured destination
+    let transfers = transfer::TransferRegistry::from_config(
+        &config.transfer.destinations,
+        config.transfer.default_destination.clone(),
+    )?;
+
+    // Reuse extracted info JSON across previews, info lookups and downloads
+    let info_cache =
+        download::info_cache::InfoCache::new(Duration::from_secs(config.cache.info_ttl_secs));
 
-    // Read server port from environment variable, defaulting to 8000
-    let port = std::env::var("SERVER_PORT")
-        .ok()
-        .and_then(|s| s.parse::<u16>().ok())
-        .unwrap_or(8000);
+    // Resolve the directories jobs may download into
+    let output_roots = roots::OutputRoots::from_config(&config)?;
+
+    // Deliver job events to the configured webhook endpoints
+    let webhook_dispatcher = webhooks::WebhookDispatcher::new(config.webhooks.clone());
+
+    let addr = format!("{}:{}", config.server.bind_address, config.server.port);
+    let state = api::AppState {
+        config: Arc::new(config),
+        jobs: Arc::new(job_store),
+        queue: job_queue,
+        cancellations: Arc::new(jobs::cancel::CancelRegistry::default()),
+        transfers: Arc::new(transfers),
+        subscriptions: Arc::new(subscriptions),
+        archive: Arc::new(archive),
+        info_cache: Arc::new(info_cache),
+        batches: Arc::new(batches),
+        auth: Arc::new(auth_store),
+        vault: Arc::new(vault),
+        webhooks: Arc::new(webhook_dispatcher),
+        roots: Arc::new(output_roots),
+    };
+
+    // Start polling subscriptions for new items
+    subscriptions::scheduler::spawn(state.clone());
+
+    // Start sending webhooks for job state changes
+    webhooks::spawn(state.clone());
+
+    // Start counting job submissions and outcomes for the metrics endpoint
+    metrics::spawn(state.clone());
+
+    let app = api::create_router(state);
 
-    // Define the address and port to listen on
-    let addr = format!("0.0.0.0:{}", port);
     info!(address = %addr, "Starting server"); // Structured logging with address
     let listener = TcpListener::bind(&addr).await.map_err(|e| {
         // Use reference to addr
@@ -75,82 +178,90 @@ async fn main() -> Result<()> {
         error::PegasusError::WebServerError(format!("Failed to bind to {}: {}", addr, e))
     })?;
 
-    // Run the Axum server
-    serve(listener, app.into_make_service())
-        .await
-        .map_err(|e| {
+    // Run the Axum server until it fails or the process is asked to stop. Event streams
+    // stay open indefinitely, so connections get a grace period rather than being
+    // waited for
+    let (stopping_tx, stopping_rx) = tokio::sync::oneshot::channel();
+    let server = serve(listener, app.into_make_service())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            let _ = stopping_tx.send(());
+        })
+        .into_future();
+    let grace_expired = async move {
+        if stopping_rx.await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(SHUTDOWN_GRACE).await;
+    };
+    let served = tokio::select! {
+        result = server => result.map_err(|e| {
             // Log the server error
             error!(error = %e, "Server error");
             // Wrap the Hyper error into our custom error type
             error::PegasusError::WebServerError(format!("Server failed: {}", e))
-        })?;
+        }),
+        _ = grace_expired => {
+            warn!(grace_secs = SHUTDOWN_GRACE.as_secs(), "Connections still open after the grace period, stopping anyway");
+            Ok(())
+        }
+    };
 
-    Ok(())
+    // Decrypted credentials must not outlive the server
+    credentials::remove_private_dir().await;
+    info!("Server stopped");
+    served
+}
+
+/// Resolves when the process receives Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(error = %e, "Failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown requested, stopping server");
 }
 
 /// Checks if ffmpeg and yt-dlp binaries are available and working.
 ///
+/// # Arguments
+///
+/// * `binaries` - The configured binary paths.
+///
 /// # Returns
 ///
 /// A `Result` containing `()` on success, or a `PegasusError` on failure.
-async fn install_binaries() -> Result<()> {
-    // Create output directory if it doesn't exist
-    let output_dir = PathBuf::from("output");
-    if !output_dir.exists() {
-        info!("Creating output directory");
-        tokio::fs::create_dir_all(&output_dir).await.map_err(|e| {
-            error!(error = %e, "Failed to create output directory");
-            error::PegasusError::IoError(e)
-        })?;
-    }
-
+async fn install_binaries(binaries: &config::BinaryPaths) -> Result<()> {
     // Check if yt-dlp is available
-    info!("Checking if yt-dlp is available");
-    let yt_dlp_version = Command::new("yt-dlp")
-        .arg("--version")
-        .output()
-        .map_err(|e| {
-            error!(error = %e, "Failed to execute yt-dlp");
-            error::PegasusError::ExternalCommandError(format!(
-                "yt-dlp not found or not executable: {}",
-                e
-            ))
-        })?;
-
-    if !yt_dlp_version.status.success() {
-        let stderr = String::from_utf8_lossy(&yt_dlp_version.stderr);
-        error!(stderr = %stderr, "yt-dlp command failed");
-        return Err(error::PegasusError::ExternalCommandError(format!(
-            "yt-dlp command failed: {}",
-            stderr
-        )));
-    }
-
-    let version = String::from_utf8_lossy(&yt_dlp_version.stdout);
-    info!(version = %version.trim(), "yt-dlp version");
+    info!(path = %binaries.yt_dlp, "Checking if yt-dlp is available");
+    let version = system::binary_version(&binaries.yt_dlp, "--version").await?;
+    info!(version = %version, "yt-dlp version");
 
     // Check if ffmpeg is available
-    info!("Checking if ffmpeg is available");
-    let ffmpeg_version = Command::new("ffmpeg")
-        .arg("-version")
-        .output()
-        .map_err(|e| {
-            error!(error = %e, "Failed to execute ffmpeg");
-            error::PegasusError::ExternalCommandError(format!(
-                "ffmpeg not found or not executable: {}",
-                e
-            ))
-        })?;
-
-    if !ffmpeg_version.status.success() {
-        let stderr = String::from_utf8_lossy(&ffmpeg_version.stderr);
-        error!(stderr = %stderr, "ffmpeg command failed");
-        return Err(error::PegasusError::ExternalCommandError(format!(
-            "ffmpeg command failed: {}",
-            stderr
-        )));
-    }
-
-    info!("ffmpeg is available");
+    info!(path = %binaries.ffmpeg, "Checking if ffmpeg is available");
+    let version = system::binary_version(&binaries.ffmpeg, "-version").await?;
+    info!(version = %version, "ffmpeg is available");
     Ok(())
 }
@@ -0,0 +1,346 @@
+// src/metrics/mod.rs
+// This module contains the Prometheus metrics served at `/metrics`.
+//
+// The metrics live in one global registry, like the progress channel, so the download and
+// processing code can record process durations without threading a handle through every
+// call. Job counters are driven by the job store's state changes; gauges that describe
+// the current state (active jobs, info cache totals) are refreshed when Prometheus
+// scrapes them.
+
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    Counter, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+use crate::api::AppState;
+use crate::jobs::{Job, JobFilter, JobStatus};
+
+/// Label used when a job's extractor is not known, e.g. it failed before the info fetch.
+const UNKNOWN: &str = "unknown";
+
+/// Every metric Pegasus exports.
+pub struct Metrics {
+    registry: Registry,
+    jobs_submitted: IntCounterVec,
+    jobs_completed: IntCounterVec,
+    jobs_failed: IntCounterVec,
+    jobs_cancelled: IntCounterVec,
+    jobs_active: IntGauge,
+    jobs_queued: IntGauge,
+    downloaded_bytes: IntCounterVec,
+    download_throughput: HistogramVec,
+    process_duration: HistogramVec,
+    websocket_clients: IntGauge,
+    http_request_duration: HistogramVec,
+    info_cache_hits: IntCounter,
+    info_cache_misses: IntCounter,
+    info_cache_extractions_saved: IntCounter,
+    info_cache_seconds_saved: Counter,
+}
+
+/// The global metrics registry.
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("pegasus".to_string()), None).expect("valid metrics prefix");
+
+        let jobs_submitted = IntCounterVec::new(
+            Opts::new(
+                "jobs_submitted_total",
+                "Jobs submitted, by where they came from (api, batch, subscription, playlist)",
+            ),
+            &["source"],
+        )
+        .unwrap();
+        let jobs_completed = IntCounterVec::new(
+            Opts::new("jobs_completed_total", "Jobs completed, by extractor"),
+            &["extractor"],
+        )
+        .unwrap();
+        let jobs_failed = IntCounterVec::new(
+            Opts::new(
+                "jobs_failed_total",
+                "Jobs failed, by extractor and failure kind",
+            ),
+            &["extractor", "kind"],
+        )
+        .unwrap();
+        let jobs_cancelled = IntCounterVec::new(
+            Opts::new("jobs_cancelled_total", "Jobs cancelled, by extractor"),
+            &["extractor"],
+        )
+        .unwrap();
+        let jobs_active = IntGauge::new(
+            "jobs_active",
+            "Jobs currently fetching info, downloading, processing or transferring",
+        )
+        .unwrap();
+        let jobs_queued = IntGauge::new("jobs_queued", "Jobs waiting for a download slot").unwrap();
+        let downloaded_bytes = IntCounterVec::new(
+            Opts::new(
+                "downloaded_bytes_total",
+                "Bytes of media downloaded by yt-dlp, by extractor",
+            ),
+            &["extractor"],
+        )
+        .unwrap();
+        // 64 KiB/s up to 1 GiB/s
+        let download_throughput = HistogramVec::new(
+            HistogramOpts::new(
+                "download_throughput_bytes_per_second",
+                "Average throughput of completed downloads, by extractor",
+            )
+            .buckets(prometheus::exponential_buckets(65536.0, 4.0, 8).unwrap()),
+            &["extractor"],
+        )
+        .unwrap();
+        let process_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "process_duration_seconds",
+                "Run time of yt-dlp and ffmpeg processes, by binary and operation",
+            )
+            .buckets(vec![
+                0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+            ]),
+            &["binary", "operation"],
+        )
+        .unwrap();
+        let websocket_clients =
+            IntGauge::new("websocket_clients", "Connected WebSocket progress clients").unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency, by method, route and status",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let info_cache_hits = IntCounter::new(
+            "info_cache_hits_total",
+            "Info lookups answered from the cache",
+        )
+        .unwrap();
+        let info_cache_misses =
+            IntCounter::new("info_cache_misses_total", "Info lookups that ran yt-dlp").unwrap();
+        let info_cache_extractions_saved = IntCounter::new(
+            "info_cache_extractions_saved_total",
+            "yt-dlp extractions avoided by reusing cached info",
+        )
+        .unwrap();
+        let info_cache_seconds_saved = Counter::new(
+            "info_cache_extraction_seconds_saved_total",
+            "yt-dlp extraction time avoided by reusing cached info",
+        )
+        .unwrap();
+
+        for collector in [
+            Box::new(jobs_submitted.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(jobs_completed.clone()),
+            Box::new(jobs_failed.clone()),
+            Box::new(jobs_cancelled.clone()),
+            Box::new(jobs_active.clone()),
+            Box::new(jobs_queued.clone()),
+            Box::new(downloaded_bytes.clone()),
+            Box::new(download_throughput.clone()),
+            Box::new(process_duration.clone()),
+            Box::new(websocket_clients.clone()),
+            Box::new(http_request_duration.clone()),
+            Box::new(info_cache_hits.clone()),
+            Box::new(info_cache_misses.clone()),
+            Box::new(info_cache_extractions_saved.clone()),
+            Box::new(info_cache_seconds_saved.clone()),
+        ] {
+            registry.register(collector).expect("unique metric names");
+        }
+
+        Metrics {
+            registry,
+            jobs_submitted,
+            jobs_completed,
+            jobs_failed,
+            jobs_cancelled,
+            jobs_active,
+            jobs_queued,
+            downloaded_bytes,
+            download_throughput,
+            process_duration,
+            websocket_clients,
+            http_request_duration,
+            info_cache_hits,
+            info_cache_misses,
+            info_cache_extractions_saved,
+            info_cache_seconds_saved,
+        }
+    }
+}
+
+/// Records how long a yt-dlp or ffmpeg process ran.
+///
+/// # Arguments
+///
+/// * `binary` - `yt-dlp` or `ffmpeg`.
+/// * `operation` - What the process did, e.g. `info`, `download` or `process`.
+/// * `elapsed` - The process's run time.
+pub fn observe_process(binary: &str, operation: &str, elapsed: Duration) {
+    METRICS
+        .process_duration
+        .with_label_values(&[binary, operation])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Records a completed download's size and average throughput.
+pub fn record_download(extractor: Option<&str>, bytes: u64, elapsed: Duration) {
+    let extractor = extractor.unwrap_or(UNKNOWN);
+    METRICS
+        .downloaded_bytes
+        .with_label_values(&[extractor])
+        .inc_by(bytes);
+    if !elapsed.is_zero() {
+        METRICS
+            .download_throughput
+            .with_label_values(&[extractor])
+            .observe(bytes as f64 / elapsed.as_secs_f64());
+    }
+}
+
+/// Records the latency of an HTTP request.
+pub fn observe_http_request(method: &str, route: &str, status: u16, elapsed: Duration) {
+    METRICS
+        .http_request_duration
+        .with_label_values(&[method, route, &status.to_string()])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Counts a WebSocket client as connected until the returned guard is dropped.
+pub fn websocket_connected() -> WebSocketGuard {
+    METRICS.websocket_clients.inc();
+    WebSocketGuard
+}
+
+/// Keeps a WebSocket client counted in `pegasus_websocket_clients`.
+pub struct WebSocketGuard;
+
+impl Drop for WebSocketGuard {
+    fn drop(&mut self) {
+        METRICS.websocket_clients.dec();
+    }
+}
+
+/// Renders every metric in the Prometheus text exposition format.
+///
+/// The gauges describing the current state are refreshed first.
+pub async fn render(state: &AppState) -> String {
+    refresh(state).await;
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer) {
+        error!(error = %e, "Failed to encode metrics");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// The content type of `render`'s output.
+pub fn content_type() -> String {
+    TextEncoder::new().format_type().to_string()
+}
+
+// Brings the point-in-time metrics up to date
+async fn refresh(state: &AppState) {
+    let filter = JobFilter {
+        statuses: JobStatus::ACTIVE.to_vec(),
+        limit: usize::MAX,
+        ..JobFilter::default()
+    };
+    let (active, _) = state.jobs.list(&filter).await;
+    let queued = active
+        .iter()
+        .filter(|job| job.status == JobStatus::Queued)
+        .count();
+    // Playlist jobs wait for their entries rather than doing any work themselves
+    let running = active
+        .iter()
+        .filter(|job| !matches!(job.status, JobStatus::Queued | JobStatus::Expanded))
+        .count();
+    METRICS.jobs_queued.set(queued as i64);
+    METRICS.jobs_active.set(running as i64);
+
+    // The cache keeps its own totals; the counters catch up with them
+    let stats = state.info_cache.stats();
+    catch_up(&METRICS.info_cache_hits, stats.hits);
+    catch_up(&METRICS.info_cache_misses, stats.misses);
+    catch_up(
+        &METRICS.info_cache_extractions_saved,
+        stats.extractions_saved,
+    );
+    let saved = stats.extraction_seconds_saved - METRICS.info_cache_seconds_saved.get();
+    if saved > 0.0 {
+        METRICS.info_cache_seconds_saved.inc_by(saved);
+    }
+}
+
+fn catch_up(counter: &IntCounter, total: u64) {
+    counter.inc_by(total.saturating_sub(counter.get()));
+}
+
+/// Starts counting job submissions and outcomes from the job store's state changes.
+pub fn spawn(state: AppState) {
+    let mut transitions = state.jobs.subscribe();
+    tokio::spawn(async move {
+        info!("Job metrics collector started");
+        loop {
+            match transitions.recv().await {
+                Ok(transition) if transition.previous.is_none() => {
+                    METRICS
+                        .jobs_submitted
+                        .with_label_values(&[source(&transition.job)])
+                        .inc();
+                }
+                Ok(transition) => record_outcome(&transition.job),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!(
+                        missed,
+                        "Job metrics collector fell behind; some job events were not counted"
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+// Counts a job that has just finished
+fn record_outcome(job: &Job) {
+    let extractor = job.extractor.as_deref().unwrap_or(UNKNOWN);
+    match job.status {
+        JobStatus::Completed => METRICS.jobs_completed.with_label_values(&[extractor]).inc(),
+        JobStatus::Failed => {
+            let kind = job.error_code.as_deref().unwrap_or(UNKNOWN);
+            METRICS
+                .jobs_failed
+                .with_label_values(&[extractor, kind])
+                .inc()
+        }
+        JobStatus::Cancelled => METRICS.jobs_cancelled.with_label_values(&[extractor]).inc(),
+        _ => {}
+    }
+}
+
+// Where a job came from, for `pegasus_jobs_submitted_total`
+fn source(job: &Job) -> &'static str {
+    if job.parent_id.is_some() {
+        "playlist"
+    } else if job.subscription_id.is_some() {
+        "subscription"
+    } else if job.batch_id.is_some() {
+        "batch"
+    } else {
+        "api"
+    }
+}
@@ -0,0 +1,765 @@
+// src/pipeline/mod.rs
+// This module drives a job through its stages once it has been recorded in the job store.
+
+mod playlist;
+pub mod retry;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::api::AppState;
+use crate::api::handlers::{send_batch_update, send_progress_update};
+use crate::batches::{self, Batch, BatchSubmission, RejectedUrl};
+use crate::download;
+use crate::download::naming::{self, Claim, OutputTarget, OutputTemplate, TemplateContext};
+use crate::error::{PegasusError, Result};
+use crate::jobs::cancel::CancelToken;
+use crate::jobs::{Job, JobStatus, NewJob};
+use crate::process;
+use crate::transfer::{self, TransferContext, TransferTarget};
+
+/// Records a new job and places it on the download queue.
+///
+/// This is the entry point for every job that does not come from a playlist expansion,
+/// whether it was submitted through the API or found by a subscription.
+///
+/// # Returns
+///
+/// A `Result` containing the recorded job.
+pub async fn submit_job(state: &AppState, new_job: NewJob) -> Result<Job> {
+    // Downloads go into the requested subdirectory of the requested output root. This is
+    // checked before the job is recorded, so a rejected directory leaves no trace but
+    // the audit log entry
+    let target_dir = state.roots.resolve(
+        new_job.root.as_deref(),
+        new_job.output_dir.as_deref(),
+        new_job.owner.as_deref(),
+    )?;
+
+    // Record the job in the registry before any work starts
+    let job = state.jobs.create(new_job).await?;
+    info!(job_id = %job.id, url = %job.url, "Generated new job ID");
+
+    // Send initial progress update
+    send_progress_update(&job.id, &job.url, "starting", 0.0, "Preparing download...");
+
+    // Hand the job to the queue; it starts once a worker slot is free
+    enqueue_job(state, &job, target_dir);
+    Ok(job)
+}
+
+/// Submits one job per URL as a named batch.
+///
+/// Every job is created from `template` with its URL and the batch ID filled in. A URL
+/// that cannot be submitted does not stop the others; it is reported back instead, and
+/// the batch is recorded with the jobs that were queued. If the batch itself cannot be
+/// recorded, its jobs are cancelled again rather than left running outside any batch.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `name` - The batch's display name.
+/// * `urls` - The normalized, de-duplicated URLs to submit.
+/// * `template` - The options shared by every job in the batch.
+///
+/// # Returns
+///
+/// A `Result` containing the recorded batch, with its member jobs in URL order, and the
+/// URLs that were rejected. Fails if no URL could be submitted at all, with the error
+/// of the first one, or if the batch cannot be recorded.
+pub async fn submit_batch(
+    state: &AppState,
+    name: String,
+    urls: Vec<String>,
+    template: NewJob,
+) -> Result<BatchSubmission> {
+    let mut batch = Batch {
+        id: Uuid::new_v4().to_string(),
+        name,
+        created_at: Utc::now(),
+        job_ids: Vec::with_capacity(urls.len()),
+        owner: template.owner.clone(),
+    };
+    info!(batch_id = %batch.id, name = %batch.name, urls = urls.len(), "Submitting batch");
+
+    let mut rejected = Vec::new();
+    let mut first_error = None;
+    for url in urls {
+        let new_job = NewJob {
+            url: url.clone(),
+            batch_id: Some(batch.id.clone()),
+            ..template.clone()
+        };
+        match submit_job(state, new_job).await {
+            Ok(job) => batch.job_ids.push(job.id),
+            Err(e) => {
+                error!(batch_id = %batch.id, url = %url, error = %e, "Failed to submit batch job");
+                rejected.push(RejectedUrl {
+                    url,
+                    code: e.code(),
+                    message: e.to_string(),
+                });
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    if batch.job_ids.is_empty()
+        && let Some(e) = first_error
+    {
+        return Err(e);
+    }
+
+    if let Err(e) = state.batches.create(batch.clone()).await {
+        error!(batch_id = %batch.id, error = %e, "Failed to record batch, cancelling its jobs");
+        for job_id in &batch.job_ids {
+            match cancel_job(state, job_id).await {
+                Ok(_) | Err(PegasusError::JobConflict(_)) => {}
+                Err(e) => error!(job_id = %job_id, error = %e, "Failed to cancel batch job"),
+            }
+        }
+        return Err(e);
+    }
+    // Members may already have finished while the rest were being submitted
+    update_batch(state, &batch.id).await;
+    if !rejected.is_empty() {
+        warn!(batch_id = %batch.id, rejected = rejected.len(), "Batch submitted without some of its URLs");
+    }
+    Ok(BatchSubmission { batch, rejected })
+}
+
+/// Places a recorded job on the download queue.
+///
+/// The job stays in the `queued` state until the queue hands it a worker slot, at which
+/// point [`run_job`] takes over.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `job` - The job record, as returned by `JobStore::create`.
+/// * `target_dir` - The directory the download should be written to.
+pub fn enqueue_job(state: &AppState, job: &Job, target_dir: PathBuf) {
+    // The token exists from the moment the job is queued so it can be cancelled at any point
+    let cancel = state.cancellations.register(&job.id);
+
+    let task_state = state.clone();
+    let task_job = job.clone();
+    state.queue.enqueue(
+        &job.id,
+        &job.url,
+        job.priority,
+        Box::pin(async move {
+            run_job(task_state, task_job, target_dir, cancel).await;
+        }),
+    );
+}
+
+/// Cancels a job, whether it is still waiting in the queue or already running.
+///
+/// Queued jobs are removed from the queue and marked cancelled immediately. Running jobs
+/// have their external processes killed; the job is marked cancelled once its task has
+/// cleaned up.
+///
+/// # Returns
+///
+/// The job record after the cancellation request, or `JobNotFound` / `JobConflict` if the
+/// job does not exist or has already finished.
+pub async fn cancel_job(state: &AppState, job_id: &str) -> Result<Job> {
+    let job = state
+        .jobs
+        .get(job_id)
+        .await
+        .ok_or_else(|| PegasusError::JobNotFound(job_id.to_string()))?;
+
+    if job.status.is_terminal() {
+        return Err(PegasusError::JobConflict(format!(
+            "Job {} has already finished with status {}",
+            job_id,
+            job.status.as_str()
+        )));
+    }
+
+    info!(job_id = %job_id, status = job.status.as_str(), "Cancelling job");
+
+    // A playlist job is cancelled together with every child that has not finished yet
+    if job.status == JobStatus::Expanded {
+        let cancelled = finish_cancelled(state, &job).await?;
+        for child_id in &job.child_ids {
+            match Box::pin(cancel_job(state, child_id)).await {
+                Ok(_) | Err(PegasusError::JobConflict(_)) => {}
+                Err(e) => error!(job_id = %child_id, error = %e, "Failed to cancel child job"),
+            }
+        }
+        return Ok(cancelled);
+    }
+
+    if state.queue.remove(job_id) {
+        // Never started, so there is nothing to kill or clean up
+        state.cancellations.remove(job_id);
+        return finish_cancelled(state, &job).await;
+    }
+
+    match state.cancellations.get(job_id) {
+        Some(token) => {
+            token.cancel();
+            Ok(job)
+        }
+        None => Err(PegasusError::JobConflict(format!(
+            "Job {} is not running",
+            job_id
+        ))),
+    }
+}
+
+/// Records a job as cancelled and sends the final progress update.
+async fn finish_cancelled(state: &AppState, job: &Job) -> Result<Job> {
+    let updated = state.jobs.cancel(&job.id).await?;
+    send_progress_update(&job.id, &job.url, "cancelled", 0.0, "Job cancelled");
+    settle_archive(state, job, Err(&PegasusError::Cancelled)).await;
+    if let Some(parent_id) = &job.parent_id {
+        playlist::update_parent(state, parent_id).await;
+    }
+    if let Some(batch_id) = &job.batch_id {
+        update_batch(state, batch_id).await;
+    }
+    Ok(updated.unwrap_or_else(|| Job {
+        status: JobStatus::Cancelled,
+        ..job.clone()
+    }))
+}
+
+/// Settles a subscription item's download archive claim once its job has finished.
+///
+/// Completed items are recorded so they are never fetched again. Cancelled ones are
+/// released so the next check of the subscription retries them, and so are failed ones
+/// until the failure is permanent or has happened too often.
+///
+/// # Arguments
+///
+/// * `outcome` - `Ok` if the job completed, or the error it failed or was cancelled with.
+async fn settle_archive(
+    state: &AppState,
+    job: &Job,
+    outcome: std::result::Result<(), &PegasusError>,
+) {
+    let (Some(subscription_id), Some(key)) = (&job.subscription_id, &job.archive_key) else {
+        return;
+    };
+    match outcome {
+        Ok(()) => {
+            if let Err(e) = state.archive.record(subscription_id, key).await {
+                error!(job_id = %job.id, key = %key, error = %e, "Failed to record download archive entry");
+            }
+        }
+        Err(PegasusError::Cancelled) => state.archive.release(subscription_id, key),
+        Err(failure) => {
+            if let Err(e) = state.archive.fail(subscription_id, key, failure).await {
+                error!(job_id = %job.id, key = %key, error = %e, "Failed to record download archive failure");
+            }
+        }
+    }
+}
+
+/// How a job's stages ended when they did not fail.
+enum StageOutcome {
+    /// The job produced a file at this location.
+    Finished(String),
+    /// The job was a playlist and has been expanded into child jobs.
+    Expanded,
+}
+
+/// Runs a job to completion, recording the outcome in the job store.
+async fn run_job(state: AppState, job: Job, target_dir: PathBuf, cancel: Arc<CancelToken>) {
+    let result = execute_stages(&state, &job, &target_dir, &cancel).await;
+    state.cancellations.remove(&job.id);
+
+    // Handle the overall result
+    match result {
+        Ok(StageOutcome::Expanded) => {
+            // The parent finishes once its children have, see `playlist::update_parent`
+            info!(job_id = %job.id, "Playlist job expanded");
+            return;
+        }
+        Ok(StageOutcome::Finished(output_path)) => {
+            info!(job_id = %job.id, file_path = %output_path, "Job completed successfully");
+            if let Err(e) = state.jobs.complete(&job.id, &output_path).await {
+                error!(job_id = %job.id, error = %e, "Failed to record job completion");
+            }
+            // Send completion update
+            send_progress_update(
+                &job.id,
+                &job.url,
+                "completed",
+                1.0,
+                "Job completed successfully",
+            );
+            settle_archive(&state, &job, Ok(())).await;
+        }
+        Err(PegasusError::Cancelled) => {
+            info!(job_id = %job.id, "Job cancelled");
+            if let Err(e) = finish_cancelled(&state, &job).await {
+                error!(job_id = %job.id, error = %e, "Failed to record job cancellation");
+            }
+        }
+        Err(e) => {
+            error!(job_id = %job.id, error = %e, "Job failed");
+            if let Err(store_err) = state.jobs.fail(&job.id, &e).await {
+                error!(job_id = %job.id, error = %store_err, "Failed to record job failure");
+            }
+            // Send error update
+            send_progress_update(
+                &job.id,
+                &job.url,
+                "error",
+                0.0,
+                &format!("Job failed: {}", e),
+            );
+            settle_archive(&state, &job, Err(&e)).await;
+        }
+    }
+
+    if let Some(parent_id) = &job.parent_id {
+        playlist::update_parent(&state, parent_id).await;
+    }
+    if let Some(batch_id) = &job.batch_id {
+        update_batch(&state, batch_id).await;
+    }
+}
+
+/// Sends a batch's aggregate progress to clients after one of its jobs has finished.
+async fn update_batch(state: &AppState, batch_id: &str) {
+    // The batch is recorded after its jobs were submitted, so early finishers find nothing
+    let Some(batch) = state.batches.get(batch_id).await else {
+        return;
+    };
+    let progress = batches::progress(&batch, &state.jobs).await;
+    if progress.finished {
+        info!(batch_id = %batch.id, completed = progress.completed, failed = progress.failed, cancelled = progress.cancelled, "Batch finished");
+    }
+    send_batch_update(&progress);
+}
+
+/// Runs the info lookup, download, any requested processing and the transfer for a job.
+///
+/// # Returns
+///
+/// A `Result` containing the final location of the job's output file, or
+/// `StageOutcome::Expanded` if the job's URL is a playlist.
+async fn execute_stages(
+    state: &AppState,
+    job: &Job,
+    target_dir: &Path,
+    cancel: &Arc<CancelToken>,
+) -> Result<StageOutcome> {
+    if cancel.is_cancelled() {
+        return Err(PegasusError::Cancelled);
+    }
+
+    // First, get video information to use for naming, or the entries of a playlist
+    info!(job_id = %job.id, "Fetching video information");
+    state
+        .jobs
+        .set_status(&job.id, JobStatus::FetchingInfo)
+        .await?;
+    send_progress_update(
+        &job.id,
+        &job.url,
+        "info",
+        0.1,
+        "Fetching video information...",
+    );
+    // Use the stored cookies or login for the site, if there are any. The temporary file
+    // yt-dlp reads them from lives until this job's stages are done.
+    let credentials = state.vault.for_url(&job.url).await?;
+    if let Some(credentials) = &credentials {
+        info!(job_id = %job.id, domain = %credentials.domain, kind = ?credentials.kind, "Using stored credentials");
+        if credentials.is_expired() {
+            warn!(job_id = %job.id, domain = %credentials.domain, "Stored cookies have expired");
+            send_progress_update(
+                &job.id,
+                &job.url,
+                "warning",
+                0.1,
+                &format!(
+                    "The stored cookies for {} have expired; the download may fail",
+                    credentials.domain
+                ),
+            );
+        }
+    }
+    let credentials = credentials.as_ref();
+
+    // A preview of the same URL moments ago has usually already extracted it
+    let playlist_args = job.playlist.yt_dlp_args();
+    let fetched = retry::with_retries(&state.config.retry, job, cancel, "info fetch", || {
+        state.info_cache.get_or_fetch(&job.url, &playlist_args, || {
+            download::get_video_info(
+                &job.url,
+                &playlist_args,
+                &state.config.binaries.yt_dlp,
+                state.config.timeouts.info_fetch(),
+                credentials,
+            )
+        })
+    })
+    .await?;
+    if fetched.cached {
+        let stats = state.info_cache.stats();
+        info!(job_id = %job.id, saved_ms = fetched.extraction_time.as_millis() as u64, total_saved_secs = stats.extraction_seconds_saved, extractions_saved = stats.extractions_saved, "Reusing cached video information");
+    }
+    let video_info = fetched.info.as_ref();
+    if cancel.is_cancelled() {
+        return Err(PegasusError::Cancelled);
+    }
+    if let Some(extractor) = download::info::extractor(video_info) {
+        state
+            .jobs
+            .update(&job.id, |job| job.extractor = Some(extractor))
+            .await?;
+    }
+
+    if download::playlist::is_playlist(video_info) {
+        if job.parent_id.is_some() {
+            return Err(PegasusError::DownloadError(
+                "Nested playlists are not supported".to_string(),
+            ));
+        }
+        playlist::expand(state, job, video_info, target_dir, cancel).await?;
+        return Ok(StageOutcome::Expanded);
+    }
+
+    // Name the file from the output template. The name is settled once, so a retried
+    // download resumes the same partial files
+    let video_id = video_info["id"].as_str().unwrap_or(&job.id);
+    let target = match claim_output(state, job, video_info, target_dir)? {
+        Claim::Download(target) => target,
+        Claim::Existing(path) => {
+            return keep_existing(state, job, path, None, target_dir, video_id, cancel).await;
+        }
+        Claim::InProgress { path, job_id } => {
+            return keep_existing(state, job, path, Some(job_id), target_dir, video_id, cancel)
+                .await;
+        }
+    };
+    let target = &target;
+
+    // A transcoded file is renamed to the profile's extension, which needs a claim of its
+    // own. If the policy keeps an existing file there, nothing needs downloading
+    let processed = match claim_processed(state, job, target, video_id)? {
+        Some(Claim::Download(processed)) => Some(processed),
+        Some(Claim::Existing(path)) => {
+            return keep_existing(state, job, path, None, target_dir, video_id, cancel).await;
+        }
+        Some(Claim::InProgress { path, job_id }) => {
+            return keep_existing(state, job, path, Some(job_id), target_dir, video_id, cancel)
+                .await;
+        }
+        None => None,
+    };
+
+    state
+        .jobs
+        .set_status(&job.id, JobStatus::Downloading)
+        .await?;
+
+    // Call the download function asynchronously with progress updates. yt-dlp resumes
+    // from its partial files, so a retried download does not start over, and it loads
+    // the info extracted above instead of extracting the URL again
+    let extraction_time = fetched.extraction_time;
+    let downloaded_file_path = retry::with_retries(
+        &state.config.retry,
+        job,
+        cancel,
+        "download",
+        || async move {
+            download::download_video_with_progress(
+                &job.url,
+                target,
+                &job.processing_options,
+                video_info,
+                &job.id,
+                cancel,
+                &state.config,
+                credentials,
+            )
+            .await
+        },
+    )
+    .await?;
+    // Counted once per download, however many attempts it took
+    state.info_cache.record_saved(extraction_time);
+    info!(job_id = %job.id, file_path = %downloaded_file_path, "Video download successful");
+    finish_stages(
+        state,
+        job,
+        downloaded_file_path,
+        processed.as_ref(),
+        target_dir,
+        video_id,
+        cancel,
+    )
+    .await
+}
+
+/// Runs any requested processing and the transfer for a file this job downloaded.
+///
+/// A transcoded file is written to its claimed `processed` path; other processing
+/// replaces the download in place.
+///
+/// # Returns
+///
+/// A `Result` containing the final location of the job's output file.
+async fn finish_stages(
+    state: &AppState,
+    job: &Job,
+    downloaded_file_path: String,
+    processed: Option<&OutputTarget>,
+    target_dir: &Path,
+    video_id: &str,
+    cancel: &Arc<CancelToken>,
+) -> Result<StageOutcome> {
+    // Run ffmpeg processing if the job asked for it
+    let post_process = &job.processing_options.post_process;
+    let output_path = if post_process.is_requested() {
+        state
+            .jobs
+            .set_status(&job.id, JobStatus::Processing)
+            .await?;
+        let input_path = Path::new(&downloaded_file_path);
+        let output_path = processed.map_or(input_path, |processed| processed.path.as_path());
+        process::process_media(
+            input_path,
+            output_path,
+            post_process,
+            &job.id,
+            &job.url,
+            cancel,
+            &state.config.binaries,
+        )
+        .await?
+    } else {
+        PathBuf::from(downloaded_file_path)
+    };
+
+    transfer_stage(state, job, output_path, target_dir, video_id, cancel).await
+}
+
+/// Finishes a job whose output file the collision policy keeps.
+///
+/// The file was not downloaded by this job, so it is delivered as it is: processing would
+/// replace it and remove the original. A file another job is still producing is waited
+/// for, then delivered to this job's own destination.
+///
+/// # Arguments
+///
+/// * `path` - The kept file.
+/// * `owner` - The job still producing the file, if any.
+///
+/// # Returns
+///
+/// A `Result` containing the final location of the job's output file, or a
+/// `JobConflict` if the job producing the file ended without leaving it in place.
+async fn keep_existing(
+    state: &AppState,
+    job: &Job,
+    path: PathBuf,
+    owner: Option<String>,
+    target_dir: &Path,
+    video_id: &str,
+    cancel: &Arc<CancelToken>,
+) -> Result<StageOutcome> {
+    if let Some(job_id) = owner {
+        send_progress_update(
+            &job.id,
+            &job.url,
+            "waiting",
+            0.0,
+            &format!(
+                "Waiting for job {}, which is downloading {}",
+                job_id,
+                path.display()
+            ),
+        );
+        let owner = wait_for_job(state, &job_id, cancel).await?;
+        if owner.status != JobStatus::Completed {
+            return Err(PegasusError::JobConflict(format!(
+                "Job {} producing {} ended as {}",
+                job_id,
+                path.display(),
+                owner.status.as_str()
+            )));
+        }
+    }
+    // Never report success for a file that is not there, e.g. one moved to the other
+    // job's destination
+    if !path.exists() {
+        return Err(PegasusError::JobConflict(format!(
+            "{} no longer exists",
+            path.display()
+        )));
+    }
+    send_progress_update(
+        &job.id,
+        &job.url,
+        "downloaded",
+        1.0,
+        &format!("Already downloaded: {}", path.display()),
+    );
+    transfer_stage(state, job, path, target_dir, video_id, cancel).await
+}
+
+/// Waits until another job reaches a terminal state.
+///
+/// # Returns
+///
+/// The job as it ended, `PegasusError::Cancelled` if this job is cancelled first, or a
+/// `JobConflict` if the job is not known.
+async fn wait_for_job(state: &AppState, job_id: &str, cancel: &CancelToken) -> Result<Job> {
+    // Subscribed before the first check, so a transition in between is not missed
+    let mut transitions = state.jobs.subscribe();
+    loop {
+        match state.jobs.get(job_id).await {
+            Some(other) if other.status.is_terminal() => return Ok(other),
+            Some(_) => {}
+            None => {
+                return Err(PegasusError::JobConflict(format!(
+                    "Job {} is not known",
+                    job_id
+                )));
+            }
+        }
+        // Any transition, or falling behind, means the job is looked up again
+        tokio::select! {
+            _ = cancel.cancelled() => return Err(PegasusError::Cancelled),
+            received = transitions.recv() => {
+                if let Err(broadcast::error::RecvError::Closed) = received {
+                    return Err(PegasusError::JobConflict(format!(
+                        "Stopped waiting for job {}",
+                        job_id
+                    )));
+                }
+            }
+        }
+    }
+}
+
+/// Delivers the job's output file to its destination, if the job has one.
+///
+/// The file keeps its path below `target_dir`, so directories from the output template
+/// are recreated at the destination, and the job's collision policy applies there.
+///
+/// # Returns
+///
+/// A `Result` containing the final location of the job's output file.
+async fn transfer_stage(
+    state: &AppState,
+    job: &Job,
+    output_path: PathBuf,
+    target_dir: &Path,
+    video_id: &str,
+    cancel: &Arc<CancelToken>,
+) -> Result<StageOutcome> {
+    let Some(destination) = &job.destination else {
+        return Ok(StageOutcome::Finished(output_path.display().to_string()));
+    };
+    if cancel.is_cancelled() {
+        return Err(PegasusError::Cancelled);
+    }
+    state
+        .jobs
+        .set_status(&job.id, JobStatus::Transferring)
+        .await?;
+    let collision = job
+        .naming
+        .collision
+        .unwrap_or(state.config.naming.collision);
+    let target = TransferTarget::new(
+        &output_path,
+        target_dir,
+        collision,
+        video_id,
+        &state.config.filenames,
+    )?;
+    let ctx = TransferContext::new(&job.id, &job.url, cancel.clone());
+    let location =
+        transfer::transfer_file(&state.transfers, destination, &output_path, &target, &ctx).await?;
+    Ok(StageOutcome::Finished(location))
+}
+
+/// Renders the job's output template and applies its collision policy.
+///
+/// The job's own template and policy take precedence over the configured ones.
+///
+/// # Returns
+///
+/// The claimed download target, or the existing file if the policy keeps it. A
+/// `ValidationError` if the target leads out of the output roots, or a `JobConflict` if
+/// no path can be claimed.
+fn claim_output(
+    state: &AppState,
+    job: &Job,
+    video_info: &Value,
+    target_dir: &Path,
+) -> Result<Claim> {
+    let config = &state.config.naming;
+    let template =
+        OutputTemplate::parse(job.naming.template.as_deref().unwrap_or(&config.template))?;
+    let context = TemplateContext {
+        ext: job.processing_options.output_extension(),
+        job_id: &job.id,
+        playlist: job.playlist_position.as_ref(),
+        sanitize: &state.config.filenames,
+    };
+    let relative = template.render(video_info, &context);
+    let policy = job.naming.collision.unwrap_or(config.collision);
+    let id = video_info["id"].as_str().unwrap_or(&job.id);
+
+    // The template only yields sanitized relative names, but a directory it names may
+    // already exist as a link out of the root
+    let path = target_dir.join(relative);
+    state.roots.verify(&path)?;
+    naming::claim(path, policy, id, &state.config.filenames, &job.id)
+}
+
+/// Claims the path a transcoded file is saved under, applying the job's collision
+/// policy.
+///
+/// # Returns
+///
+/// `None` if the job is not transcoded or the profile keeps the download's extension, in
+/// which case the processed file replaces the download.
+fn claim_processed(
+    state: &AppState,
+    job: &Job,
+    target: &OutputTarget,
+    video_id: &str,
+) -> Result<Option<Claim>> {
+    let Some(profile) = job.processing_options.post_process.profile else {
+        return Ok(None);
+    };
+    if profile.extension() == job.processing_options.output_extension() {
+        return Ok(None);
+    }
+    let policy = job
+        .naming
+        .collision
+        .unwrap_or(state.config.naming.collision);
+    // Shortened again, as the new extension may be longer
+    let file_name = state
+        .config
+        .filenames
+        .file_name(&target.stem(), &format!(".{}", profile.extension()));
+    naming::claim(
+        target.path.with_file_name(file_name),
+        policy,
+        video_id,
+        &state.config.filenames,
+        &job.id,
+    )
+    .map(Some)
+}
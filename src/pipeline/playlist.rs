@@ -0,0 +1,194 @@
+// src/pipeline/playlist.rs
+// Expands playlist jobs into child jobs and rolls the children's outcomes up into the parent.
+
+use std::path::Path;
+
+use chrono::Utc;
+use serde_json::Value;
+use tracing::{error, info};
+
+use super::{enqueue_job, settle_archive, update_batch};
+use crate::api::AppState;
+use crate::api::handlers::{ChildJob, send_expansion_update, send_progress_update};
+use crate::download::playlist::{self, PlaylistOptions, PlaylistPosition};
+use crate::error::{PegasusError, Result};
+use crate::jobs::cancel::CancelToken;
+use crate::jobs::{Job, JobStatus, NewJob};
+
+/// Creates and enqueues one child job per selected playlist entry.
+///
+/// Children inherit the parent's processing options, naming, priority and destination,
+/// and download into the parent's directory. Each child records its position in the
+/// playlist, so output templates can name files after it (the default template puts
+/// them in a subdirectory named after the playlist).
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `job` - The playlist job.
+/// * `info` - yt-dlp's flat playlist JSON for the job's URL.
+/// * `target_dir` - The parent job's download directory.
+/// * `cancel` - The parent job's cancellation token.
+pub(super) async fn expand(
+    state: &AppState,
+    job: &Job,
+    info: &Value,
+    target_dir: &Path,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let listing = playlist::parse_playlist(info);
+    let entries = playlist::select_entries(&listing, &job.playlist);
+    info!(job_id = %job.id, title = ?listing.title, available = listing.entries.len(), selected = entries.len(), "Expanding playlist");
+
+    if entries.is_empty() {
+        return Err(PegasusError::DownloadError(
+            "Playlist has no entries to download".to_string(),
+        ));
+    }
+    if cancel.is_cancelled() {
+        return Err(PegasusError::Cancelled);
+    }
+
+    let mut children = Vec::with_capacity(entries.len());
+    let mut announced = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let child = state
+            .jobs
+            .create(NewJob {
+                url: entry.url.clone(),
+                root: job.root.clone(),
+                output_dir: job.output_dir.clone(),
+                processing_options: job.processing_options.clone(),
+                priority: job.priority,
+                destination: job.destination.clone(),
+                playlist: PlaylistOptions::default(),
+                naming: job.naming.clone(),
+                playlist_position: Some(PlaylistPosition {
+                    title: listing.title.clone(),
+                    id: listing.id.clone(),
+                    index: entry.index,
+                    count: listing.entries.len(),
+                }),
+                parent_id: Some(job.id.clone()),
+                subscription_id: job.subscription_id.clone(),
+                archive_key: None,
+                // The parent job stands for the whole playlist in its batch
+                batch_id: None,
+                owner: job.owner.clone(),
+            })
+            .await?;
+        announced.push(ChildJob {
+            job_id: child.id.clone(),
+            url: child.url.clone(),
+            title: entry.title,
+        });
+        children.push(child);
+    }
+
+    // Children are queued before the parent is marked expanded, so cancelling the parent
+    // always finds them in the queue
+    for child in &children {
+        enqueue_job(state, child, target_dir.to_path_buf());
+    }
+    let child_ids = children.iter().map(|c| c.id.clone()).collect();
+    state.jobs.expand(&job.id, child_ids).await?;
+
+    let message = format!(
+        "Expanded {} into {} items",
+        listing.title.as_deref().unwrap_or("playlist"),
+        announced.len()
+    );
+    send_expansion_update(&job.id, &job.url, &message, announced);
+
+    // Children may already have finished while the parent was being recorded
+    update_parent(state, &job.id).await;
+    Ok(())
+}
+
+/// Recomputes a playlist job's aggregate progress from its children.
+///
+/// Once every child has finished, the parent is completed if any child completed,
+/// cancelled if all were cancelled and failed otherwise.
+pub(super) async fn update_parent(state: &AppState, parent_id: &str) {
+    let Some(parent) = state.jobs.get(parent_id).await else {
+        return;
+    };
+    if parent.status != JobStatus::Expanded {
+        return;
+    }
+
+    let (mut completed, mut failed, mut cancelled) = (0, 0, 0);
+    for child_id in &parent.child_ids {
+        match state.jobs.get(child_id).await.map(|child| child.status) {
+            Some(JobStatus::Completed) => completed += 1,
+            Some(JobStatus::Cancelled) => cancelled += 1,
+            Some(JobStatus::Failed) | None => failed += 1,
+            Some(_) => {}
+        }
+    }
+
+    let total = parent.child_ids.len();
+    let finished = completed + failed + cancelled;
+    let summary = format!(
+        "{} of {} items finished ({} failed, {} cancelled)",
+        finished, total, failed, cancelled
+    );
+
+    if finished < total {
+        send_progress_update(
+            &parent.id,
+            &parent.url,
+            "playlist",
+            finished as f32 / total as f32,
+            &summary,
+        );
+        return;
+    }
+
+    let (status, update_status, error) = if completed > 0 {
+        let error = (failed > 0).then(|| format!("{} of {} items failed", failed, total));
+        (JobStatus::Completed, "completed", error)
+    } else if cancelled == total {
+        (
+            JobStatus::Cancelled,
+            "cancelled",
+            Some("All items were cancelled".to_string()),
+        )
+    } else {
+        (
+            JobStatus::Failed,
+            "error",
+            Some(format!("All {} items failed or were cancelled", total)),
+        )
+    };
+
+    // Two children finishing together may both get here; only the first one records it
+    let mut changed = false;
+    let result = state
+        .jobs
+        .update(&parent.id, |job| {
+            job.status = status;
+            job.error = error;
+            job.finished_at = Some(Utc::now());
+            changed = true;
+        })
+        .await;
+    if let Err(e) = result {
+        error!(job_id = %parent.id, error = %e, "Failed to record playlist outcome");
+        return;
+    }
+
+    if changed {
+        info!(job_id = %parent.id, status = status.as_str(), completed, failed, cancelled, "Playlist finished");
+        send_progress_update(&parent.id, &parent.url, update_status, 1.0, &summary);
+        let failure = match status {
+            JobStatus::Completed => None,
+            JobStatus::Cancelled => Some(PegasusError::Cancelled),
+            _ => Some(PegasusError::DownloadError(summary)),
+        };
+        settle_archive(state, &parent, failure.as_ref().map_or(Ok(()), Err)).await;
+        if let Some(batch_id) = &parent.batch_id {
+            update_batch(state, batch_id).await;
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// src/pipeline/retry.rs
+// Retries transient yt-dlp failures with exponential backoff and jitter.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use tracing::warn;
+
+use crate::api::handlers::send_progress_update;
+use crate::config::RetryConfig;
+use crate::error::{PegasusError, Result};
+use crate::jobs::Job;
+use crate::jobs::cancel::CancelToken;
+
+/// Runs a stage, retrying it while it fails with a transient error.
+///
+/// Permanent failures (private, unavailable, unsupported, ...) are returned immediately.
+/// Between attempts a `retrying` update tells clients why and when the next attempt
+/// starts; cancelling the job interrupts the wait.
+///
+/// # Arguments
+///
+/// * `config` - The retry limits.
+/// * `job` - The job the stage belongs to.
+/// * `cancel` - The job's cancellation token.
+/// * `stage` - A short description of the stage for logs and updates, e.g. "download".
+/// * `operation` - Starts one attempt of the stage.
+///
+/// # Returns
+///
+/// The result of the last attempt.
+pub(super) async fn with_retries<T, F, Fut>(
+    config: &RetryConfig,
+    job: &Job,
+    cancel: &CancelToken,
+    stage: &str,
+    mut operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if e.is_transient() && attempt < config.max_attempts => {
+                let delay = backoff_delay(config.base_delay_secs, config.max_delay_secs, attempt);
+                attempt += 1;
+                warn!(job_id = %job.id, stage, error = %e, delay_ms = delay.as_millis() as u64, attempt, max_attempts = config.max_attempts, "Transient failure, retrying");
+                send_progress_update(
+                    &job.id,
+                    &job.url,
+                    "retrying",
+                    0.0,
+                    &format!(
+                        "{}; retrying {} in {:.1}s (attempt {} of {})",
+                        e,
+                        stage,
+                        delay.as_secs_f32(),
+                        attempt,
+                        config.max_attempts
+                    ),
+                );
+
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = cancel.cancelled() => return Err(PegasusError::Cancelled),
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Computes the delay before the attempt after `attempt`.
+///
+/// The delay doubles with every attempt up to the maximum, and a random factor between
+/// 0.5 and 1 spreads out jobs (or webhook deliveries) that failed at the same time.
+///
+/// # Arguments
+///
+/// * `base_secs` - The delay after the first attempt, before jitter.
+/// * `max_secs` - The longest delay, before jitter.
+/// * `attempt` - The attempt that just failed, counting from 1.
+pub fn backoff_delay(base_secs: u64, max_secs: u64, attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(20);
+    let ceiling = base_secs
+        .saturating_mul(1 << exponent)
+        .min(max_secs)
+        .saturating_mul(1000);
+    let millis = rand::thread_rng().gen_range(ceiling / 2..=ceiling);
+    Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_delay_doubles_up_to_the_maximum() {
+        for (attempt, ceiling) in [(1, 5), (2, 10), (3, 20), (10, 300), (40, 300)] {
+            let delay = backoff_delay(5, 300, attempt);
+            let ceiling = Duration::from_secs(ceiling);
+            assert!(delay <= ceiling && delay >= ceiling / 2, "{:?}", delay);
+        }
+        assert_eq!(backoff_delay(0, 300, 3), Duration::ZERO);
+    }
+}
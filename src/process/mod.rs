@@ -1,10 +1,722 @@
 // src/process/mod.rs
 // This module handles media processing using ffmpeg.
+//
+// After a download finishes, a job may ask for its file to be transcoded to a target
+// profile, loudness-normalized, trimmed, downmixed or scaled down. All of that is done
+// in a single ffmpeg run whose `-progress` output is streamed as `processing` updates.
 
-// Example function (to be replaced)
-pub fn process_media(input_path: &str, output_path: &str) -> Result<(), String> {
-    // TODO: Implement ffmpeg interaction
-    // Use tracing::info! instead of println!
-    tracing::info!(input = %input_path, output = %output_path, "Processing media (placeholder)");
-    Ok(())
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tracing::{debug, error, info};
+
+use crate::api::handlers::send_progress_update;
+use crate::config::BinaryPaths;
+use crate::error::{PegasusError, Result};
+use crate::jobs::cancel::CancelToken;
+use crate::metrics;
+
+/// Integrated loudness target for EBU R128 normalization, in LUFS.
+const LOUDNESS_TARGET_LUFS: f64 = -23.0;
+
+/// Codec and container combinations ffmpeg can transcode to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TranscodeProfile {
+    /// H.264 video and AAC audio in MP4.
+    H264Mp4,
+    /// H.265 video and AAC audio in MKV.
+    HevcMkv,
+    /// VP9 video and Opus audio in WebM.
+    Vp9Webm,
+    /// AAC audio in M4A.
+    AacM4a,
+    /// MP3 audio.
+    Mp3,
+    /// Opus audio in Ogg.
+    Opus,
+    /// Lossless FLAC audio.
+    Flac,
+}
+
+impl TranscodeProfile {
+    /// Extension of the file this profile produces.
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscodeProfile::H264Mp4 => "mp4",
+            TranscodeProfile::HevcMkv => "mkv",
+            TranscodeProfile::Vp9Webm => "webm",
+            TranscodeProfile::AacM4a => "m4a",
+            TranscodeProfile::Mp3 => "mp3",
+            TranscodeProfile::Opus => "opus",
+            TranscodeProfile::Flac => "flac",
+        }
+    }
+
+    /// Returns `true` if the profile produces a video file.
+    pub fn has_video(self) -> bool {
+        matches!(
+            self,
+            TranscodeProfile::H264Mp4 | TranscodeProfile::HevcMkv | TranscodeProfile::Vp9Webm
+        )
+    }
+
+    /// ffmpeg encoder arguments for this profile.
+    fn codec_args(self) -> &'static [&'static str] {
+        match self {
+            TranscodeProfile::H264Mp4 => &[
+                "-c:v",
+                "libx264",
+                "-preset",
+                "medium",
+                "-crf",
+                "23",
+                "-c:a",
+                "aac",
+                "-b:a",
+                "192k",
+                "-movflags",
+                "+faststart",
+            ],
+            TranscodeProfile::HevcMkv => &[
+                "-c:v", "libx265", "-preset", "medium", "-crf", "28", "-c:a", "aac", "-b:a", "192k",
+            ],
+            TranscodeProfile::Vp9Webm => &[
+                "-c:v",
+                "libvpx-vp9",
+                "-crf",
+                "32",
+                "-b:v",
+                "0",
+                "-c:a",
+                "libopus",
+                "-b:a",
+                "128k",
+            ],
+            TranscodeProfile::AacM4a => &["-c:a", "aac", "-b:a", "192k"],
+            TranscodeProfile::Mp3 => &["-c:a", "libmp3lame", "-q:a", "2"],
+            TranscodeProfile::Opus => &["-c:a", "libopus", "-b:a", "128k"],
+            TranscodeProfile::Flac => &["-c:a", "flac"],
+        }
+    }
+
+    /// ffmpeg encoder for the subtitles kept in this profile's container, if it keeps any.
+    fn subtitle_codec(self) -> Option<&'static str> {
+        match self {
+            TranscodeProfile::H264Mp4 => Some("mov_text"),
+            TranscodeProfile::HevcMkv => Some("ass"),
+            TranscodeProfile::Vp9Webm => Some("webvtt"),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this profile's container can carry embedded cover art.
+    fn keeps_cover_art(self) -> bool {
+        !matches!(self, TranscodeProfile::Vp9Webm | TranscodeProfile::Opus)
+    }
+}
+
+/// Post-download processing requested for a job.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct PostProcessOptions {
+    /// Transcode to this codec/container profile.
+    pub profile: Option<TranscodeProfile>,
+    /// Normalize loudness to the EBU R128 target.
+    pub normalize_loudness: bool,
+    /// Start of the section to keep (`SS`, `MM:SS` or `HH:MM:SS`, with optional fraction).
+    pub trim_start: Option<String>,
+    /// End of the section to keep, in the same format as `trim_start`.
+    pub trim_end: Option<String>,
+    /// Downmix audio to this many channels (e.g. 2 for stereo).
+    pub audio_channels: Option<u8>,
+    /// Scale video down so it is at most this many pixels tall.
+    pub max_height: Option<u32>,
+}
+
+impl PostProcessOptions {
+    /// Returns `true` if any processing was requested.
+    pub fn is_requested(&self) -> bool {
+        self.profile.is_some()
+            || self.normalize_loudness
+            || self.trim_start.is_some()
+            || self.trim_end.is_some()
+            || self.audio_channels.is_some()
+            || self.max_height.is_some()
+    }
+
+    /// Checks the values that serde cannot validate on its own.
+    pub fn validate(&self) -> Result<()> {
+        let start = match &self.trim_start {
+            Some(ts) => Some(parse_timestamp(ts).ok_or_else(|| {
+                PegasusError::ValidationError(format!("Invalid trim start timestamp: {:?}", ts))
+            })?),
+            None => None,
+        };
+        let end = match &self.trim_end {
+            Some(ts) => Some(parse_timestamp(ts).ok_or_else(|| {
+                PegasusError::ValidationError(format!("Invalid trim end timestamp: {:?}", ts))
+            })?),
+            None => None,
+        };
+        if let (Some(start), Some(end)) = (start, end)
+            && end <= start
+        {
+            return Err(PegasusError::ValidationError(
+                "Trim end must be after trim start".to_string(),
+            ));
+        }
+
+        if let Some(channels) = self.audio_channels
+            && !(1..=8).contains(&channels)
+        {
+            return Err(PegasusError::ValidationError(format!(
+                "Audio channels must be between 1 and 8, got {}",
+                channels
+            )));
+        }
+
+        if let Some(height) = self.max_height
+            && !(144..=4320).contains(&height)
+        {
+            return Err(PegasusError::ValidationError(format!(
+                "Maximum height must be between 144 and 4320, got {}",
+                height
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// What ffprobe tells us about an input file.
+struct MediaProbe {
+    duration: Option<f64>,
+    /// One entry per video stream, in order: `true` if the stream is embedded cover art
+    /// rather than moving pictures.
+    cover_art: Vec<bool>,
+}
+
+/// Processes a downloaded file with ffmpeg according to `options`.
+///
+/// The result replaces the input file: it is written next to `output_path` under a
+/// temporary name unique to the job and renamed into place once ffmpeg succeeds, after
+/// which the original download is removed.
+///
+/// # Arguments
+///
+/// * `input_path` - The file this job downloaded.
+/// * `output_path` - Where the processed file goes: the input itself, or a path the job
+///   has claimed if the extension changes.
+/// * `options` - The processing steps to apply.
+/// * `job_id` - The job this processing belongs to, for progress updates.
+/// * `url` - The job's media URL, for progress updates.
+/// * `cancel` - The job's cancellation token; cancelling kills ffmpeg.
+/// * `binaries` - Paths of the external binaries.
+///
+/// # Returns
+///
+/// A `Result` containing the path of the processed file, or `PegasusError::ProcessingError`
+/// on failure.
+pub async fn process_media(
+    input_path: &Path,
+    output_path: &Path,
+    options: &PostProcessOptions,
+    job_id: &str,
+    url: &str,
+    cancel: &CancelToken,
+    binaries: &BinaryPaths,
+) -> Result<PathBuf> {
+    info!(job_id = %job_id, input = %input_path.display(), options = ?options, "Processing media with ffmpeg");
+    send_progress_update(job_id, url, "processing", 0.0, "Starting processing...");
+
+    let probe = probe_media(input_path, &binaries.ffprobe).await?;
+
+    // ffmpeg writes to a temporary file named after the job, so concurrent jobs never
+    // share one; ffmpeg picks the container from its extension
+    let extension = output_path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "mkv".to_string());
+    let temp_path = output_path.with_file_name(format!("{}.processing.{}", job_id, extension));
+
+    // Duration of the section being kept, used to turn ffmpeg's timestamps into progress
+    let trim_start = options.trim_start.as_deref().and_then(parse_timestamp);
+    let trim_end = options.trim_end.as_deref().and_then(parse_timestamp);
+    let duration = match (trim_start, trim_end, probe.duration) {
+        (start, Some(end), _) => Some(end - start.unwrap_or(0.0)),
+        (Some(start), None, Some(total)) => Some((total - start).max(0.0)),
+        (None, None, total) => total,
+        _ => None,
+    };
+
+    let args = build_ffmpeg_args(input_path, &temp_path, options, &probe.cover_art);
+    debug!(job_id = %job_id, args = ?args, "ffmpeg arguments");
+
+    let mut cmd = Command::new(&binaries.ffmpeg);
+    cmd.args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    // Run ffmpeg in its own process group so cancelling can kill it
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let started = std::time::Instant::now();
+    let mut child = cmd.spawn().map_err(|e| {
+        error!(error = %e, "Failed to execute ffmpeg command");
+        PegasusError::from_spawn_error(&binaries.ffmpeg, e)
+    })?;
+    if let Some(pid) = child.id() {
+        cancel.attach_process(pid);
+    }
+
+    // Collect stderr so a failure can be reported with ffmpeg's own explanation
+    let stderr_task = child.stderr.take().map(|stderr| {
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            let mut tail: Vec<String> = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("ffmpeg stderr: {}", line);
+                tail.push(line);
+                if tail.len() > 20 {
+                    tail.remove(0);
+                }
+            }
+            tail
+        })
+    });
+
+    // Stream `-progress` key=value output as progress updates
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        let mut speed = String::from("N/A");
+        while let Ok(Some(line)) = lines.next_line().await {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key {
+                "speed" => speed = value.trim().to_string(),
+                "out_time_us" | "out_time_ms" => {
+                    let Ok(micros) = value.trim().parse::<f64>() else {
+                        continue;
+                    };
+                    let seconds = micros / 1_000_000.0;
+                    let (progress, message) = match duration {
+                        Some(total) if total > 0.0 => {
+                            let fraction = (seconds / total).clamp(0.0, 1.0) as f32;
+                            (
+                                fraction,
+                                format!("Processing: {:.1}%, Speed: {}", fraction * 100.0, speed),
+                            )
+                        }
+                        _ => (
+                            0.0,
+                            format!("Processing: {:.0}s done, Speed: {}", seconds, speed),
+                        ),
+                    };
+                    send_progress_update(job_id, url, "processing", progress, &message);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let status = child.wait().await.map_err(|e| {
+        error!(error = %e, "Failed to wait for ffmpeg command");
+        PegasusError::ProcessingError(format!("Failed to wait for ffmpeg: {}", e))
+    })?;
+    metrics::observe_process("ffmpeg", "process", started.elapsed());
+    cancel.detach_process();
+
+    let stderr_tail = match stderr_task {
+        Some(task) => task.await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    if cancel.is_cancelled() {
+        info!(job_id = %job_id, "Processing cancelled, removing temporary output");
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(PegasusError::Cancelled);
+    }
+
+    if !status.success() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        let reason = stderr_tail.last().cloned().unwrap_or_default();
+        error!(job_id = %job_id, status = %status, stderr = %stderr_tail.join("\n"), "ffmpeg command failed");
+        return Err(PegasusError::ProcessingError(format!(
+            "ffmpeg exited with status {}: {}",
+            status, reason
+        )));
+    }
+
+    // Swap the processed file into place
+    tokio::fs::rename(&temp_path, output_path)
+        .await
+        .map_err(|e| {
+            PegasusError::ProcessingError(format!(
+                "Failed to move processed file into place: {}",
+                e
+            ))
+        })?;
+    if output_path != input_path
+        && let Err(e) = tokio::fs::remove_file(input_path).await
+    {
+        error!(error = %e, path = %input_path.display(), "Failed to remove original download");
+    }
+
+    info!(job_id = %job_id, output = %output_path.display(), "Processing complete");
+    send_progress_update(job_id, url, "processing", 1.0, "Processing complete");
+    Ok(output_path.to_path_buf())
+}
+
+/// Builds the ffmpeg command line for the requested processing steps.
+///
+/// # Arguments
+///
+/// * `input_path` - The file to process.
+/// * `output_path` - Where ffmpeg writes its output.
+/// * `options` - The processing steps to apply.
+/// * `cover_art` - For every video stream of the input, whether it is embedded cover art.
+///
+/// # Returns
+///
+/// The arguments to run ffmpeg with.
+fn build_ffmpeg_args(
+    input_path: &Path,
+    output_path: &Path,
+    options: &PostProcessOptions,
+    cover_art: &[bool],
+) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-hide_banner".into(), "-nostdin".into(), "-y".into()];
+
+    // Input-side seeking keeps trimming fast
+    if let Some(start) = &options.trim_start {
+        args.extend(["-ss".into(), start.clone()]);
+    }
+    if let Some(end) = &options.trim_end {
+        args.extend(["-to".into(), end.clone()]);
+    }
+    args.extend(["-i".into(), input_path.to_string_lossy().to_string()]);
+    args.extend(["-map_metadata".into(), "0".into()]);
+
+    let pictures: Vec<usize> = (0..cover_art.len()).filter(|&i| !cover_art[i]).collect();
+    let covers: Vec<usize> = (0..cover_art.len()).filter(|&i| cover_art[i]).collect();
+    let produces_video =
+        !pictures.is_empty() && options.profile.is_none_or(TranscodeProfile::has_video);
+
+    // Map the streams explicitly. Left to itself ffmpeg keeps one video and one audio
+    // stream, dropping subtitles, attachments such as fonts and the embedded thumbnail.
+    // Cover art goes last so its output index is known
+    let map = |args: &mut Vec<String>, spec: String| args.extend(["-map".into(), spec]);
+    let kept_covers: Vec<usize> = match options.profile {
+        Some(profile) if !profile.has_video() => {
+            map(&mut args, "0:a".into());
+            // Audio containers hold a single picture at most
+            covers
+                .first()
+                .filter(|_| profile.keeps_cover_art())
+                .copied()
+                .into_iter()
+                .collect()
+        }
+        profile => {
+            for &index in &pictures {
+                map(&mut args, format!("0:v:{}", index));
+            }
+            map(&mut args, "0:a?".into());
+            if profile.is_none_or(|p| p.subtitle_codec().is_some()) {
+                map(&mut args, "0:s?".into());
+            }
+            if profile.is_none_or(|p| p == TranscodeProfile::HevcMkv) {
+                map(&mut args, "0:t?".into());
+            }
+            if profile.is_none_or(TranscodeProfile::keeps_cover_art) {
+                covers
+            } else {
+                Vec::new()
+            }
+        }
+    };
+    for &index in &kept_covers {
+        map(&mut args, format!("0:v:{}", index));
+    }
+    // Output index of the first cover art stream
+    let first_cover = if produces_video { pictures.len() } else { 0 };
+
+    // Video filters, for the moving pictures only: cover art is always stream copied
+    let scale = options.max_height.filter(|_| produces_video);
+    if let Some(height) = scale {
+        for index in 0..pictures.len() {
+            args.extend([
+                format!("-filter:v:{}", index),
+                format!("scale=-2:'min(ih,{})'", height),
+            ]);
+        }
+    }
+
+    // Audio filters
+    if options.normalize_loudness {
+        args.extend([
+            "-af".into(),
+            format!("loudnorm=I={}:TP=-1.5:LRA=11", LOUDNESS_TARGET_LUFS),
+        ]);
+    }
+    if let Some(channels) = options.audio_channels {
+        args.extend(["-ac".into(), channels.to_string()]);
+    }
+
+    // Codecs: the profile's encoders, or stream copy for anything left unfiltered
+    match options.profile {
+        Some(profile) => {
+            args.extend(profile.codec_args().iter().map(|a| a.to_string()));
+            if let Some(codec) = profile.subtitle_codec() {
+                args.extend(["-c:s".into(), codec.into()]);
+            }
+            if profile == TranscodeProfile::HevcMkv {
+                args.extend(["-c:t".into(), "copy".into()]);
+            }
+        }
+        None => {
+            if scale.is_none() {
+                args.extend(["-c:v".into(), "copy".into()]);
+            }
+            if !options.normalize_loudness && options.audio_channels.is_none() {
+                args.extend(["-c:a".into(), "copy".into()]);
+            }
+            args.extend(["-c:s".into(), "copy".into(), "-c:t".into(), "copy".into()]);
+        }
+    }
+    // Cover art is copied as is, overriding the video encoder, and stays marked as an
+    // attached picture so players show it as artwork rather than a second video
+    for offset in 0..kept_covers.len() {
+        let index = first_cover + offset;
+        args.extend([
+            format!("-c:v:{}", index),
+            "copy".into(),
+            format!("-disposition:v:{}", index),
+            "attached_pic".into(),
+        ]);
+    }
+
+    args.extend([
+        "-progress".into(),
+        "pipe:1".into(),
+        "-nostats".into(),
+        output_path.to_string_lossy().to_string(),
+    ]);
+    args
+}
+
+/// Reads the duration and stream layout of a media file with ffprobe.
+async fn probe_media(path: &Path, ffprobe: &str) -> Result<MediaProbe> {
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+        ])
+        .arg(path)
+        .output()
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to execute ffprobe command");
+            PegasusError::from_spawn_error(ffprobe, e)
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PegasusError::ProcessingError(format!(
+            "ffprobe failed: {}",
+            stderr.trim()
+        )));
+    }
+
+    let probe: Value = serde_json::from_slice(&output.stdout).map_err(|e| {
+        PegasusError::ProcessingError(format!("Failed to parse ffprobe output: {}", e))
+    })?;
+
+    let duration = probe["format"]["duration"]
+        .as_str()
+        .and_then(|d| d.parse::<f64>().ok());
+    // Embedded cover art shows up as a video stream flagged as an attached picture
+    let cover_art = probe["streams"]
+        .as_array()
+        .map(|streams| {
+            streams
+                .iter()
+                .filter(|s| s["codec_type"] == "video")
+                .map(|s| s["disposition"]["attached_pic"].as_i64() == Some(1))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(MediaProbe {
+        duration,
+        cover_art,
+    })
+}
+
+/// Parses an ffmpeg-style timestamp (`SS`, `MM:SS` or `HH:MM:SS`, optional fraction) into seconds.
+pub fn parse_timestamp(ts: &str) -> Option<f64> {
+    let parts: Vec<&str> = ts.trim().split(':').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+
+    let mut seconds = 0.0;
+    for (index, part) in parts.iter().enumerate() {
+        let is_last = index == parts.len() - 1;
+        let valid = !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_digit() || (is_last && c == '.'));
+        if !valid {
+            return None;
+        }
+        let value: f64 = part.parse().ok()?;
+        // Minutes and seconds after the first component must be below 60
+        if index > 0 && value >= 60.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args_for(options: PostProcessOptions, cover_art: &[bool]) -> Vec<String> {
+        build_ffmpeg_args(
+            Path::new("in.mkv"),
+            Path::new("out.mkv"),
+            &options,
+            cover_art,
+        )
+    }
+
+    /// Returns `true` if `flag` is immediately followed by `value`.
+    fn has(args: &[String], flag: &str, value: &str) -> bool {
+        args.windows(2)
+            .any(|pair| pair[0] == flag && pair[1] == value)
+    }
+
+    fn position(args: &[String], flag: &str, value: &str) -> usize {
+        args.windows(2)
+            .position(|pair| pair[0] == flag && pair[1] == value)
+            .unwrap_or_else(|| panic!("{} {} missing from {:?}", flag, value, args))
+    }
+
+    #[test]
+    fn stream_copy_keeps_every_stream() {
+        let options = PostProcessOptions {
+            trim_start: Some("10".into()),
+            ..Default::default()
+        };
+        let args = args_for(options, &[false, true]);
+
+        for spec in ["0:v:0", "0:a?", "0:s?", "0:t?", "0:v:1"] {
+            assert!(
+                has(&args, "-map", spec),
+                "{} not mapped in {:?}",
+                spec,
+                args
+            );
+        }
+        for kind in ["-c:v", "-c:a", "-c:s", "-c:t"] {
+            assert!(
+                has(&args, kind, "copy"),
+                "{} not copied in {:?}",
+                kind,
+                args
+            );
+        }
+        assert!(has(&args, "-disposition:v:1", "attached_pic"));
+        // Seeking stays on the input side
+        assert!(position(&args, "-ss", "10") < position(&args, "-i", "in.mkv"));
+        assert_eq!(args.last().map(String::as_str), Some("out.mkv"));
+    }
+
+    #[test]
+    fn scaling_leaves_cover_art_alone() {
+        let options = PostProcessOptions {
+            max_height: Some(720),
+            ..Default::default()
+        };
+        let args = args_for(options, &[true, false]);
+
+        // The picture stream is mapped first, the cover art after it
+        assert!(position(&args, "-map", "0:v:1") < position(&args, "-map", "0:v:0"));
+        assert!(has(&args, "-filter:v:0", "scale=-2:'min(ih,720)'"));
+        assert!(!args.iter().any(|a| a == "-vf" || a == "-filter:v:1"));
+        assert!(!has(&args, "-c:v", "copy"));
+        assert!(has(&args, "-c:v:1", "copy"));
+        assert!(has(&args, "-disposition:v:1", "attached_pic"));
+        assert!(has(&args, "-c:a", "copy"));
+    }
+
+    #[test]
+    fn video_profiles_convert_subtitles_and_copy_cover_art() {
+        let options = PostProcessOptions {
+            profile: Some(TranscodeProfile::H264Mp4),
+            ..Default::default()
+        };
+        let args = args_for(options, &[false, true]);
+
+        assert!(has(&args, "-map", "0:s?"));
+        assert!(has(&args, "-c:s", "mov_text"));
+        // MP4 cannot hold attachments
+        assert!(!has(&args, "-map", "0:t?"));
+        // The cover art override has to come after the profile's encoder to win
+        assert!(position(&args, "-c:v", "libx264") < position(&args, "-c:v:1", "copy"));
+        assert!(has(&args, "-disposition:v:1", "attached_pic"));
+
+        let options = PostProcessOptions {
+            profile: Some(TranscodeProfile::Vp9Webm),
+            ..Default::default()
+        };
+        let args = args_for(options, &[false, true]);
+        assert!(has(&args, "-c:s", "webvtt"));
+        // WebM has no cover art
+        assert!(!has(&args, "-map", "0:v:1"));
+        assert!(!args.iter().any(|a| a.starts_with("-disposition")));
+    }
+
+    #[test]
+    fn audio_profiles_keep_only_audio_and_one_cover() {
+        let options = PostProcessOptions {
+            profile: Some(TranscodeProfile::Mp3),
+            normalize_loudness: true,
+            ..Default::default()
+        };
+        let args = args_for(options, &[false, true, true]);
+
+        assert!(has(&args, "-map", "0:a"));
+        assert!(has(&args, "-map", "0:v:1"));
+        assert!(!has(&args, "-map", "0:v:0"));
+        assert!(!has(&args, "-map", "0:v:2"));
+        assert!(!has(&args, "-map", "0:s?"));
+        assert!(has(&args, "-c:v:0", "copy"));
+        assert!(has(&args, "-disposition:v:0", "attached_pic"));
+        assert!(has(&args, "-c:a", "libmp3lame"));
+        assert!(args.iter().any(|a| a.starts_with("loudnorm=")));
+
+        let options = PostProcessOptions {
+            profile: Some(TranscodeProfile::Opus),
+            ..Default::default()
+        };
+        let args = args_for(options, &[false, true]);
+        assert!(!args.iter().any(|a| a.starts_with("0:v")));
+    }
 }
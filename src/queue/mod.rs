@@ -0,0 +1,438 @@
+// src/queue/mod.rs
+// This module contains the bounded job queue that limits how many downloads run at once.
+//
+// Jobs wait in a pending list ordered by priority (highest first) and then submission
+// order. A job only starts when a global worker slot is free and its domain is below
+// its own concurrency limit.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use tracing::{debug, info};
+
+use crate::api::handlers::send_queue_update;
+
+/// The work a queued job performs once it is given a worker slot.
+pub type JobTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Concurrency limits applied by the queue.
+#[derive(Clone, Debug)]
+pub struct QueueLimits {
+    /// Maximum number of jobs running at once across all domains.
+    pub max_concurrent: usize,
+    /// Default maximum number of jobs running at once for any single domain.
+    pub per_domain: usize,
+    /// Per-domain overrides of `per_domain`, keyed by host (without `www.`).
+    pub domain_overrides: HashMap<String, usize>,
+}
+
+impl QueueLimits {
+    fn limit_for(&self, domain: &str) -> usize {
+        self.domain_overrides
+            .get(domain)
+            .copied()
+            .unwrap_or(self.per_domain)
+            .max(1)
+    }
+}
+
+struct PendingJob {
+    job_id: String,
+    url: String,
+    domain: String,
+    priority: i32,
+    seq: u64,
+    last_reported_position: Option<usize>,
+    task: JobTask,
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: Vec<PendingJob>,
+    running: usize,
+    running_by_domain: HashMap<String, usize>,
+    next_seq: u64,
+}
+
+/// A priority FIFO queue backed by a bounded pool of worker slots.
+pub struct JobQueue {
+    limits: QueueLimits,
+    state: Mutex<QueueState>,
+}
+
+impl JobQueue {
+    /// Creates an empty queue with the given limits.
+    pub fn new(limits: QueueLimits) -> Arc<Self> {
+        info!(
+            max_concurrent = limits.max_concurrent,
+            per_domain = limits.per_domain,
+            "Creating job queue"
+        );
+        Arc::new(JobQueue {
+            limits,
+            state: Mutex::new(QueueState::default()),
+        })
+    }
+
+    /// Adds a job to the queue. `task` runs once a worker slot is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `job_id` - The job's unique identifier.
+    /// * `url` - The media URL, used to determine the job's domain.
+    /// * `priority` - Higher priorities start first; equal priorities start in submission order.
+    /// * `task` - The work to perform when the job starts.
+    pub fn enqueue(self: &Arc<Self>, job_id: &str, url: &str, priority: i32, task: JobTask) {
+        {
+            let mut state = self.state.lock().unwrap();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+
+            let job = PendingJob {
+                job_id: job_id.to_string(),
+                url: url.to_string(),
+                domain: domain_of(url),
+                priority,
+                seq,
+                last_reported_position: None,
+                task,
+            };
+
+            // Keep the pending list ordered by priority, then submission order
+            let index = state
+                .pending
+                .iter()
+                .position(|p| {
+                    (p.priority, std::cmp::Reverse(p.seq)) < (priority, std::cmp::Reverse(seq))
+                })
+                .unwrap_or(state.pending.len());
+            state.pending.insert(index, job);
+            debug!(job_id = %job_id, priority, position = index, "Job enqueued");
+        }
+
+        self.dispatch();
+    }
+
+    /// Removes a job that has not started yet.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the job was waiting and has been removed, `false` if it is not in the
+    /// queue (because it already started or never existed).
+    pub fn remove(self: &Arc<Self>, job_id: &str) -> bool {
+        let removed = {
+            let mut state = self.state.lock().unwrap();
+            match state.pending.iter().position(|p| p.job_id == job_id) {
+                Some(index) => {
+                    state.pending.remove(index);
+                    true
+                }
+                None => false,
+            }
+        };
+
+        if removed {
+            debug!(job_id = %job_id, "Job removed from queue");
+            // Jobs behind the removed one have moved up
+            self.dispatch();
+        }
+        removed
+    }
+
+    /// Starts every pending job that fits within the limits and reports new queue positions.
+    fn dispatch(self: &Arc<Self>) {
+        let mut started = Vec::new();
+        let mut position_updates = Vec::new();
+
+        {
+            let mut state = self.state.lock().unwrap();
+
+            let mut index = 0;
+            while index < state.pending.len() && state.running < self.limits.max_concurrent {
+                let domain = &state.pending[index].domain;
+                let running_for_domain = state.running_by_domain.get(domain).copied().unwrap_or(0);
+                if running_for_domain >= self.limits.limit_for(domain) {
+                    index += 1;
+                    continue;
+                }
+
+                let job = state.pending.remove(index);
+                state.running += 1;
+                *state
+                    .running_by_domain
+                    .entry(job.domain.clone())
+                    .or_insert(0) += 1;
+                started.push(job);
+            }
+
+            for (position, job) in state.pending.iter_mut().enumerate() {
+                if job.last_reported_position != Some(position) {
+                    job.last_reported_position = Some(position);
+                    position_updates.push((job.job_id.clone(), job.url.clone(), position));
+                }
+            }
+        }
+
+        for job in started {
+            info!(job_id = %job.job_id, domain = %job.domain, "Starting queued job");
+            let slot = WorkerSlot {
+                queue: self.clone(),
+                domain: job.domain,
+            };
+            tokio::spawn(async move {
+                // The slot is released when dropped, even if the task panics
+                let _slot = slot;
+                job.task.await;
+            });
+        }
+
+        for (job_id, url, position) in position_updates {
+            send_queue_update(&job_id, &url, position);
+        }
+    }
+
+    /// Releases a worker slot held by a finished job and starts the next ones.
+    fn release(self: &Arc<Self>, domain: &str) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.running = state.running.saturating_sub(1);
+            if let Some(count) = state.running_by_domain.get_mut(domain) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    state.running_by_domain.remove(domain);
+                }
+            }
+        }
+        self.dispatch();
+    }
+}
+
+/// A held worker slot, returned to the queue on drop.
+struct WorkerSlot {
+    queue: Arc<JobQueue>,
+    domain: String,
+}
+
+impl Drop for WorkerSlot {
+    fn drop(&mut self) {
+        self.queue.release(&self.domain);
+    }
+}
+
+/// Extracts the concurrency domain of a URL: its host without a leading `www.`.
+pub fn domain_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            u.host_str()
+                .map(|h| h.trim_start_matches("www.").to_lowercase())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    type StartLog = Arc<Mutex<Vec<String>>>;
+
+    fn limits(max_concurrent: usize, per_domain: usize) -> QueueLimits {
+        QueueLimits {
+            max_concurrent,
+            per_domain,
+            domain_overrides: HashMap::new(),
+        }
+    }
+
+    // A task that records its start and then runs until the returned sender is used or dropped
+    fn held_task(started: &StartLog, job_id: &str) -> (JobTask, oneshot::Sender<()>) {
+        let (finish_tx, finish_rx) = oneshot::channel::<()>();
+        let started = started.clone();
+        let job_id = job_id.to_string();
+        let task = Box::pin(async move {
+            started.lock().unwrap().push(job_id);
+            let _ = finish_rx.await;
+        });
+        (task, finish_tx)
+    }
+
+    // A task that records its start and finishes straight away
+    fn quick_task(started: &StartLog, job_id: &str) -> JobTask {
+        let started = started.clone();
+        let job_id = job_id.to_string();
+        Box::pin(async move {
+            started.lock().unwrap().push(job_id);
+        })
+    }
+
+    async fn wait_for_starts(started: &StartLog, count: usize) -> Vec<String> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if started.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("jobs did not start in time");
+        // Give anything that should not have started a chance to do so
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        started.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn higher_priorities_start_first_then_in_submission_order() {
+        let queue = JobQueue::new(limits(1, 1));
+        let started = StartLog::default();
+
+        let (blocker, finish_blocker) = held_task(&started, "queue-order-blocker");
+        queue.enqueue(
+            "queue-order-blocker",
+            "https://a.example/blocker",
+            0,
+            blocker,
+        );
+        for (job_id, priority) in [
+            ("queue-order-low-1", 0),
+            ("queue-order-high-1", 5),
+            ("queue-order-low-2", 0),
+            ("queue-order-urgent", 9),
+            ("queue-order-high-2", 5),
+            ("queue-order-negative", -1),
+        ] {
+            let url = format!("https://a.example/{}", job_id);
+            queue.enqueue(job_id, &url, priority, quick_task(&started, job_id));
+        }
+        assert_eq!(
+            wait_for_starts(&started, 1).await,
+            vec!["queue-order-blocker"]
+        );
+
+        finish_blocker.send(()).unwrap();
+        assert_eq!(
+            wait_for_starts(&started, 7).await,
+            vec![
+                "queue-order-blocker",
+                "queue-order-urgent",
+                "queue-order-high-1",
+                "queue-order-high-2",
+                "queue-order-low-1",
+                "queue-order-low-2",
+                "queue-order-negative",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_global_limit_caps_running_jobs() {
+        let queue = JobQueue::new(limits(2, 10));
+        let started = StartLog::default();
+
+        let mut finishers = HashMap::new();
+        for job_id in ["queue-global-1", "queue-global-2", "queue-global-3"] {
+            let (task, finish) = held_task(&started, job_id);
+            let url = format!("https://{}.example/video", job_id);
+            queue.enqueue(job_id, &url, 0, task);
+            finishers.insert(job_id, finish);
+        }
+        assert_eq!(
+            wait_for_starts(&started, 2).await,
+            vec!["queue-global-1", "queue-global-2"]
+        );
+        assert_eq!(queue.state.lock().unwrap().running, 2);
+
+        // A finished job frees its slot for the next one
+        drop(finishers.remove("queue-global-1"));
+        assert_eq!(
+            wait_for_starts(&started, 3).await,
+            vec!["queue-global-1", "queue-global-2", "queue-global-3"]
+        );
+        assert_eq!(queue.state.lock().unwrap().running, 2);
+    }
+
+    #[tokio::test]
+    async fn per_domain_limits_let_other_domains_go_ahead() {
+        let mut limits = limits(3, 1);
+        limits.domain_overrides.insert("a.example".to_string(), 2);
+        let queue = JobQueue::new(limits);
+        let started = StartLog::default();
+
+        let mut finishers = HashMap::new();
+        for (job_id, url) in [
+            ("queue-domain-a1", "https://www.a.example/1"),
+            ("queue-domain-a2", "https://a.example/2"),
+            ("queue-domain-a3", "https://A.example/3"),
+            ("queue-domain-b1", "https://b.example/1"),
+            ("queue-domain-b2", "https://b.example/2"),
+        ] {
+            let (task, finish) = held_task(&started, job_id);
+            queue.enqueue(job_id, url, 0, task);
+            finishers.insert(job_id, finish);
+        }
+
+        // a.example is overridden to two jobs, b.example gets the default of one, and
+        // the later b.example job does not overtake the waiting a.example one
+        assert_eq!(
+            wait_for_starts(&started, 3).await,
+            vec!["queue-domain-a1", "queue-domain-a2", "queue-domain-b1"]
+        );
+        {
+            let state = queue.state.lock().unwrap();
+            assert_eq!(state.running_by_domain.get("a.example"), Some(&2));
+            assert_eq!(state.running_by_domain.get("b.example"), Some(&1));
+        }
+
+        // Freeing an a.example slot starts the waiting a.example job, not the b.example one
+        drop(finishers.remove("queue-domain-a1"));
+        assert_eq!(wait_for_starts(&started, 4).await[3..], ["queue-domain-a3"]);
+
+        // Freeing the b.example slot lets the last job start once a global slot is free
+        drop(finishers.remove("queue-domain-b1"));
+        assert_eq!(wait_for_starts(&started, 5).await[4..], ["queue-domain-b2"]);
+        assert!(queue.state.lock().unwrap().pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_queued_jobs_never_start() {
+        let queue = JobQueue::new(limits(1, 1));
+        let started = StartLog::default();
+
+        let (blocker, finish_blocker) = held_task(&started, "queue-cancel-blocker");
+        queue.enqueue("queue-cancel-blocker", "https://a.example/0", 0, blocker);
+        for job_id in ["queue-cancel-removed", "queue-cancel-kept"] {
+            let url = format!("https://a.example/{}", job_id);
+            queue.enqueue(job_id, &url, 0, quick_task(&started, job_id));
+        }
+        wait_for_starts(&started, 1).await;
+
+        assert!(queue.remove("queue-cancel-removed"));
+        // Removing twice, removing a running job and removing an unknown job do nothing
+        assert!(!queue.remove("queue-cancel-removed"));
+        assert!(!queue.remove("queue-cancel-blocker"));
+        assert!(!queue.remove("queue-cancel-unknown"));
+
+        finish_blocker.send(()).unwrap();
+        assert_eq!(
+            wait_for_starts(&started, 2).await,
+            vec!["queue-cancel-blocker", "queue-cancel-kept"]
+        );
+        let state = queue.state.lock().unwrap();
+        assert!(state.pending.is_empty());
+        assert_eq!(state.running, 0);
+    }
+
+    #[test]
+    fn domains_ignore_www_and_case() {
+        assert_eq!(
+            domain_of("https://www.YouTube.com/watch?v=1"),
+            "youtube.com"
+        );
+        assert_eq!(domain_of("https://youtu.be/1"), "youtu.be");
+        assert_eq!(domain_of("not a url"), "");
+    }
+}
@@ -0,0 +1,242 @@
+// src/roots/mod.rs
+// This module decides where on disk a job may write.
+//
+// Clients never send a filesystem path. They pick one of the configured output roots by
+// name and may add a relative subdirectory below it. The subdirectory is normalized
+// lexically (`a/./b/../c` becomes `a/c`), may not climb above the root, and the part of
+// it that already exists is resolved with symlinks followed to make sure a link inside
+// the root does not lead somewhere else. Every rejected request is written to the `audit`
+// log target, so attempts to escape the roots can be alerted on.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::error::{PegasusError, Result};
+
+/// The root that is always available: the configured download directory.
+pub const DEFAULT_ROOT: &str = "default";
+
+/// The subdirectory of the default root used when a job names neither a root nor a
+/// subdirectory, as before output roots existed.
+const DEFAULT_SUBDIR: &str = "default";
+
+/// A root as shown to clients.
+#[derive(Clone, Debug, Serialize)]
+pub struct RootSummary {
+    pub name: String,
+    pub path: PathBuf,
+    /// Whether jobs that name no root use this one.
+    pub default: bool,
+}
+
+/// The configured output roots, with their paths resolved.
+#[derive(Debug)]
+pub struct OutputRoots {
+    roots: BTreeMap<String, PathBuf>,
+}
+
+impl OutputRoots {
+    /// Creates every root directory that does not exist yet and resolves its real path.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The application configuration; `paths.download_dir` becomes the
+    ///   `default` root next to the ones in `paths.roots`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the roots, or a `PegasusError` if a root cannot be created.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let configured = config
+            .paths
+            .roots
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_str()));
+        let mut roots = BTreeMap::new();
+        for (name, path) in
+            std::iter::once((DEFAULT_ROOT, config.paths.download_dir.as_str())).chain(configured)
+        {
+            std::fs::create_dir_all(path)?;
+            let canonical = std::fs::canonicalize(path).map_err(|e| {
+                PegasusError::ConfigError(format!("Output root {:?} ({}): {}", name, path, e))
+            })?;
+            info!(root = %name, path = %canonical.display(), "Registered output root");
+            roots.insert(name.to_string(), canonical);
+        }
+        Ok(OutputRoots { roots })
+    }
+
+    /// Lists the roots, sorted by name.
+    pub fn list(&self) -> Vec<RootSummary> {
+        self.roots
+            .iter()
+            .map(|(name, path)| RootSummary {
+                name: name.clone(),
+                path: path.clone(),
+                default: name == DEFAULT_ROOT,
+            })
+            .collect()
+    }
+
+    /// Resolves a job's root and subdirectory to the directory it downloads into.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The root's name, or `None` for the default root.
+    /// * `subdir` - A relative subdirectory of the root, or `None` for the root itself
+    ///   (the `default` subdirectory if no root was named either).
+    /// * `requested_by` - The user the request came from, for the audit log.
+    ///
+    /// # Returns
+    ///
+    /// The directory, which is inside the root, or a `ValidationError` if the root does
+    /// not exist or the subdirectory would leave it.
+    pub fn resolve(
+        &self,
+        root: Option<&str>,
+        subdir: Option<&str>,
+        requested_by: Option<&str>,
+    ) -> Result<PathBuf> {
+        let subdir = match (root, subdir) {
+            (None, None) => Some(DEFAULT_SUBDIR),
+            (_, subdir) => subdir.filter(|s| !s.trim().is_empty()),
+        };
+        let root_name = root.unwrap_or(DEFAULT_ROOT);
+
+        let reject = |reason: &str| {
+            warn!(target: "audit", user = ?requested_by, root = %root_name, subdir = ?subdir, reason = %reason, "Rejected output directory");
+            PegasusError::ValidationError(format!("Invalid output directory: {}", reason))
+        };
+
+        let Some(root_path) = self.roots.get(root_name) else {
+            return Err(reject(&format!("unknown output root {:?}", root_name)));
+        };
+        let relative = match subdir {
+            Some(subdir) => normalize(subdir).map_err(|reason| reject(&reason))?,
+            None => PathBuf::new(),
+        };
+
+        let path = root_path.join(&relative);
+        self.check_links(root_path, &path)
+            .map_err(|reason| reject(&reason))?;
+        debug!(root = %root_name, path = %path.display(), "Resolved output directory");
+        Ok(path)
+    }
+
+    /// Checks that a path about to be written to is still inside one of the roots once
+    /// symlinks are followed. Directories named after a video's metadata may already
+    /// exist as links.
+    pub fn verify(&self, path: &Path) -> Result<()> {
+        let Some((name, root_path)) = self
+            .roots
+            .iter()
+            .find(|(_, root_path)| path.starts_with(root_path))
+        else {
+            warn!(target: "audit", path = %path.display(), "Rejected output path outside every root");
+            return Err(PegasusError::ValidationError(format!(
+                "Invalid output path: {} is outside the output roots",
+                path.display()
+            )));
+        };
+        self.check_links(root_path, path).map_err(|reason| {
+            warn!(target: "audit", root = %name, path = %path.display(), reason = %reason, "Rejected output path");
+            PegasusError::ValidationError(format!("Invalid output path: {}", reason))
+        })
+    }
+
+    // Resolves the deepest part of `path` that exists and checks it is inside `root`
+    fn check_links(&self, root: &Path, path: &Path) -> std::result::Result<(), String> {
+        let existing = path
+            .ancestors()
+            .find(|ancestor| ancestor.symlink_metadata().is_ok())
+            .unwrap_or(root);
+        let real = std::fs::canonicalize(existing)
+            .map_err(|e| format!("cannot resolve {}: {}", existing.display(), e))?;
+        if real.starts_with(root) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} leads outside its output root through a symbolic link",
+                existing.display()
+            ))
+        }
+    }
+}
+
+/// Normalizes a client-supplied subdirectory without touching the filesystem.
+///
+/// `.` components are dropped and `..` removes the previous component. Absolute paths,
+/// `..` above the start and control characters are rejected. Backslashes count as
+/// separators, so Windows-style paths cannot slip a `..` through.
+fn normalize(subdir: &str) -> std::result::Result<PathBuf, String> {
+    if subdir.chars().any(char::is_control) {
+        return Err("it contains control characters".to_string());
+    }
+    let subdir = subdir.trim().replace('\\', "/");
+
+    let mut normalized = PathBuf::new();
+    for component in Path::new(&subdir).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return Err("it leads outside its output root".to_string());
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err("it must be relative to an output root".to_string());
+            }
+        }
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_keeps_relative_paths() {
+        assert_eq!(normalize("music/2024"), Ok(PathBuf::from("music/2024")));
+        assert_eq!(normalize(""), Ok(PathBuf::new()));
+        assert_eq!(normalize("  music/  "), Ok(PathBuf::from("music")));
+        assert_eq!(normalize("a/./b/../c"), Ok(PathBuf::from("a/c")));
+        assert_eq!(normalize("a//b/"), Ok(PathBuf::from("a/b")));
+    }
+
+    #[test]
+    fn normalize_rejects_parent_dirs_above_the_root() {
+        assert!(normalize("..").is_err());
+        assert!(normalize("../etc").is_err());
+        assert!(normalize("a/../..").is_err());
+        assert!(normalize("a/b/../../../c").is_err());
+        assert_eq!(normalize("a/b/../.."), Ok(PathBuf::new()));
+    }
+
+    #[test]
+    fn normalize_rejects_absolute_paths() {
+        assert!(normalize("/etc").is_err());
+        assert!(normalize("/").is_err());
+        assert!(normalize("\\etc").is_err());
+    }
+
+    #[test]
+    fn normalize_treats_backslashes_as_separators() {
+        assert_eq!(normalize("a\\b"), Ok(PathBuf::from("a/b")));
+        assert!(normalize("..\\etc").is_err());
+        assert!(normalize("a\\..\\..\\etc").is_err());
+    }
+
+    #[test]
+    fn normalize_rejects_control_characters() {
+        assert!(normalize("a\nb").is_err());
+        assert!(normalize("a\0b").is_err());
+        assert!(normalize("music\t").is_err());
+        assert!(normalize("\u{7f}").is_err());
+    }
+}
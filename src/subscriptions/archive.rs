@@ -0,0 +1,333 @@
+// src/subscriptions/archive.rs
+// The download archive: every item a subscription has fetched, so it is never fetched twice.
+//
+// Items are tracked per subscription, so two subscriptions that share an item (say, one
+// fetching a channel as video and one as audio only) each fetch it once. The file holds
+// one `subscription-id extractor id` line per item. Lines in yt-dlp's own
+// `--download-archive` format (`extractor id`, with no subscription) count as fetched
+// for every subscription, so an existing yt-dlp archive, or one written before items
+// were tracked per subscription, can still be used.
+//
+// Items whose jobs fail are tracked in a second, JSON-lines file next to it
+// (`archive.failures.jsonl` for `archive.txt`), where the last line for an item wins. An
+// item is given up on after a permanent failure, such as a deleted or geo-blocked video,
+// or after `MAX_ITEM_ATTEMPTS` failed jobs; deleting its line there lets it be fetched
+// again after a restart.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::error::{PegasusError, Result};
+
+/// How many jobs are submitted for an item that keeps failing before it is given up on.
+const MAX_ITEM_ATTEMPTS: u32 = 3;
+
+/// The failed attempts at fetching an item.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemFailure {
+    /// The subscription that tried to fetch the item. Entries written before items were
+    /// tracked per subscription have none and apply to every subscription.
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+    pub key: String,
+    /// Number of failed jobs for the item.
+    pub attempts: u32,
+    /// The error code of the last failure, e.g. `video_unavailable`.
+    pub error_code: String,
+    /// Whether the item is no longer submitted.
+    pub gave_up: bool,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// The state of every item, keyed by [`scoped`] keys. Keys without a subscription come
+/// from yt-dlp-style lines and apply to every subscription.
+#[derive(Default)]
+struct ArchiveState {
+    /// Items that have been downloaded.
+    recorded: HashSet<String>,
+    /// Items with a job in flight; recorded once the job completes, released if it fails.
+    pending: HashSet<String>,
+    /// Items whose jobs have failed and that have not been downloaded since.
+    failures: HashMap<String, ItemFailure>,
+}
+
+/// The key an item is tracked under for one subscription, which is also its line in the
+/// archive file.
+fn scoped(subscription_id: &str, key: &str) -> String {
+    format!("{} {}", subscription_id, key)
+}
+
+/// The key an archive line or failure entry is tracked under. Lines that start with a
+/// subscription ID are kept as they are; anything else is a yt-dlp-style line for every
+/// subscription.
+fn tracked_key(subscription_id: Option<&str>, key: &str) -> String {
+    match subscription_id {
+        Some(subscription_id) => scoped(subscription_id, key),
+        None => key.to_string(),
+    }
+}
+
+/// Thread-safe download archive backed by an append-only file.
+pub struct DownloadArchive {
+    state: Mutex<ArchiveState>,
+    file: tokio::sync::Mutex<File>,
+    failures_file: tokio::sync::Mutex<File>,
+}
+
+impl DownloadArchive {
+    /// Opens the archive file, creating it on first use.
+    pub async fn open(path: &Path) -> Result<Self> {
+        info!(path = %path.display(), "Opening download archive");
+
+        let recorded: HashSet<String> = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => {
+                error!(error = %e, path = %path.display(), "Failed to read download archive");
+                return Err(PegasusError::IoError(e));
+            }
+        };
+        info!(items = recorded.len(), "Loaded download archive");
+
+        // Replay the failure log, keeping the latest entry of every item not downloaded since
+        let failures_path = path.with_extension("failures.jsonl");
+        let mut failures = HashMap::new();
+        match tokio::fs::read_to_string(&failures_path).await {
+            Ok(contents) => {
+                for (line_no, line) in contents.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<ItemFailure>(line) {
+                        Ok(failure) => {
+                            let key = tracked_key(failure.subscription_id.as_deref(), &failure.key);
+                            failures.insert(key, failure);
+                        }
+                        Err(e) => {
+                            warn!(line = line_no + 1, error = %e, "Skipping malformed archive failure entry");
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                error!(error = %e, path = %failures_path.display(), "Failed to read archive failures");
+                return Err(PegasusError::IoError(e));
+            }
+        }
+        failures.retain(|key, _| !recorded.contains(key));
+        let unscoped = recorded
+            .iter()
+            .filter(|line| {
+                line.split_once(' ')
+                    .is_none_or(|(first, _)| Uuid::parse_str(first).is_err())
+            })
+            .count();
+        if unscoped > 0 {
+            info!(
+                items = unscoped,
+                "Archive items without a subscription count for every subscription"
+            );
+        }
+        let given_up = failures.values().filter(|f| f.gave_up).count();
+        info!(failed = failures.len(), given_up, "Loaded archive failures");
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let failures_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&failures_path)
+            .await?;
+
+        Ok(DownloadArchive {
+            state: Mutex::new(ArchiveState {
+                recorded,
+                pending: HashSet::new(),
+                failures,
+            }),
+            file: tokio::sync::Mutex::new(file),
+            failures_file: tokio::sync::Mutex::new(failures_file),
+        })
+    }
+
+    /// Claims an item for download by a subscription.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the subscription has already downloaded the item, is downloading it or
+    /// has given up on it.
+    pub fn reserve(&self, subscription_id: &str, key: &str) -> bool {
+        let scoped_key = scoped(subscription_id, key);
+        let mut state = self.state.lock().unwrap();
+        let done = |k: &str| {
+            state.recorded.contains(k) || state.failures.get(k).is_some_and(|f| f.gave_up)
+        };
+        if done(&scoped_key) || done(key) {
+            return false;
+        }
+        state.pending.insert(scoped_key)
+    }
+
+    /// Gives up a claim after the item's job was cancelled or could not be submitted, so
+    /// a later check can try again.
+    pub fn release(&self, subscription_id: &str, key: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .remove(&scoped(subscription_id, key));
+    }
+
+    /// Gives up a claim after the item's job failed, counting the failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscription_id` - The subscription that submitted the job.
+    /// * `key` - The item's archive key.
+    /// * `error` - Why the job failed.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the item's failures, which say whether it will be retried.
+    pub async fn fail(
+        &self,
+        subscription_id: &str,
+        key: &str,
+        error: &PegasusError,
+    ) -> Result<ItemFailure> {
+        let scoped_key = scoped(subscription_id, key);
+        let failure = {
+            let mut state = self.state.lock().unwrap();
+            state.pending.remove(&scoped_key);
+            let attempts = state.failures.get(&scoped_key).map_or(0, |f| f.attempts) + 1;
+            let failure = ItemFailure {
+                subscription_id: Some(subscription_id.to_string()),
+                key: key.to_string(),
+                attempts,
+                error_code: error.code().to_string(),
+                gave_up: error.is_permanent() || attempts >= MAX_ITEM_ATTEMPTS,
+                failed_at: Utc::now(),
+            };
+            state.failures.insert(scoped_key, failure.clone());
+            failure
+        };
+        if failure.gave_up {
+            warn!(subscription_id = %subscription_id, key = %key, attempts = failure.attempts, error_code = %failure.error_code, "Giving up on archive item");
+        }
+
+        let line = serde_json::to_string(&failure).map_err(|e| {
+            PegasusError::Unknown(format!("Failed to serialize archive failure: {}", e))
+        })?;
+        let mut file = self.failures_file.lock().await;
+        file.write_all(format!("{}\n", line).as_bytes())
+            .await
+            .map_err(|e| {
+                error!(error = %e, key = %key, "Failed to write archive failure entry");
+                PegasusError::IoError(e)
+            })?;
+        file.flush().await?;
+        Ok(failure)
+    }
+
+    /// Records an item as downloaded by a subscription.
+    pub async fn record(&self, subscription_id: &str, key: &str) -> Result<()> {
+        let line = scoped(subscription_id, key);
+        {
+            let mut state = self.state.lock().unwrap();
+            state.pending.remove(&line);
+            state.failures.remove(&line);
+            if !state.recorded.insert(line.clone()) {
+                return Ok(());
+            }
+        }
+
+        let mut file = self.file.lock().await;
+        file.write_all(format!("{}\n", line).as_bytes())
+            .await
+            .map_err(|e| {
+                error!(error = %e, key = %key, "Failed to write download archive entry");
+                PegasusError::IoError(e)
+            })?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "0b4f1a46-5b49-4d7a-9a39-6d1f0c0f0a01";
+    const SECOND: &str = "0b4f1a46-5b49-4d7a-9a39-6d1f0c0f0a02";
+
+    #[tokio::test]
+    async fn items_are_tracked_per_subscription() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.txt");
+        let archive = DownloadArchive::open(&path).await.unwrap();
+
+        assert!(archive.reserve(FIRST, "youtube abc"));
+        assert!(!archive.reserve(FIRST, "youtube abc"));
+        // The same item is still new to another subscription
+        assert!(archive.reserve(SECOND, "youtube abc"));
+        archive.record(FIRST, "youtube abc").await.unwrap();
+        archive.release(SECOND, "youtube abc");
+        assert!(!archive.reserve(FIRST, "youtube abc"));
+        assert!(archive.reserve(SECOND, "youtube abc"));
+
+        // Giving up is per subscription too
+        let gone = PegasusError::VideoUnavailable("removed".to_string());
+        let failure = archive.fail(FIRST, "youtube def", &gone).await.unwrap();
+        assert!(failure.gave_up);
+        assert!(!archive.reserve(FIRST, "youtube def"));
+        assert!(archive.reserve(SECOND, "youtube def"));
+        drop(archive);
+
+        let archive = DownloadArchive::open(&path).await.unwrap();
+        assert!(!archive.reserve(FIRST, "youtube abc"));
+        assert!(!archive.reserve(FIRST, "youtube def"));
+        assert!(archive.reserve(SECOND, "youtube abc"));
+        assert!(archive.reserve(SECOND, "youtube def"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{} youtube abc\n", FIRST)
+        );
+    }
+
+    #[tokio::test]
+    async fn yt_dlp_lines_count_for_every_subscription() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.txt");
+        std::fs::write(&path, "youtube abc\n\nurl https://example.com/v\n").unwrap();
+        std::fs::write(
+            path.with_extension("failures.jsonl"),
+            r#"{"key":"youtube gone","attempts":3,"error_code":"video_unavailable","gave_up":true,"failed_at":"2024-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+
+        let archive = DownloadArchive::open(&path).await.unwrap();
+        for subscription_id in [FIRST, SECOND] {
+            assert!(!archive.reserve(subscription_id, "youtube abc"));
+            assert!(!archive.reserve(subscription_id, "url https://example.com/v"));
+            assert!(!archive.reserve(subscription_id, "youtube gone"));
+            assert!(archive.reserve(subscription_id, "youtube new"));
+        }
+    }
+}
@@ -0,0 +1,294 @@
+// src/subscriptions/mod.rs
+// This module contains the subscription registry.
+//
+// A subscription is a channel, playlist or podcast URL that the scheduler polls on an
+// interval. New items are submitted as regular jobs; the download archive makes sure an
+// item is never fetched twice. Subscriptions are few and rarely change, so the registry is
+// persisted as a single JSON file that is rewritten on every change.
+
+pub mod archive;
+pub mod scheduler;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::download::naming::NamingOptions;
+use crate::download::options::ProcessingOptions;
+use crate::download::playlist::PlaylistOptions;
+use crate::error::{PegasusError, Result};
+
+/// Shortest allowed polling interval, to stay friendly with the sites being polled.
+pub const MIN_INTERVAL_MINUTES: u32 = 5;
+
+/// Longest allowed polling interval (one week).
+pub const MAX_INTERVAL_MINUTES: u32 = 7 * 24 * 60;
+
+/// A followed channel, playlist or podcast.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: String,
+    pub url: String,
+    pub name: Option<String>,
+    /// How often the URL is polled for new items.
+    pub interval_minutes: u32,
+    /// Options for the jobs created for new items; the configured defaults if `None`.
+    pub processing_options: Option<ProcessingOptions>,
+    /// Transfer destination for new items; the configured default if `None`.
+    pub destination: Option<String>,
+    /// Output root new items download into; the default root if `None`.
+    #[serde(default)]
+    pub root: Option<String>,
+    pub output_dir: Option<String>,
+    /// Which entries of the listing are considered on every check.
+    pub playlist: PlaylistOptions,
+    /// Output template and collision policy overrides for new items.
+    #[serde(default)]
+    pub naming: NamingOptions,
+    pub priority: i32,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    pub next_check_at: DateTime<Utc>,
+    /// The error of the last check, if it failed.
+    pub last_error: Option<String>,
+    /// Number of jobs the last check submitted.
+    pub last_new_items: usize,
+    /// The user who created the subscription; its jobs are submitted as them.
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+/// The caller-supplied fields of a subscription being created or replaced.
+#[derive(Clone, Debug)]
+pub struct NewSubscription {
+    pub url: String,
+    pub name: Option<String>,
+    pub interval_minutes: u32,
+    pub processing_options: Option<ProcessingOptions>,
+    pub destination: Option<String>,
+    pub root: Option<String>,
+    pub output_dir: Option<String>,
+    pub playlist: PlaylistOptions,
+    pub naming: NamingOptions,
+    pub priority: i32,
+    pub enabled: bool,
+    /// Only used on creation; replacing a subscription keeps its owner.
+    pub owner: Option<String>,
+}
+
+/// The result of polling a subscription once.
+pub enum CheckOutcome {
+    /// The check succeeded and submitted this many new jobs.
+    NewItems(usize),
+    /// The check failed with this error.
+    Failed(String),
+}
+
+/// Thread-safe registry of subscriptions backed by a JSON file.
+pub struct SubscriptionStore {
+    subscriptions: RwLock<HashMap<String, Subscription>>,
+    path: PathBuf,
+    /// Serialises writes so concurrent changes cannot interleave their file writes.
+    write_lock: Mutex<()>,
+}
+
+impl SubscriptionStore {
+    /// Opens the subscription file, creating it on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The location of the JSON file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the opened `SubscriptionStore`, or a `PegasusError` on failure.
+    pub async fn open(path: &Path) -> Result<Self> {
+        info!(path = %path.display(), "Opening subscription store");
+
+        let subscriptions: Vec<Subscription> = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| {
+                error!(error = %e, path = %path.display(), "Failed to parse subscriptions");
+                PegasusError::ConfigError(format!(
+                    "Failed to parse subscriptions in {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                error!(error = %e, path = %path.display(), "Failed to read subscriptions");
+                return Err(PegasusError::IoError(e));
+            }
+        };
+        info!(count = subscriptions.len(), "Loaded subscriptions");
+
+        Ok(SubscriptionStore {
+            subscriptions: RwLock::new(
+                subscriptions
+                    .into_iter()
+                    .map(|s| (s.id.clone(), s))
+                    .collect(),
+            ),
+            path: path.to_path_buf(),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Lists every subscription, oldest first.
+    pub async fn list(&self) -> Vec<Subscription> {
+        let mut subscriptions: Vec<Subscription> =
+            self.subscriptions.read().await.values().cloned().collect();
+        subscriptions.sort_by_key(|s| s.created_at);
+        subscriptions
+    }
+
+    /// Returns a snapshot of a single subscription.
+    pub async fn get(&self, id: &str) -> Option<Subscription> {
+        self.subscriptions.read().await.get(id).cloned()
+    }
+
+    /// Registers a new subscription under a freshly generated ID. It is checked right away.
+    pub async fn create(&self, new: NewSubscription) -> Result<Subscription> {
+        let now = Utc::now();
+        let subscription = Subscription {
+            id: Uuid::new_v4().to_string(),
+            url: new.url,
+            name: new.name,
+            interval_minutes: new.interval_minutes,
+            processing_options: new.processing_options,
+            destination: new.destination,
+            root: new.root,
+            output_dir: new.output_dir,
+            playlist: new.playlist,
+            naming: new.naming,
+            priority: new.priority,
+            enabled: new.enabled,
+            created_at: now,
+            updated_at: now,
+            last_checked_at: None,
+            next_check_at: now,
+            last_error: None,
+            last_new_items: 0,
+            owner: new.owner,
+        };
+
+        self.subscriptions
+            .write()
+            .await
+            .insert(subscription.id.clone(), subscription.clone());
+        self.persist().await?;
+        Ok(subscription)
+    }
+
+    /// Replaces the editable fields of a subscription, keeping its check history.
+    ///
+    /// # Returns
+    ///
+    /// The updated subscription, or `None` if no subscription with that ID exists.
+    pub async fn replace(&self, id: &str, new: NewSubscription) -> Result<Option<Subscription>> {
+        let updated = {
+            let mut subscriptions = self.subscriptions.write().await;
+            let Some(subscription) = subscriptions.get_mut(id) else {
+                return Ok(None);
+            };
+            // A shorter interval takes effect immediately rather than after the old one
+            let next_check = subscription
+                .last_checked_at
+                .map(|t| t + Duration::minutes(new.interval_minutes.into()))
+                .unwrap_or_else(Utc::now);
+            subscription.next_check_at = next_check.min(subscription.next_check_at);
+            subscription.url = new.url;
+            subscription.name = new.name;
+            subscription.interval_minutes = new.interval_minutes;
+            subscription.processing_options = new.processing_options;
+            subscription.destination = new.destination;
+            subscription.root = new.root;
+            subscription.output_dir = new.output_dir;
+            subscription.playlist = new.playlist;
+            subscription.naming = new.naming;
+            subscription.priority = new.priority;
+            subscription.enabled = new.enabled;
+            subscription.updated_at = Utc::now();
+            subscription.clone()
+        };
+
+        self.persist().await?;
+        Ok(Some(updated))
+    }
+
+    /// Removes a subscription. Jobs it already submitted are left alone.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the subscription existed.
+    pub async fn delete(&self, id: &str) -> Result<bool> {
+        let removed = self.subscriptions.write().await.remove(id).is_some();
+        if removed {
+            self.persist().await?;
+        }
+        Ok(removed)
+    }
+
+    /// Returns the enabled subscriptions whose next check is due.
+    pub async fn due(&self, now: DateTime<Utc>) -> Vec<Subscription> {
+        let mut due: Vec<Subscription> = self
+            .subscriptions
+            .read()
+            .await
+            .values()
+            .filter(|s| s.enabled && s.next_check_at <= now)
+            .cloned()
+            .collect();
+        due.sort_by_key(|s| s.next_check_at);
+        due
+    }
+
+    /// Records the outcome of a check and schedules the next one.
+    pub async fn record_check(&self, id: &str, outcome: CheckOutcome) -> Result<()> {
+        {
+            let mut subscriptions = self.subscriptions.write().await;
+            let Some(subscription) = subscriptions.get_mut(id) else {
+                return Ok(());
+            };
+            let now = Utc::now();
+            subscription.last_checked_at = Some(now);
+            subscription.next_check_at =
+                now + Duration::minutes(subscription.interval_minutes.into());
+            match outcome {
+                CheckOutcome::NewItems(count) => {
+                    subscription.last_error = None;
+                    subscription.last_new_items = count;
+                }
+                CheckOutcome::Failed(error) => {
+                    subscription.last_error = Some(error);
+                    subscription.last_new_items = 0;
+                }
+            }
+        }
+
+        self.persist().await
+    }
+
+    /// Writes every subscription to the JSON file, replacing it atomically.
+    async fn persist(&self) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+        let contents = serde_json::to_string_pretty(&self.list().await).map_err(|e| {
+            PegasusError::Unknown(format!("Failed to serialize subscriptions: {}", e))
+        })?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, contents).await.map_err(|e| {
+            error!(error = %e, path = %tmp_path.display(), "Failed to write subscriptions");
+            PegasusError::IoError(e)
+        })?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+// src/subscriptions/scheduler.rs
+// Background task that polls due subscriptions and submits their new items as jobs.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::{debug, error, info, warn};
+
+use super::{CheckOutcome, Subscription};
+use crate::api::AppState;
+use crate::download::playlist::{PlaylistEntry, PlaylistOptions};
+use crate::download::{self, playlist};
+use crate::error::Result;
+use crate::jobs::NewJob;
+use crate::pipeline;
+
+/// How often the scheduler looks for due subscriptions.
+const TICK: Duration = Duration::from_secs(30);
+
+/// Starts the scheduler on the Tokio runtime.
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        info!(tick_secs = TICK.as_secs(), "Subscription scheduler started");
+        let mut interval = tokio::time::interval(TICK);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            for subscription in state.subscriptions.due(Utc::now()).await {
+                check_subscription(&state, &subscription).await;
+            }
+        }
+    });
+}
+
+/// Polls a subscription once and records the outcome.
+pub async fn check_subscription(state: &AppState, subscription: &Subscription) {
+    info!(subscription_id = %subscription.id, url = %subscription.url, "Checking subscription");
+
+    let outcome = match submit_new_items(state, subscription).await {
+        Ok(count) => {
+            info!(subscription_id = %subscription.id, new_items = count, "Subscription checked");
+            CheckOutcome::NewItems(count)
+        }
+        Err(e) => {
+            warn!(subscription_id = %subscription.id, error = %e, "Subscription check failed");
+            CheckOutcome::Failed(e.to_string())
+        }
+    };
+
+    if let Err(e) = state
+        .subscriptions
+        .record_check(&subscription.id, outcome)
+        .await
+    {
+        error!(subscription_id = %subscription.id, error = %e, "Failed to record subscription check");
+    }
+}
+
+/// Lists a subscription's items and submits a job for every one not in the archive.
+///
+/// # Returns
+///
+/// A `Result` containing the number of jobs submitted.
+async fn submit_new_items(state: &AppState, subscription: &Subscription) -> Result<usize> {
+    let credentials = state.vault.for_url(&subscription.url).await?;
+    let info = download::get_video_info(
+        &subscription.url,
+        &subscription.playlist.yt_dlp_args(),
+        &state.config.binaries.yt_dlp,
+        state.config.timeouts.info_fetch(),
+        credentials.as_ref(),
+    )
+    .await?;
+
+    // A subscription to a single video is unusual but harmless: it is fetched once
+    let entries = if playlist::is_playlist(&info) {
+        let listing = playlist::parse_playlist(&info);
+        playlist::select_entries(&listing, &subscription.playlist)
+    } else {
+        vec![PlaylistEntry {
+            url: info["webpage_url"]
+                .as_str()
+                .unwrap_or(&subscription.url)
+                .to_string(),
+            title: info["title"].as_str().map(str::to_string),
+            timestamp: None,
+            archive_key: playlist::archive_key(&info),
+            index: 1,
+        }]
+    };
+
+    // Resolve the destination now so a changed default applies to new items
+    let destination = state
+        .transfers
+        .resolve(subscription.destination.as_deref())?;
+    let processing_options = subscription
+        .processing_options
+        .clone()
+        .unwrap_or_else(|| state.config.default_processing_options.clone());
+
+    let mut submitted = 0;
+    for entry in entries {
+        // Entries without an extractor ID are identified by their URL instead
+        let key = entry
+            .archive_key
+            .clone()
+            .unwrap_or_else(|| format!("url {}", entry.url));
+        if !state.archive.reserve(&subscription.id, &key) {
+            debug!(subscription_id = %subscription.id, key = %key, "Skipping archived item");
+            continue;
+        }
+
+        let result = pipeline::submit_job(
+            state,
+            NewJob {
+                url: entry.url.clone(),
+                root: subscription.root.clone(),
+                output_dir: subscription.output_dir.clone(),
+                processing_options: processing_options.clone(),
+                priority: subscription.priority,
+                destination: destination.clone(),
+                playlist: PlaylistOptions::default(),
+                naming: subscription.naming.clone(),
+                playlist_position: None,
+                parent_id: None,
+                subscription_id: Some(subscription.id.clone()),
+                archive_key: Some(key.clone()),
+                batch_id: None,
+                owner: subscription.owner.clone(),
+            },
+        )
+        .await;
+        match result {
+            Ok(job) => {
+                info!(subscription_id = %subscription.id, job_id = %job.id, title = ?entry.title, "Submitted new subscription item");
+                submitted += 1;
+            }
+            Err(e) => {
+                state.archive.release(&subscription.id, &key);
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(submitted)
+}
@@ -0,0 +1,427 @@
+// src/system/mod.rs
+// This module reports on the health of the server and the environment it runs in.
+//
+// Startup already refuses to run without working yt-dlp and ffmpeg binaries, but a binary
+// can be removed, a disk can fill up and a volume can be unmounted while the server is
+// running. The same checks are therefore available at runtime: `/healthz` only says the
+// process is serving requests, `/readyz` re-checks everything a job needs, and
+// `/api/system` describes the installed tools, the directories and the build.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::process::Command;
+use tracing::{debug, error, warn};
+
+use crate::api::AppState;
+use crate::config::{self, BinaryPaths, Config};
+use crate::error::{PegasusError, Result};
+
+/// How long a binary may take to report its version or capabilities.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long probed versions and ffmpeg capabilities are reused. Probing runs every
+/// binary, which is too slow to do on every request.
+const PROBE_TTL: Duration = Duration::from_secs(300);
+
+/// When the server started.
+static STARTED: Lazy<(Instant, DateTime<Utc>)> = Lazy::new(|| (Instant::now(), Utc::now()));
+
+/// The last probe of the binaries, reused for `PROBE_TTL`.
+static PROBE_CACHE: Lazy<Mutex<Option<(Instant, BinaryReport)>>> = Lazy::new(|| Mutex::new(None));
+
+/// Records the server's start time for the uptime report. Call once at startup.
+pub fn mark_started() {
+    Lazy::force(&STARTED);
+}
+
+/// The size of the filesystem a directory lives on.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct DiskSpace {
+    pub total_bytes: u64,
+    /// Space available to the server's user.
+    pub available_bytes: u64,
+}
+
+/// Reports the total and available space of the filesystem holding `path`.
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> std::io::Result<DiskSpace> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let block_size = stat.f_frsize as u64;
+    Ok(DiskSpace {
+        total_bytes: stat.f_blocks as u64 * block_size,
+        available_bytes: stat.f_bavail as u64 * block_size,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn disk_space(_path: &Path) -> std::io::Result<DiskSpace> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "disk space is only reported on Unix",
+    ))
+}
+
+/// Runs a binary with its version flag and returns the version it reports.
+///
+/// # Arguments
+///
+/// * `binary` - Path or name of the binary.
+/// * `version_arg` - The flag that prints the version, e.g. `--version` or `-version`.
+///
+/// # Returns
+///
+/// The version, e.g. `2024.08.06` for yt-dlp or `6.1.1` for ffmpeg, or a `PegasusError`
+/// if the binary is missing or fails.
+pub async fn binary_version(binary: &str, version_arg: &str) -> Result<String> {
+    let stdout = run_probe(binary, &[version_arg]).await?;
+    let first_line = stdout.lines().next().unwrap_or_default().trim();
+    // ffmpeg and ffprobe start with "ffmpeg version 6.1.1 Copyright ..."
+    let mut words = first_line.split_whitespace();
+    let version = match (words.next(), words.next(), words.next()) {
+        (Some(_), Some("version"), Some(version)) => version,
+        _ => first_line,
+    };
+    Ok(version.to_string())
+}
+
+// Runs a binary with a timeout and returns its standard output
+async fn run_probe(binary: &str, args: &[&str]) -> Result<String> {
+    let mut cmd = Command::new(binary);
+    cmd.args(args).stdin(Stdio::null()).kill_on_drop(true);
+    let output = match tokio::time::timeout(PROBE_TIMEOUT, cmd.output()).await {
+        Ok(output) => output.map_err(|e| {
+            error!(binary = %binary, error = %e, "Failed to execute binary");
+            PegasusError::from_spawn_error(binary, e)
+        })?,
+        Err(_) => {
+            return Err(PegasusError::ExternalCommandError(format!(
+                "{} did not respond within {}s",
+                binary,
+                PROBE_TIMEOUT.as_secs()
+            )));
+        }
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!(binary = %binary, stderr = %stderr, "Binary check failed");
+        return Err(PegasusError::ExternalCommandError(format!(
+            "{} {} failed: {}",
+            binary,
+            args.join(" "),
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The encoders and muxers the installed ffmpeg supports.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FfmpegCapabilities {
+    pub encoders: Vec<String>,
+    pub muxers: Vec<String>,
+}
+
+/// Lists ffmpeg's encoders and muxers.
+pub async fn ffmpeg_capabilities(ffmpeg: &str) -> Result<FfmpegCapabilities> {
+    let encoders = run_probe(ffmpeg, &["-hide_banner", "-encoders"]).await?;
+    let muxers = run_probe(ffmpeg, &["-hide_banner", "-muxers"]).await?;
+    Ok(FfmpegCapabilities {
+        encoders: parse_listing(&encoders),
+        muxers: parse_listing(&muxers),
+    })
+}
+
+/// Extracts the names from an `ffmpeg -encoders` or `-muxers` listing.
+///
+/// Both print a legend, a line starting with ` --` (or ` ------`), and then one entry per
+/// line as `<flags> <name> <description>`.
+fn parse_listing(output: &str) -> Vec<String> {
+    let mut names: Vec<String> = output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("--"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// What a binary reported about itself.
+#[derive(Clone, Debug, Serialize)]
+pub struct BinaryInfo {
+    /// The configured path or name.
+    pub path: String,
+    pub version: Option<String>,
+    /// Why the version could not be determined.
+    pub error: Option<String>,
+}
+
+/// The versions of the external binaries and ffmpeg's capabilities.
+#[derive(Clone, Debug, Serialize)]
+pub struct BinaryReport {
+    pub yt_dlp: BinaryInfo,
+    pub ffmpeg: BinaryInfo,
+    pub ffprobe: BinaryInfo,
+    pub ffmpeg_capabilities: FfmpegCapabilities,
+}
+
+/// Probes every binary, reusing the previous probe if it is recent.
+pub async fn probe_binaries(binaries: &BinaryPaths) -> BinaryReport {
+    if let Some((probed_at, report)) = PROBE_CACHE.lock().unwrap().as_ref()
+        && probed_at.elapsed() < PROBE_TTL
+    {
+        return report.clone();
+    }
+
+    let info = |path: &str, result: Result<String>| match result {
+        Ok(version) => BinaryInfo {
+            path: path.to_string(),
+            version: Some(version),
+            error: None,
+        },
+        Err(e) => BinaryInfo {
+            path: path.to_string(),
+            version: None,
+            error: Some(e.to_string()),
+        },
+    };
+    let (yt_dlp, ffmpeg, ffprobe, capabilities) = tokio::join!(
+        binary_version(&binaries.yt_dlp, "--version"),
+        binary_version(&binaries.ffmpeg, "-version"),
+        binary_version(&binaries.ffprobe, "-version"),
+        ffmpeg_capabilities(&binaries.ffmpeg),
+    );
+    let report = BinaryReport {
+        yt_dlp: info(&binaries.yt_dlp, yt_dlp),
+        ffmpeg: info(&binaries.ffmpeg, ffmpeg),
+        ffprobe: info(&binaries.ffprobe, ffprobe),
+        ffmpeg_capabilities: capabilities.unwrap_or_else(|e| {
+            warn!(error = %e, "Failed to list ffmpeg encoders and muxers");
+            FfmpegCapabilities::default()
+        }),
+    };
+    *PROBE_CACHE.lock().unwrap() = Some((Instant::now(), report.clone()));
+    report
+}
+
+/// The outcome of one readiness check.
+#[derive(Clone, Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    /// What is wrong, or a measurement such as the free space.
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, result: std::result::Result<Option<String>, String>) -> Self {
+        match result {
+            Ok(detail) => Check {
+                name: name.into(),
+                ok: true,
+                detail,
+            },
+            Err(problem) => Check {
+                name: name.into(),
+                ok: false,
+                detail: Some(problem),
+            },
+        }
+    }
+}
+
+/// The result of every readiness check.
+#[derive(Clone, Debug, Serialize)]
+pub struct Readiness {
+    /// Whether every check passed.
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Checks that the server can run jobs: the binaries are executable, the working
+/// directories are writable and have enough free space, and the job store responds.
+pub async fn readiness(state: &AppState) -> Readiness {
+    let config = state.config.clone();
+    let mut checks = tokio::task::spawn_blocking(move || filesystem_checks(&config))
+        .await
+        .unwrap_or_else(|e| vec![Check::new("filesystem", Err(e.to_string()))]);
+
+    checks.push(Check::new(
+        "job_store",
+        state
+            .jobs
+            .check()
+            .await
+            .map(|_| None)
+            .map_err(|e| e.to_string()),
+    ));
+
+    let ready = checks.iter().all(|check| check.ok);
+    if !ready {
+        let failed: Vec<&str> = checks
+            .iter()
+            .filter(|check| !check.ok)
+            .map(|check| check.name.as_str())
+            .collect();
+        warn!(failed = ?failed, "Readiness check failed");
+    }
+    Readiness { ready, checks }
+}
+
+// The checks that touch the filesystem, run on a blocking thread
+fn filesystem_checks(config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    for (name, binary) in [
+        ("binary:yt_dlp", &config.binaries.yt_dlp),
+        ("binary:ffmpeg", &config.binaries.ffmpeg),
+        ("binary:ffprobe", &config.binaries.ffprobe),
+    ] {
+        let result = match config::find_executable(binary) {
+            Some(path) => Ok(Some(path.display().to_string())),
+            None => Err(format!("{:?} is not an executable", binary)),
+        };
+        checks.push(Check::new(name, result));
+    }
+
+    let min_free = config.health.min_free_disk_mb.saturating_mul(1024 * 1024);
+    for (name, dir) in directories(config) {
+        let path = Path::new(&dir);
+        checks.push(Check::new(
+            format!("writable:{}", name),
+            config::check_writable_dir(path)
+                .map(|_| None)
+                .map_err(|e| format!("{:?} is not writable: {}", dir, e)),
+        ));
+
+        let result = match disk_space(path) {
+            Ok(space) if space.available_bytes >= min_free => Ok(Some(format!(
+                "{} MiB free",
+                space.available_bytes / (1024 * 1024)
+            ))),
+            Ok(space) => Err(format!(
+                "only {} MiB free, below the {} MiB minimum",
+                space.available_bytes / (1024 * 1024),
+                config.health.min_free_disk_mb
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => Ok(None),
+            Err(e) => Err(format!("failed to read free space of {:?}: {}", dir, e)),
+        };
+        checks.push(Check::new(format!("disk_space:{}", name), result));
+    }
+    debug!(checks = checks.len(), "Filesystem checks done");
+    checks
+}
+
+// The directories jobs write to, by config key, followed by the output roots as
+// `root:<name>`, sorted by name
+fn directories(config: &Config) -> Vec<(String, String)> {
+    let mut roots: Vec<_> = config
+        .paths
+        .roots
+        .iter()
+        .map(|(name, path)| (format!("root:{}", name), path.clone()))
+        .collect();
+    roots.sort();
+
+    let mut directories = vec![
+        (
+            "download_dir".to_string(),
+            config.paths.download_dir.clone(),
+        ),
+        ("output_dir".to_string(), config.paths.output_dir.clone()),
+    ];
+    directories.extend(roots);
+    directories
+}
+
+/// Where a working directory is and how much room it has.
+#[derive(Clone, Debug, Serialize)]
+pub struct DirectoryInfo {
+    /// The config key, e.g. `download_dir`, or `root:<name>` for an output root.
+    pub name: String,
+    pub path: PathBuf,
+    pub total_bytes: Option<u64>,
+    pub available_bytes: Option<u64>,
+}
+
+/// How this binary was built.
+#[derive(Clone, Debug, Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// `debug` or `release`.
+    pub profile: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+}
+
+/// Everything `/api/system` reports.
+#[derive(Clone, Debug, Serialize)]
+pub struct SystemInfo {
+    pub build: BuildInfo,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    pub binaries: BinaryReport,
+    pub directories: Vec<DirectoryInfo>,
+}
+
+/// Collects the build, binary and directory information for `/api/system`.
+pub async fn system_info(state: &AppState) -> SystemInfo {
+    let binaries = probe_binaries(&state.config.binaries).await;
+
+    let config = state.config.clone();
+    let directories = tokio::task::spawn_blocking(move || {
+        directories(&config)
+            .into_iter()
+            .map(|(name, dir)| {
+                let space = disk_space(Path::new(&dir)).ok();
+                DirectoryInfo {
+                    name,
+                    path: PathBuf::from(dir),
+                    total_bytes: space.map(|s| s.total_bytes),
+                    available_bytes: space.map(|s| s.available_bytes),
+                }
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default();
+
+    let (started, started_at) = *STARTED;
+    SystemInfo {
+        build: BuildInfo {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            profile: if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        },
+        started_at,
+        uptime_secs: started.elapsed().as_secs(),
+        binaries,
+        directories,
+    }
+}
@@ -0,0 +1,224 @@
+// src/transfer/local.rs
+// Transfer backend for local (or mounted) directories.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{debug, info, warn};
+
+use super::{
+    CHUNK_SIZE, LocalMode, TransferBackend, TransferContext, TransferTarget, sha256_file,
+    verify_checksum,
+};
+use crate::error::{PegasusError, Result};
+
+/// Moves or copies files into a directory on the local filesystem.
+pub struct LocalBackend {
+    root: PathBuf,
+    mode: LocalMode,
+}
+
+impl LocalBackend {
+    pub fn new(root: &str, mode: LocalMode) -> Self {
+        LocalBackend {
+            root: PathBuf::from(root),
+            mode,
+        }
+    }
+
+    /// Copies `source` to `part_path`, resuming from whatever is already there.
+    async fn copy_resumable(
+        &self,
+        source: &Path,
+        part_path: &Path,
+        ctx: &TransferContext,
+    ) -> Result<()> {
+        let total = tokio::fs::metadata(source).await?.len();
+
+        // An existing partial copy no larger than the source is resumed from its end
+        let existing = match tokio::fs::metadata(part_path).await {
+            Ok(meta) if meta.len() <= total => meta.len(),
+            Ok(_) => {
+                tokio::fs::remove_file(part_path).await?;
+                0
+            }
+            Err(_) => 0,
+        };
+        if existing > 0 {
+            info!(job_id = %ctx.job_id, offset = existing, "Resuming partial local copy");
+        }
+
+        let mut reader = tokio::fs::File::open(source).await?;
+        reader.seek(std::io::SeekFrom::Start(existing)).await?;
+        let mut writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(part_path)
+            .await?;
+
+        let mut copied = existing;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            ctx.check_cancelled()?;
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read]).await?;
+            copied += read as u64;
+            ctx.report(copied, total);
+        }
+        writer.sync_all().await?;
+        Ok(())
+    }
+}
+
+/// Moves `from` to `to` unless something already exists at `to`.
+///
+/// A hard link fails atomically if the name is taken, so two jobs racing for the same
+/// name cannot replace each other's file. Filesystems without hard links fall back to a
+/// check followed by a rename.
+///
+/// # Returns
+///
+/// `Ok(true)` if the file was moved, `Ok(false)` if `to` was taken.
+async fn move_no_clobber(from: &Path, to: &Path) -> std::io::Result<bool> {
+    match tokio::fs::hard_link(from, to).await {
+        Ok(()) => {
+            tokio::fs::remove_file(from).await?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => {
+            debug!(error = %e, "Hard link failed, falling back to rename");
+            if tokio::fs::symlink_metadata(to).await.is_ok() {
+                return Ok(false);
+            }
+            tokio::fs::rename(from, to).await?;
+            Ok(true)
+        }
+    }
+}
+
+/// Moves `from` into `dir` under the first name the collision policy allows.
+///
+/// # Returns
+///
+/// `Ok(Some(path))` with the new location, or `Ok(None)` if the name was taken and the
+/// policy keeps the existing file.
+async fn place(
+    from: &Path,
+    dir: &Path,
+    target: &TransferTarget,
+) -> std::io::Result<Option<PathBuf>> {
+    for name in target.names() {
+        let destination = dir.join(&name);
+        if target.overwrite() {
+            tokio::fs::rename(from, &destination).await?;
+            return Ok(Some(destination));
+        }
+        if move_no_clobber(from, &destination).await? {
+            return Ok(Some(destination));
+        }
+        debug!(destination = %destination.display(), "Destination name is taken");
+    }
+    Ok(None)
+}
+
+#[async_trait]
+impl TransferBackend for LocalBackend {
+    async fn transfer(
+        &self,
+        file: &Path,
+        target: &TransferTarget,
+        ctx: &TransferContext,
+    ) -> Result<String> {
+        let dir = target
+            .dirs
+            .iter()
+            .fold(self.root.clone(), |dir, part| dir.join(part));
+        tokio::fs::create_dir_all(&dir).await.map_err(|e| {
+            PegasusError::TransferError(format!(
+                "Failed to create destination directory {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+
+        // Under the skip policy an existing file wins and the source is left alone
+        let existing = dir.join(&target.file_name);
+        if target.keeps_existing() && tokio::fs::symlink_metadata(&existing).await.is_ok() {
+            info!(job_id = %ctx.job_id, destination = %existing.display(), "File already exists at destination, skipping");
+            return Ok(existing.display().to_string());
+        }
+
+        // A rename is atomic and instant when both paths share a filesystem
+        if self.mode == LocalMode::Move {
+            match place(file, &dir, target).await {
+                Ok(Some(destination)) => {
+                    debug!(job_id = %ctx.job_id, destination = %destination.display(), "Moved file with rename");
+                    let size = tokio::fs::metadata(&destination).await?.len();
+                    ctx.report(size, size);
+                    return Ok(destination.display().to_string());
+                }
+                Ok(None) => {
+                    info!(job_id = %ctx.job_id, destination = %existing.display(), "File already exists at destination, skipping");
+                    return Ok(existing.display().to_string());
+                }
+                Err(e) => {
+                    debug!(error = %e, "Rename failed, falling back to copy");
+                }
+            }
+        }
+
+        let expected = sha256_file(file).await?;
+        let part_path = dir.join(target.part_name(&ctx.job_id));
+
+        // A stale partial copy fails verification once; the retry starts from scratch
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            self.copy_resumable(file, &part_path, ctx).await?;
+            let actual = sha256_file(&part_path).await?;
+            match verify_checksum(&expected, &actual, &part_path.display().to_string()) {
+                Ok(()) => break,
+                Err(e) if attempts < 2 => {
+                    warn!(job_id = %ctx.job_id, error = %e, "Checksum mismatch, restarting copy");
+                    tokio::fs::remove_file(&part_path).await?;
+                }
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(&part_path).await;
+                    return Err(e);
+                }
+            }
+        }
+
+        let placed = place(&part_path, &dir, target).await.map_err(|e| {
+            PegasusError::TransferError(format!(
+                "Failed to move {} into place: {}",
+                part_path.display(),
+                e
+            ))
+        })?;
+        let destination = match placed {
+            Some(destination) => destination,
+            None => {
+                // Another job delivered the same name while this copy was running
+                info!(job_id = %ctx.job_id, destination = %existing.display(), "File appeared at destination during copy, skipping");
+                let _ = tokio::fs::remove_file(&part_path).await;
+                existing
+            }
+        };
+
+        if self.mode == LocalMode::Move
+            && let Err(e) = tokio::fs::remove_file(file).await
+        {
+            warn!(error = %e, file = %file.display(), "Failed to remove source after copy");
+        }
+
+        Ok(destination.display().to_string())
+    }
+}
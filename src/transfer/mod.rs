@@ -1,10 +1,428 @@
 // src/transfer/mod.rs
 // This module handles transferring files to the media server.
+//
+// Every destination is served by a `TransferBackend`. Backends write to a temporary
+// `.part` name first, resume an existing partial upload where they can, verify the
+// SHA-256 checksum of the copy and only then move it to its final name, so a media
+// library never sees a half-written file.
+//
+// Files keep the directories the output template put them in, relative to the job's
+// download directory, and the job's collision policy decides what happens when the name
+// is taken at the destination. The final move never replaces a file unless the policy is
+// `overwrite`, so two jobs delivering the same name at once cannot clobber each other.
 
-// Example function (to be replaced)
-pub fn transfer_file(file_path: &str, destination: &str) -> Result<(), String> {
-    // TODO: Implement file transfer logic (e.g., SCP, SMB, NFS)
-    // Use tracing::info! instead of println!
-    tracing::info!(file = %file_path, dest = %destination, "Transferring file (placeholder)");
-    Ok(())
+pub mod local;
+pub mod sftp;
+pub mod webdav;
+
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use tracing::info;
+
+use crate::api::handlers::send_progress_update;
+use crate::download::naming::{self, CollisionPolicy};
+use crate::download::sanitize::SanitizePolicy;
+use crate::error::{PegasusError, Result};
+use crate::jobs::cancel::CancelToken;
+
+/// Size of the chunks backends copy and hash files in.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Minimum time between two transfer progress updates for the same job.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Whether the local backend keeps the source file after transferring it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalMode {
+    /// Move the file, using an atomic rename when source and destination share a filesystem.
+    #[default]
+    Move,
+    /// Copy the file and keep the original.
+    Copy,
+}
+
+/// A configured transfer destination.
+///
+/// SMB and NFS shares can be used through the `local` backend by mounting them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DestinationConfig {
+    /// A directory on a local or mounted filesystem.
+    Local {
+        path: String,
+        #[serde(default)]
+        mode: LocalMode,
+    },
+    /// A directory on a remote host reachable over SFTP.
+    Sftp {
+        host: String,
+        #[serde(default = "default_sftp_port")]
+        port: u16,
+        username: String,
+        password: Option<String>,
+        private_key: Option<String>,
+        remote_dir: String,
+        /// The server's expected host key fingerprint, as printed by `ssh-keygen -l`
+        /// (`SHA256:...`). Takes precedence over `known_hosts`.
+        host_key_fingerprint: Option<String>,
+        /// OpenSSH known_hosts file the host key is looked up in. Defaults to
+        /// `~/.ssh/known_hosts`.
+        known_hosts: Option<String>,
+        /// Connect to a host that is in neither, trusting whatever key it presents. A key
+        /// that contradicts `known_hosts` is refused regardless.
+        #[serde(default)]
+        accept_unknown_host: bool,
+    },
+    /// A WebDAV collection, e.g. a Nextcloud folder.
+    Webdav {
+        url: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+}
+
+fn default_sftp_port() -> u16 {
+    22
+}
+
+/// Per-transfer context handed to a backend.
+#[derive(Clone)]
+pub struct TransferContext {
+    pub job_id: String,
+    pub url: String,
+    pub cancel: Arc<CancelToken>,
+    last_report: Arc<Mutex<Option<Instant>>>,
+}
+
+impl TransferContext {
+    pub fn new(job_id: &str, url: &str, cancel: Arc<CancelToken>) -> Self {
+        TransferContext {
+            job_id: job_id.to_string(),
+            url: url.to_string(),
+            cancel,
+            last_report: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Reports transfer progress through the progress channel, throttled per job.
+    pub fn report(&self, bytes_done: u64, total_bytes: u64) {
+        let finished = bytes_done >= total_bytes;
+        {
+            let mut last = self.last_report.lock().unwrap();
+            if !finished && last.is_some_and(|t| t.elapsed() < PROGRESS_INTERVAL) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+
+        let fraction = if total_bytes == 0 {
+            1.0
+        } else {
+            (bytes_done as f64 / total_bytes as f64) as f32
+        };
+        send_progress_update(
+            &self.job_id,
+            &self.url,
+            "transferring",
+            fraction,
+            &format!(
+                "Transferring: {:.1}% ({} of {} bytes)",
+                fraction * 100.0,
+                bytes_done,
+                total_bytes
+            ),
+        );
+    }
+
+    /// Returns `Err(PegasusError::Cancelled)` once the job has been cancelled.
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            Err(PegasusError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Where a file goes at a destination.
+#[derive(Clone, Debug)]
+pub struct TransferTarget {
+    /// The directories below the destination's root, e.g. `["Someone", "2024"]`.
+    pub dirs: Vec<String>,
+    /// The file's name.
+    pub file_name: String,
+    /// What to do if the name is taken at the destination.
+    pub collision: CollisionPolicy,
+    /// The video's ID, for `CollisionPolicy::Id`.
+    pub video_id: String,
+    /// Keeps suffixed names valid and within the length limit.
+    pub sanitize: SanitizePolicy,
+}
+
+impl TransferTarget {
+    /// Describes a file by its path relative to the directory it was downloaded into.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file to deliver.
+    /// * `base_dir` - The job's download directory; the directories between it and the
+    ///   file are recreated at the destination. Files outside it keep only their name.
+    pub fn new(
+        file: &Path,
+        base_dir: &Path,
+        collision: CollisionPolicy,
+        video_id: &str,
+        sanitize: &SanitizePolicy,
+    ) -> Result<Self> {
+        let file_name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| {
+                PegasusError::TransferError(format!("Not a file: {}", file.display()))
+            })?;
+        let dirs = file
+            .parent()
+            .and_then(|parent| parent.strip_prefix(base_dir).ok())
+            .map(|relative| {
+                relative
+                    .components()
+                    .filter_map(|component| match component {
+                        Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(TransferTarget {
+            dirs,
+            file_name,
+            collision,
+            video_id: video_id.to_string(),
+            sanitize: sanitize.clone(),
+        })
+    }
+
+    /// The file names to try, in the order the collision policy prefers them. Never
+    /// ends for `counter` and `id`.
+    pub fn names(&self) -> impl Iterator<Item = String> + Send + '_ {
+        naming::candidates(
+            Path::new(&self.file_name),
+            self.collision,
+            &self.video_id,
+            &self.sanitize,
+        )
+        .map(|name| name.to_string_lossy().to_string())
+    }
+
+    /// Whether the file may replace one already at the destination.
+    pub fn overwrite(&self) -> bool {
+        self.collision == CollisionPolicy::Overwrite
+    }
+
+    /// Whether an existing file at the destination is kept instead of delivering this one.
+    pub fn keeps_existing(&self) -> bool {
+        self.collision == CollisionPolicy::Skip
+    }
+
+    /// The name of the partial upload, unique to the job so concurrent deliveries of the
+    /// same name do not write into the same file.
+    pub fn part_name(&self, job_id: &str) -> String {
+        let short_id = job_id.get(..8).unwrap_or(job_id);
+        format!("{}.{}.part", self.file_name, short_id)
+    }
+
+    /// The path below the destination's root, `/`-separated.
+    pub fn relative_path(&self, file_name: &str) -> String {
+        self.dirs
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(file_name))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// A place finished files can be delivered to.
+#[async_trait]
+pub trait TransferBackend: Send + Sync {
+    /// Delivers `file` to the destination at `target`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the final location of the file (a path or URL), which is an
+    /// existing file if the collision policy keeps it, or a
+    /// `PegasusError::TransferError` on failure.
+    async fn transfer(
+        &self,
+        file: &Path,
+        target: &TransferTarget,
+        ctx: &TransferContext,
+    ) -> Result<String>;
+}
+
+/// The transfer backends for every configured destination, keyed by destination name.
+#[derive(Default)]
+pub struct TransferRegistry {
+    backends: HashMap<String, Arc<dyn TransferBackend>>,
+    default_destination: Option<String>,
+}
+
+impl TransferRegistry {
+    /// Builds one backend per configured destination.
+    pub fn from_config(
+        destinations: &HashMap<String, DestinationConfig>,
+        default_destination: Option<String>,
+    ) -> Result<Self> {
+        let mut backends: HashMap<String, Arc<dyn TransferBackend>> = HashMap::new();
+        for (name, destination) in destinations {
+            info!(destination = %name, "Configuring transfer destination");
+            let backend: Arc<dyn TransferBackend> = match destination {
+                DestinationConfig::Local { path, mode } => {
+                    Arc::new(local::LocalBackend::new(path, *mode))
+                }
+                DestinationConfig::Sftp {
+                    host,
+                    port,
+                    username,
+                    password,
+                    private_key,
+                    remote_dir,
+                    host_key_fingerprint,
+                    known_hosts,
+                    accept_unknown_host,
+                } => Arc::new(sftp::SftpBackend::new(
+                    host,
+                    *port,
+                    username,
+                    password.clone(),
+                    private_key.clone(),
+                    remote_dir,
+                    sftp::HostKeyCheck {
+                        fingerprint: host_key_fingerprint.clone(),
+                        known_hosts: known_hosts.clone(),
+                        accept_unknown: *accept_unknown_host,
+                    },
+                )),
+                DestinationConfig::Webdav {
+                    url,
+                    username,
+                    password,
+                } => Arc::new(webdav::WebDavBackend::new(
+                    url,
+                    username.clone(),
+                    password.clone(),
+                )?),
+            };
+            backends.insert(name.clone(), backend);
+        }
+
+        if let Some(default) = &default_destination
+            && !backends.contains_key(default)
+        {
+            return Err(PegasusError::ConfigError(format!(
+                "Default transfer destination {:?} is not configured",
+                default
+            )));
+        }
+
+        Ok(TransferRegistry {
+            backends,
+            default_destination,
+        })
+    }
+
+    /// Resolves the destination a job should be transferred to.
+    ///
+    /// # Returns
+    ///
+    /// The requested destination name, the configured default if none was requested, or
+    /// `None` if the job should not be transferred. Unknown names are a validation error.
+    pub fn resolve(&self, requested: Option<&str>) -> Result<Option<String>> {
+        match requested {
+            Some(name) if self.backends.contains_key(name) => Ok(Some(name.to_string())),
+            Some(name) => Err(PegasusError::ValidationError(format!(
+                "Unknown transfer destination: {}",
+                name
+            ))),
+            None => Ok(self.default_destination.clone()),
+        }
+    }
+
+    /// Returns the backend serving a destination.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn TransferBackend>> {
+        self.backends.get(name).cloned()
+    }
+}
+
+/// Delivers a finished file to the named destination.
+///
+/// # Arguments
+///
+/// * `registry` - The configured transfer backends.
+/// * `destination` - The destination name, as returned by `TransferRegistry::resolve`.
+/// * `file_path` - The file to deliver.
+/// * `target` - Where the file goes at the destination.
+/// * `ctx` - The job's transfer context.
+///
+/// # Returns
+///
+/// A `Result` containing the final location of the file.
+pub async fn transfer_file(
+    registry: &TransferRegistry,
+    destination: &str,
+    file_path: &Path,
+    target: &TransferTarget,
+    ctx: &TransferContext,
+) -> Result<String> {
+    let backend = registry.get(destination).ok_or_else(|| {
+        PegasusError::TransferError(format!("Unknown transfer destination: {}", destination))
+    })?;
+
+    info!(job_id = %ctx.job_id, file = %file_path.display(), destination = %destination, path = %target.relative_path(&target.file_name), collision = ?target.collision, "Transferring file");
+    send_progress_update(
+        &ctx.job_id,
+        &ctx.url,
+        "transferring",
+        0.0,
+        &format!("Transferring to {}...", destination),
+    );
+
+    let location = backend.transfer(file_path, target, ctx).await?;
+    info!(job_id = %ctx.job_id, location = %location, "Transfer complete");
+    Ok(location)
+}
+
+/// Computes the hex-encoded SHA-256 checksum of a local file.
+pub async fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Fails with a `TransferError` if two checksums differ.
+pub fn verify_checksum(expected: &str, actual: &str, location: &str) -> Result<()> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(PegasusError::TransferError(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            location, expected, actual
+        )))
+    }
 }
@@ -0,0 +1,377 @@
+// src/transfer/sftp.rs
+// Transfer backend for remote hosts reachable over SFTP.
+//
+// libssh2 is a blocking library, so every transfer runs on Tokio's blocking thread pool.
+//
+// The server's host key is checked right after the handshake, before any credentials are
+// sent: against a configured fingerprint, or else against an OpenSSH known_hosts file.
+// Unknown hosts are refused unless the destination explicitly accepts them.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use sha2::{Digest, Sha256};
+use ssh2::{
+    CheckResult, HashType, KnownHostFileKind, OpenFlags, OpenType, RenameFlags, Session, Sftp,
+};
+use tracing::{debug, info, warn};
+
+use super::{CHUNK_SIZE, TransferBackend, TransferContext, TransferTarget, verify_checksum};
+use crate::error::{PegasusError, Result};
+
+/// Uploads files into a directory on a remote host over SFTP.
+#[derive(Clone)]
+pub struct SftpBackend {
+    host: String,
+    port: u16,
+    username: String,
+    password: Option<String>,
+    private_key: Option<PathBuf>,
+    remote_dir: String,
+    host_key: HostKeyCheck,
+}
+
+/// How the server's host key is verified.
+#[derive(Clone, Debug, Default)]
+pub struct HostKeyCheck {
+    /// Expected SHA-256 fingerprint, in OpenSSH's `SHA256:<base64>` form.
+    pub fingerprint: Option<String>,
+    /// known_hosts file to look the host up in; `~/.ssh/known_hosts` if `None`.
+    pub known_hosts: Option<String>,
+    /// Trust a host found in neither.
+    pub accept_unknown: bool,
+}
+
+impl SftpBackend {
+    pub fn new(
+        host: &str,
+        port: u16,
+        username: &str,
+        password: Option<String>,
+        private_key: Option<String>,
+        remote_dir: &str,
+        host_key: HostKeyCheck,
+    ) -> Self {
+        SftpBackend {
+            host: host.to_string(),
+            port,
+            username: username.to_string(),
+            password,
+            private_key: private_key.map(PathBuf::from),
+            remote_dir: remote_dir.trim_end_matches('/').to_string(),
+            host_key,
+        }
+    }
+
+    /// Opens an authenticated SFTP session.
+    fn connect(&self) -> Result<Sftp> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|e| sftp_error(&format!("connect to {}:{}", self.host, self.port), e))?;
+        let mut session = Session::new().map_err(|e| sftp_error("create session", e))?;
+        session.set_tcp_stream(tcp);
+        session
+            .handshake()
+            .map_err(|e| sftp_error("SSH handshake", e))?;
+        // Nothing secret is sent before the server has proven who it is
+        self.verify_host_key(&session)?;
+
+        if let Some(key) = &self.private_key {
+            session
+                .userauth_pubkey_file(&self.username, None, key, self.password.as_deref())
+                .map_err(|e| sftp_error("public key authentication", e))?;
+        } else if let Some(password) = &self.password {
+            session
+                .userauth_password(&self.username, password)
+                .map_err(|e| sftp_error("password authentication", e))?;
+        } else {
+            session
+                .userauth_agent(&self.username)
+                .map_err(|e| sftp_error("SSH agent authentication", e))?;
+        }
+
+        session
+            .sftp()
+            .map_err(|e| sftp_error("start SFTP subsystem", e))
+    }
+
+    /// Checks the key the server presented during the handshake.
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if the key matches the configured fingerprint or the known_hosts entry, or
+    /// the host is unknown and unknown hosts are accepted; a `TransferError` otherwise.
+    fn verify_host_key(&self, session: &Session) -> Result<()> {
+        let refuse = |reason: String| {
+            warn!(host = %self.host, port = self.port, reason = %reason, "Refusing SFTP host key");
+            PegasusError::TransferError(format!(
+                "SFTP host key check failed for {}:{}: {}",
+                self.host, self.port, reason
+            ))
+        };
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| refuse("the server presented no host key".to_string()))?;
+        let fingerprint = session
+            .host_key_hash(HashType::Sha256)
+            .map(|hash| format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)))
+            .ok_or_else(|| refuse("the host key could not be hashed".to_string()))?;
+
+        if let Some(expected) = &self.host_key.fingerprint {
+            // `ssh-keygen` prints the fingerprint without padding, but accept it with
+            let expected = expected.trim().trim_end_matches('=');
+            let expected = if expected.starts_with("SHA256:") {
+                expected.to_string()
+            } else {
+                format!("SHA256:{}", expected)
+            };
+            if expected != fingerprint {
+                return Err(refuse(format!(
+                    "expected fingerprint {}, got {}",
+                    expected, fingerprint
+                )));
+            }
+            debug!(host = %self.host, fingerprint = %fingerprint, "Host key matches the configured fingerprint");
+            return Ok(());
+        }
+
+        let known_hosts = match &self.host_key.known_hosts {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(std::env::var("HOME").unwrap_or_default())
+                .join(".ssh")
+                .join("known_hosts"),
+        };
+        let mut hosts = session
+            .known_hosts()
+            .map_err(|e| sftp_error("read known hosts", e))?;
+        if known_hosts.exists() {
+            hosts
+                .read_file(&known_hosts, KnownHostFileKind::OpenSSH)
+                .map_err(|e| sftp_error(&format!("read {}", known_hosts.display()), e))?;
+        }
+
+        match hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => {
+                debug!(host = %self.host, known_hosts = %known_hosts.display(), "Host key matches known_hosts");
+                Ok(())
+            }
+            CheckResult::Mismatch => Err(refuse(format!(
+                "the key ({}) does not match the one in {}",
+                fingerprint,
+                known_hosts.display()
+            ))),
+            CheckResult::NotFound if self.host_key.accept_unknown => {
+                warn!(host = %self.host, port = self.port, fingerprint = %fingerprint, "Accepting unknown SFTP host key");
+                Ok(())
+            }
+            CheckResult::NotFound => Err(refuse(format!(
+                "the host is not in {} (its key is {}); add it there, set host_key_fingerprint or set accept_unknown_host",
+                known_hosts.display(),
+                fingerprint
+            ))),
+            CheckResult::Failure => Err(refuse(format!(
+                "the key could not be checked against {}",
+                known_hosts.display()
+            ))),
+        }
+    }
+
+    /// Creates `dir` and any missing parents.
+    fn ensure_remote_dir(&self, sftp: &Sftp, dir: &Path) -> Result<()> {
+        let mut current = PathBuf::new();
+        for component in dir.components() {
+            current.push(component);
+            if sftp.stat(&current).is_err() {
+                debug!(dir = %current.display(), "Creating remote directory");
+                sftp.mkdir(&current, 0o755)
+                    .map_err(|e| sftp_error(&format!("create {}", current.display()), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The remote path of `file_name` in the target's directory.
+    fn remote_path(&self, target: &TransferTarget, file_name: &str) -> PathBuf {
+        PathBuf::from(format!(
+            "{}/{}",
+            self.remote_dir.trim_end_matches('/'),
+            target.relative_path(file_name)
+        ))
+    }
+
+    /// Renames the finished upload to the first name the collision policy allows.
+    ///
+    /// Without the overwrite policy the rename is made without `OVERWRITE`, so the
+    /// server refuses to replace a file that appeared since the upload started.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(Some(path))` with the final path, or `Ok(None)` if the name was taken and the
+    /// policy keeps the existing file.
+    fn place(
+        &self,
+        sftp: &Sftp,
+        part_path: &Path,
+        target: &TransferTarget,
+    ) -> Result<Option<PathBuf>> {
+        let flags = if target.overwrite() {
+            RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE
+        } else {
+            RenameFlags::ATOMIC | RenameFlags::NATIVE
+        };
+        for name in target.names() {
+            let final_path = self.remote_path(target, &name);
+            match sftp.rename(part_path, &final_path, Some(flags)) {
+                Ok(()) => return Ok(Some(final_path)),
+                // Servers report a taken name with a generic failure, so look for it
+                Err(e) if !target.overwrite() && sftp.stat(&final_path).is_ok() => {
+                    debug!(destination = %final_path.display(), error = %e, "Remote name is taken");
+                }
+                Err(e) => return Err(sftp_error("rename upload into place", e)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The location reported for a remote path.
+    fn location(&self, path: &Path) -> String {
+        format!(
+            "sftp://{}@{}:{}{}",
+            self.username,
+            self.host,
+            self.port,
+            path.display()
+        )
+    }
+
+    /// Performs the whole upload synchronously.
+    fn upload_blocking(
+        &self,
+        file: &Path,
+        target: &TransferTarget,
+        ctx: &TransferContext,
+    ) -> Result<String> {
+        let sftp = self.connect()?;
+        let remote_dir = target
+            .dirs
+            .iter()
+            .fold(PathBuf::from(&self.remote_dir), |dir, part| dir.join(part));
+        self.ensure_remote_dir(&sftp, &remote_dir)?;
+
+        // Under the skip policy an existing file wins and nothing is uploaded
+        let existing = self.remote_path(target, &target.file_name);
+        if target.keeps_existing() && sftp.stat(&existing).is_ok() {
+            info!(job_id = %ctx.job_id, destination = %existing.display(), "File already exists at destination, skipping");
+            return Ok(self.location(&existing));
+        }
+
+        let part_path = self.remote_path(target, &target.part_name(&ctx.job_id));
+
+        let mut local = std::fs::File::open(file)?;
+        let total = local.metadata()?.len();
+
+        // Resume from the end of an existing partial upload that is not too large
+        let offset = match sftp.stat(&part_path) {
+            Ok(stat) if stat.size.unwrap_or(0) <= total => stat.size.unwrap_or(0),
+            Ok(_) => {
+                sftp.unlink(&part_path)
+                    .map_err(|e| sftp_error("remove oversized partial upload", e))?;
+                0
+            }
+            Err(_) => 0,
+        };
+        if offset > 0 {
+            info!(job_id = %ctx.job_id, offset, "Resuming partial SFTP upload");
+        }
+
+        let mut remote = sftp
+            .open_mode(
+                &part_path,
+                OpenFlags::WRITE | OpenFlags::CREATE,
+                0o644,
+                OpenType::File,
+            )
+            .map_err(|e| sftp_error("open remote file", e))?;
+        remote.seek(SeekFrom::Start(offset))?;
+        local.seek(SeekFrom::Start(offset))?;
+
+        let mut sent = offset;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            ctx.check_cancelled()?;
+            let read = local.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            remote.write_all(&buffer[..read])?;
+            sent += read as u64;
+            ctx.report(sent, total);
+        }
+        remote.fsync().ok();
+        drop(remote);
+
+        // Verify by reading the upload back and hashing both sides
+        let expected = hash_reader(std::fs::File::open(file)?)?;
+        let remote_file = sftp
+            .open(&part_path)
+            .map_err(|e| sftp_error("reopen remote file", e))?;
+        let actual = hash_reader(remote_file)?;
+        if let Err(e) = verify_checksum(&expected, &actual, &part_path.display().to_string()) {
+            warn!(job_id = %ctx.job_id, error = %e, "Removing corrupt partial upload");
+            let _ = sftp.unlink(&part_path);
+            return Err(e);
+        }
+
+        let final_path = match self.place(&sftp, &part_path, target)? {
+            Some(final_path) => final_path,
+            None => {
+                // Another job delivered the same name while this upload was running
+                info!(job_id = %ctx.job_id, destination = %existing.display(), "File appeared at destination during upload, skipping");
+                let _ = sftp.unlink(&part_path);
+                existing
+            }
+        };
+
+        Ok(self.location(&final_path))
+    }
+}
+
+#[async_trait]
+impl TransferBackend for SftpBackend {
+    async fn transfer(
+        &self,
+        file: &Path,
+        target: &TransferTarget,
+        ctx: &TransferContext,
+    ) -> Result<String> {
+        let backend = self.clone();
+        let file = file.to_path_buf();
+        let target = target.clone();
+        let ctx = ctx.clone();
+
+        tokio::task::spawn_blocking(move || backend.upload_blocking(&file, &target, &ctx))
+            .await
+            .map_err(|e| PegasusError::TransferError(format!("SFTP upload task failed: {}", e)))?
+    }
+}
+
+/// Hashes everything a reader yields with SHA-256.
+fn hash_reader<R: Read>(mut reader: R) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn sftp_error(action: &str, e: impl std::fmt::Display) -> PegasusError {
+    PegasusError::TransferError(format!("SFTP: failed to {}: {}", action, e))
+}
@@ -0,0 +1,315 @@
+// src/transfer/webdav.rs
+// Transfer backend for WebDAV servers (Nextcloud, Apache mod_dav, rclone serve, ...).
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::{Client, Method, StatusCode, header};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{debug, info, warn};
+
+use super::{
+    CHUNK_SIZE, TransferBackend, TransferContext, TransferTarget, sha256_file, verify_checksum,
+};
+use crate::error::{PegasusError, Result};
+
+/// Uploads files into a WebDAV collection.
+pub struct WebDavBackend {
+    client: Client,
+    base_url: reqwest::Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl WebDavBackend {
+    pub fn new(url: &str, username: Option<String>, password: Option<String>) -> Result<Self> {
+        // Collection URLs end in a slash; file names are appended as path segments
+        let normalized = format!("{}/", url.trim_end_matches('/'));
+        let base_url = reqwest::Url::parse(&normalized).map_err(|e| {
+            PegasusError::ConfigError(format!("Invalid WebDAV URL {:?}: {}", url, e))
+        })?;
+
+        Ok(WebDavBackend {
+            client: Client::new(),
+            base_url,
+            username,
+            password,
+        })
+    }
+
+    /// Builds a request with the configured credentials.
+    fn request(&self, method: Method, url: reqwest::Url) -> reqwest::RequestBuilder {
+        let builder = self.client.request(method, url);
+        match &self.username {
+            Some(username) => builder.basic_auth(username, self.password.as_deref()),
+            None => builder,
+        }
+    }
+
+    /// Builds the URL of a resource below the base collection.
+    ///
+    /// Each directory and the name are added as single path segments and
+    /// percent-encoded, so `#`, `?`, `%` and `:` in a title are part of the file name
+    /// rather than URL syntax. An empty name gives the URL of the collection itself.
+    fn resource_url(&self, dirs: &[String], name: &str) -> Result<reqwest::Url> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| {
+                PegasusError::TransferError(format!("WebDAV: {} cannot hold files", self.base_url))
+            })?
+            .pop_if_empty()
+            .extend(dirs)
+            .push(name);
+        Ok(url)
+    }
+
+    /// Makes sure the base collection and the collections in `dirs` exist, creating
+    /// them from the top down like `mkdir -p`.
+    async fn ensure_collections(&self, dirs: &[String]) -> Result<()> {
+        let mkcol = Method::from_bytes(b"MKCOL").expect("MKCOL is a valid method");
+        for depth in 0..=dirs.len() {
+            let url = self.resource_url(&dirs[..depth], "")?;
+            let response = self
+                .request(mkcol.clone(), url.clone())
+                .send()
+                .await
+                .map_err(|e| webdav_error("create collection", e))?;
+            match response.status() {
+                // 405 means the collection already exists
+                s if s.is_success() || s == StatusCode::METHOD_NOT_ALLOWED => {}
+                s => {
+                    return Err(PegasusError::TransferError(format!(
+                        "WebDAV: failed to create collection {}: {}",
+                        url, s
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether a remote resource exists.
+    async fn exists(&self, url: &reqwest::Url) -> Result<bool> {
+        let response = self
+            .request(Method::HEAD, url.clone())
+            .send()
+            .await
+            .map_err(|e| webdav_error("check destination", e))?;
+        Ok(response.status().is_success())
+    }
+
+    /// Moves the finished upload to the first name the collision policy allows.
+    ///
+    /// Without the overwrite policy the MOVE is sent with `Overwrite: F`, so the server
+    /// refuses to replace a file that appeared since the upload started.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(Some(url))` with the final URL, or `Ok(None)` if the name was taken and the
+    /// policy keeps the existing file.
+    async fn place(
+        &self,
+        part_url: &reqwest::Url,
+        target: &TransferTarget,
+    ) -> Result<Option<reqwest::Url>> {
+        let move_method = Method::from_bytes(b"MOVE").expect("MOVE is a valid method");
+        let overwrite = if target.overwrite() { "T" } else { "F" };
+        for name in target.names() {
+            let final_url = self.resource_url(&target.dirs, &name)?;
+            let response = self
+                .request(move_method.clone(), part_url.clone())
+                .header("Destination", final_url.as_str())
+                .header("Overwrite", overwrite)
+                .send()
+                .await
+                .map_err(|e| webdav_error("move upload into place", e))?;
+            match response.status() {
+                s if s.is_success() => return Ok(Some(final_url)),
+                // 412 means the destination exists and Overwrite was F
+                StatusCode::PRECONDITION_FAILED => {
+                    debug!(url = %final_url, "Remote name is taken");
+                }
+                s => {
+                    return Err(PegasusError::TransferError(format!(
+                        "WebDAV: failed to move {} into place: {}",
+                        part_url, s
+                    )));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the size of a remote resource, or `None` if it does not exist.
+    async fn remote_size(&self, url: &reqwest::Url) -> Result<Option<u64>> {
+        let response = self
+            .request(Method::HEAD, url.clone())
+            .send()
+            .await
+            .map_err(|e| webdav_error("query partial upload", e))?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        Ok(response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()))
+    }
+
+    /// Uploads `file` starting at `offset`, using a `Content-Range` PUT when resuming.
+    async fn put_from(
+        &self,
+        file: &Path,
+        url: &reqwest::Url,
+        offset: u64,
+        total: u64,
+        ctx: &TransferContext,
+    ) -> Result<StatusCode> {
+        let mut reader = tokio::fs::File::open(file).await?;
+        reader.seek(std::io::SeekFrom::Start(offset)).await?;
+
+        // Stream the file in chunks, reporting progress and stopping on cancellation
+        let progress_ctx = ctx.clone();
+        let body_stream =
+            futures::stream::unfold((reader, offset, false), move |(mut reader, sent, done)| {
+                let ctx = progress_ctx.clone();
+                async move {
+                    if done {
+                        return None;
+                    }
+                    if ctx.cancel.is_cancelled() {
+                        let err = std::io::Error::other("transfer cancelled");
+                        return Some((Err(err), (reader, sent, true)));
+                    }
+                    let mut buffer = vec![0u8; CHUNK_SIZE];
+                    match reader.read(&mut buffer).await {
+                        Ok(0) => None,
+                        Ok(read) => {
+                            buffer.truncate(read);
+                            let sent = sent + read as u64;
+                            ctx.report(sent, total);
+                            Some((Ok(buffer), (reader, sent, false)))
+                        }
+                        Err(e) => Some((Err(e), (reader, sent, true))),
+                    }
+                }
+            });
+
+        let mut request = self
+            .request(Method::PUT, url.clone())
+            .header(header::CONTENT_LENGTH, total - offset)
+            .body(reqwest::Body::wrap_stream(body_stream));
+        if offset > 0 {
+            request = request.header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", offset, total.saturating_sub(1), total),
+            );
+        }
+
+        let response = request.send().await.map_err(|e| {
+            if ctx.cancel.is_cancelled() {
+                PegasusError::Cancelled
+            } else {
+                webdav_error("upload", e)
+            }
+        })?;
+        Ok(response.status())
+    }
+
+    /// Downloads a remote resource and returns its SHA-256 checksum.
+    async fn remote_checksum(&self, url: &reqwest::Url) -> Result<String> {
+        let response = self
+            .request(Method::GET, url.clone())
+            .send()
+            .await
+            .map_err(|e| webdav_error("read back upload", e))?;
+        if !response.status().is_success() {
+            return Err(PegasusError::TransferError(format!(
+                "WebDAV: failed to read back {}: {}",
+                url,
+                response.status()
+            )));
+        }
+
+        let mut hasher = Sha256::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| webdav_error("read back upload", e))?;
+            hasher.update(&chunk);
+        }
+        Ok(hex::encode(hasher.finalize()))
+    }
+}
+
+#[async_trait]
+impl TransferBackend for WebDavBackend {
+    async fn transfer(
+        &self,
+        file: &Path,
+        target: &TransferTarget,
+        ctx: &TransferContext,
+    ) -> Result<String> {
+        self.ensure_collections(&target.dirs).await?;
+
+        // Under the skip policy an existing file wins and nothing is uploaded
+        let existing_url = self.resource_url(&target.dirs, &target.file_name)?;
+        if target.keeps_existing() && self.exists(&existing_url).await? {
+            info!(job_id = %ctx.job_id, url = %existing_url, "File already exists at destination, skipping");
+            return Ok(existing_url.to_string());
+        }
+
+        let part_url = self.resource_url(&target.dirs, &target.part_name(&ctx.job_id))?;
+
+        let total = tokio::fs::metadata(file).await?.len();
+        let expected = sha256_file(file).await?;
+
+        // Resume a previous partial upload when the server keeps one around
+        let offset = match self.remote_size(&part_url).await? {
+            Some(size) if size > 0 && size < total => size,
+            _ => 0,
+        };
+        if offset > 0 {
+            info!(job_id = %ctx.job_id, offset, "Resuming partial WebDAV upload");
+        }
+
+        let mut status = self.put_from(file, &part_url, offset, total, ctx).await?;
+        if offset > 0 && !status.is_success() {
+            // Not every server accepts ranged PUTs; fall back to a full upload
+            warn!(job_id = %ctx.job_id, status = %status, "Ranged upload rejected, restarting from the beginning");
+            status = self.put_from(file, &part_url, 0, total, ctx).await?;
+        }
+        if !status.is_success() {
+            return Err(PegasusError::TransferError(format!(
+                "WebDAV: upload of {} failed: {}",
+                part_url, status
+            )));
+        }
+
+        let actual = self.remote_checksum(&part_url).await?;
+        if let Err(e) = verify_checksum(&expected, &actual, part_url.as_str()) {
+            let _ = self.request(Method::DELETE, part_url.clone()).send().await;
+            return Err(e);
+        }
+
+        // MOVE is atomic on the server, so the library only ever sees the complete file
+        let final_url = match self.place(&part_url, target).await? {
+            Some(final_url) => final_url,
+            None => {
+                // Another job delivered the same name while this upload was running
+                info!(job_id = %ctx.job_id, url = %existing_url, "File appeared at destination during upload, skipping");
+                let _ = self.request(Method::DELETE, part_url.clone()).send().await;
+                existing_url
+            }
+        };
+
+        debug!(job_id = %ctx.job_id, url = %final_url, "WebDAV upload verified");
+        Ok(final_url.to_string())
+    }
+}
+
+fn webdav_error(action: &str, e: impl std::fmt::Display) -> PegasusError {
+    PegasusError::TransferError(format!("WebDAV: failed to {}: {}", action, e))
+}
@@ -23,15 +23,31 @@ document.addEventListener("DOMContentLoaded", () => {
     videoOptionsSection: videoOptionsSection,
     audioOptionsSection: audioOptionsSection,
     mediaUrlsInput: document.getElementById("mediaUrls"),
+    outputRootSelect: document.getElementById("outputRoot"),
     outputDirInput: document.getElementById("outputDir"),
-    progressInfoDiv: document.getElementById('progress-info')
+    linksFileInput: document.getElementById("linksFile"),
+    progressInfoDiv: document.getElementById('progress-info'),
+    playlistGroupsDiv: document.getElementById('playlist-groups'),
+    previewBtn: document.getElementById('previewBtn'),
+    previewDiv: document.getElementById('media-preview'),
+    previewFormatSelect: document.getElementById('preview-format'),
+    loginForm: document.getElementById('loginForm'),
+    loginError: document.getElementById('loginError'),
+    sessionBar: document.getElementById('sessionBar'),
+    sessionUser: document.getElementById('sessionUser'),
+    logoutBtn: document.getElementById('logoutBtn'),
+    systemFooter: document.getElementById('systemFooter')
   };
 
   // Application state
   const state = {
     activeDownloads: new Map(),
     socket: null,
-    isProcessing: false
+    // URL the format picker applies to, set when a preview is loaded
+    previewUrl: null,
+    isProcessing: false,
+    // Set on logout so the WebSocket does not reconnect
+    loggedOut: false
   };
 
   /**
@@ -51,6 +67,13 @@ document.addEventListener("DOMContentLoaded", () => {
       // Connection opened
       state.socket.addEventListener('open', (event) => {
         console.log('Connected to Pegasus WebSocket server');
+        // Resubscribe after a reconnect; the snapshot fills in anything missed meanwhile
+        const tracker = window.pegasusJobTracker;
+        if (tracker) {
+          const ids = Object.keys(tracker.jobIdToUrl);
+          if (tracker.batchId) ids.push(tracker.batchId);
+          this.subscribe(ids);
+        }
       });
 
       // Listen for messages
@@ -64,7 +87,11 @@ document.addEventListener("DOMContentLoaded", () => {
 
           // Parse the progress update
           const update = JSON.parse(event.data);
-          this.handleUpdate(update);
+          if (update.type === 'snapshot') {
+            this.handleSnapshot(update);
+          } else if (!update.type) {
+            this.handleUpdate(update);
+          }
         } catch (error) {
           console.error('Error handling WebSocket message:', error);
         }
@@ -73,8 +100,9 @@ document.addEventListener("DOMContentLoaded", () => {
       // Connection closed
       state.socket.addEventListener('close', (event) => {
         console.log('Disconnected from Pegasus WebSocket server');
-        // Try to reconnect after a delay
-        setTimeout(() => this.connect(), 3000);
+        // Try to reconnect after a delay, unless the user logged out
+        if (state.loggedOut) return;
+        setTimeout(() => authManager.start(), 3000);
       });
 
       // Connection error
@@ -84,12 +112,73 @@ document.addEventListener("DOMContentLoaded", () => {
     },
 
     // Handle progress updates from WebSocket
+    // Ask the server for updates on these job or batch IDs
+    subscribe(ids) {
+      if (ids.length === 0 || !state.socket || state.socket.readyState !== WebSocket.OPEN) return;
+      state.socket.send(JSON.stringify({ type: 'subscribe', jobs: ids }));
+    },
+
+    // Replay the current state of subscribed jobs as if their updates had just arrived
+    handleSnapshot(snapshot) {
+      const finalStatus = { completed: 'completed', failed: 'error', cancelled: 'cancelled' };
+      snapshot.batches.forEach(batch => this.handleUpdate({
+        job_id: batch.id,
+        status: 'batch',
+        progress: batch.progress,
+        message: `${batch.name}: ${batch.completed + batch.failed + batch.cancelled} of ${batch.total} finished`,
+      }));
+      const urls = Object.fromEntries(snapshot.jobs.map(job => [job.id, job.url]));
+      snapshot.jobs.forEach(job => {
+        if (job.status === 'expanded' && !playlistView.hasGroup(job.id)) {
+          this.handleUpdate({
+            job_id: job.id,
+            url: job.url,
+            status: 'expanded',
+            message: `Playlist expanded into ${job.child_ids.length} items`,
+            children: job.child_ids.map(id => ({ job_id: id, url: urls[id] || '', title: null })),
+          });
+        }
+        if (finalStatus[job.status]) {
+          this.handleUpdate({
+            job_id: job.id,
+            url: job.url,
+            status: finalStatus[job.status],
+            progress: job.status === 'completed' ? 1 : 0,
+            message: job.error || `Job ${job.status}`,
+          });
+        } else if (job.last_update) {
+          this.handleUpdate(job.last_update);
+        }
+      });
+    },
+
     handleUpdate(update) {
       // Only handle updates for tracked jobs
       if (!window.pegasusJobTracker || !update.job_id) return;
       const tracker = window.pegasusJobTracker;
 
-      if (update.status === 'completed') {
+      // Children of a playlist job are shown in their parent's group only
+      if (tracker.childToParent[update.job_id]) {
+        playlistView.updateChild(update);
+        return;
+      }
+
+      if (update.status === 'expanded') {
+        if (tracker.jobIdToUrl[update.job_id]) {
+          playlistView.addGroup(update);
+          (update.children || []).forEach(child => {
+            tracker.childToParent[child.job_id] = update.job_id;
+          });
+        }
+      } else if (update.status === 'batch') {
+        // Aggregate progress of a batch; its job_id is the batch ID
+        if (update.job_id === tracker.batchId) {
+          uiManager.updateProgressInfo(update.message);
+        }
+      } else if (update.status === 'playlist') {
+        // Aggregate progress of a playlist job
+        playlistView.updateGroup(update);
+      } else if (update.status === 'completed') {
         if (tracker.jobIdToUrl[update.job_id]) {
           tracker.completedJobs++;
           tracker.updateProgress();
@@ -98,9 +187,34 @@ document.addEventListener("DOMContentLoaded", () => {
           delete tracker.jobIdToUrl[update.job_id];
         }
       } else if (update.status === 'error') {
-        tracker.failedJobs++;
-        tracker.updateProgress();
-        uiManager.appendStatus(`Error: ${update.message || update.job_id}`, 'error');
+        if (tracker.jobIdToUrl[update.job_id]) {
+          tracker.failedJobs++;
+          tracker.updateProgress();
+          uiManager.appendStatus(`Error: ${update.message || update.job_id}`, 'error');
+          delete tracker.jobIdToUrl[update.job_id];
+        }
+      } else if (update.status === 'cancelled') {
+        if (tracker.jobIdToUrl[update.job_id]) {
+          tracker.failedJobs++;
+          tracker.updateProgress();
+          uiManager.appendStatus(`Cancelled: ${tracker.jobIdToUrl[update.job_id]}`, 'error');
+          delete tracker.jobIdToUrl[update.job_id];
+        }
+      } else if (update.status === 'queued') {
+        // Show how far back in the download queue the job is
+        if (tracker.jobIdToUrl[update.job_id]) {
+          uiManager.updateProgressInfo(`${tracker.jobIdToUrl[update.job_id]}: ${update.message}`);
+        }
+      } else if (update.status === 'retrying') {
+        // A transient failure (e.g. rate limiting) is being retried after a delay
+        if (tracker.jobIdToUrl[update.job_id]) {
+          uiManager.updateProgressInfo(`${tracker.jobIdToUrl[update.job_id]}: ${update.message}`);
+        }
+      } else if (update.status === 'transferring') {
+        // Show delivery progress to the configured destination
+        if (tracker.jobIdToUrl[update.job_id]) {
+          uiManager.updateProgressInfo(`${tracker.jobIdToUrl[update.job_id]}: ${update.message}`);
+        }
       } else if (update.status === 'downloading' && update.progress) {
         // Optionally: update the progress bar for the current job only if desired
         // For global progress, we only update on completion
@@ -111,6 +225,69 @@ document.addEventListener("DOMContentLoaded", () => {
     }
   };
 
+  /**
+   * Playlist View - groups the child jobs of playlist jobs under their parent
+   */
+  const playlistView = {
+    // Whether a playlist job already has a group
+    hasGroup(jobId) {
+      return elements.playlistGroupsDiv.querySelector(`[data-job-id="${jobId}"]`) !== null;
+    },
+
+    // Create a group for a freshly expanded playlist job
+    addGroup(update) {
+      const group = document.createElement('div');
+      group.className = 'download-progress info';
+      group.dataset.jobId = update.job_id;
+
+      const header = document.createElement('div');
+      header.className = 'download-header';
+      const title = document.createElement('h3');
+      title.textContent = update.message;
+      header.appendChild(title);
+
+      const progress = document.createElement('div');
+      progress.className = 'progress-container';
+      const bar = document.createElement('div');
+      bar.className = 'progress-bar';
+      progress.appendChild(bar);
+
+      const summary = document.createElement('div');
+      summary.className = 'progress-text';
+
+      const list = document.createElement('ul');
+      list.className = 'playlist-children';
+      (update.children || []).forEach(child => {
+        const item = document.createElement('li');
+        item.dataset.jobId = child.job_id;
+        item.dataset.title = child.title || utils.getDisplayNameFromUrl(child.url);
+        item.textContent = `${item.dataset.title}: queued`;
+        list.appendChild(item);
+      });
+
+      group.append(header, progress, summary, list);
+      elements.playlistGroupsDiv.appendChild(group);
+    },
+
+    // Show the aggregate progress of a playlist job
+    updateGroup(update) {
+      const group = elements.playlistGroupsDiv.querySelector(`[data-job-id="${update.job_id}"]`);
+      if (!group) return;
+      group.querySelector('.progress-bar').style.width = `${Math.round(update.progress * 100)}%`;
+      group.querySelector('.progress-text').textContent = update.message;
+    },
+
+    // Show the latest status of a single child job
+    updateChild(update) {
+      const item = elements.playlistGroupsDiv.querySelector(`li[data-job-id="${update.job_id}"]`);
+      if (!item) return;
+      item.textContent = `${item.dataset.title}: ${update.message}`;
+      item.className = update.status === 'completed' ? 'completed'
+        : (update.status === 'error' || update.status === 'cancelled') ? 'error'
+        : 'active';
+    }
+  };
+
   /**
    * Utility functions
    */
@@ -156,28 +333,44 @@ document.addEventListener("DOMContentLoaded", () => {
     // Collect form data
     collectFormData() {
       const mediaUrlsRaw = elements.mediaUrlsInput.value;
-      const outputDir = elements.outputDirInput.value || "/tmp/pegasus_downloads";
+      // Where downloads go: a named output root and an optional subfolder below it
+      const output = {
+        root: elements.outputRootSelect.value || undefined,
+        outputDir: elements.outputDirInput.value.trim() || undefined,
+      };
       const urls = this.parseUrls(mediaUrlsRaw);
 
-      // Get selected options (these apply to all URLs)
-      const selectedOptions = [];
-      document
-        .querySelectorAll('input[name="options"]:checked')
-        .forEach((option) => {
-          selectedOptions.push(option.value);
-        });
+      // Build the typed processing options (these apply to all URLs)
+      const audioOnly = elements.audioOnlyCheckbox.checked;
+      const prefix = audioOnly ? 'audio' : 'video';
+      const selectedOptions = {
+        audioOnly: audioOnly,
+        embedThumbnail: document.getElementById(`${prefix}-add-thumbnail`).checked,
+        embedMetadata: document.getElementById(`${prefix}-embed-metadata`).checked,
+      };
 
-      if (elements.audioOnlyCheckbox.checked) {
-        const audioFormat = document.getElementById('audio-format').value;
-        const audioQuality = document.getElementById('audio-quality').value;
-        selectedOptions.push(audioFormat);
-        selectedOptions.push(audioQuality);
+      if (audioOnly) {
+        selectedOptions.audioFormat = document.getElementById('audio-format').value;
+        selectedOptions.audioQuality = document.getElementById('audio-quality').value;
       } else {
-        const videoQuality = document.getElementById('video-quality').value;
-        selectedOptions.push(videoQuality);
+        selectedOptions.videoQuality = document.getElementById('video-quality').value;
+        selectedOptions.embedSubtitles = document.getElementById('video-embed-subs').checked;
       }
 
-      return { urls, outputDir, selectedOptions };
+      // Playlist options only matter for playlist and channel URLs
+      const playlistOptions = {
+        reverse: document.getElementById('playlist-reverse').checked,
+      };
+      const items = document.getElementById('playlist-items').value.trim();
+      if (items) {
+        playlistOptions.items = items;
+      }
+      const newest = parseInt(document.getElementById('playlist-newest').value, 10);
+      if (newest > 0) {
+        playlistOptions.newest = newest;
+      }
+
+      return { urls, output, selectedOptions, playlistOptions };
     }
   }
 
@@ -307,17 +500,122 @@ document.addEventListener("DOMContentLoaded", () => {
     }
   };
 
+  /**
+   * Auth Manager - shows the login form when the server requires one
+   */
+  const authManager = {
+    // Checks the session and either shows the app or the login form
+    async start() {
+      let response;
+      try {
+        response = await fetch('/api/auth/me');
+      } catch (error) {
+        // Server unreachable; try again shortly
+        setTimeout(() => this.start(), 3000);
+        return;
+      }
+      if (response.status === 401) {
+        this.showLogin();
+        return;
+      }
+      const me = await response.json();
+      this.showApp(me);
+    },
+
+    showLogin() {
+      elements.form.hidden = true;
+      elements.sessionBar.hidden = true;
+      elements.systemFooter.hidden = true;
+      elements.loginForm.hidden = false;
+    },
+
+    showApp(me) {
+      elements.loginForm.hidden = true;
+      elements.form.hidden = false;
+      if (me.auth_enabled) {
+        elements.sessionUser.textContent = `Signed in as ${me.username}`;
+        elements.sessionBar.hidden = false;
+      }
+      state.loggedOut = false;
+      if (!state.socket || state.socket.readyState === WebSocket.CLOSED) {
+        webSocketManager.connect();
+      }
+      this.showSystemInfo();
+      this.loadRoots();
+    },
+
+    // Lists the output roots the server allows downloading into
+    async loadRoots() {
+      const response = await fetch('/api/roots').catch(() => null);
+      if (!response || !response.ok) return;
+      const roots = await response.json();
+      const selected = elements.outputRootSelect.value;
+      elements.outputRootSelect.replaceChildren(...roots.map(root => {
+        const option = document.createElement('option');
+        option.value = root.name;
+        option.textContent = `${root.name} (${root.path})`;
+        option.selected = selected ? root.name === selected : root.default;
+        return option;
+      }));
+    },
+
+    // Shows the server and tool versions in the footer
+    async showSystemInfo() {
+      const response = await fetch('/api/system').catch(() => null);
+      if (!response || !response.ok) return;
+      const info = await response.json();
+      const hours = Math.floor(info.uptime_secs / 3600);
+      const parts = [
+        `Pegasus v${info.build.version}`,
+        `yt-dlp ${info.binaries.yt_dlp.version || 'unavailable'}`,
+        `ffmpeg ${info.binaries.ffmpeg.version || 'unavailable'}`,
+        `up ${hours}h`,
+      ];
+      elements.systemFooter.textContent = parts.join(' · ');
+      elements.systemFooter.hidden = false;
+    },
+
+    async login(e) {
+      e.preventDefault();
+      elements.loginError.hidden = true;
+      const response = await fetch('/api/auth/login', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({
+          username: document.getElementById('loginUsername').value,
+          password: document.getElementById('loginPassword').value,
+        }),
+      });
+      if (!response.ok) {
+        const body = await response.json().catch(() => ({}));
+        elements.loginError.textContent = body.error?.message || 'Login failed';
+        elements.loginError.hidden = false;
+        return;
+      }
+      document.getElementById('loginPassword').value = '';
+      this.showApp(await response.json());
+    },
+
+    async logout() {
+      state.loggedOut = true;
+      await fetch('/api/auth/logout', { method: 'POST' });
+      if (state.socket) state.socket.close();
+      this.showLogin();
+    }
+  };
+
   /**
    * API Service - handles all API interactions
    */
   const apiService = {
     // Submit a single URL for processing
-    async submitUrl(url, outputDir, processingOptions) {
+    async submitUrl(url, output, processingOptions, playlistOptions) {
       try {
         const data = {
           mediaUrl: url,
-          outputDir: outputDir,
+          ...output,
           processingOptions: processingOptions,
+          playlist: playlistOptions,
         };
 
         const response = await fetch("/api/submit", {
@@ -329,14 +627,137 @@ document.addEventListener("DOMContentLoaded", () => {
         });
 
         if (!response.ok) {
-          const error = await response.json();
-          throw new Error(error.message || 'Unknown server error');
+          // API errors look like { error: { code, message, details } }
+          const body = await response.json().catch(() => ({}));
+          throw new Error(body.error?.message || 'Unknown server error');
         }
 
         return await response.json();
       } catch (error) {
         throw error;
       }
+    },
+
+    // Submit a list of URLs and/or a block of text to extract URLs from as one batch
+    async submitBatch(name, urls, text, output, processingOptions, playlistOptions) {
+      const data = {
+        name: name,
+        urls: urls,
+        text: text,
+        ...output,
+        processingOptions: processingOptions,
+        playlist: playlistOptions,
+      };
+
+      const response = await fetch("/api/batches", {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+        },
+        body: JSON.stringify(data),
+      });
+
+      if (!response.ok) {
+        const body = await response.json().catch(() => ({}));
+        throw new Error(body.error?.message || 'Unknown server error');
+      }
+
+      return await response.json();
+    },
+
+    // Fetch the metadata and formats of a URL without submitting it
+    async fetchInfo(url) {
+      const response = await fetch(`/api/info?url=${encodeURIComponent(url)}`);
+      if (!response.ok) {
+        const body = await response.json().catch(() => ({}));
+        throw new Error(body.error?.message || 'Unknown server error');
+      }
+      return await response.json();
+    }
+  };
+
+  /**
+   * Preview Manager - shows a URL's metadata and lets the user pick an exact format
+   */
+  const previewManager = {
+    async load() {
+      const [url] = utils.parseUrls(elements.mediaUrlsInput.value);
+      if (!url) {
+        uiManager.showStatus("Enter a media URL to preview.", "error");
+        return;
+      }
+
+      elements.previewBtn.disabled = true;
+      elements.previewBtn.textContent = 'Loading preview...';
+      try {
+        const info = await apiService.fetchInfo(url);
+        this.show(url, info);
+      } catch (error) {
+        uiManager.showStatus(`Preview failed: ${error.message}`, "error");
+      } finally {
+        elements.previewBtn.disabled = false;
+        elements.previewBtn.textContent = 'Preview First URL';
+      }
+    },
+
+    show(url, info) {
+      state.previewUrl = url;
+
+      // The largest thumbnail is listed last
+      const thumbnail = info.thumbnails[info.thumbnails.length - 1];
+      const img = document.getElementById('preview-thumbnail');
+      img.hidden = !thumbnail;
+      img.src = thumbnail ? thumbnail.url : '';
+
+      document.getElementById('preview-title').textContent = info.title || url;
+      const meta = [info.uploader, this.formatDuration(info.duration), info.upload_date];
+      if (info.playlist) {
+        meta.push(`${info.playlist.entry_count} items`);
+      }
+      if (info.subtitle_languages.length) {
+        meta.push(`Subtitles: ${info.subtitle_languages.join(', ')}`);
+      }
+      document.getElementById('preview-meta').textContent = meta.filter(Boolean).join(' · ');
+
+      // Rebuild the format picker, keeping the automatic choice first
+      const select = elements.previewFormatSelect;
+      select.replaceChildren(select.options[0]);
+      info.formats.forEach(format => {
+        const option = document.createElement('option');
+        option.value = format.selector;
+        option.textContent = this.describeFormat(format);
+        select.appendChild(option);
+      });
+      select.value = '';
+      select.disabled = info.formats.length === 0;
+
+      elements.previewDiv.hidden = false;
+    },
+
+    // Selected format selector for a URL, if the user picked one in its preview
+    formatFor(url) {
+      return url === state.previewUrl ? elements.previewFormatSelect.value || null : null;
+    },
+
+    describeFormat(format) {
+      const parts = [format.format_id, format.ext, format.resolution || format.note];
+      if (format.kind === 'video_only') parts.push('video only, best audio added');
+      if (format.kind === 'audio_only') parts.push('audio only');
+      if (format.bitrate_kbps) parts.push(`${Math.round(format.bitrate_kbps)} kbit/s`);
+      if (format.filesize) {
+        const size = (format.filesize / 1048576).toFixed(1);
+        parts.push(`${format.filesize_is_estimate ? '~' : ''}${size} MiB`);
+      }
+      return parts.filter(Boolean).join(' · ');
+    },
+
+    formatDuration(seconds) {
+      if (!seconds) return null;
+      const total = Math.round(seconds);
+      const h = Math.floor(total / 3600);
+      const m = Math.floor((total % 3600) / 60);
+      const s = String(total % 60).padStart(2, '0');
+      return h ? `${h}:${String(m).padStart(2, '0')}:${s}` : `${m}:${s}`;
     }
   };
 
@@ -351,6 +772,8 @@ document.addEventListener("DOMContentLoaded", () => {
         completedJobs: 0,
         failedJobs: 0,
         jobIdToUrl: {},
+        batchId: null,
+        childToParent: {},
         updateProgress: function () {
           const percent = Math.round((this.completedJobs / this.totalJobs) * 100);
           let status = (this.failedJobs > 0) ? 'error' : (percent === 100 ? 'success' : 'processing');
@@ -378,7 +801,14 @@ document.addEventListener("DOMContentLoaded", () => {
       e.preventDefault();
 
       // Get form data
-      const { urls, outputDir, selectedOptions } = utils.collectFormData();
+      const { urls, output, selectedOptions, playlistOptions } = utils.collectFormData();
+
+      // An imported links file is submitted as a batch together with any typed URLs
+      const linksFile = elements.linksFileInput.files[0];
+      if (linksFile) {
+        await this.processBatch(linksFile, urls, output, selectedOptions, playlistOptions);
+        return;
+      }
 
       // Validate form
       if (urls.length === 0) {
@@ -393,11 +823,8 @@ document.addEventListener("DOMContentLoaded", () => {
       uiManager.showProgressIndicator();
       uiManager.updateProgressIndicator(0); // Start with 0%
 
-      if (!elements.outputDirInput.value && outputDir === "/tmp/pegasus_downloads") {
-        uiManager.appendStatus(`No output directory specified. Using default: ${outputDir}`, "info");
-      }
-
       // Initialize job tracking
+      elements.playlistGroupsDiv.replaceChildren();
       const tracker = jobTracker.init(urls.length);
       uiManager.updateProgressInfo('Submitting jobs to backend...');
 
@@ -408,9 +835,13 @@ document.addEventListener("DOMContentLoaded", () => {
 
         try {
           // Submit URL to API
-          const result = await apiService.submitUrl(currentUrl, outputDir, selectedOptions);
+          // A format picked in the preview only applies to the previewed URL
+          const format = previewManager.formatFor(currentUrl);
+          const options = format ? { ...selectedOptions, format } : selectedOptions;
+          const result = await apiService.submitUrl(currentUrl, output, options, playlistOptions);
           uiManager.appendStatus(`(${i + 1}/${urls.length}) Success for ${currentUrl} - Job ID: ${result.job_id}`, "success");
           tracker.jobIdToUrl[result.job_id] = currentUrl;
+          webSocketManager.subscribe([result.job_id]);
         } catch (error) {
           tracker.failedJobs++;
           uiManager.appendStatus(`(${i + 1}/${urls.length}) Error for ${currentUrl}: ${error.message}`, "error");
@@ -422,6 +853,33 @@ document.addEventListener("DOMContentLoaded", () => {
 
       // Reset button state
       uiManager.setFormLoading(false);
+    },
+
+    // Submit the URLs found in an imported file, plus the typed ones, as one batch
+    async processBatch(file, urls, output, selectedOptions, playlistOptions) {
+      uiManager.setFormLoading(true);
+      uiManager.showProgressIndicator();
+      uiManager.updateProgressIndicator(0);
+      elements.playlistGroupsDiv.replaceChildren();
+
+      try {
+        const text = await file.text();
+        const result = await apiService.submitBatch(file.name, urls, text, output, selectedOptions, playlistOptions);
+        const tracker = jobTracker.init(result.jobs.length);
+        tracker.batchId = result.batch_id;
+        result.jobs.forEach(job => {
+          tracker.jobIdToUrl[job.job_id] = job.url;
+        });
+        webSocketManager.subscribe([result.batch_id]);
+        const skipped = result.duplicates ? ` (${result.duplicates} duplicates skipped)` : '';
+        uiManager.appendStatus(`Batch "${result.name}" submitted with ${result.jobs.length} URLs${skipped}`, "success");
+        uiManager.updateProgressInfo('Waiting for downloads and conversions to finish...');
+        elements.linksFileInput.value = '';
+      } catch (error) {
+        uiManager.showStatus(`Batch submission failed: ${error.message}`, "error");
+      } finally {
+        uiManager.setFormLoading(false);
+      }
     }
   };
 
@@ -430,11 +888,16 @@ document.addEventListener("DOMContentLoaded", () => {
     // Initialize UI
     uiManager.init();
 
-    // Initialize WebSocket connection
-    webSocketManager.connect();
+    // Log in if needed, then connect to the WebSocket feed
+    authManager.start();
+    elements.loginForm.addEventListener("submit", (e) => authManager.login(e));
+    elements.logoutBtn.addEventListener("click", () => authManager.logout());
 
     // Setup form submission handler
     elements.form.addEventListener("submit", (e) => formHandler.processForm(e));
+
+    // Setup the metadata preview
+    elements.previewBtn.addEventListener("click", () => previewManager.load());
   }
 
   // Start the application